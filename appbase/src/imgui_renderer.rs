@@ -9,14 +9,15 @@ use gl::types::*;
 use super::tinygl::*;
 
 pub struct Renderer {
+    ctx: super::tinygl::Context,
     program: Program,
-    vbo: GLuint,
-    ebo: GLuint,
-    font_texture: GLuint,
+    vbo: VertexBuffer,
+    ebo: IndexBuffer,
+    font_texture: Texture,
 }
 
 impl Renderer {
-    pub fn new(imgui: &mut Context) -> Self {
+    pub fn new(context: &super::tinygl::Context, imgui: &mut Context) -> Self {
         let vert_source = "
             uniform mat4 ProjMtx;
             attribute vec2 Position;
@@ -40,120 +41,95 @@ impl Renderer {
                 gl_FragColor = Frag_Color * texture2D(Texture, Frag_UV.st);
             }";
 
-        let program = Program::new_versioned(vert_source, frag_source, 100);
-
-        unsafe {
-            let vbo = return_param(|x| gl::GenBuffers(1, x) );
-            let ebo = return_param(|x| gl::GenBuffers(1, x) );
-
-            let mut current_texture = 0;
-            gl::GetIntegerv(gl::TEXTURE_BINDING_2D, &mut current_texture);
-
-            let font_texture = return_param(|x| gl::GenTextures(1, x));
-            gl::BindTexture(gl::TEXTURE_2D, font_texture);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as _);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as _);
-            
-            let font_data = imgui.fonts().build_rgba32_texture();
-            gl::TexImage2D(gl::TEXTURE_2D, 0, gl::RGBA as _, font_data.width as _, font_data.height as _, 0, gl::RGBA, gl::UNSIGNED_BYTE, font_data.data.as_ptr() as _);
-
-            gl::BindTexture(gl::TEXTURE_2D, current_texture as _);
-
-            Self {
-                program,
-                vbo,
-                ebo,
-                font_texture,
-            }
+        let program = Program::new_versioned(context, vert_source, frag_source, 100);
+        program.set_label("imgui");
+
+        let font_data = imgui.fonts().build_rgba32_texture();
+        let descriptor = TextureDescriptor::flat(TextureFormat::RGBA8, (font_data.width as _, font_data.height as _));
+        let mut font_texture = Texture::with_descriptor(context, descriptor).expect("Invalid font texture size");
+        font_texture.set_image(0, 0, font_data.data).expect("Font texture data doesn't match its size");
+        font_texture.set_label("imgui font");
+
+        Self {
+            ctx: context.clone(),
+            program,
+            vbo: VertexBuffer::stream(context, 0),
+            ebo: IndexBuffer::dynamic::<DrawIdx>(context, 0),
+            font_texture,
         }
     }
 
     pub fn render(&mut self, imgui: &mut Context) {
+        let ctx = &self.ctx;
+        // the index buffer binding belongs to the vertex array, and the attributes go to the default one
+        Mesh::release(ctx);
+
+        enable(ctx, gl::BLEND);
+        enable(ctx, gl::SCISSOR_TEST);
         unsafe {
-            gl::Enable(gl::BLEND);
-            gl::Enable(gl::SCISSOR_TEST);
             gl::BlendEquation(gl::FUNC_ADD);
             gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
-            gl::Disable(gl::CULL_FACE);
-            gl::Disable(gl::DEPTH_TEST);
-            gl::ActiveTexture(gl::TEXTURE0);
-            
-            let draw_data = imgui.render();
-
-            let [width, height] = draw_data.display_size;
-            let fb_width = width * draw_data.framebuffer_scale[0];
-            let fb_height = height * draw_data.framebuffer_scale[1];
-
-            gl::Viewport(0, 0, fb_width as _, fb_height as _);
-            let matrix = cgmath::Matrix4::new(
-                 2.0 / width as f32, 0.0,                     0.0, 0.0,
-                 0.0,                2.0 / -(height as f32),  0.0, 0.0,
-                 0.0,                0.0,                    -1.0, 0.0,
-                -1.0,                1.0,                     0.0, 1.0,
-            );
-            self.program.bind();
-            self.program.uniform("Texture", Uniform::Signed(0));
-            self.program.uniform("ProjMtx", Uniform::Mat4(matrix));
-
-            let ploc = self.program.vertex_attrib_location("Position").unwrap_or(0);
-            let puv = self.program.vertex_attrib_location("UV").unwrap_or(0);
-            let pcolor = self.program.vertex_attrib_location("Color").unwrap_or(0);
-            gl::EnableVertexAttribArray(ploc);
-            gl::EnableVertexAttribArray(puv);
-            gl::EnableVertexAttribArray(pcolor);
-
-            // draw_data.scale_clip_rects(draw_data.framebuffer_scale.into());
-
-            for draw_list in draw_data.draw_lists() {
-                gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
-                gl::BufferData(gl::ARRAY_BUFFER, (draw_list.vtx_buffer().len() * mem::size_of::<DrawVert>()) as _, draw_list.vtx_buffer().as_ptr() as _, gl::STREAM_DRAW);
-
-                gl::VertexAttribPointer(ploc,   2, gl::FLOAT,         gl::FALSE, mem::size_of::<DrawVert>() as _, field_offset::<DrawVert, _, _>(|v| &v.pos) as _);
-                gl::VertexAttribPointer(puv,    2, gl::FLOAT,         gl::FALSE, mem::size_of::<DrawVert>() as _, field_offset::<DrawVert, _, _>(|v| &v.uv) as _);
-                gl::VertexAttribPointer(pcolor, 4, gl::UNSIGNED_BYTE, gl::TRUE,  mem::size_of::<DrawVert>() as _, field_offset::<DrawVert, _, _>(|v| &v.col) as _);
-
-                gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.ebo);
-                gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, (draw_list.idx_buffer().len() * mem::size_of::<DrawIdx>()) as _, draw_list.idx_buffer().as_ptr() as _, gl::STREAM_DRAW);
-
-                let mut idx_start = 0;
-                for cmd in draw_list.commands() {
-                    match cmd {
-                        DrawCmd::Elements { count, cmd_params } => {
+        }
+        disable(ctx, gl::CULL_FACE);
+        disable(ctx, gl::DEPTH_TEST);
+
+        let draw_data = imgui.render();
+
+        let [width, height] = draw_data.display_size;
+        let fb_width = width * draw_data.framebuffer_scale[0];
+        let fb_height = height * draw_data.framebuffer_scale[1];
+
+        viewport(ctx, 0, 0, fb_width as _, fb_height as _);
+        let matrix = cgmath::Matrix4::new(
+             2.0 / width as f32, 0.0,                     0.0, 0.0,
+             0.0,                2.0 / -(height as f32),  0.0, 0.0,
+             0.0,                0.0,                    -1.0, 0.0,
+            -1.0,                1.0,                     0.0, 1.0,
+        );
+        self.program.bind();
+        self.program.uniform("Texture", Uniform::Signed(0));
+        self.program.uniform("ProjMtx", Uniform::Mat4(matrix));
+
+        // draw_data.scale_clip_rects(draw_data.framebuffer_scale.into());
+
+        let stride = mem::size_of::<DrawVert>() as GLsizei;
+        for draw_list in draw_data.draw_lists() {
+            self.vbo.update(draw_list.vtx_buffer());
+            self.program.vertex_attrib_buffer("Position", &self.vbo, 2, gl::FLOAT,         false, stride, field_offset::<DrawVert, _, _>(|v| &v.pos) as _);
+            self.program.vertex_attrib_buffer("UV",       &self.vbo, 2, gl::FLOAT,         false, stride, field_offset::<DrawVert, _, _>(|v| &v.uv) as _);
+            self.program.vertex_attrib_buffer("Color",    &self.vbo, 4, gl::UNSIGNED_BYTE, true,  stride, field_offset::<DrawVert, _, _>(|v| &v.col) as _);
+
+            if let Err(err) = self.ebo.update(draw_list.idx_buffer()) {
+                ctx.warn(err);
+                continue;
+            }
+
+            let mut idx_start = 0;
+            for cmd in draw_list.commands() {
+                match cmd {
+                    DrawCmd::Elements { count, cmd_params } => {
+                        unsafe {
                             gl::Scissor(cmd_params.clip_rect[0] as GLint,
                                         (fb_height - cmd_params.clip_rect[3]) as GLint,
                                         (cmd_params.clip_rect[2] - cmd_params.clip_rect[0]) as GLint,
                                         (cmd_params.clip_rect[3] - cmd_params.clip_rect[1]) as GLint);
-                            let texture = if cmd_params.texture_id.id() == 0 {
-                                self.font_texture
-                            } else {
-                                unimplemented!("no support for custom textures yet")
-                            };
-                            gl::BindTexture(gl::TEXTURE_2D, texture as _);
-                            gl::DrawElements(gl::TRIANGLES, count as _, if mem::size_of::<DrawIdx>() == 2 { gl::UNSIGNED_SHORT } else { gl::UNSIGNED_INT }, idx_start as _);
-                            idx_start += count * mem::size_of::<DrawIdx>();
-                        },
-                        DrawCmd::ResetRenderState => unimplemented!("Haven't implemented user callbacks yet"),
-                        DrawCmd::RawCallback { .. } => unimplemented!("Haven't implemented user callbacks yet"),
-                    }
+                        }
+                        if cmd_params.texture_id.id() == 0 {
+                            self.font_texture.bind_at(0);
+                        } else {
+                            unimplemented!("no support for custom textures yet")
+                        }
+                        self.ebo.draw(gl::TRIANGLES, count as _, idx_start as _);
+                        idx_start += count * mem::size_of::<DrawIdx>();
+                    },
+                    DrawCmd::ResetRenderState => unimplemented!("Haven't implemented user callbacks yet"),
+                    DrawCmd::RawCallback { .. } => unimplemented!("Haven't implemented user callbacks yet"),
                 }
             }
-
-            gl::DisableVertexAttribArray(ploc);
-            gl::DisableVertexAttribArray(puv);
-            gl::DisableVertexAttribArray(pcolor);
-
-            gl::Disable(gl::SCISSOR_TEST);
         }
-    }
-}
 
-impl Drop for Renderer {
-    fn drop(&mut self) {
-        unsafe {
-            gl::DeleteBuffers(1, &self.vbo);
-            gl::DeleteBuffers(1, &self.ebo);
-            gl::DeleteTextures(1, &self.font_texture);
-        }
+        self.program.disable_all_vertex_attribs();
+        disable(ctx, gl::SCISSOR_TEST);
     }
 }
 
@@ -171,9 +147,3 @@ fn field_offset<T, U, F: for<'a> FnOnce(&'a T) -> &'a U>(f: F) -> usize {
         offset
     }
 }
-
-fn return_param<T, F>(f: F) -> T where F: FnOnce(&mut T), T: Default {
-    let mut val = T::default();
-    f(&mut val);
-    val
-}
@@ -5,6 +5,7 @@ extern crate imgui_sdl2;
 extern crate tinygl;
 
 pub mod fpswidget;
+pub mod profilerwidget;
 pub mod webrunner;
 pub mod imgui_renderer;
 pub mod imgui_helper;
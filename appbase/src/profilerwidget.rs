@@ -0,0 +1,97 @@
+use imgui::{Condition, ProgressBar};
+
+/// Shows the scopes of a `tinygl::Profiler` with their average time and share of the frame,
+/// and the history of the selected one
+pub struct ProfilerWidget {
+    // frames to average over
+    average: usize,
+    selected: Option<String>,
+    trace_path: String,
+    message: String,
+}
+
+impl ProfilerWidget {
+    /// "Save Trace" writes the history to `trace_path`
+    pub fn new(average: usize, trace_path: &str) -> Self {
+        ProfilerWidget {
+            average,
+            selected: None,
+            trace_path: trace_path.to_string(),
+            message: String::new(),
+        }
+    }
+
+    pub fn render(&mut self, ui: &imgui::Ui, profiler: &mut tinygl::Profiler, position: (f32, f32), size: (f32, f32), opened: &mut bool) {
+        ui.window("Profiler")
+            .opened(opened)
+            .save_settings(false)
+            .size([size.0, size.1], Condition::FirstUseEver)
+            .position([position.0, position.1], Condition::FirstUseEver)
+            .build(|| {
+                let mut gpu = profiler.gpu();
+                if profiler.timer_queries() {
+                    if ui.checkbox("GPU Times", &mut gpu) {
+                        profiler.set_gpu(gpu);
+                    }
+                } else {
+                    ui.text_disabled("No timer queries, CPU times only");
+                }
+
+                let frames = profiler.frames();
+                let count = self.average.min(frames.len()).max(1);
+                let frame_time = frames.iter().rev().take(count).map(|frame| frame.duration).sum::<f64>() / count as f64;
+                ui.text(format!("Frame: {0:.2} ms", frame_time));
+                ui.separator();
+
+                //
+                // Breakdown of the scopes
+                //
+                let width = ui.window_size()[0];
+                ui.columns(3, "scopes", false);
+                ui.set_column_width(0, width - 150.0);
+                ui.set_column_width(1, 60.0);
+                for (depth, name) in profiler.scope_names() {
+                    let average = profiler.average(&name, self.average);
+                    let selected = self.selected.as_ref() == Some(&name);
+                    let label = format!("{}{}##{}", "  ".repeat(depth - 1), name, depth);
+                    if ui.selectable_config(label).selected(selected).span_all_columns(true).build() {
+                        self.selected = if selected { None } else { Some(name.clone()) };
+                    }
+                    ui.next_column();
+                    ui.text(format!("{0:.2}", average));
+                    ui.next_column();
+                    let share = if frame_time > 0.0 { (average / frame_time) as f32 } else { 0.0 };
+                    ProgressBar::new(share.min(1.0))
+                        .size([-1.0, 0.0])
+                        .overlay_text(format!("{0:.0}%", share * 100.0))
+                        .build(ui);
+                    ui.next_column();
+                }
+                ui.columns(1, "scopes", false);
+                ui.separator();
+
+                //
+                // History of the selected scope, or of the whole frames
+                //
+                let (title, history) = match self.selected.as_ref() {
+                    Some(name) => (name.clone(), profiler.scope_history(name)),
+                    None => (String::from("frame"), profiler.frame_history()),
+                };
+                let max = history.iter().cloned().fold(0.0, f32::max);
+                ui.text(format!("{} (max {:.2} ms)", title, max));
+                ui.plot_lines("##history", &history)
+                    .graph_size([width - 20.0, 60.0])
+                    .scale_min(0.0)
+                    .scale_max(max.max(0.001))
+                    .build();
+
+                if ui.button("Save Trace") {
+                    self.message = match profiler.save_chrome_trace(&self.trace_path) {
+                        Ok(()) => format!("Saved {} for chrome://tracing", self.trace_path),
+                        Err(err) => err,
+                    };
+                }
+                ui.text_wrapped(&self.message);
+            });
+    }
+}
@@ -10,18 +10,24 @@ use std::os::raw::{c_void};
 use super::imgui_renderer::Renderer;
 
 pub trait WebApp {
-    fn new(size: (u32, u32)) -> Self;
+    fn new(context: &tinygl::Context, size: (u32, u32)) -> Self;
     fn resize(&mut self, size: (u32, u32));
     fn render(&mut self, dt: f32);
     fn event(&mut self, event: &sdl2::event::Event);
     fn do_ui(&mut self, ui: &imgui::Ui, keymod: sdl2::keyboard::Mod);
+    /// A game controller got connected (with its name) or disconnected, its instance id is the
+    /// `which` of the controller events
+    fn controller(&mut self, _instance: u32, _name: Option<&str>) {}
 }
 
 pub struct AppRunner<T> {
     window: sdl2::video::Window,
     gl_ctx: sdl2::video::GLContext,
+    context: tinygl::Context,
 
     events: sdl2::EventPump,
+    controller_ctx: Option<sdl2::GameControllerSubsystem>,
+    controllers: Vec<sdl2::controller::GameController>,
     keymod: sdl2::keyboard::Mod,
     last_frame: Option<std::time::SystemTime>,
     private: Option<T>,
@@ -57,18 +63,30 @@ impl<T: WebApp> AppRunner<T> {
 
         let gl_ctx = window.gl_create_context().ok().expect("No OpenGL context found");
         gl::load_with(|s| video_ctx.gl_get_proc_address(s) as *const c_void);
+        // WebGL 2 only has timestamp queries with EXT_disjoint_timer_query_webgl2
+        #[cfg(target_os = "emscripten")]
+        {
+            gl::QueryCounter::load_with(|_| video_ctx.gl_get_proc_address("glQueryCounterEXT") as *const c_void);
+            gl::GetQueryObjectui64v::load_with(|_| video_ctx.gl_get_proc_address("glGetQueryObjectui64vEXT") as *const c_void);
+        }
+        let context = tinygl::Context::new();
 
         let events = ctx.event_pump().unwrap();
+        // controllers get opened as they show up, including the ones connected at startup
+        let controller_ctx = ctx.game_controller().ok();
 
         let mut imgui = imgui::Context::create();
         imgui.set_ini_filename(None);
         let imgui_sdl2 = imgui_sdl2::ImguiSdl2::new(&mut imgui, &window);
-        let imgui_renderer = Renderer::new(&mut imgui);
+        let imgui_renderer = Renderer::new(&context, &mut imgui);
 
         let mut runner = Rc::new(AppRunner::<T> {
             gl_ctx,
+            context,
             window,
             events,
+            controller_ctx,
+            controllers: Vec::new(),
             keymod: sdl2::keyboard::Mod::empty(),
             last_frame: None,
             private: None,
@@ -101,7 +119,7 @@ impl<T: WebApp> AppRunner<T> {
         // first init?
         let dt = match self.last_frame.take() {
             None => {
-                self.private = Some(T::new(self.window.size()));
+                self.private = Some(T::new(&self.context, self.window.size()));
                 self.private.as_mut().unwrap().resize(self.window.size());
                 0.0
             },
@@ -138,6 +156,16 @@ impl<T: WebApp> AppRunner<T> {
                         private.resize((w as u32, h as u32));
                     }
                 }
+                sdl2::event::Event::ControllerDeviceAdded{which, ..} => {
+                    if let Some(controller) = self.controller_ctx.as_ref().and_then(|ctx| ctx.open(which).ok()) {
+                        private.controller(controller.instance_id(), Some(&controller.name()));
+                        self.controllers.push(controller);
+                    }
+                }
+                sdl2::event::Event::ControllerDeviceRemoved{which, ..} => {
+                    self.controllers.retain(|controller| controller.instance_id() != which);
+                    private.controller(which, None);
+                }
                 sdl2::event::Event::MouseWheel{timestamp, window_id, which, x, y, direction, precise_x, precise_y} => {
                     let sgn = |x| if x > 0 { 1 } else if x < 0 { -1 } else { 0 };
                     let x = sgn(x);
@@ -162,5 +190,6 @@ impl<T: WebApp> AppRunner<T> {
         self.imgui_renderer.render(&mut self.imgui);
 
         self.window.gl_swap_window();
+        self.context.end_frame();
     }
 }
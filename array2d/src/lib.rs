@@ -1,4 +1,4 @@
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct Array2D<T: Copy> {
     width: usize,
     height: usize,
@@ -12,6 +12,7 @@ struct Upload
     std::string inputItem;
     std::string fileName;
     std::vector<unsigned char> data;
+    double lastModified = 0.0;
     bool done = false;
 };
 
@@ -51,8 +52,8 @@ extern "C" void UploadStart(const char *inputItem)
                                 "[inputItem, sub, start, end]);"
                     "}"
                     "Module.ccall('UploadFinished', 'void', "
-                            "['string', 'string'], "
-                            "[inputItem, elem.files[0].name]);"
+                            "['string', 'string', 'number'], "
+                            "[inputItem, elem.files[0].name, elem.files[0].lastModified]);"
             "    }); "
             "    reader.readAsArrayBuffer(elem.files[0]); "
             "}); "
@@ -79,11 +80,12 @@ extern "C" void UploadData(const char *input, const unsigned char *data, int sta
     memcpy(upload->data.data() + start, data, end-start);
 }
 
-extern "C" void UploadFinished(const char *input, const char *filename)
+extern "C" void UploadFinished(const char *input, const char *filename, double lastModified)
 {
     Upload *upload = s_uploaded.back();
     upload->inputItem = std::string(input);
     upload->fileName = std::string(filename);
+    upload->lastModified = lastModified;
     upload->done = true;
 }
 
@@ -109,6 +111,16 @@ extern "C" int UploadFilenameSize(const char *inputItem)
     return -1;
 }
 
+extern "C" double UploadLastModified(const char *inputItem)
+{
+    for (auto up : s_uploaded) {
+        if (up->inputItem == inputItem) {
+            return up->lastModified;
+        }
+    }
+    return 0.0;
+}
+
 extern "C" int UploadGetData(const char *inputItem, char *buffer, int len)
 {
     // check if there is such a download already
@@ -7,6 +7,7 @@ extern "C" {
     fn UploadStart(element: *const c_char);
     fn UploadResultSize(element: *const c_char) -> c_int;
     fn UploadFilenameSize(element: *const c_char) -> c_int;
+    fn UploadLastModified(element: *const c_char) -> f64;
     fn UploadGetData(element: *const c_char, data: *mut u8, len: c_int) -> c_int;
     fn UploadGetFilename(element: *const c_char, data: *mut u8, len: c_int) -> c_int;
     fn DoDownload(name: *const c_char, name_len: c_int, data: *const c_char, data_len: c_int);
@@ -26,6 +27,11 @@ pub fn start_upload(element: &str) {
 }
 
 pub fn get_result(element: &str) -> Option<(String, Vec<u8>)> {
+    get_result_with_date(element).map(|(filename, data, _)| (filename, data))
+}
+
+/// Also has the modification time of the file, in milliseconds since the epoch
+pub fn get_result_with_date(element: &str) -> Option<(String, Vec<u8>, f64)> {
     // prepare C string buffers
     let element = CString::new(element).unwrap();
 
@@ -47,11 +53,14 @@ pub fn get_result(element: &str) -> Option<(String, Vec<u8>)> {
     unsafe { UploadGetFilename(element.as_ptr(), filename.as_mut_ptr(), filename_sz); }
     let filename = unsafe { String::from_utf8_unchecked(filename) };
 
+    // before the data, which removes the upload
+    let modified = unsafe { UploadLastModified(element.as_ptr()) };
+
     let mut data = Vec::new();
     data.resize(result_sz as usize, 0);
     unsafe { UploadGetData(element.as_ptr(), data.as_mut_ptr(), result_sz); }
 
-    Some((filename, data))
+    Some((filename, data, modified))
 }
 
 pub fn download(name: &str, data: &Vec<u8>) {
@@ -118,3 +118,11 @@ pub fn store(db: &str, name: &str, data: &[u8]) {
         emscripten_idb_async_store(db.as_ptr(), name.as_ptr(), data.as_ptr() as _, data.len() as i32, std::ptr::null_mut(), None, None)
     }
 }
+
+pub fn delete(db: &str, name: &str) {
+    let db = CString::new(db).unwrap();
+    let name = CString::new(name).unwrap();
+    unsafe {
+        emscripten_idb_async_delete(db.as_ptr(), name.as_ptr(), std::ptr::null_mut(), None, None)
+    }
+}
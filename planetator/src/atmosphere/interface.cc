@@ -241,17 +241,17 @@ extern "C" int AtmosphereGetShaderSource(char *buffer, int size)
     return shader_str.size();
 }
 
-extern "C" void AtmospherePrepareShader(GLuint program, int first_tex_unit)
+// the caller binds the textures and sets the uniforms
+extern "C" void AtmosphereGetShaderParams(GLuint *textures, float *white_point, float *sun_size, float *exposure)
 {
-    glUseProgram(program);
+    model_->GetTextures(textures);
 
-    model_->SetProgramUniforms(program, first_tex_unit, first_tex_unit+1, first_tex_unit+2, first_tex_unit+3);
-    glUniform3f(glGetUniformLocation(program, "white_point"),
-        white_point_r_, white_point_g_, white_point_b_);
-    glUniform2f(glGetUniformLocation(program, "sun_size"),
-        tan(kSunAngularRadius),
-        cos(kSunAngularRadius));
+    white_point[0] = white_point_r_;
+    white_point[1] = white_point_g_;
+    white_point[2] = white_point_b_;
 
-    glUniform1f(glGetUniformLocation(program, "exposure"),
-        use_luminance_ != NONE ? AtmosphereExposure * 1e-5 : AtmosphereExposure);
+    sun_size[0] = tan(kSunAngularRadius);
+    sun_size[1] = cos(kSunAngularRadius);
+
+    *exposure = use_luminance_ != NONE ? AtmosphereExposure * 1e-5 : AtmosphereExposure;
 }
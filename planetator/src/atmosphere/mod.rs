@@ -9,7 +9,7 @@ extern "C" {
     fn AtmosphereInitModel();
     fn AtmosphereDestroy();
     fn AtmosphereGetShaderSource(buffer: *mut c_char, size: c_int) -> c_int;
-    fn AtmospherePrepareShader(program: GLuint, first_tex_unit: c_int);
+    fn AtmosphereGetShaderParams(textures: *mut GLuint, white_point: *mut f32, sun_size: *mut f32, exposure: *mut f32);
 
     static mut AtmosphereUseConstantSolarSpectrum: c_int;
     static mut AtmosphereUseOzone: c_int;
@@ -28,6 +28,13 @@ extern "C" {
 static mut did_init: bool = false;
 static mut dirty: bool = false;
 static E: f32 = 0.000001;
+/// The samplers of the shader source, in the order of the textures of AtmosphereGetShaderParams
+static SAMPLERS: [(&str, GLenum); 4] = [
+    ("transmittance_texture", gl::TEXTURE_2D),
+    ("scattering_texture", gl::TEXTURE_3D),
+    ("irradiance_texture", gl::TEXTURE_2D),
+    ("single_mie_scattering_texture", gl::TEXTURE_3D),
+];
 
 pub fn is_dirty() -> bool {
     unsafe { dirty }
@@ -173,6 +180,27 @@ pub fn shader_source() -> String {
         .unwrap()
 }
 
-pub fn prepare_shader(program: GLuint, first_tex_unit: usize) {
-    unsafe { AtmospherePrepareShader(program, first_tex_unit as _); }
+/// Binds the precomputed textures to the units from `first_tex_unit` on and sets the uniforms
+/// of a program using `shader_source()`, which has to be bound
+pub fn prepare_shader(context: &tinygl::Context, program: &tinygl::Program, first_tex_unit: u32) -> Result<(), String> {
+    let mut textures: [GLuint; 4] = [0; 4];
+    let mut white_point = [0.0f32; 3];
+    let mut sun_size = [0.0f32; 2];
+    let mut exposure = 0.0f32;
+    unsafe { AtmosphereGetShaderParams(textures.as_mut_ptr(), white_point.as_mut_ptr(), sun_size.as_mut_ptr(), &mut exposure); }
+
+    let mut prepared = Vec::new();
+    for (index, ((uniform, target), &texture)) in SAMPLERS.iter().zip(textures.iter()).enumerate() {
+        // the single mie scattering is part of the scattering texture when they are combined
+        if texture == 0 {
+            continue;
+        }
+        let unit = first_tex_unit + index as u32;
+        tinygl::bind_texture(context, unit, *target, texture);
+        prepared.push(program.set_uniform(uniform, tinygl::Uniform::Signed(unit as _)));
+    }
+    prepared.push(program.set_uniform("white_point", tinygl::Uniform::Vec3(white_point.into())));
+    prepared.push(program.set_uniform("sun_size", tinygl::Uniform::Vec2(sun_size.into())));
+    prepared.push(program.set_uniform("exposure", tinygl::Uniform::Float(exposure)));
+    prepared.into_iter().collect()
 }
\ No newline at end of file
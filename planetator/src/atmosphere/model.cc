@@ -985,6 +985,13 @@ void Model::SetProgramUniforms(
   }
 }
 
+void Model::GetTextures(GLuint textures[4]) const {
+  textures[0] = transmittance_texture_;
+  textures[1] = scattering_texture_;
+  textures[2] = irradiance_texture_;
+  textures[3] = optional_single_mie_scattering_texture_;
+}
+
 /*
 <p>The utility method <code>ConvertSpectrumToLinearSrgb</code> is implemented
 with a simple numerical integration of the given function, times the CIE color
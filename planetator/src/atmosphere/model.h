@@ -302,6 +302,10 @@ class Model {
       GLuint irradiance_texture_unit,
       GLuint optional_single_mie_scattering_texture_unit = 0) const;
 
+  // The textures of SetProgramUniforms in the same order, for binding them elsewhere. The
+  // last one is 0 when the scattering textures are combined.
+  void GetTextures(GLuint textures[4]) const;
+
   // Utility method to convert a function of the wavelength to linear sRGB.
   // 'wavelengths' and 'spectrum' must have the same size. The integral of
   // 'spectrum' times each CIE_2_DEG_COLOR_MATCHING_FUNCTIONS (and times
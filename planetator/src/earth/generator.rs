@@ -49,14 +49,14 @@ pub struct PlateDataManager {
 pub type PlateDataManagerPtr = Rc<RefCell<PlateDataManager>>;
 
 impl PlateDataManager {
-    pub fn new(vertex_depth: u32, texture_delta: u32, radius: f32, vertex_generator: Program, channels: &Channels) -> Self {
-        PlateDataManager {
+    pub fn new(context: &Context, shaders: &ShaderRegistry, vertex_depth: u32, texture_delta: u32, radius: f32, vertex_generator: Program, channels: &Channels) -> std::result::Result<Self, String> {
+        Ok(PlateDataManager {
             vertex_size: 2u32.pow(vertex_depth),
             radius,
-            generator: Generator::new(vertex_depth, texture_delta as _, 100, radius, 12, vertex_generator, channels),
+            generator: Generator::new(context, shaders, vertex_depth, texture_delta as _, 100, radius, 12, vertex_generator, channels)?,
             cache: LruCache::new(400),
             waiting: HashMap::new()
-        }
+        })
     }
 
     pub fn request(&mut self, position: &plate::Position, priority: f32) -> Option<Result> {
@@ -119,6 +119,10 @@ impl PlateDataManager {
         self.generator.generate_indices()
     }
 
+    pub fn context(&self) -> &Context {
+        &self.generator.context
+    }
+
     pub fn vertex_tile_size(&self) -> u32 {
         self.vertex_size
     }
@@ -147,8 +151,8 @@ struct GeneratorBuffers {
 }
 
 impl GeneratorBuffers {
-    fn new(tex_size: i32, vert_size: i32, channels: &Channels) -> Self {
-        let mut position_pass = FrameBufferObject::new((tex_size, tex_size));
+    fn new(context: &Context, tex_size: i32, vert_size: i32, channels: &Channels) -> Self {
+        let mut position_pass = FrameBufferObject::new(context, (tex_size, tex_size));
         position_pass.add("posHeight", gl::RGBA32F, gl::RGBA, gl::FLOAT);
         position_pass.add("height", gl::R32F, gl::RED, gl::FLOAT);
         // TODO: avoid duplication
@@ -163,10 +167,10 @@ impl GeneratorBuffers {
             position_pass.add(&chan.0, int_fmt.0, int_fmt.1, gl::UNSIGNED_BYTE);
         }
 
-        let mut normal_pass = FrameBufferObject::new((tex_size, tex_size));
+        let mut normal_pass = FrameBufferObject::new(context, (tex_size, tex_size));
         normal_pass.add("normal", gl::RGB, gl::RGB, gl::UNSIGNED_BYTE);
 
-        let mut downscale_pass = FrameBufferObject::new((vert_size, vert_size));
+        let mut downscale_pass = FrameBufferObject::new(context, (vert_size, vert_size));
         downscale_pass.add("posHeight", gl::RGBA32F, gl::RGBA, gl::FLOAT);
         downscale_pass.add("detail", gl::RGBA, gl::RGBA, gl::UNSIGNED_BYTE);
 
@@ -178,25 +182,27 @@ impl GeneratorBuffers {
     }
 }
 
-fn passthrough_vert() ->  & 'static str {
-    include_str!("../shaders/passthrough.vert")
+fn passthrough_vert(shaders: &ShaderRegistry) -> Source {
+    Source::new("passthrough.vert", &shaders.get("passthrough.vert"))
 }
 
 //
 // Shaders for Vertex + Height (+ Channels) generation
 //
-pub fn compile_generator(generator: &str, channels: &Channels) -> Program {
-    let src = include_str!("../shaders/generator_vertices.frag");
-    let src = src.replace("$CHANNELS", &channels.glsl_output_declarations(2));
-    let src = src.replace("$NOISE", noise::ShaderNoise::definitions());
-    let src = src.replace("$GENERATOR", generator);
-    Program::new(passthrough_vert(), &src)
+pub fn compile_generator(context: &Context, shaders: &ShaderRegistry, generator: &str, channels: &Channels) -> Program {
+    let src = Preprocessor::new()
+        .add_include("channels", &channels.glsl_output_declarations(2))
+        .add_include("noise", noise::ShaderNoise::definitions())
+        .add_include("generator", generator)
+        .process("generator_vertices.frag", &shaders.get("generator_vertices.frag"));
+    Program::from_sources(context, &passthrough_vert(shaders), &src, 300)
 }
 
 //
 // Responsible for actually generating the tile data using specialized shaders
 //
 struct Generator {
+    context: Context,
     vertex_depth: u32,
     vertex_grid_size: u32,
     texture_delta: u32,
@@ -246,20 +252,22 @@ impl Generator {
         }
     }
 
-    pub fn new(vertex_depth: u32,
+    pub fn new(context: &Context,
+               shaders: &ShaderRegistry,
+               vertex_depth: u32,
                texture_delta: u32,
                detail: u8,
                radius: f32,
                max_framebuffer_cache: usize,
                vertex_generator: Program,
                channels: &Channels
-    ) -> Self {
+    ) -> std::result::Result<Self, String> {
         let vertex_size = 2u32.pow(vertex_depth);
         let texture_size = (vertex_size + 2) * 2u32.pow(texture_delta) + 1;
 
         // Screen-Space Quad
         let quad_verts: Vec<f32> = vec![-1.0, -1.0, 1.0, 1.0, 1.0, -1.0, -1.0, -1.0, -1.0, 1.0, 1.0, 1.0,];
-        let quad = VertexBuffer::from(&quad_verts);
+        let quad = VertexBuffer::from(context, &quad_verts);
 
         //
         // The Offset texture decribes for each vertex the higher-level neighbor vertices,
@@ -278,10 +286,11 @@ impl Generator {
             }
         }
 
-        let mut offset_texture = Texture::new(gl::TEXTURE_2D);
+        let offset_descriptor = TextureDescriptor::flat(TextureFormat::RGBA32F, ((vertex_size+1) as _, (vertex_size+1) as _));
+        let mut offset_texture = Texture::with_descriptor(context, offset_descriptor)?;
         offset_texture.filter(gl::TEXTURE_MIN_FILTER, gl::NEAREST as _);
         offset_texture.filter(gl::TEXTURE_MAG_FILTER, gl::NEAREST as _);
-        unsafe { offset_texture.teximage(((vertex_size+1) as _, (vertex_size+1) as _), gl::RGBA32F, gl::RGBA, gl::FLOAT, offset_tex_data.as_ptr() as _); }
+        offset_texture.set_image(0, 0, &offset_tex_data)?;
 
         // Set uniforms for Vertex/Height/Channels Generator
         vertex_generator.bind();
@@ -292,16 +301,22 @@ impl Generator {
         vertex_generator.uniform("radius", Uniform::Float(radius));
 
         // Set uniforms for normal generator
-        let gen_normals_shader = include_str!("../shaders/generator_normals.frag");
-        let normals_generator = Program::new(passthrough_vert(), gen_normals_shader);
+        let gen_normals_shader = Source::new("generator_normals.frag", &shaders.get("generator_normals.frag"));
+        let normals_generator = Program::from_sources(context, &passthrough_vert(shaders), &gen_normals_shader, 300);
+        if !normals_generator.valid() {
+            return Err(normals_generator.error_report());
+        }
         normals_generator.bind();
         normals_generator.uniform("invTargetSize", Uniform::Float(1.0 / texture_size as f32));
         normals_generator.uniform("radius", Uniform::Float(radius));
         normals_generator.uniform("positions", Uniform::Signed(0));
 
         // Set uniforms for downscaling and detail computation shader
-        let gen_postvertex_shader = include_str!("../shaders/generator_postvertex.frag");
-        let post_generator = Program::new(passthrough_vert(), gen_postvertex_shader);
+        let gen_postvertex_shader = Source::new("generator_postvertex.frag", &shaders.get("generator_postvertex.frag"));
+        let post_generator = Program::from_sources(context, &passthrough_vert(shaders), &gen_postvertex_shader, 300);
+        if !post_generator.valid() {
+            return Err(post_generator.error_report());
+        }
         post_generator.bind();
         post_generator.uniform("vertexGridSize", Uniform::Float(vertex_size as f32));
         post_generator.uniform("textureDelta", Uniform::Float(2.0f32.powi(texture_delta as _)));
@@ -310,7 +325,8 @@ impl Generator {
         post_generator.uniform("positions", Uniform::Signed(0));
         post_generator.uniform("parentCoords", Uniform::Signed(1));
 
-        Generator {
+        Ok(Generator {
+            context: context.clone(),
             vertex_depth,
             vertex_grid_size: vertex_size,
             texture_delta,
@@ -330,7 +346,7 @@ impl Generator {
             previous_operations: Vec::new(),
             max_framebuffer_cache,
             framebuffers: HashMap::new()
-        }
+        })
     }
 
     pub fn generate_indices(&self) -> (Vec<Idx>, Vec<Idx>) {
@@ -381,7 +397,7 @@ impl Generator {
         let vert_size = (self.vertex_grid_size + 3) as i32;
         let fbos = match self.framebuffer_cache.pop() {
             Some(fbos) => fbos,
-            None => GeneratorBuffers::new(self.texture_size as _, vert_size, &self.channels)
+            None => GeneratorBuffers::new(&self.context, self.texture_size as _, vert_size, &self.channels)
         };
 
         //
@@ -391,11 +407,9 @@ impl Generator {
         let xofs = -1.0 + pos.x() as f32 * fac;
         let yofs = -1.0 + pos.y() as f32 * fac;
 
-        unsafe {
-            gl::Disable(gl::DEPTH_TEST);
-            gl::Disable(gl::CULL_FACE);
-            gl::Disable(gl::BLEND);
-        }
+        tinygl::disable(&self.context, gl::DEPTH_TEST);
+        tinygl::disable(&self.context, gl::CULL_FACE);
+        tinygl::disable(&self.context, gl::BLEND);
 
         //
         // Prepare program
@@ -407,7 +421,7 @@ impl Generator {
         self.vertex_generator.uniform("cubeTransformMatrix", Uniform::Mat3(pos.direction().square_to_cubic_transform()));
         self.vertex_generator.vertex_attrib_buffer("xy", &self.quad, 2, gl::FLOAT, false, 8, 0);
         fbos.position_pass.bind();
-        unsafe { gl::DrawArrays(gl::TRIANGLES, 0, 6) }
+        tinygl::draw_arrays(&self.context, gl::TRIANGLES, 0, 6);
         self.vertex_generator.disable_all_vertex_attribs();
 
         //
@@ -417,7 +431,7 @@ impl Generator {
         self.normals_generator.vertex_attrib_buffer("xy", &self.quad, 2, gl::FLOAT, false, 8, 0);
         fbos.position_pass.texture("posHeight").unwrap().bind_at(0);
         fbos.normal_pass.bind();
-        unsafe { gl::DrawArrays(gl::TRIANGLES, 0, 6) }
+        tinygl::draw_arrays(&self.context, gl::TRIANGLES, 0, 6);
         self.normals_generator.disable_all_vertex_attribs();
 
         //
@@ -427,10 +441,10 @@ impl Generator {
         self.post_generator.vertex_attrib_buffer("xy", &self.quad, 2, gl::FLOAT, false, 8, 0);
         self.offset_texture.bind_at(1);
         fbos.downscale_pass.bind();
-        unsafe { gl::DrawArrays(gl::TRIANGLES, 0, 6) }
+        tinygl::draw_arrays(&self.context, gl::TRIANGLES, 0, 6);
         self.post_generator.disable_all_vertex_attribs();
 
-        FrameBufferObject::unbind();
+        FrameBufferObject::unbind(&self.context);
 
         self.current_operations.push(pos);
         self.framebuffers.insert(pos, fbos);
@@ -28,6 +28,8 @@ use util3d::noise;
 /// # offers different methods of rendering the planet and accessing the results
 ///
 pub struct Renderer {
+    context: tinygl::Context,
+    shaders: tinygl::ShaderRegistry,
     camera: FlyCamera,
     program_plates: Option<tinygl::Program>,
     program_water: tinygl::Program,
@@ -56,8 +58,6 @@ pub struct Renderer {
     colorator: String,
     textures: Vec<(String, tinygl::Texture)>,
 
-    fbo_scene: Option<tinygl::FrameBufferObject>,
-    fbo_color: Option<tinygl::FrameBufferObject>,
     fsquad: tinygl::shapes::FullscreenQuad,
     water_plate_factory: WaterPlateFactory,
     water_depth: u32,
@@ -68,32 +68,40 @@ pub struct Renderer {
     errors_colorator: Option<String>,
 }
 
-fn create_water_program(channels: &Channels) -> tinygl::Program {
-    let vert = include_str!("../shaders/render_water.vert");
-    let frag = include_str!("../shaders/render_water.frag");
-    let frag = frag.replace("$CHANNEL_OUTPUTS", &channels.glsl_texture_declarations());
-    let frag = frag.replace("$CHANNEL_TEXTURES", &channels.glsl_output_declarations(2));
-    let frag = frag.replace("$CHANNEL_ASSIGNMENTS", &channels.glsl_assignments("tc"));
-    tinygl::Program::new_versioned(vert, &frag, 300)
+// the shader files each program is built from
+static PLANET_SHADERS: [&str; 4] = ["passthrough.vert", "generator_vertices.frag", "generator_normals.frag", "generator_postvertex.frag"];
+static WATER_SHADERS: [&str; 2] = ["render_water.vert", "render_water.frag"];
+static PLATES_SHADERS: [&str; 2] = ["render_plates.vert", "render_plates.frag"];
+static COLOR_SHADERS: [&str; 4] = ["render_color.vert", "render_color.frag", "render_color_texture.glsl", "render_color_icosahedron.glsl"];
+
+fn create_water_program(context: &tinygl::Context, shaders: &tinygl::ShaderRegistry, channels: &Channels) -> tinygl::Program {
+    let vert = tinygl::Source::new("render_water.vert", &shaders.get("render_water.vert"));
+    let frag = tinygl::Preprocessor::new()
+        .add_include("channel_outputs", &channels.glsl_texture_declarations())
+        .add_include("channel_textures", &channels.glsl_output_declarations(2))
+        .add_include("channel_assignments", &channels.glsl_assignments("tc"))
+        .process("render_water.frag", &shaders.get("render_water.frag"));
+    tinygl::Program::from_sources(context, &vert, &frag, 300)
 }
 
-fn create_plates_program(channels: &Channels) -> tinygl::Program {
-    let vert = include_str!("../shaders/render_plates.vert");
-    let frag = include_str!("../shaders/render_plates.frag");
-    let frag = frag.replace("$CHANNEL_OUTPUTS", &channels.glsl_texture_declarations());
-    let frag = frag.replace("$CHANNEL_TEXTURES", &channels.glsl_output_declarations(2));
-    let frag = frag.replace("$CHANNEL_ASSIGNMENTS", &channels.glsl_assignments("plateTc"));
-    tinygl::Program::new_versioned(vert, &frag, 300)
+fn create_plates_program(context: &tinygl::Context, shaders: &tinygl::ShaderRegistry, channels: &Channels) -> tinygl::Program {
+    let vert = tinygl::Source::new("render_plates.vert", &shaders.get("render_plates.vert"));
+    let frag = tinygl::Preprocessor::new()
+        .add_include("channel_outputs", &channels.glsl_texture_declarations())
+        .add_include("channel_textures", &channels.glsl_output_declarations(2))
+        .add_include("channel_assignments", &channels.glsl_assignments("plateTc"))
+        .process("render_plates.frag", &shaders.get("render_plates.frag"));
+    tinygl::Program::from_sources(context, &vert, &frag, 300)
 }
 
-fn create_color_program(colorator: &str, channels: &Channels, textures: &Vec<(String, tinygl::Texture)>) -> tinygl::Program {
-    let vert = include_str!("../shaders/render_color.vert");
+fn create_color_program(context: &tinygl::Context, shaders: &tinygl::ShaderRegistry, colorator: &str, channels: &Channels, textures: &Vec<(String, tinygl::Texture)>) -> tinygl::Program {
+    let vert = tinygl::Source::new("render_color.vert", &shaders.get("render_color.vert"));
 
     // TODO: proper texture filtering + borders for channel textures
 
     let texture_function_definitions = textures.iter().fold(String::new(), |acc, tex| {
         let texname = String::from("_texture_") + &tex.0;
-        let src = include_str!("../shaders/render_color_texture.glsl");
+        let src = shaders.get("render_color_texture.glsl");
         let src = src.replace("$TEXNAME", &texname);
         let src = src.replace("$FUNCNAME", &tex.0);
         src
@@ -103,18 +111,26 @@ fn create_color_program(colorator: &str, channels: &Channels, textures: &Vec<(St
         String::new(), |acc, chan| acc + chan + ";\n"
     );
 
-    let icosahedron = include_str!("../shaders/render_color_icosahedron.glsl");
-
-    let frag = include_str!("../shaders/render_color.frag");
-    let frag = frag.replace("$CHANNEL_TEXTURES", &channels.glsl_texture_declarations());
-    let frag = frag.replace("$CHANNEL_VARIABLES", &channel_variables);
-    let frag = frag.replace("$CHANNEL_ASSIGNMENTS", &channels.glsl_assignments("tc_screen"));
-    let frag = frag.replace("$NOISE", noise::ShaderNoise::definitions());
-    let frag = frag.replace("$ICOSAHEDRON", icosahedron);
-    let frag = frag.replace("$TEXTURE_FUNCTIONS", &texture_function_definitions);
-    let frag = frag.replace("$COLORATOR", colorator);
+    let frag = tinygl::Preprocessor::new()
+        .add_include("channel_textures", &channels.glsl_texture_declarations())
+        .add_include("channel_variables", &channel_variables)
+        .add_include("channel_assignments", &channels.glsl_assignments("tc_screen"))
+        .add_include("noise", noise::ShaderNoise::definitions())
+        .add_include("icosahedron", &shaders.get("render_color_icosahedron.glsl"))
+        .add_include("texture_functions", &texture_function_definitions)
+        .add_include("colorator", colorator)
+        .process("render_color.frag", &shaders.get("render_color.frag"));
+
+    tinygl::Program::from_sources(context, &vert, &frag, 300)
+}
 
-    tinygl::Program::new(vert, &frag)
+/// The program if it compiled, or its errors
+fn checked(program: tinygl::Program) -> Result<tinygl::Program, String> {
+    if program.valid() {
+        Ok(program)
+    } else {
+        Err(program.error_report())
+    }
 }
 
 pub fn default_generator() -> & 'static str {
@@ -126,17 +142,20 @@ pub fn default_colorator() -> & 'static str {
 }
 
 impl Renderer {
-    fn create_planet(&mut self, generator: &str, channels: Channels, update_errors: bool) -> bool {
-        let planet = tree::Planet::new(self.plate_depth as _, self.texture_delta, self.planet_radius, generator, &channels);
+    fn build_planet(&self, generator: &str, channels: &Channels) -> Result<tree::Planet, String> {
+        let mut planet = tree::Planet::new(&self.context, &self.shaders, self.plate_depth as _, self.texture_delta, self.planet_radius, generator, channels)?;
 
-        match planet {
-            Ok(mut planet) => {
-                // start data generation for the first levels
-                let culler = culling::Culler::new(&self.camera.mvp((2, 1), false));
-                planet.set_detail((255.0 * self.vertex_detail) as _);
-                planet.update_quad_tree(&self.camera.eye(), &culler, 3, self.hide_backside);
-                planet.start_data_generation(30);
+        // start data generation for the first levels
+        let culler = culling::Culler::new(&self.camera.mvp((2, 1), false));
+        planet.set_detail((255.0 * self.vertex_detail) as _);
+        planet.update_quad_tree(&self.camera.eye(), &culler, 3, self.hide_backside);
+        planet.start_data_generation(30);
+        Ok(planet)
+    }
 
+    fn create_planet(&mut self, generator: &str, channels: Channels, update_errors: bool) -> bool {
+        match self.build_planet(generator, &channels) {
+            Ok(planet) => {
                 // Clear errors
                 self.planet = Some(planet);
                 self.generator = generator.to_string();
@@ -156,7 +175,7 @@ impl Renderer {
         }
     }
 
-    pub fn new(planet_radius: f32, plate_depth: u32, texture_delta: u32) -> Self {
+    pub fn new(context: &tinygl::Context, shaders: &tinygl::ShaderRegistry, planet_radius: f32, plate_depth: u32, texture_delta: u32) -> Self {
         let colorator = default_colorator().to_string();
         let channels = Channels::new();
 
@@ -167,11 +186,13 @@ impl Renderer {
         let water_depth = 3;
 
         let mut ret = Renderer {
+            context: context.clone(),
+            shaders: shaders.clone(),
             camera: FlyCamera::from(planet_radius, start_pos * planet_radius, start_view, start_angle),
-            program_plates: Some(create_plates_program(&channels)),
+            program_plates: Some(create_plates_program(context, shaders, &channels)),
             program_color: None,
-            program_color_default: create_color_program(&colorator, &channels, &Vec::new()),
-            program_water: create_water_program(&channels),
+            program_color_default: create_color_program(context, shaders, &colorator, &channels, &Vec::new()),
+            program_water: create_water_program(context, shaders, &channels),
 
             planet: None,
             plate_depth,
@@ -187,10 +208,8 @@ impl Renderer {
             rendered_plates: 0,
             rendered_triangles: 0,
 
-            fbo_scene: None,
-            fbo_color: None,
-            fsquad: tinygl::shapes::FullscreenQuad::new(),
-            water_plate_factory: WaterPlateFactory::new(water_depth, plate_depth, texture_delta),
+            fsquad: tinygl::shapes::FullscreenQuad::new(context),
+            water_plate_factory: WaterPlateFactory::new(context, water_depth, plate_depth, texture_delta),
             water_height: 0.0,
             water_depth,
 
@@ -207,16 +226,36 @@ impl Renderer {
         ret
     }
 
-    pub fn out_position(&self) -> &tinygl::Texture {
-        self.fbo_scene.as_ref().unwrap().texture("positionHeight").as_ref().unwrap()
-    }
-
-    pub fn out_normal(&self) -> &tinygl::Texture {
-        self.fbo_scene.as_ref().unwrap().texture("normalWf").as_ref().unwrap()
-    }
+    fn channel_target(name: &str) -> String {
+        format!("channels.{}", name)
+    }
+
+    /// The scene and color passes, which write "normalWf", "positionHeight" and "color". They
+    /// change with the window size and the channels.
+    pub fn passes(&self, windowsize: (u32, u32)) -> Result<Vec<tinygl::Pass>, String> {
+        let size = (windowsize.0 as _, windowsize.1 as _);
+
+        let mut scene = tinygl::Pass::new("scene", size)
+            .output("normalWf", tinygl::TextureFormat::RGBA32F)
+            .output("positionHeight", tinygl::TextureFormat::RGBA32F);
+        let mut color = tinygl::Pass::new("color", size)
+            .input("normalWf")
+            .input("positionHeight");
+        for chan in self.channels.iter() {
+            let format = match chan.1 {
+                1 => tinygl::TextureFormat::R8,
+                2 => tinygl::TextureFormat::RG8,
+                3 => tinygl::TextureFormat::RGB8,
+                4 => tinygl::TextureFormat::RGBA8,
+                n => return Err(format!("Channel {} has {} components, it can have 1 to 4", chan.0, n)),
+            };
+            scene = scene.output(&Self::channel_target(chan.0), format);
+            color = color.input(&Self::channel_target(chan.0));
+        }
+        scene = scene.output("depth", tinygl::TextureFormat::Depth24);
+        color = color.output("color", tinygl::TextureFormat::RGBA8);
 
-    pub fn out_color(&self) -> &tinygl::Texture {
-        self.fbo_color.as_ref().unwrap().texture("color").as_ref().unwrap()
+        Ok(vec!(scene, color))
     }
 
     pub fn camera(&mut self) -> &mut FlyCamera {
@@ -266,7 +305,7 @@ impl Renderer {
 
     pub fn set_plate_depth(&mut self, depth: u32) {
         self.plate_depth = depth;
-        self.water_plate_factory = WaterPlateFactory::new(self.water_depth, self.plate_depth, self.texture_delta);
+        self.water_plate_factory = WaterPlateFactory::new(&self.context, self.water_depth, self.plate_depth, self.texture_delta);
         let gen = self.generator.clone();
         let chan = self.channels.clone();
         self.create_planet(&gen, chan, false);
@@ -278,7 +317,7 @@ impl Renderer {
 
     pub fn set_water_depth(&mut self, depth: u32) {
         self.water_depth = depth;
-        self.water_plate_factory = WaterPlateFactory::new(self.water_depth, self.plate_depth, self.texture_delta);
+        self.water_plate_factory = WaterPlateFactory::new(&self.context, self.water_depth, self.plate_depth, self.texture_delta);
     }
 
     pub fn texture_delta(&self) -> u32 {
@@ -287,7 +326,7 @@ impl Renderer {
 
     pub fn set_texture_delta(&mut self, delta: u32) {
         self.texture_delta = delta;
-        self.water_plate_factory = WaterPlateFactory::new(self.water_depth, self.plate_depth, self.texture_delta);
+        self.water_plate_factory = WaterPlateFactory::new(&self.context, self.water_depth, self.plate_depth, self.texture_delta);
         let gen = self.generator.clone();
         let chan = self.channels.clone();
         self.create_planet(&gen, chan, false);
@@ -314,7 +353,7 @@ impl Renderer {
     }
 
     pub fn set_colorator(&mut self, colorator: &str) -> bool {
-        let new_program = create_color_program(colorator, &self.channels, &self.textures);
+        let new_program = create_color_program(&self.context, &self.shaders, colorator, &self.channels, &self.textures);
         let ret = new_program.valid();
 
         if ret {
@@ -322,7 +361,7 @@ impl Renderer {
             self.errors_colorator = None;
             self.program_color = Some(new_program);
         } else {
-            self.errors_colorator = Some(new_program.fragment_log());
+            self.errors_colorator = Some(new_program.error_report());
         }
         ret
     }
@@ -332,9 +371,8 @@ impl Renderer {
         if ret {
             self.generator = generator.to_string();
             self.channels = Channels::from(channels);
-            self.program_plates = Some(create_plates_program(&self.channels));
-            self.program_water = create_water_program(&self.channels);
-            self.fbo_scene = None;  // need to re-create channels FBOs
+            self.program_plates = Some(create_plates_program(&self.context, &self.shaders, &self.channels));
+            self.program_water = create_water_program(&self.context, &self.shaders, &self.channels);
         }
         ret
     }
@@ -350,17 +388,57 @@ impl Renderer {
 
     fn recreate_program(&mut self) {
         // need to check if our colorator still fits with all those new channels and textures coming in
-        let new_program = create_color_program(&self.colorator, &self.channels, &self.textures);
+        let new_program = create_color_program(&self.context, &self.shaders, &self.colorator, &self.channels, &self.textures);
 
         if new_program.valid() {
             self.errors_colorator = None;
             self.program_color = Some(new_program);
         } else {
-            self.errors_colorator = Some(new_program.fragment_log());
+            self.errors_colorator = Some(new_program.error_report());
             self.program_color = None;
         }
     }
 
+    /// Rebuilds what uses the shader files that changed in the last poll. Programs that don't
+    /// compile keep the old ones, and their errors get returned.
+    pub fn reload_shaders(&mut self) -> Vec<String> {
+        let mut errors = Vec::new();
+
+        if self.shaders.changed(&PLANET_SHADERS) {
+            match self.build_planet(&self.generator, &self.channels) {
+                Ok(planet) => self.planet = Some(planet),
+                Err(err) => errors.push(err),
+            }
+        }
+        if self.shaders.changed(&WATER_SHADERS) {
+            match checked(create_water_program(&self.context, &self.shaders, &self.channels)) {
+                Ok(program) => self.program_water = program,
+                Err(err) => errors.push(err),
+            }
+        }
+        if self.shaders.changed(&PLATES_SHADERS) {
+            match checked(create_plates_program(&self.context, &self.shaders, &self.channels)) {
+                Ok(program) => self.program_plates = Some(program),
+                Err(err) => errors.push(err),
+            }
+        }
+        if self.shaders.changed(&COLOR_SHADERS) {
+            match checked(create_color_program(&self.context, &self.shaders, &default_colorator(), &self.channels, &Vec::new())) {
+                Ok(program) => self.program_color_default = program,
+                Err(err) => errors.push(err),
+            }
+            // a colorator that doesn't compile has its errors shown in its editor already
+            if self.program_color.is_some() {
+                match checked(create_color_program(&self.context, &self.shaders, &self.colorator, &self.channels, &self.textures)) {
+                    Ok(program) => self.program_color = Some(program),
+                    Err(err) => errors.push(err),
+                }
+            }
+        }
+
+        errors
+    }
+
     pub fn set_channels(&mut self, channels: &HashMap<String, usize>, generator: &str) -> bool {
         let ret = self.create_planet(generator, Channels::from(channels), true);
 
@@ -368,9 +446,8 @@ impl Renderer {
             self.generator = generator.to_string();
             self.channels = Channels::from(channels);
             self.recreate_program();
-            self.program_plates = Some(create_plates_program(&self.channels));
-            self.program_water = create_water_program(&self.channels);
-            self.fbo_scene = None;  // need to re-create channels FBOs
+            self.program_plates = Some(create_plates_program(&self.context, &self.shaders, &self.channels));
+            self.program_water = create_water_program(&self.context, &self.shaders, &self.channels);
         }
         ret
     }
@@ -403,10 +480,8 @@ impl Renderer {
         &self.textures
     }
 
-    /// Renders the planet into the internal FBO
-    ///
-    /// The results can be accessed through `out_position()`, `out_normal()`, and `out_color()`.
-    pub fn render(&mut self, windowsize: (u32, u32), dt: f32) {
+    /// Renders the planet with the passes of `passes()`, which the graph has to contain
+    pub fn render(&mut self, graph: &tinygl::RenderGraph, windowsize: (u32, u32), dt: f32, profiler: &mut tinygl::Profiler) -> Result<(), String> {
         //
         // Setup view/projection matrices
         //
@@ -416,6 +491,7 @@ impl Renderer {
         //
         // Update Quad-Trees
         //
+        profiler.begin("plate generation");
         let planet = self.planet.as_mut().unwrap();
         planet.collect_render_data();
         if !self.no_update_plates {
@@ -423,38 +499,20 @@ impl Renderer {
         }
         planet.update_priorities();
         planet.start_data_generation(3);
+        profiler.end();
 
         //
-        // Update and bind Scene Render Target
+        // Begin with the Scene
         //
-        if self.fbo_scene.as_ref().map(|fbo| fbo.size() != windowsize).unwrap_or(true) {
-            let mut fbo = tinygl::FrameBufferObject::new((windowsize.0 as _, windowsize.1 as _));
-            fbo.add("normalWf", gl::RGBA32F, gl::RGBA, gl::FLOAT);
-            fbo.add("positionHeight", gl::RGBA32F, gl::RGBA, gl::FLOAT);
-            // TODO: avoid duplication
-            for chan in self.channels.iter() {
-                let int_fmt = match chan.1 {
-                    1 => (gl::R8, gl::RED),
-                    2 => (gl::RG8, gl::RG),
-                    3 => (gl::RGB8, gl::RGB),
-                    4 => (gl::RGBA8, gl::RGBA),
-                    _ => { panic!("Does not compute"); },
-                };
-                fbo.add(&chan.0, int_fmt.0, int_fmt.1, gl::UNSIGNED_BYTE);
-            }
-            fbo.add_depth_renderbuffer();
-            self.fbo_scene = Some(fbo);
-        }
-        self.fbo_scene.as_ref().unwrap().bind();
-
-        unsafe {
-            gl::ClearColor(0.0, 0.0, 0.0, 0.0);
-            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
-            gl::Enable(gl::CULL_FACE);
-            gl::Enable(gl::DEPTH_TEST);
-            gl::Disable(gl::BLEND);
-            gl::DepthFunc(gl::LEQUAL);
-        }
+        graph.begin("scene")?;
+        profiler.begin("terrain");
+
+        tinygl::clear_color(&self.context, 0.0, 0.0, 0.0, 0.0);
+        tinygl::clear(&self.context, gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        tinygl::enable(&self.context, gl::CULL_FACE);
+        tinygl::enable(&self.context, gl::DEPTH_TEST);
+        tinygl::disable(&self.context, gl::BLEND);
+        tinygl::depth_func(&self.context, gl::LEQUAL);
 
         //
         // Prepare scene program
@@ -499,10 +557,12 @@ impl Renderer {
 
         program_plates.disable_all_vertex_attribs();
         self.rendered_plates = rendered_plates.len();
+        profiler.end();
 
         //
         // Render water on top of terrain
         //
+        profiler.begin("water");
         let water_plates = planet.rendered_water_plates(&culler, self.water_height);
         self.program_water.bind();
         self.program_water.uniform("mvp", tinygl::Uniform::Mat4(mvp));
@@ -517,52 +577,46 @@ impl Renderer {
         self.water_plate_factory.indices().bind();
         for water_plate in water_plates {
             water_plate.borrow().bind_render_data(&self.program_water, 0);
-            unsafe { gl::DrawElements(gl::TRIANGLES, water_idx_count, gl::UNSIGNED_SHORT, std::ptr::null()); }
+            tinygl::draw_elements(&self.context, gl::TRIANGLES, water_idx_count, gl::UNSIGNED_SHORT, 0);
         }
         self.program_water.disable_all_vertex_attribs();
+        profiler.end();
 
         //
-        // Update and bind Color Render Target
+        // Color pass, which gets the scene textures bound
         //
-        if self.fbo_color.as_ref().map(|fbo| fbo.size() != windowsize).unwrap_or(true) {
-            let mut fbo = tinygl::FrameBufferObject::new((windowsize.0 as _, windowsize.1 as _));
-            fbo.add("color", gl::RGBA, gl::RGBA, gl::UNSIGNED_BYTE);
-            self.fbo_color = Some(fbo);
-        }
-        self.fbo_color.as_ref().unwrap().bind();
+        let pass = graph.begin("color")?;
+        profiler.begin("color");
+        let unit = |target: &str| tinygl::Uniform::Signed(pass.unit(target).unwrap() as _);
 
         let program_color = match self.program_color.as_mut() {
             Some(prog) => prog,
             None => &mut self.program_color_default
         };
 
-        // bind scene textures
         program_color.bind();
         program_color.uniform("waterHeight", tinygl::Uniform::Float(self.water_height));
-        program_color.uniform("scene_normal", tinygl::Uniform::Signed(0));
-        program_color.uniform("scene_position", tinygl::Uniform::Signed(1));
-        self.fbo_scene.as_ref().unwrap().texture("normalWf").unwrap().bind_at(0);
-        self.fbo_scene.as_ref().unwrap().texture("positionHeight").unwrap().bind_at(1);
+        program_color.uniform("scene_normal", unit("normalWf"));
+        program_color.uniform("scene_position", unit("positionHeight"));
+
+        // Bind channel textures
+        for chan in self.channels.iter() {
+            program_color.uniform(&format!("_channel_texture_{}", chan.0), unit(&Self::channel_target(chan.0)));
+        }
 
-        // Bind Textures
+        // Bind Textures, after the ones of the pass
         for tex in self.textures.iter().enumerate() {
-            let idx = tex.0 + 2;
+            let idx = pass.units() as usize + tex.0;
             (tex.1).1.bind_at(idx as _);
             program_color.uniform(&format!("_texture_{}", (tex.1).0), tinygl::Uniform::Signed(idx as _));
         }
 
-        // Bind channel textures
-        // TODO: avoid duplication
-        for chan in self.channels.iter().enumerate() {
-            let idx = 2 + self.textures.len() + chan.0;
-            self.fbo_scene.as_ref().unwrap().texture((chan.1).0).unwrap().bind_at(idx as _);
-            program_color.uniform(&format!("_channel_texture_{}", (chan.1).0), tinygl::Uniform::Signed(idx as _));
-        }
-
         self.fsquad.render(program_color, "vertex");
         program_color.disable_all_vertex_attribs();
 
-        unsafe { gl::BindFramebuffer(gl::FRAMEBUFFER, 0); }
+        graph.end();
+        profiler.end();
+        Ok(())
     }
 
     /// Simply renders the planet for the given camera and using the given program
@@ -25,6 +25,8 @@ pub struct Planet {
 
 impl Planet {
     pub fn new(
+        context: &tinygl::Context,
+        shaders: &tinygl::ShaderRegistry,
         plate_depth: u32,
         texture_delta: u32,
         radius: f32,
@@ -34,16 +36,16 @@ impl Planet {
         let plate_size = 2u32.pow(plate_depth);
 
         // Create Generator program
-        let generator = generator::compile_generator(generator, channels);
+        let generator = generator::compile_generator(context, shaders, generator, channels);
 
         if !generator.valid() {
-            return Err(generator.fragment_log());
+            return Err(generator.error_report());
         }
 
-        let manager = generator::PlateDataManager::new(plate_depth, texture_delta, radius, generator, channels);
+        let manager = generator::PlateDataManager::new(context, shaders, plate_depth, texture_delta, radius, generator, channels)?;
 
         let indices = manager.generate_indices();
-        let plate_coords = tinygl::VertexBuffer::from(&manager.generate_plate_coords());
+        let plate_coords = tinygl::VertexBuffer::from(context, &manager.generate_plate_coords());
 
         let manager = Rc::new(RefCell::new(manager));
 
@@ -58,8 +60,8 @@ impl Planet {
             radius,
             seed: cgmath::Vector3::new(0.0, 0.0, 0.0),
             plate_coords,
-            triangles: tinygl::IndexBuffer::from16(&indices.0),
-            wireframe: tinygl::IndexBuffer::from16(&indices.1),
+            triangles: tinygl::IndexBuffer::from16(context, &indices.0),
+            wireframe: tinygl::IndexBuffer::from16(context, &indices.1),
             root_plates,
             plate_data_manager: manager
         })
@@ -75,8 +77,17 @@ impl Planet {
         let sz = self.plate_size + 3;
         self.traverse_mut(|node| {
             if node.generated_data.is_some() {
-                node.data_manager.borrow().retriangulate(node.generated_data.as_mut().unwrap());
-                node.gpu_data = Some(GpuData::new(node.generated_data.as_ref().unwrap(), sz as _));
+                let manager = node.data_manager.borrow();
+                let data = node.generated_data.as_mut().unwrap();
+                manager.retriangulate(data);
+                // only the triangulation changes, so the plate keeps its buffers
+                match node.gpu_data {
+                    Some(ref mut gpu_data) => {
+                        let triangulation = data.triangulation.as_ref().expect("No Triangulation data found");
+                        gpu_data.indices.update(&triangulation.indices).expect("Plate indices changed their type");
+                    }
+                    None => node.gpu_data = Some(GpuData::new(manager.context(), data, sz as _)),
+                }
             }
             true
         });
@@ -239,12 +250,12 @@ struct GpuData {
 }
 
 impl GpuData {
-    fn new(data: &generator::Result, tex_size: u32) -> Self {
+    fn new(context: &tinygl::Context, data: &generator::Result, tex_size: u32) -> Self {
         let triangulation = data.triangulation.as_ref().expect("No Triangulation data found");
 
         let ret = GpuData {
-            positions: tinygl::VertexBuffer::from(&data.vertex_data),
-            indices: tinygl::IndexBuffer::from16(&triangulation.indices),
+            positions: tinygl::VertexBuffer::from(context, &data.vertex_data),
+            indices: tinygl::IndexBuffer::from16(context, &triangulation.indices),
         };
 
         ret
@@ -322,7 +333,8 @@ impl Plate {
         let depth = self.position().depth();
         self.update_bounding_box((depth, data.height_extent.0, data.height_extent.1));
 
-        self.gpu_data = Some(GpuData::new(&data, self.data_manager.borrow().vertex_tile_size() + 3));
+        let manager = self.data_manager.borrow();
+        self.gpu_data = Some(GpuData::new(manager.context(), &data, manager.vertex_tile_size() + 3));
         self.generated_data = Some(data);
     }
 
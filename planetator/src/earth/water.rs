@@ -2,6 +2,7 @@ use cgmath::prelude::*;
 use cgmath::*;
 
 pub struct WaterPlateFactory {
+    context: tinygl::Context,
     water_depth: u32,  // number of vertices along the water plate edge is 2^depth
     plate_depth: u32,  // number of texels in plate vertices
     indices: tinygl::IndexBuffer,
@@ -17,7 +18,7 @@ pub struct WaterPlate (
 
 impl WaterPlateFactory {
     /// Generate buffer that contains the 'ribbon' flag and that matches the plate's pos/height buffer
-    fn gen_ribbon_buffer(plate_depth: u32) -> tinygl::VertexBuffer {
+    fn gen_ribbon_buffer(context: &tinygl::Context, plate_depth: u32) -> tinygl::VertexBuffer {
         let mut data = Vec::new();
         let vertsize = 2i16.pow(plate_depth);
 
@@ -28,11 +29,11 @@ impl WaterPlateFactory {
             }
         }
 
-        tinygl::VertexBuffer::from(&data)
+        tinygl::VertexBuffer::from(context, &data)
     }
 
     /// Generate index buffer for rendering of water-plates, with ribbons
-    fn gen_indices(water_depth: u32, plate_depth: u32, ribbons: bool) -> tinygl::IndexBuffer {
+    fn gen_indices(context: &tinygl::Context, water_depth: u32, plate_depth: u32, ribbons: bool) -> tinygl::IndexBuffer {
         let mut indices = Vec::new();
         let vertsize = 2i16.pow(plate_depth);
         let water_depth = water_depth.min(plate_depth);
@@ -78,19 +79,20 @@ impl WaterPlateFactory {
             }
         }
 
-        tinygl::IndexBuffer::from16(&indices)
+        tinygl::IndexBuffer::from16(context, &indices)
     }
 
-    pub fn new(water_depth: u32, plate_depth: u32, texture_delta: u32) -> Self {
+    pub fn new(context: &tinygl::Context, water_depth: u32, plate_depth: u32, texture_delta: u32) -> Self {
         let tex_coords = super::util::generate_tex_coords_buffer(plate_depth, plate_depth, texture_delta);
-        let tex_coords = tinygl::VertexBuffer::from(&tex_coords);
+        let tex_coords = tinygl::VertexBuffer::from(context, &tex_coords);
 
         Self {
+            context: context.clone(),
             water_depth,
             plate_depth,
             tex_coords,
-            indices: Self::gen_indices(water_depth, plate_depth, true),
-            ribbons: Self::gen_ribbon_buffer(plate_depth),
+            indices: Self::gen_indices(context, water_depth, plate_depth, true),
+            ribbons: Self::gen_ribbon_buffer(context, plate_depth),
         }
     }
 
@@ -113,13 +115,24 @@ impl WaterPlateFactory {
         ).unwrap_or(true);
 
         if needs_creation {
-            water_plate.replace(WaterPlate(self.water_depth, self.plate_depth, self.create(position)));
+            match water_plate {
+                Some(water_plate) => {
+                    water_plate.0 = self.water_depth;
+                    water_plate.1 = self.plate_depth;
+                    water_plate.2.update(&self.vertices(position));
+                }
+                None => { water_plate.replace(WaterPlate(self.water_depth, self.plate_depth, self.create(position))); }
+            }
         }
     }
 
     /// Create vertex buffer with sphere coordinates for given position, including ribbon flag
     /// (this is outdated, we ain't doin that anymore)
     pub fn create(&self, plate: &super::plate::Position) -> tinygl::VertexBuffer {
+        tinygl::VertexBuffer::from(&self.context, &self.vertices(plate))
+    }
+
+    fn vertices(&self, plate: &super::plate::Position) -> Vec<f32> {
         let mut vertices = Vec::new();
         let inv_vert_size = 1.0 / 2.0f32.powi(self.water_depth as _);
         let water_plate_size = 2i32.pow(self.water_depth);
@@ -144,6 +157,6 @@ impl WaterPlateFactory {
             vy += inv_vert_size;
         }
 
-        tinygl::VertexBuffer::from(&vertices)
+        vertices
     }
 }
\ No newline at end of file
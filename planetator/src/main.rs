@@ -29,10 +29,17 @@ use sdl2::mouse::MouseButton;
 use sdl2::event::{Event};
 use imgui::*;
 use appbase::fpswidget::FpsWidget;
+use appbase::profilerwidget::ProfilerWidget;
 use cgmath::prelude::*;
 
 const HTML_INPUT_PLANET: &str = "input_loadsavegame";
 const HTML_INPUT_TEXTURE: &str = "input_texupload";
+/// Where debug builds look for the shader files, relative to the working directory unless
+/// the environment variable PLANETATOR_SHADERS says otherwise
+#[cfg(all(debug_assertions, not(target_os = "emscripten")))]
+const SHADER_DIR: &str = "src/shaders";
+/// Seconds between looking for changed shader files
+const SHADER_POLL_INTERVAL: f32 = 0.5;
 
 const KEY_LEFT: Keycode = Keycode::Left;
 const KEY_RIGHT: Keycode = Keycode::Right;
@@ -42,7 +49,33 @@ const KEY_FORWARDS: Keycode = Keycode::Up;
 const KEY_BACKWARDS: Keycode = Keycode::Down;
 const KEY_TOGGLE_FLY: Keycode = Keycode::Return;
 
+/// The uniforms of the postprocessing shader besides the textures, the shadows and the atmosphere
+struct PostprocessUniforms {
+    eye: cgmath::Vector3<f32>,
+    inverse_mvp: cgmath::Matrix4<f32>,
+    angle_to_horizon: f32,
+    terrain_max_height: f32,
+    radius: f32,
+    water_seed: f32,
+    in_scatter: f32,
+}
+
+impl tinygl::Uniforms for PostprocessUniforms {
+    fn set(&self, program: &tinygl::Program) -> Result<(), String> {
+        vec!(
+            program.set_uniform("eyePosition", tinygl::Uniform::Vec3(self.eye)),
+            program.set_uniform("inverseViewProjectionMatrix", tinygl::Uniform::Mat4(self.inverse_mvp)),
+            program.set_uniform("angleToHorizon", tinygl::Uniform::Float(self.angle_to_horizon)),
+            program.set_uniform("terrainMaxHeight", tinygl::Uniform::Float(self.terrain_max_height)),
+            program.set_uniform("planetRadius", tinygl::Uniform::Float(self.radius)),
+            program.set_uniform("waterSeed", tinygl::Uniform::Float(self.water_seed)),
+            program.set_uniform("inScatterFac", tinygl::Uniform::Float(self.in_scatter)),
+        ).into_iter().collect()
+    }
+}
+
 struct MyApp {
+    context: tinygl::Context,
     windowsize: (u32, u32),
     errors: Vec<String>,
 
@@ -50,6 +83,8 @@ struct MyApp {
     current_mouse_press: Option<(i32, i32)>,
 
     fps: FpsWidget,
+    profiler: tinygl::Profiler,
+    profiler_widget: ProfilerWidget,
 
     edit_generator: guiutil::ShaderEditData,
     edit_colorator: guiutil::ShaderEditData,
@@ -61,6 +96,7 @@ struct MyApp {
     left_panel_height: f32,
     right_panel_height: f32,
     show_fps: bool,
+    show_profiler: bool,
     show_about_dialog: bool,
     show_graphics_dialog: bool,
 
@@ -76,7 +112,12 @@ struct MyApp {
 
     shadows: shadowmap::ShadowMap,
 
+    shaders: tinygl::ShaderRegistry,
+    // seconds since the shader files were last polled
+    shader_poll: f32,
     renderer: earth::renderer::Renderer,
+    // the planet, shadow and postprocessing passes, rebuilt when one of them changes
+    graph: Option<tinygl::RenderGraph>,
     postprocess: Option<tinygl::Program>,
     atmoshpere_in_scatter: f32,
     water_time: f32,
@@ -84,6 +125,32 @@ struct MyApp {
     fsquad: tinygl::shapes::FullscreenQuad,
 }
 
+/// The shader files. Native debug builds read them from `SHADER_DIR` and reload them when they
+/// change, so they can be edited while running.
+fn shader_registry() -> tinygl::ShaderRegistry {
+    #[cfg(all(debug_assertions, not(target_os = "emscripten")))]
+    let shaders = tinygl::ShaderRegistry::watch(std::env::var("PLANETATOR_SHADERS").unwrap_or_else(|_| String::from(SHADER_DIR)));
+    #[cfg(not(all(debug_assertions, not(target_os = "emscripten"))))]
+    let shaders = tinygl::ShaderRegistry::new();
+
+    shaders
+        .add("passthrough.vert", include_str!("shaders/passthrough.vert"))
+        .add("generator_vertices.frag", include_str!("shaders/generator_vertices.frag"))
+        .add("generator_normals.frag", include_str!("shaders/generator_normals.frag"))
+        .add("generator_postvertex.frag", include_str!("shaders/generator_postvertex.frag"))
+        .add("render_water.vert", include_str!("shaders/render_water.vert"))
+        .add("render_water.frag", include_str!("shaders/render_water.frag"))
+        .add("render_plates.vert", include_str!("shaders/render_plates.vert"))
+        .add("render_plates.frag", include_str!("shaders/render_plates.frag"))
+        .add("render_color.vert", include_str!("shaders/render_color.vert"))
+        .add("render_color.frag", include_str!("shaders/render_color.frag"))
+        .add("render_color_texture.glsl", include_str!("shaders/render_color_texture.glsl"))
+        .add("render_color_icosahedron.glsl", include_str!("shaders/render_color_icosahedron.glsl"))
+        .add("postprocess.vert", include_str!("shaders/postprocess.vert"))
+        .add("postprocess.frag", include_str!("shaders/postprocess.frag"));
+    shaders
+}
+
 fn window<'ui,'p>(ui: &'ui imgui::Ui, name: &'static str, title: bool, movable: bool, collapsible: bool,
                   size: (f32, f32), pos: (f32, f32)) -> Window<'ui, 'p, &'static str> where 'ui: 'p  {
     ui.window(name)
@@ -209,7 +276,7 @@ impl MyApp {
                     self.select_channels = select_channels.clone();
                     self.renderer.clear_textures();
                     for tex in &active_textures {
-                        self.renderer.add_texture(&tex.0, tinygl::Texture::from_data_2d(&tex.2, tex.1));
+                        self.renderer.add_texture(&tex.0, tinygl::Texture::from_data_2d(&self.context, &tex.2, tex.1));
                     }
                     self.active_textures = active_textures;
                 }
@@ -231,29 +298,170 @@ impl MyApp {
     }
 
     fn create_postprocess_shader(&self) -> tinygl::Program {
-        let vert = include_str!("shaders/postprocess.vert");
-        let frag = include_str!("shaders/postprocess.frag");
-        let frag = frag.replace("$SHADOWS", &self.shadows.glsl());
-        let frag = frag.replace("$NOISE", util3d::noise::ShaderNoise::definitions());
-        let frag = frag.replace("$ATMOSPHERE", &atmosphere::shader_source().replace("#version 300 es", ""));
-        tinygl::Program::new_versioned(vert, &frag, 300)
+        let vert = tinygl::Source::new("postprocess.vert", &self.shaders.get("postprocess.vert"));
+        let mut preprocessor = tinygl::Preprocessor::new();
+        self.shadows.add_glsl(&mut preprocessor);
+        let frag = preprocessor
+            .add_include("noise", util3d::noise::ShaderNoise::definitions())
+            .add_include("atmosphere", &atmosphere::shader_source())
+            .process("postprocess.frag", &self.shaders.get("postprocess.frag"));
+        tinygl::Program::from_sources(&self.context, &vert, &frag, 300)
+    }
+
+    /// Rebuilds the programs whose shader files changed, keeping the old ones when the new ones
+    /// don't compile
+    fn reload_shaders(&mut self, dt: f32) {
+        self.shader_poll += dt;
+        if self.shader_poll < SHADER_POLL_INTERVAL {
+            return;
+        }
+        self.shader_poll = 0.0;
+        if !self.shaders.poll() {
+            return;
+        }
+
+        if self.shaders.changed(&["postprocess.vert", "postprocess.frag"]) {
+            let program = self.create_postprocess_shader();
+            if program.valid() {
+                self.postprocess = Some(program);
+            } else {
+                self.errors.push(program.error_report());
+            }
+        }
+        let errors = self.renderer.reload_shaders();
+        self.errors.extend(errors);
+    }
+
+    /// Rebuilds the render graph when a pass changed, e.g. for another window size, other
+    /// channels or other shadow map settings
+    fn update_graph(&mut self) -> Result<(), String> {
+        let size = (self.windowsize.0 as _, self.windowsize.1 as _);
+        let mut passes = self.renderer.passes(self.windowsize)?;
+        passes.push(self.shadows.pass());
+        passes.push(tinygl::Pass::screen("postprocess", size)
+            .input("color")
+            .input("normalWf")
+            .input("positionHeight")
+            .input(shadowmap::TARGET));
+
+        if let Some(graph) = self.graph.as_ref() {
+            if passes.iter().all(|pass| graph.pass(pass.name()) == Some(pass)) {
+                return Ok(());
+            }
+        }
+
+        let mut graph = tinygl::RenderGraph::new(&self.context);
+        for pass in passes {
+            graph.add(pass);
+        }
+        graph.keep(shadowmap::TARGET);
+        // the old textures go first
+        self.graph = None;
+        graph.compile().map_err(|err| format!("Couldn't create render targets: {}", err))?;
+        self.shadows.invalidate();
+        self.graph = Some(graph);
+        Ok(())
+    }
+
+    fn render_frame(&mut self, dt: f32) -> Result<(), String> {
+        let radius = self.renderer.radius();
+
+        // advance water glitter
+        self.water_time += dt;
+        let water_phase = 1000.0;
+        if self.water_time > water_phase { self.water_time -= water_phase; };
+        let water_seed = (water_phase - self.water_time * 2.0).abs();
+
+        self.advance_camera(dt, radius);
+
+        let eye = self.renderer.camera().eye();
+        let mvp = self.renderer.camera().mvp(self.windowsize, false);
+        let look = self.renderer.camera().look();
+
+        //
+        // render planet into the scene and color passes
+        //
+        self.update_graph()?;
+        let graph = self.graph.as_ref().unwrap();
+        self.renderer.render(graph, self.windowsize, dt, &mut self.profiler)?;
+
+        //
+        // Move Sun
+        //
+        self.sun_lon += dt * self.sun_speed;
+        if self.sun_lon > 360.0 { self.sun_lon -= 360.0 }
+        if self.sun_lon < 0.0 { self.sun_lon += 360.0 }
+        let sun_lon = self.sun_lon * 3.14159 / 180.0;
+        let sun_lat = self.sun_lat * 3.14159 / 180.0;
+        let sun_direction = cgmath::Vector3::new(sun_lon.sin(), sun_lat.sin(), sun_lon.cos()).normalize();
+
+        //
+        // Update Shadow Depth Cascades
+        //
+        self.profiler.begin("shadow");
+        self.shadows.set_radius(radius);
+        self.shadows.push_sun_direction(sun_direction);
+        let to_render = self.shadows.prepare_render(graph, eye, look)?;
+        self.renderer.render_for(self.shadows.program(), to_render.1, to_render.0);
+        self.shadows.finish_render();
+        self.profiler.end();
+
+        //
+        // Setup Post-processing shader
+        //
+        self.profiler.begin("postprocess");
+        let pass = graph.begin("postprocess")?;
+        let postprocess = self.postprocess.as_ref().unwrap();
+        postprocess.bind();
+        let uniforms = PostprocessUniforms {
+            eye,
+            inverse_mvp: mvp.invert().unwrap(),
+            angle_to_horizon: (radius / eye.magnitude()).min(1.0).asin(),
+            terrain_max_height: atmosphere::raleigh_height(),
+            radius,
+            water_seed,
+            in_scatter: self.atmoshpere_in_scatter,
+        };
+        let prepared = vec!(
+            pass.set_sampler(postprocess, "planetColor", "color"),
+            pass.set_sampler(postprocess, "planetNormal", "normalWf"),
+            pass.set_sampler(postprocess, "planetPosition", "positionHeight"),
+            self.shadows.prepare_postprocess(&postprocess, &pass),
+            postprocess.set_uniforms(&uniforms),
+            atmosphere::prepare_shader(&self.context, postprocess, pass.units()),
+        );
+        for err in prepared.into_iter().filter_map(|prepared| prepared.err()) {
+            self.context.warn(err);
+        }
+
+        tinygl::disable(&self.context, gl::DEPTH_TEST);
+        tinygl::disable(&self.context, gl::BLEND);
+
+        self.fsquad.render(&postprocess, "vertex");
+        graph.end();
+        self.profiler.end();
+        Ok(())
     }
 }
 
 impl webrunner::WebApp for MyApp {
-    fn new(windowsize: (u32, u32)) -> Self {
+    fn new(context: &tinygl::Context, windowsize: (u32, u32)) -> Self {
         // check for loading savegame files
         #[cfg(target_os = "emscripten")] fileload::start_upload(HTML_INPUT_PLANET);
         #[cfg(target_os = "emscripten")] fileload::start_upload(HTML_INPUT_TEXTURE);
 
         let radius = 300.0;
+        let shaders = shader_registry();
 
         let mut app = MyApp {
+            context: context.clone(),
             windowsize,
             errors: Vec::new(),
             keyboard: HashMap::new(),
             current_mouse_press: None,
             fps: FpsWidget::new(150),
+            profiler: tinygl::Profiler::new(context, 300),
+            profiler_widget: ProfilerWidget::new(30, "planetator_trace.json"),
             edit_generator: guiutil::ShaderEditData::new("Generator", &earth::renderer::default_generator(), (250.0, 250.0), (600.0, 400.0)),
             edit_colorator: guiutil::ShaderEditData::new("Kolorator", &earth::renderer::default_colorator(), (250.0, 250.0), (600.0, 400.0)),
             edit_js: guiutil::ShaderEditData::new("JavaScript executor", "var elem = document.getElementById('state');", (250.0, 250.0), (600.0, 400.0)),
@@ -268,12 +476,16 @@ impl webrunner::WebApp for MyApp {
             left_panel_height: 0.0,
             right_panel_height: 0.0,
             show_fps: true,
+            show_profiler: false,
             show_about_dialog: false,
             show_graphics_dialog: true,
-            renderer: earth::renderer::Renderer::new(radius, 4, 1),
-            shadows: shadowmap::ShadowMap::new(radius),
+            renderer: earth::renderer::Renderer::new(context, &shaders, radius, 4, 1),
+            shaders,
+            shader_poll: 0.0,
+            shadows: shadowmap::ShadowMap::new(context, radius),
+            graph: None,
             postprocess: None,
-            fsquad: tinygl::shapes::FullscreenQuad::new(),
+            fsquad: tinygl::shapes::FullscreenQuad::new(context),
             flying: true,
             fly_speed: 0.5,
             walk_speed: cgmath::Vector3::new(0.0, 0.0, 0.0),
@@ -294,73 +506,16 @@ impl webrunner::WebApp for MyApp {
 
     fn render(&mut self, dt: f32) {
         self.fps.push(dt);
-        let radius = self.renderer.radius();
-
-        // advance water glitter
-        self.water_time += dt;
-        let water_phase = 1000.0;
-        if self.water_time > water_phase { self.water_time -= water_phase; };
-        let water_seed = (water_phase - self.water_time * 2.0).abs();
-
-        self.advance_camera(dt, radius);
-
-        let eye = self.renderer.camera().eye();
-        let mvp = self.renderer.camera().mvp(self.windowsize, false);
-        let look = self.renderer.camera().look();
-
-        //
-        // render planet into FBO
-        //
-        self.renderer.render(self.windowsize, dt);
-
-        //
-        // Move Sun
-        //
-        self.sun_lon += dt * self.sun_speed;
-        if self.sun_lon > 360.0 { self.sun_lon -= 360.0 }
-        if self.sun_lon < 0.0 { self.sun_lon += 360.0 }
-        let sun_lon = self.sun_lon * 3.14159 / 180.0;
-        let sun_lat = self.sun_lat * 3.14159 / 180.0;
-        let sun_direction = cgmath::Vector3::new(sun_lon.sin(), sun_lat.sin(), sun_lon.cos()).normalize();
-
-        //
-        // Update Shadow Depth Cascades
-        //
-        self.shadows.set_radius(radius);
-        self.shadows.push_sun_direction(sun_direction);
-        let to_render = self.shadows.prepare_render(eye, look);
-        self.renderer.render_for(self.shadows.program(), to_render.1, to_render.0);
-        self.shadows.finish_render();
+        self.profiler.begin_frame();
+        self.reload_shaders(dt);
 
-        //
-        // Setup Post-processing shader
-        //
-        let postprocess = self.postprocess.as_ref().unwrap();
-        postprocess.bind();
-        self.shadows.prepare_postprocess(&postprocess, 3);
-        postprocess.uniform("eyePosition", tinygl::Uniform::Vec3(eye));
-        postprocess.uniform("inverseViewProjectionMatrix", tinygl::Uniform::Mat4(mvp.invert().unwrap()));
-        postprocess.uniform("angleToHorizon", tinygl::Uniform::Float((radius / eye.magnitude()).min(1.0).asin()));
-        postprocess.uniform("terrainMaxHeight", tinygl::Uniform::Float(atmosphere::raleigh_height()));
-        postprocess.uniform("planetColor", tinygl::Uniform::Signed(0));
-        postprocess.uniform("planetNormal", tinygl::Uniform::Signed(1));
-        postprocess.uniform("planetPosition", tinygl::Uniform::Signed(2));
-        postprocess.uniform("planetRadius", tinygl::Uniform::Float(radius));
-        postprocess.uniform("waterSeed", tinygl::Uniform::Float(water_seed));
-        postprocess.uniform("inScatterFac", tinygl::Uniform::Float(self.atmoshpere_in_scatter));
-        atmosphere::prepare_shader(postprocess.handle().unwrap(), 4);
-
-        unsafe {
-            gl::Viewport(0, 0, self.windowsize.0 as _, self.windowsize.1 as _);
-            gl::Disable(gl::DEPTH_TEST);
-            gl::Disable(gl::BLEND);
+        if let Err(err) = self.render_frame(dt) {
+            // it's likely the same error every frame
+            if !self.errors.contains(&err) {
+                self.errors.push(err);
+            }
         }
-
-        self.renderer.out_position().bind_at(2);
-        self.renderer.out_normal().bind_at(1);
-        self.renderer.out_color().bind_at(0);
-
-        self.fsquad.render(&postprocess, "vertex");
+        self.profiler.end_frame();
     }
 
     fn do_ui(&mut self, ui: &imgui::Ui, keymod: sdl2::keyboard::Mod) {
@@ -406,6 +561,11 @@ impl webrunner::WebApp for MyApp {
             });
         }
 
+        if self.show_profiler {
+            let pos = (210.0, 0.5 * self.windowsize.1 as f32 - 200.0);
+            self.profiler_widget.render(ui, &mut self.profiler, pos, (360.0, 400.0), &mut self.show_profiler);
+        }
+
         // About button
         window(ui, "_about_dialog", false, false, false, (66.0, 36.0), (0.0, self.windowsize.1 as f32 - 36.0))
             .build(|| {
@@ -450,6 +610,7 @@ impl webrunner::WebApp for MyApp {
             .build(|| {
                 // assorted settings
                 ui.checkbox("Show FPS", &mut self.show_fps);
+                ui.checkbox("Show Profiler", &mut self.show_profiler);
                 let water_level = guiutil::slider_float(ui, "Water Level", self.renderer.water_height(), (-1.0, 1.0), 1.0);
                 self.renderer.set_water_height(water_level);
 
@@ -515,6 +676,13 @@ impl webrunner::WebApp for MyApp {
                     }
                 }
 
+                // passes and render targets of the planet
+                if ui.collapsing_header("Render Graph", TreeNodeFlags::empty()) {
+                    if let Some(graph) = self.graph.as_ref() {
+                        ui.text(graph.dump());
+                    }
+                }
+
                 self.left_panel_height = ui.cursor_pos()[1];
             });
 
@@ -656,7 +824,7 @@ impl webrunner::WebApp for MyApp {
                     if let Some(texupload) = self.texuploads.pop() {
                         if let Some(texdata) = imgdecode::get(texupload.1) {
                             let texname = texupload.0.split(".").next().unwrap().to_string();
-                            let mut tex = tinygl::Texture::from_data_2d(&texdata.1, texdata.0);
+                            let mut tex = tinygl::Texture::from_data_2d(&self.context, &texdata.1, texdata.0);
                             tex.wrap(gl::TEXTURE_WRAP_S, gl::MIRRORED_REPEAT);
                             tex.wrap(gl::TEXTURE_WRAP_T, gl::MIRRORED_REPEAT);
                             self.renderer.add_texture(&texname, tex);
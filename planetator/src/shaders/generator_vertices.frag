@@ -11,11 +11,10 @@ uniform mat3 cubeTransformMatrix;
 layout(location = 0) out vec4 posHeight;
 layout(location = 1) out float height;
 
-$CHANNELS
-$NOISE
+#include "channels"
+#include "noise"
 
-#line 1
-$GENERATOR
+#include "generator"
 
 void main()
 {
@@ -11,9 +11,9 @@ uniform sampler2D planetColor;
 uniform sampler2D planetNormal;
 uniform sampler2D planetPosition;
 
-$SHADOWS
-$NOISE
-$ATMOSPHERE
+#include "shadows"
+#include "noise"
+#include "atmosphere"
 
 in vec2 clipPos;
 out vec4 outColor;
@@ -13,13 +13,13 @@ vec3 uvDists;
 vec3 sceneNormal;
 vec3 scenePosition;
 
-$CHANNEL_TEXTURES
-$CHANNEL_VARIABLES
+#include "channel_textures"
+#include "channel_variables"
 
-$NOISE
+#include "noise"
 
-$ICOSAHEDRON
-$TEXTURE_FUNCTIONS
+#include "icosahedron"
+#include "texture_functions"
 
 vec3 color(vec3 normal, vec3 position, float height);
 
@@ -148,10 +148,9 @@ void main()
 
     _generateUvMaps(sceneNormal, scenePosition);
 
-    $CHANNEL_ASSIGNMENTS
+#include "channel_assignments"
     vec3 col = color(sceneNormal, scenePosition, scenePosTex.w);
     outColorReflectivity = vec4(col, 0.0);
 }
 
-#line 1
-$COLORATOR
+#include "colorator"
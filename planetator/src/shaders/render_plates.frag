@@ -1,10 +1,10 @@
 layout(location = 0) out vec4 outNormalWf;
 layout(location = 1) out vec4 outPositionHeight;
-$CHANNEL_OUTPUTS
+#include "channel_outputs"
 
 uniform sampler2D tex_normals;
 uniform sampler2D tex_heights;
-$CHANNEL_TEXTURES
+#include "channel_textures"
 
 uniform float wf;
 uniform float radius;
@@ -18,5 +18,5 @@ void main()
     float height = texture(tex_heights, plateTc).r;
     outNormalWf = vec4(normalFromTex, wf);
     outPositionHeight = vec4(normalize(pos) * (radius + height), height);
-    $CHANNEL_ASSIGNMENTS
+#include "channel_assignments"
 }
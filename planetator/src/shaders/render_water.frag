@@ -1,10 +1,10 @@
 layout(location = 0) out vec4 outNormalWf;
 layout(location = 1) out vec4 outPositionHeight;
-$CHANNEL_OUTPUTS
+#include "channel_outputs"
 
 uniform sampler2D heights;
 uniform sampler2D normals;
-$CHANNEL_TEXTURES
+#include "channel_textures"
 
 in vec3 pos;
 in vec2 tc;
@@ -14,5 +14,5 @@ void main()
     float terrainHeight = texture(heights, tc).r;
     outNormalWf = vec4(texture(normals, tc).xyz, 0.0);
     outPositionHeight = vec4(pos, terrainHeight);
-    $CHANNEL_ASSIGNMENTS
+#include "channel_assignments"
 }
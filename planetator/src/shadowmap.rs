@@ -1,22 +1,29 @@
 use cgmath::prelude::*;
 use cgmath::*;
-use tinygl::{Program, Texture, Uniform, FrameBufferObject};
+use tinygl::{Context, Pass, PassContext, Preprocessor, Program, RenderGraph, Sampler, TextureFormat, Uniform, Uniforms, UniformBuffer, Std140};
 use super::guiutil;
 
 static MAX_REL_EXTENT: f32 = 1.2;
-
-fn glsl(tex_count: u32) -> String {
-    String::from("
+// where the ShadowMaps uniform block gets its buffer
+static SHADOW_MAPS_BINDING: u32 = 0;
+/// The render graph pass of the cascades, which renders one layer of the target a frame
+pub static PASS: &str = "shadows";
+/// The depth array target of `PASS`, it has to be kept since the cascades are reused
+pub static TARGET: &str = "shadowMaps";
+
+// needs MAX_SHADOW_MAPS defined
+static GLSL: &str = "
     struct ShadowMap {
         float depth;
         mat4 mvp;
     };
 
-    #define MAX_SHADOW_MAPS ") + &tex_count.to_string() + "
     uniform int shadowMapsPrevLayer;
     uniform int shadowMapsCurrLayer;
     uniform highp sampler2DArray shadowMaps;
-    uniform ShadowMap shadowMapsPrevCurr[2 * MAX_SHADOW_MAPS];
+    layout(std140) uniform ShadowMaps {
+        ShadowMap shadowMapsPrevCurr[2 * MAX_SHADOW_MAPS];
+    };
     uniform float shadowMapSize;
     uniform float shadowMapProgress;
     uniform float shadowBlurRadius;
@@ -147,13 +154,38 @@ fn glsl(tex_count: u32) -> String {
 
         return shadow;
     }
-    "
+    ";
+
+/// The uniforms of `GLSL` besides the shadow maps
+struct ShadowUniforms {
+    prev_layer: i32,
+    curr_layer: i32,
+    sun_direction: Vector3<f32>,
+    size: f32,
+    progress: f32,
+    blur_radius: f32,
+    // min, max and intensity
+    selfshadow: Vector3<f32>,
+}
+
+impl Uniforms for ShadowUniforms {
+    fn set(&self, program: &Program) -> Result<(), String> {
+        vec!(
+            program.set_uniform("shadowMapsPrevLayer", Uniform::Signed(self.prev_layer)),
+            program.set_uniform("shadowMapsCurrLayer", Uniform::Signed(self.curr_layer)),
+            program.set_uniform("sunDirection", Uniform::Vec3(self.sun_direction)),
+            program.set_uniform("shadowMapSize", Uniform::Float(self.size)),
+            program.set_uniform("shadowMapProgress", Uniform::Float(self.progress)),
+            program.set_uniform("shadowBlurRadius", Uniform::Float(self.blur_radius)),
+            program.set_uniform("selfshadow", Uniform::Vec3(self.selfshadow)),
+        ).into_iter().collect()
+    }
 }
 
 struct ShadowCascade {
     // constant:
     level: i32,
-    fbo: FrameBufferObject,
+    size: u32,
     extent: f32,
     granularity: f32,
     orthogonal_depth: f32,
@@ -164,23 +196,10 @@ struct ShadowCascade {
 }
 
 impl ShadowCascade {
-    fn new(size: u32, level: i32, extent: f32, depth_texture: &Texture, depth_layer: u32) -> Self {
-        // Create FBO
-        let mut fbo = FrameBufferObject::new((size as _, size as _));
-        // fbo.add_depth_texture();
-        fbo.attach_depth_texture(depth_texture, Some(depth_layer));
-        // fbo.add("depth", gl::R32F, gl::RED, gl::FLOAT);
-        {
-            // let tex = fbo.texture_mut("depth").unwrap();
-            // tex.wrap(gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE);
-            // tex.wrap(gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE);
-            // tex.filter(gl::TEXTURE_MIN_FILTER, gl::LINEAR as _);
-            // tex.filter(gl::TEXTURE_MAG_FILTER, gl::LINEAR as _);
-        }
-
+    fn new(size: u32, level: i32, extent: f32) -> Self {
         let mut ret = ShadowCascade {
             level,
-            fbo,
+            size,
             extent,
             granularity: 0.0,
             orthogonal_depth: 0.0,
@@ -191,14 +210,9 @@ impl ShadowCascade {
         ret
     }
 
-    fn depth_texture(&self) -> &Texture {
-        // self.fbo.texture("depth").unwrap()
-        self.fbo.depth_texture().unwrap()
-    }
-
     fn set_extent(&mut self, extent: f32) {
         self.extent = extent;
-        self.granularity = 2.0 * self.extent / self.fbo.size().0 as f32;
+        self.granularity = 2.0 * self.extent / self.size as f32;
         self.orthogonal_depth = self.extent * 20.0;
     }
 
@@ -224,10 +238,10 @@ struct SunPositionCascades {
 }
 
 impl SunPositionCascades {
-    fn new(size: u32, radius: f32, levels: i32, depth_texture: &Texture, start_layer: u32) -> Self {
+    fn new(size: u32, radius: f32, levels: i32, start_layer: u32) -> Self {
         let mut cascades = Vec::new();
         for i in 0..levels {
-            cascades.push(ShadowCascade::new(size, i, radius * MAX_REL_EXTENT, depth_texture, start_layer + i as u32));
+            cascades.push(ShadowCascade::new(size, i, radius * MAX_REL_EXTENT));
         }
 
         SunPositionCascades {
@@ -273,6 +287,7 @@ pub struct CascadeInfo {
 }
 
 pub struct ShadowMap {
+    context: Context,
     size_step: u32,
     levels: i32,
     level_scale: f32,
@@ -280,7 +295,10 @@ pub struct ShadowMap {
     blur_radius: f32,
     program: Program,
 
-    depth_texture: Texture,
+    // the shader reads the depth values, nearest and without comparing
+    sampler: Sampler,
+    // the depth and mvp of the prev and curr cascades
+    cascade_buffer: UniformBuffer,
 
     selfshadow_min: f32,
     selfshadow_max: f32,
@@ -293,46 +311,44 @@ pub struct ShadowMap {
 }
 
 impl ShadowMap {
-    fn create_depth_texture(size: u32, levels: i32) -> Texture {
-        let mut depth_tex = Texture::new(gl::TEXTURE_2D_ARRAY);
-        depth_tex.bind_at(0);
-        unsafe {
-            depth_tex.teximage_layer((size as _, size as _), (3 * levels) as _, gl::DEPTH_COMPONENT24, gl::DEPTH_COMPONENT, gl::UNSIGNED_INT, std::ptr::null());
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_COMPARE_MODE, gl::COMPARE_REF_TO_TEXTURE as _);
-            // gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_COMPARE_MODE, gl::NONE as _);
-            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_COMPARE_FUNC, gl::LEQUAL as _);
-        }
-        depth_tex.filter(gl::TEXTURE_MIN_FILTER, gl::NEAREST as _);
-        depth_tex.filter(gl::TEXTURE_MAG_FILTER, gl::NEAREST as _);
-        depth_tex.wrap(gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE);
-        depth_tex.wrap(gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE);
-        depth_tex
+    fn create_sampler(context: &Context) -> Sampler {
+        let mut sampler = Sampler::new(context);
+        sampler.filter(gl::TEXTURE_MIN_FILTER, gl::NEAREST);
+        sampler.filter(gl::TEXTURE_MAG_FILTER, gl::NEAREST);
+        sampler.wrap(gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE);
+        sampler.wrap(gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE);
+        sampler.set_label("shadow maps");
+        sampler
     }
 
-    pub fn glsl(&self) -> String {
-        glsl(self.levels as _)
+    /// Adds the "shadows" include with the define it needs
+    pub fn add_glsl(&self, preprocessor: &mut Preprocessor) {
+        preprocessor
+            .add_include("shadows", GLSL)
+            .define("MAX_SHADOW_MAPS", &self.levels.to_string());
     }
 
-    pub fn new(radius: f32) -> Self {
+    pub fn new(context: &Context, radius: f32) -> Self {
         let levels = 4;
         let level_scale = 0.25;
 
         let size_step = 10;
         let size = 2u32.pow(size_step);
 
-        let depth_tex = Self::create_depth_texture(size, levels);
-        let prev = SunPositionCascades::new(size, radius, levels, &depth_tex, 0);
-        let curr = SunPositionCascades::new(size, radius, levels, &depth_tex, levels as _);
-        let next = SunPositionCascades::new(size, radius, levels, &depth_tex, (2 * levels) as _);
+        let prev = SunPositionCascades::new(size, radius, levels, 0);
+        let curr = SunPositionCascades::new(size, radius, levels, levels as _);
+        let next = SunPositionCascades::new(size, radius, levels, (2 * levels) as _);
 
         let mut ret = ShadowMap {
+            context: context.clone(),
             size_step,
             radius,
-            depth_texture: depth_tex,
+            sampler: Self::create_sampler(context),
+            cascade_buffer: UniformBuffer::new(context, &Std140::new()),
             blur_radius: 1.0,
             levels,
             level_scale,
-            program: Program::new_versioned("
+            program: Program::new_versioned(context, "
                 in vec4 posHeight;
                 uniform float radius;
                 uniform mat4 mvp;
@@ -373,10 +389,21 @@ impl ShadowMap {
 
     fn create_cascades(&mut self) {
         let size = 2u32.pow(self.size_step as _);
-        self.depth_texture = Self::create_depth_texture(size, self.levels);
-        self.prev = Some(SunPositionCascades::new(size, self.radius, self.levels, &self.depth_texture, 0));
-        self.curr = Some(SunPositionCascades::new(size, self.radius, self.levels, &self.depth_texture, self.levels as u32));
-        self.next = Some(SunPositionCascades::new(size, self.radius, self.levels, &self.depth_texture, 2 * self.levels as u32));
+        self.prev = Some(SunPositionCascades::new(size, self.radius, self.levels, 0));
+        self.curr = Some(SunPositionCascades::new(size, self.radius, self.levels, self.levels as u32));
+        self.next = Some(SunPositionCascades::new(size, self.radius, self.levels, 2 * self.levels as u32));
+    }
+
+    /// Renders the prev, curr and next cascades one after the other into the layers of `TARGET`
+    pub fn pass(&self) -> Pass {
+        let size = 2i32.pow(self.size_step as _);
+        Pass::layered(PASS, (size, size), 3 * self.levels).output(TARGET, TextureFormat::Depth24)
+    }
+
+    /// Starts over with empty cascades, for when the render graph got a new `TARGET`
+    pub fn invalidate(&mut self) {
+        self.create_cascades();
+        self.scale_cascades();
     }
 
     pub fn size_step(&self) -> u32 {
@@ -454,9 +481,10 @@ impl ShadowMap {
     // that the movement of the sun is stable
     pub fn prepare_render(
         &mut self,
+        graph: &RenderGraph,
         eye: Vector3<f32>,
         look: Vector3<f32>,
-    ) -> (Matrix4<f32>, Vector3<f32>)
+    ) -> Result<(Matrix4<f32>, Vector3<f32>), String>
     {
         //
         // if any of the cascades isn't filled yet, do that first
@@ -504,11 +532,11 @@ impl ShadowMap {
         let sun_rotation = self.get_sun_cascades(to_render.tp).sun_rotation;
         let sunspace_center = sun_rotation.transform_vector(look_surface_center);
 
+        let layer = self.get_sun_cascades(to_render.tp).start_layer() + to_render.index as u32;
+        graph.begin_layer(PASS, layer as _)?;
         let projection = {
-            // setup FBO
             let cascade = self.get_cascade(to_render);
             cascade.set_center(sunspace_center);
-            cascade.fbo.bind();
             cascade.projection
         };
 
@@ -519,61 +547,66 @@ impl ShadowMap {
         self.program.uniform("mvp", Uniform::Mat4(mvp));
 
         // setup GL
-        unsafe {
-            gl::Clear(gl::DEPTH_BUFFER_BIT | gl::COLOR_BUFFER_BIT);
-            gl::Enable(gl::POLYGON_OFFSET_FILL);
-            gl::Disable(gl::BLEND);
-            gl::DepthFunc(gl::LESS);
-            gl::PolygonOffset(1.0, 1.0);
-        }
+        tinygl::clear(&self.context, gl::DEPTH_BUFFER_BIT | gl::COLOR_BUFFER_BIT);
+        tinygl::enable(&self.context, gl::POLYGON_OFFSET_FILL);
+        tinygl::disable(&self.context, gl::BLEND);
+        tinygl::depth_func(&self.context, gl::LESS);
+        unsafe { gl::PolygonOffset(1.0, 1.0); }
 
         self.get_sun_cascades(to_render.tp).filled += 1;
 
-        (mvp, look_surface_center)
+        Ok((mvp, look_surface_center))
     }
 
     pub fn finish_render(&self) {
         self.program().disable_all_vertex_attribs();
         // reset GL
-        unsafe {
-            gl::Disable(gl::POLYGON_OFFSET_FILL);
-            gl::DepthFunc(gl::LEQUAL);
-            gl::PolygonOffset(0.0, 0.0);
+        tinygl::disable(&self.context, gl::POLYGON_OFFSET_FILL);
+        tinygl::depth_func(&self.context, gl::LEQUAL);
+        unsafe { gl::PolygonOffset(0.0, 0.0); }
+        tinygl::FrameBufferObject::unbind(&self.context);
+    }
+
+    fn push_shadow_maps(data: &mut Std140, cascades: &SunPositionCascades) {
+        for cascade in &cascades.cascades {
+            data.push_struct(|shadow_map| {
+                shadow_map.push(Uniform::Float(cascade.orthogonal_depth));
+                shadow_map.push(Uniform::Mat4(cascade.projection * cascades.sun_rotation));
+            });
         }
-        tinygl::FrameBufferObject::unbind();
     }
 
-    fn bind_shadow_map(program: &Program, index: usize, sun_rotation: &Matrix4<f32>, cascade: &ShadowCascade) {
-        program.uniform(&format!("shadowMapsPrevCurr[{}].depth", index), Uniform::Float(cascade.orthogonal_depth));
-        program.uniform(&format!("shadowMapsPrevCurr[{}].mvp", index),   Uniform::Mat4(cascade.projection * *sun_rotation));
-    }
-
-    pub fn prepare_postprocess(&self, program: &Program, texunit: u32) {
-        self.depth_texture.bind_at(texunit);
-
-        program.uniform("shadowMaps", Uniform::Signed(texunit as i32));
-        program.uniform("shadowMapsPrevLayer", Uniform::Signed(self.prev.as_ref().unwrap().start_layer() as i32));
-        program.uniform("shadowMapsCurrLayer", Uniform::Signed(self.curr.as_ref().unwrap().start_layer() as i32));
-
-        let sun_rotation = self.prev.as_ref().unwrap().sun_rotation;
-        for cascade in self.prev.as_ref().unwrap().cascades.iter().enumerate() {
-            Self::bind_shadow_map(program, cascade.0, &sun_rotation, cascade.1);
-        }
-
-        let sun_rotation = self.curr.as_ref().unwrap().sun_rotation;
-        for cascade in self.curr.as_ref().unwrap().cascades.iter().enumerate() {
-            Self::bind_shadow_map(program, cascade.0 + self.levels() as usize, &sun_rotation, cascade.1);
-        }
+    /// The pass has to read `TARGET`
+    pub fn prepare_postprocess(&mut self, program: &Program, pass: &PassContext) -> Result<(), String> {
+        let (texunit, depth_texture) = match (pass.unit(TARGET), pass.texture(TARGET)) {
+            (Some(unit), Some(texture)) => (unit, texture),
+            _ => return Err(format!("pass '{}' doesn't read '{}'", pass.name(), TARGET)),
+        };
 
-        let filled = self.next.as_ref().unwrap().filled;
-        let progress = filled as f32 / self.levels as f32;
+        let prev = self.prev.as_ref().unwrap();
+        let curr = self.curr.as_ref().unwrap();
+
+        let mut data = Std140::new();
+        Self::push_shadow_maps(&mut data, prev);
+        Self::push_shadow_maps(&mut data, curr);
+        self.cascade_buffer.update(&data);
+
+        let progress = self.next.as_ref().unwrap().filled as f32 / self.levels as f32;
+        let uniforms = ShadowUniforms {
+            prev_layer: prev.start_layer() as i32,
+            curr_layer: curr.start_layer() as i32,
+            sun_direction: ((1.0 - progress) * prev.sun_direction + progress * curr.sun_direction).normalize(),
+            size: 2u32.pow(self.size_step as _) as f32,
+            progress,
+            blur_radius: self.blur_radius,
+            selfshadow: Vector3::new(self.selfshadow_min, self.selfshadow_max, self.selfshadow_intensity),
+        };
 
-        let sun_direction = (1.0 - progress) * self.prev.as_ref().unwrap().sun_direction + progress * self.curr.as_ref().unwrap().sun_direction;
-        program.uniform("sunDirection", Uniform::Vec3(sun_direction.normalize()));
-        program.uniform("shadowMapSize", Uniform::Float(2u32.pow(self.size_step as _) as f32));
-        program.uniform("shadowMapProgress", Uniform::Float(progress));
-        program.uniform("shadowBlurRadius", Uniform::Float(self.blur_radius));
-        program.uniform("selfshadow", Uniform::Vec3(cgmath::Vector3::new(self.selfshadow_min, self.selfshadow_max, self.selfshadow_intensity)));
+        vec!(
+            program.set_uniform_block("ShadowMaps", &self.cascade_buffer, SHADOW_MAPS_BINDING),
+            program.set_sampler_with("shadowMaps", depth_texture, &self.sampler, texunit),
+            program.set_uniforms(&uniforms),
+        ).into_iter().collect()
     }
 
     pub fn num_textures(&self) -> usize {
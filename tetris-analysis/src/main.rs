@@ -0,0 +1,44 @@
+extern crate tetris;
+
+use tetris::analysis::Timeline;
+use tetris::replay::Replay;
+
+const USAGE: &str = "Usage: tetris-analysis [--csv] <replay file>
+
+Writes the per-piece timeline of a replay to stdout, as JSON (default) or CSV.";
+
+fn run() -> Result<(), String> {
+    let mut csv = false;
+    let mut path = None;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--csv" => csv = true,
+            "--json" => csv = false,
+            "-h" | "--help" => return Err(String::new()),
+            _ if path.is_none() && !arg.starts_with('-') => path = Some(arg),
+            _ => return Err(format!("Unknown option: {}", arg)),
+        }
+    }
+
+    let path = path.ok_or(String::new())?;
+    let data = std::fs::read_to_string(&path).map_err(|err| format!("Can't read {}: {}", path, err))?;
+    let replay = Replay::from_text(&data).ok_or(format!("{} doesn't contain a valid replay", path))?;
+
+    let timeline = Timeline::from_replay(&replay)?;
+    if csv {
+        print!("{}", timeline.to_csv());
+    } else {
+        println!("{}", timeline.to_json());
+    }
+    Ok(())
+}
+
+fn main() {
+    if let Err(err) = run() {
+        if !err.is_empty() {
+            eprintln!("{}\n", err);
+        }
+        eprintln!("{}", USAGE);
+        std::process::exit(1);
+    }
+}
@@ -14,7 +14,9 @@ export EMMAKEN_CFLAGS="\
     -s FETCH=1 \
     -Os \
     -s TOTAL_MEMORY=33554432 \
+    -s EXPORTED_FUNCTIONS='[\"_UploadData\", \"_UploadFinished\", \"_main\", \"_malloc\"]' \
     -s EXTRA_EXPORTED_RUNTIME_METHODS='[\"ccall\", \"cwrap\"]' \
+    --bind \
 "
 
 export RUST_BACKTRACE=0
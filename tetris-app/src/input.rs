@@ -0,0 +1,282 @@
+//! Maps keyboard keys and game controller buttons and sticks to game actions. There is one
+//! profile of bindings for the keyboard, one for controllers in general and one for each kind
+//! of controller that got its own bindings.
+
+use std::collections::HashMap;
+
+use sdl2::controller::{Axis, Button};
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Serialize, Deserialize)]
+pub enum Action {
+    Left,
+    Right,
+    Down,
+    RotateLeft,
+    RotateRight,
+    Pause,
+}
+
+pub const ACTIONS: [(Action, &str); 6] = [
+    (Action::Left, "Left"),
+    (Action::Right, "Right"),
+    (Action::Down, "Down"),
+    (Action::RotateLeft, "Rotate Left"),
+    (Action::RotateRight, "Rotate Right"),
+    (Action::Pause, "Pause"),
+];
+
+/// Device name of the keyboard profile
+pub const KEYBOARD: &str = "Keyboard";
+/// Device name of the profile for controllers without one of their own
+pub const CONTROLLER: &str = "Controller";
+
+#[derive(Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub enum Binding {
+    /// SDL keycode
+    Key(i32),
+    /// SDL name of a controller button, like "a" or "dpleft"
+    Button(String),
+    /// SDL name of a controller axis and the direction it's pushed in, true for positive
+    Axis(String, bool),
+}
+
+impl Binding {
+    pub fn name(&self) -> String {
+        match self {
+            Binding::Key(key) => Keycode::from_i32(*key).map_or(String::from("?"), |key| key.name()),
+            Binding::Button(button) => button.clone(),
+            Binding::Axis(axis, positive) => format!("{}{}", axis, if *positive { '+' } else { '-' }),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+pub struct Profile {
+    pub device: String,
+    pub bindings: Vec<(Action, Binding)>,
+    /// how far a stick has to be pushed to count as pressed, in percent
+    pub deadzone: i32,
+}
+
+impl Profile {
+    pub fn keyboard() -> Self {
+        let key = |keycode: Keycode| Binding::Key(keycode as i32);
+        Profile {
+            device: String::from(KEYBOARD),
+            bindings: vec!(
+                (Action::Left, key(Keycode::Left)),
+                (Action::Right, key(Keycode::Right)),
+                (Action::Down, key(Keycode::Down)),
+                (Action::RotateLeft, key(Keycode::Y)),
+                (Action::RotateRight, key(Keycode::X)),
+                (Action::Pause, key(Keycode::Return)),
+            ),
+            deadzone: 0,
+        }
+    }
+
+    /// NES style: d-pad or left stick, B rotates left, A right
+    pub fn controller(device: &str) -> Self {
+        let button = |button: Button| Binding::Button(button.string());
+        let axis = |axis: Axis, positive| Binding::Axis(axis.string(), positive);
+        Profile {
+            device: String::from(device),
+            bindings: vec!(
+                (Action::Left, button(Button::DPadLeft)),
+                (Action::Left, axis(Axis::LeftX, false)),
+                (Action::Right, button(Button::DPadRight)),
+                (Action::Right, axis(Axis::LeftX, true)),
+                (Action::Down, button(Button::DPadDown)),
+                (Action::Down, axis(Axis::LeftY, true)),
+                (Action::RotateLeft, button(Button::B)),
+                (Action::RotateRight, button(Button::A)),
+                (Action::Pause, button(Button::Start)),
+            ),
+            deadzone: 50,
+        }
+    }
+
+    pub fn bindings(&self, action: Action) -> Vec<&Binding> {
+        self.bindings.iter().filter(|(a, _)| *a == action).map(|(_, binding)| binding).collect()
+    }
+
+    /// Replaces the bindings of the action, a binding only ever triggers one action
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        self.bindings.retain(|(a, b)| *a != action && *b != binding);
+        self.bindings.push((action, binding));
+    }
+
+    fn action(&self, binding: &Binding) -> Option<Action> {
+        self.bindings.iter().find(|(_, b)| b == binding).map(|(action, _)| *action)
+    }
+}
+
+pub fn default_profiles() -> Vec<Profile> {
+    vec!(Profile::keyboard(), Profile::controller(CONTROLLER))
+}
+
+/// The profile for a device, controllers fall back to the general one
+pub fn profile<'a>(profiles: &'a [Profile], device: &str) -> Option<&'a Profile> {
+    profiles.iter().find(|profile| profile.device == device)
+        .or_else(|| profiles.iter().find(|profile| device != KEYBOARD && profile.device == CONTROLLER))
+}
+
+/// The device's own profile, a new one starts out with the bindings of the general profile
+pub fn profile_mut<'a>(profiles: &'a mut Vec<Profile>, device: &str) -> &'a mut Profile {
+    if !profiles.iter().any(|profile| profile.device == device) {
+        let profile = match profile(profiles, device) {
+            Some(general) => Profile { device: String::from(device), ..general.clone() },
+            None => Profile::controller(device),
+        };
+        profiles.push(profile);
+    }
+    profiles.iter_mut().find(|profile| profile.device == device).unwrap()
+}
+
+/// A key, button or stick direction that got pressed or released
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub device: String,
+    /// of the controller, None for the keyboard
+    pub instance: Option<u32>,
+    pub binding: Binding,
+    pub pressed: bool,
+}
+
+pub struct Input {
+    // names of the connected controllers by instance id
+    controllers: HashMap<u32, String>,
+    // direction each stick axis counts as pushed in, -1, 0 or 1
+    axes: HashMap<(u32, String), i32>,
+    // everything held down right now, with the instance id of the controller holding it
+    held: Vec<(Option<u32>, String, Binding)>,
+    // the controller that was used last, to show its bindings
+    last_controller: Option<String>,
+}
+
+impl Input {
+    pub fn new() -> Self {
+        Input {
+            controllers: HashMap::new(),
+            axes: HashMap::new(),
+            held: Vec::new(),
+            last_controller: None,
+        }
+    }
+
+    /// The controller the controls page shows and binds: the one used last, or the general profile
+    pub fn shown_controller(&self) -> String {
+        self.last_controller.clone().unwrap_or(String::from(CONTROLLER))
+    }
+
+    /// A controller got connected or, without a name, disconnected. Whatever a disconnected
+    /// one held can't be released anymore, so that comes back as changes releasing it.
+    pub fn controller(&mut self, instance: u32, name: Option<&str>) -> Vec<Change> {
+        match name {
+            Some(name) => {
+                self.controllers.insert(instance, String::from(name));
+                self.last_controller = Some(String::from(name));
+                Vec::new()
+            }
+            None => {
+                self.controllers.remove(&instance);
+                self.axes.retain(|(which, _), _| *which != instance);
+                self.held.iter()
+                    .filter(|(which, _, _)| *which == Some(instance))
+                    .map(|(_, device, binding)| Change { device: device.clone(), instance: Some(instance), binding: binding.clone(), pressed: false })
+                    .collect()
+            }
+        }
+    }
+
+    /// The keys, buttons and stick directions an event presses or releases. Sticks count as
+    /// pushed beyond the deadzone and only as released once they are back well inside of it,
+    /// so a shaky stick doesn't keep resetting DAS.
+    pub fn changes(&mut self, profiles: &[Profile], event: &Event) -> Vec<Change> {
+        let change = |device: &str, instance, binding, pressed| Change { device: String::from(device), instance, binding, pressed };
+        let controllers = &self.controllers;
+        let controller = |which: &u32| controllers.get(which).cloned().unwrap_or(String::from(CONTROLLER));
+
+        match event {
+            Event::KeyDown { keycode: Some(key), repeat: false, .. } => vec!(change(KEYBOARD, None, Binding::Key(*key as i32), true)),
+            Event::KeyUp { keycode: Some(key), .. } => vec!(change(KEYBOARD, None, Binding::Key(*key as i32), false)),
+            Event::ControllerButtonDown { which, button, .. } => {
+                let device = controller(which);
+                self.last_controller = Some(device.clone());
+                vec!(change(&device, Some(*which), Binding::Button(button.string()), true))
+            }
+            Event::ControllerButtonUp { which, button, .. } => {
+                vec!(change(&controller(which), Some(*which), Binding::Button(button.string()), false))
+            }
+            Event::ControllerAxisMotion { which, axis, value, .. } => {
+                let device = controller(which);
+                let deadzone = profile(profiles, &device).map_or(50, |profile| profile.deadzone).max(1) * 32767 / 100;
+                let value = *value as i32;
+
+                let last = self.axes.get(&(*which, axis.string())).cloned().unwrap_or(0);
+                let direction = if last != 0 && value * last >= deadzone * 3 / 4 {
+                    last
+                } else if value >= deadzone {
+                    1
+                } else if value <= -deadzone {
+                    -1
+                } else {
+                    0
+                };
+                if direction == last {
+                    return Vec::new();
+                }
+                self.axes.insert((*which, axis.string()), direction);
+                self.last_controller = Some(device.clone());
+
+                let mut ret = Vec::new();
+                if last != 0 {
+                    ret.push(change(&device, Some(*which), Binding::Axis(axis.string(), last > 0), false));
+                }
+                if direction != 0 {
+                    ret.push(change(&device, Some(*which), Binding::Axis(axis.string(), direction > 0), true));
+                }
+                ret
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Actions that start or stop with the changes. An action is held as long as any of its
+    /// bindings is, key repeats and a second binding being pressed don't trigger it again.
+    pub fn actions(&mut self, profiles: &[Profile], changes: &[Change]) -> Vec<(Action, bool)> {
+        let mut ret = Vec::new();
+
+        for change in changes {
+            let action = match profile(profiles, &change.device).and_then(|profile| profile.action(&change.binding)) {
+                Some(action) => action,
+                None => continue,
+            };
+
+            let was_held = self.holds(profiles, action);
+            let key = (change.instance, change.device.clone(), change.binding.clone());
+            self.held.retain(|held| *held != key);
+            if change.pressed {
+                self.held.push(key);
+            }
+
+            let held = self.holds(profiles, action);
+            if held != was_held {
+                ret.push((action, held));
+            }
+        }
+
+        ret
+    }
+
+    fn holds(&self, profiles: &[Profile], action: Action) -> bool {
+        self.held.iter().any(|(_, device, binding)| {
+            profile(profiles, device).and_then(|profile| profile.action(binding)) == Some(action)
+        })
+    }
+}
@@ -0,0 +1,247 @@
+//! Every finished game is kept on this machine, in the local storage of the browser or in the
+//! data directory on the desktop. The library has the personal bests for the highscores page,
+//! imports and exports replay files, and remembers the games that didn't reach the server yet.
+
+use tetris::replay::{Replay, Replayer};
+use tetris::PlayedGame;
+
+#[cfg(target_os = "emscripten")]
+use emscripten_util::localstorage;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Entry {
+    /// score, levels and date, the replay id is the one of the entry
+    pub game: PlayedGame,
+    pub lines: i32,
+    /// still has to be uploaded
+    pub pending: bool,
+    pub replay: Replay,
+}
+
+impl Entry {
+    /// Plays the replay through to get score, levels and lines
+    fn new(id: usize, name: &str, replay: &Replay, date: chrono::DateTime<chrono::Utc>) -> Result<Self, String> {
+        let replayer = Replayer::new(replay)?;
+        let snapshot = replayer.history().snapshot();
+        Ok(Entry {
+            game: PlayedGame::new(id, date, String::from(name), snapshot.score(),
+                                  replay.config().level, snapshot.level(), replay.duration()),
+            lines: snapshot.lines(),
+            pending: false,
+            replay: replay.clone(),
+        })
+    }
+
+    #[cfg(target_os = "emscripten")]
+    fn with_id(self, id: usize) -> Self {
+        let game = &self.game;
+        Entry {
+            game: PlayedGame::new(id, game.utc(), game.name(), game.score(), game.start_level(), game.end_level(), game.duration()),
+            ..self
+        }
+    }
+}
+
+/// Each game is stored on its own, next to an index with the ids of all of them, so that adding
+/// a game doesn't write all the others again.
+pub struct Library {
+    entries: Vec<Entry>,
+    // the local storage answers later, games that finish before that get merged in
+    #[cfg(target_os = "emscripten")] loading: Option<Loading>,
+}
+
+#[cfg(target_os = "emscripten")]
+enum Loading {
+    Index(localstorage::StorageLoad),
+    // the entries that arrived, and the ones still on their way
+    Entries(Vec<Entry>, Vec<localstorage::StorageLoad>),
+}
+
+/// Name of the stored entry, or of the index
+fn key(id: Option<usize>) -> String {
+    match id {
+        Some(id) => format!("library-{}", id),
+        None => String::from("library"),
+    }
+}
+
+/// Where the desktop build keeps the library
+#[cfg(not(target_os = "emscripten"))]
+fn library_dir() -> Option<std::path::PathBuf> {
+    sdl2::filesystem::pref_path("tetris", "tetris-app").ok().map(|dir| std::path::Path::new(&dir).join("replays"))
+}
+
+fn decode<T: serde::de::DeserializeOwned>(data: Vec<u8>) -> Option<T> {
+    String::from_utf8(data).ok().and_then(|data| tetris::networking::decode(&data))
+}
+
+impl Library {
+    #[cfg(target_os = "emscripten")]
+    pub fn open() -> Self {
+        Library {
+            entries: Vec::new(),
+            loading: Some(Loading::Index(localstorage::load("TETRIS", &key(None)))),
+        }
+    }
+
+    #[cfg(not(target_os = "emscripten"))]
+    pub fn open() -> Self {
+        let read = |id| library_dir().and_then(|dir| std::fs::read(dir.join(key(id))).ok());
+        let ids: Vec<usize> = read(None).and_then(decode).unwrap_or_default();
+        Library {
+            entries: ids.into_iter().filter_map(|id| read(Some(id)).and_then(decode)).collect(),
+        }
+    }
+
+    /// Picks up the stored games once the local storage delivered them
+    pub fn poll(&mut self) {
+        #[cfg(target_os = "emscripten")] {
+            let mut loaded = None;
+            match self.loading.take() {
+                Some(Loading::Index(mut load)) => {
+                    self.loading = match load.consume(|data| decode::<Vec<usize>>(data), || None) {
+                        Some(ids) => {
+                            let loads = ids.unwrap_or_default().into_iter()
+                                .map(|id| localstorage::load("TETRIS", &key(Some(id))))
+                                .collect();
+                            Some(Loading::Entries(Vec::new(), loads))
+                        }
+                        None => Some(Loading::Index(load)),
+                    };
+                }
+                Some(Loading::Entries(mut stored, mut loads)) => {
+                    // entries that can't be read are left out
+                    loads.retain_mut(|load| match load.consume(decode::<Entry>, || None) {
+                        Some(entry) => {
+                            stored.extend(entry);
+                            false
+                        }
+                        None => true,
+                    });
+                    if loads.is_empty() {
+                        loaded = Some(stored);
+                    } else {
+                        self.loading = Some(Loading::Entries(stored, loads));
+                    }
+                }
+                None => {}
+            }
+
+            if let Some(stored) = loaded {
+                let added = std::mem::replace(&mut self.entries, stored);
+                for entry in added {
+                    let id = self.next_id();
+                    self.entries.push(entry.with_id(id));
+                    self.save(id);
+                }
+                self.save_index();
+            }
+        }
+    }
+
+    fn write(&self, id: Option<usize>, data: Option<String>) {
+        #[cfg(target_os = "emscripten")] {
+            // don't overwrite what hasn't been loaded yet
+            if self.loading.is_none() {
+                match data {
+                    Some(data) => localstorage::store("TETRIS", &key(id), data.as_bytes()),
+                    None => localstorage::delete("TETRIS", &key(id)),
+                }
+            }
+        }
+        #[cfg(not(target_os = "emscripten"))] {
+            if let Some(dir) = library_dir() {
+                let path = dir.join(key(id));
+                let result = std::fs::create_dir_all(&dir).and_then(|_| match data {
+                    Some(data) => std::fs::write(&path, data),
+                    None => std::fs::remove_file(&path),
+                });
+                if let Err(err) = result {
+                    println!("Can't save the replay library to {:?}: {}", path, err);
+                }
+            }
+        }
+    }
+
+    /// Writes the entry, or deletes it once it's gone
+    fn save(&self, id: usize) {
+        self.write(Some(id), self.get(id).map(tetris::networking::encode));
+    }
+
+    fn save_index(&self) {
+        let ids: Vec<usize> = self.entries.iter().map(|entry| entry.game.replay()).collect();
+        self.write(None, Some(tetris::networking::encode(&ids)));
+    }
+
+    fn next_id(&self) -> usize {
+        self.entries.iter().map(|entry| entry.game.replay() + 1).max().unwrap_or(1)
+    }
+
+    /// Keeps a finished game, it's queued for uploading
+    pub fn add(&mut self, name: &str, replay: &Replay) -> Result<usize, String> {
+        let id = self.next_id();
+        self.entries.push(Entry { pending: true, ..Entry::new(id, name, replay, chrono::Utc::now())? });
+        self.save(id);
+        self.save_index();
+        Ok(id)
+    }
+
+    /// Adds the replay of a file, unless it's in the library already. Replay files don't know
+    /// when the game got played, `date` is when the file got last modified.
+    pub fn import(&mut self, name: &str, text: &str, date: chrono::DateTime<chrono::Utc>) -> Result<usize, String> {
+        let replay = Replay::from_text(text).ok_or_else(|| format!("{} is no replay", name))?;
+        replay.validate().map_err(|err| format!("{} is a broken replay: {}", name, err))?;
+        let text = replay.to_text();
+        if let Some(entry) = self.entries.iter().find(|entry| entry.replay.to_text() == text) {
+            return Ok(entry.game.replay());
+        }
+
+        let id = self.next_id();
+        self.entries.push(Entry::new(id, name, &replay, date)?);
+        self.save(id);
+        self.save_index();
+        Ok(id)
+    }
+
+    /// The contents of a replay file
+    pub fn export(&self, id: usize) -> Option<String> {
+        self.get(id).map(|entry| entry.replay.to_text())
+    }
+
+    pub fn remove(&mut self, id: usize) {
+        self.entries.retain(|entry| entry.game.replay() != id);
+        self.save(id);
+        self.save_index();
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Entry> {
+        self.entries.iter().find(|entry| entry.game.replay() == id)
+    }
+
+    /// Personal bests, or the latest games first
+    pub fn games(&self, by_score: bool) -> Vec<PlayedGame> {
+        let mut ret: Vec<PlayedGame> = self.entries.iter().map(|entry| entry.game.clone()).collect();
+        if by_score {
+            ret.sort_by(|a, b| b.score().cmp(&a.score()));
+        } else {
+            ret.sort_by(|a, b| b.utc().cmp(&a.utc()));
+        }
+        ret
+    }
+
+    /// Games that didn't make it to the server yet
+    pub fn pending(&self) -> Vec<(usize, &PlayedGame, &Replay)> {
+        self.entries.iter()
+            .filter(|entry| entry.pending)
+            .map(|entry| (entry.game.replay(), &entry.game, &entry.replay))
+            .collect()
+    }
+
+    /// The server has the game now, or refused it for good
+    pub fn uploaded(&mut self, id: usize) {
+        if let Some(entry) = self.entries.iter_mut().find(|entry| entry.game.replay() == id) {
+            entry.pending = false;
+        }
+        self.save(id);
+    }
+}
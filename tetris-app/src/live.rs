@@ -0,0 +1,174 @@
+//! Games that are going on right now: the player's own game gets sent to the server every
+//! couple of seconds, and the games of others can be watched a few seconds behind.
+
+use tetris::networking::ServerAnswer;
+use tetris::replay::{Replay, Replayer};
+use tetris::PlayedGame;
+
+use client;
+
+/// Seconds between the updates of a broadcast game
+const BROADCAST_INTERVAL: f32 = 2.0;
+/// Seconds between asking the server for more of a watched game
+const WATCH_INTERVAL: f32 = 1.0;
+/// Seconds a watched game is shown behind the latest update, so it doesn't stutter
+const LIVE_DELAY: f32 = 3.0;
+
+/// Sends the game of this player to the server while it is played
+pub struct Broadcast {
+    // the server hands out the session with the answer to the start
+    session: Option<usize>,
+    // entries the server has
+    sent: usize,
+    timer: f32,
+    request: Option<client::Request>,
+    // the last update is on the way, or the server got it
+    finishing: bool,
+    done: bool,
+}
+
+impl Broadcast {
+    pub fn start(server: &client::ServerConfig, name: &str, replay: &Replay) -> Self {
+        Broadcast {
+            session: None,
+            sent: 0,
+            timer: 0.0,
+            request: Some(server.start_live(name, &replay.header())),
+            finishing: false,
+            done: false,
+        }
+    }
+
+    /// Sends what got recorded since the last update, the end of the game right away
+    pub fn update(&mut self, server: &client::ServerConfig, replay: &Replay, finished: bool, dt: f32) {
+        self.timer += dt;
+
+        if let Some(request) = self.request.as_ref() {
+            match request.response() {
+                client::Response::Waiting => return,
+                client::Response::Success(ServerAnswer::LiveUpdated { session, entries }) => {
+                    self.session = Some(session);
+                    self.sent = entries;
+                    self.done = self.finishing && entries == replay.position().0;
+                }
+                // tried again with the next update
+                _ => self.finishing = false,
+            }
+            self.request = None;
+        }
+
+        if let Some(session) = self.session {
+            let due = self.timer >= BROADCAST_INTERVAL || (finished && !self.finishing);
+            if !self.done && due {
+                self.timer = 0.0;
+                self.finishing = finished;
+                self.request = Some(server.update_live(session, replay.delta(self.sent), finished));
+            }
+        }
+    }
+
+    /// Nothing left to send: the server has the end of the game, or it never started it
+    pub fn done(&self) -> bool {
+        self.done || (self.session.is_none() && self.request.is_none())
+    }
+}
+
+/// A game of someone else, as far as the server has it
+pub struct Spectator {
+    pub session: usize,
+    pub name: String,
+    replay: Option<Replay>,
+    /// there once the first entries with the board arrived
+    pub replayer: Option<Replayer>,
+    request: Option<client::Request>,
+    timer: f32,
+    /// the player finished, or the server doesn't have the game anymore
+    pub finished: bool,
+    /// what the server sent didn't play back, watching stopped there
+    pub error: Option<String>,
+}
+
+impl Spectator {
+    pub fn new(server: &client::ServerConfig, game: &PlayedGame) -> Self {
+        Spectator {
+            session: game.replay(),
+            name: game.name(),
+            replay: None,
+            replayer: None,
+            request: Some(server.watch_live(game.replay(), 0)),
+            timer: 0.0,
+            finished: false,
+            error: None,
+        }
+    }
+
+    pub fn update(&mut self, server: &client::ServerConfig, dt: f32) {
+        self.timer += dt;
+
+        let answer = match self.request.as_ref().map(|request| request.response()) {
+            Some(client::Response::Waiting) => None,
+            Some(answer) => {
+                self.request = None;
+                Some(answer)
+            }
+            None => None,
+        };
+
+        match answer {
+            Some(client::Response::Success(ServerAnswer::LiveData { session, header, delta, finished })) => {
+                if session == self.session {
+                    self.receive(header, &delta, finished);
+                }
+            }
+            // the game got dropped by the server
+            Some(client::Response::Success(ServerAnswer::ServerError(_))) => self.finished = true,
+            _ => {}
+        }
+
+        if self.request.is_none() && !self.finished && self.timer >= WATCH_INTERVAL {
+            self.timer = 0.0;
+            let from = self.replay.as_ref().map_or(0, |replay| replay.position().0);
+            self.request = Some(server.watch_live(self.session, from));
+        }
+
+        // keeps the distance to the live end, but plays on to the end once the game is over
+        if let Some(replayer) = self.replayer.as_mut() {
+            replayer.advance(dt);
+            if !self.finished && replayer.length() - replayer.frame() > 2.0 * LIVE_DELAY {
+                let time = replayer.length() - LIVE_DELAY;
+                replayer.jump(time);
+            }
+        }
+    }
+
+    fn receive(&mut self, header: Option<Replay>, delta: &tetris::replay::ReplayDelta, finished: bool) {
+        if self.replay.is_none() {
+            self.replay = header;
+        }
+
+        if let Err(err) = self.play(delta, finished) {
+            self.error = Some(err);
+            self.finished = true;
+        }
+    }
+
+    fn play(&mut self, delta: &tetris::replay::ReplayDelta, finished: bool) -> Result<(), String> {
+        if let Some(replay) = self.replay.as_mut() {
+            replay.apply(delta)?;
+            self.finished = finished;
+
+            match self.replayer.as_mut() {
+                Some(replayer) => replayer.extend(replay)?,
+                // the board comes with the first entries
+                None if replay.position().0 > 0 => {
+                    let mut replayer = Replayer::new(replay)?;
+                    let time = replayer.length() - LIVE_DELAY;
+                    replayer.jump(time);
+                    self.replayer = Some(replayer);
+                }
+                None => {}
+            }
+        }
+        Ok(())
+    }
+}
@@ -4,15 +4,15 @@ extern crate imgui;
 extern crate cgmath;
 extern crate appbase;
 extern crate util3d;
-extern crate webutil;
 #[cfg(target_os = "emscripten")] extern crate emscripten_util;
 extern crate tetris;
+extern crate tetris_client as client;
 extern crate rand;
 extern crate tinygl;
+extern crate chrono;
 
 extern crate serde;
-extern crate base64;
-extern crate bincode;
+extern crate serde_json;
 #[macro_use] extern crate serde_derive;
 
 use imgui::*;
@@ -23,29 +23,60 @@ use appbase::webrunner;
 use std::collections::HashMap;
 
 mod renderer;
-mod client;
+mod sound;
+mod input;
+mod library;
+mod replayview;
+mod live;
+mod theme;
 
 const ZFAR: f32 = 700.0;
-const FRAME: f32 = 1.0 / 60.0;
-
-#[derive(Clone, Serialize, Deserialize)]
-struct PlayerOptions {
-    name: String,
+const CURVES: [&str; 4] = ["NES", "PAL (50 Hz)", "Guideline", "20G"];
+const PIECE_SETS: [&str; 3] = ["Tetrominoes", "Pentominoes", "Big"];
+/// Seconds between attempts to upload the games the server didn't get yet
+const SYNC_INTERVAL: f32 = 30.0;
+/// Seconds between refreshing the list of live games
+const LIVE_INTERVAL: f32 = 5.0;
+#[cfg(target_os = "emscripten")]
+const HTML_INPUT_REPLAY: &str = "input_replay";
+
+/// Keys as they were stored before the input profiles, only read to carry them over
+#[derive(Clone, Deserialize)]
+struct LegacyKeys {
     left: i32,
     right: i32,
     drop: i32,
     rotl: i32,
     rotr: i32,
     pause: i32,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct PlayerOptions {
+    name: String,
+    #[serde(flatten, skip_serializing)]
+    legacy_keys: Option<LegacyKeys>,
+    #[serde(default = "input::default_profiles")]
+    profiles: Vec<input::Profile>,
     level: i32,
     ghost: bool,
     sound: bool,
     render3d: bool,
+    #[serde(default)]
+    curve: tetris::LevelCurve,
+    // others can watch the games while they are played
+    #[serde(default)]
+    broadcast: bool,
+    // name of the theme
+    #[serde(default)]
+    theme: String,
 }
 
 enum State {
     MainMenu,
     PreGame {
+        // the controls page, with the binding that waits for a key or button:
+        // the action index, plus the number of actions for the controller column
         keyconfig: Option<i32>,
     },
     Game {
@@ -53,18 +84,44 @@ enum State {
         dtime: f32,
         paused: bool,
         finished: bool,
+        broadcast: Option<live::Broadcast>,
     },
-    Replay {
-        replayer: tetris::replay::Replayer,
+    Replay(replayview::ReplayView),
+    Spectate(live::Spectator),
+    Practice {
+        // no game while the drill gets chosen
+        game: Option<tetris::game::Game>,
+        position: tetris::notation::Position,
+        drill: Option<tetris::practice::Drill>,
+        result: tetris::practice::Judgement,
+        slots: Vec<Option<tetris::game::Game>>,
+        board: String,
+        error: String,
+        dtime: f32,
+        paused: bool,
     },
     Highscores {
         selected: Option<usize>,
         sort_by_score: bool,
-        global: bool,
+        source: Scores,
+        // replay file to import or export to, and how that went
+        file: String,
+        message: String,
     },
     About,
 }
 
+#[derive(Clone, Copy, PartialEq)]
+enum Scores {
+    Global,
+    // the games of this player on the server
+    Player,
+    // the games in the library on this machine
+    Library,
+    // games that are played right now
+    Live,
+}
+
 struct TetrisApp {
     windowsize: (f32, f32),
     fixedsize: (f32, f32),
@@ -76,13 +133,20 @@ struct TetrisApp {
 
     player: PlayerOptions,
 
+    context: tinygl::Context,
     renderer: renderer::Renderer,
-
-    rotl: bool,
-    rotr: bool,
+    // the second board when comparing replays
+    compare_renderer: renderer::Renderer,
+    sound: sound::Sound,
+    input: input::Input,
 
     server: client::ServerConfig,
     requests: Vec<client::Request>,
+    // uploads of library games, by id
+    uploads: Vec<(usize, client::Request)>,
+    sync_timer: f32,
+    // broadcasts of games that were left before the server had their end
+    abandoned: Vec<(live::Broadcast, tetris::replay::Replay)>,
 
     // a unique ID tag will be stored in the browsers local storage, and will be attached to
     // the replays when they get uploaded
@@ -95,25 +159,143 @@ struct TetrisApp {
     scores_local: Vec<tetris::PlayedGame>,
     last_global: Vec<tetris::PlayedGame>,
     last_local: Vec<tetris::PlayedGame>,
+    live_games: Vec<tetris::PlayedGame>,
+    live_timer: f32,
 
     replays: HashMap<usize, tetris::replay::Replay>,
+    library: library::Library,
+
+    drills: Vec<tetris::practice::Drill>,
+    themes: Vec<theme::Theme>,
 
     fpswidget: appbase::fpswidget::FpsWidget,
 }
 
-fn play_sound(tagname: &str) {
-    #[cfg(target_os = "emscripten")] {
-        emscripten_util::run_javascript(&(String::from("{
-            let element = document.getElementById('") + tagname + "');
-            element.currentTime = 0;
-            element.play();
-        }"));
+/// Where the desktop build keeps the player settings
+#[cfg(not(target_os = "emscripten"))]
+fn player_file() -> Option<std::path::PathBuf> {
+    sdl2::filesystem::pref_path("tetris", "tetris-app").ok().map(|dir| std::path::Path::new(&dir).join("player"))
+}
+
+/// Board, next piece and score display: the ones of the theme, or the left or right ones of two
+/// replays side by side
+fn layout(theme: &theme::Theme, side: Option<bool>) -> (renderer::Rectangle, renderer::Rectangle, renderer::Rectangle) {
+    let rect = renderer::Rectangle::new;
+    match side {
+        None => (theme.layout.field, theme.layout.next, theme.layout.info),
+        Some(false) => (rect(-345.0, -300.0, 240.0, 480.0), rect(-95.0, -300.0, 80.0, 80.0), rect(-345.0, 185.0, 240.0, 140.0)),
+        Some(true) => (rect(105.0, -300.0, 240.0, 480.0), rect(15.0, -300.0, 80.0, 80.0), rect(105.0, 185.0, 240.0, 140.0)),
+    }
+}
+
+fn judge(drill: &Option<tetris::practice::Drill>, game: &tetris::game::Game) -> tetris::practice::Judgement {
+    drill.as_ref().map_or(tetris::practice::Judgement::Playing, |drill| drill.judge(game))
+}
+
+/// Drills bring their own board size and pieces
+fn practice_game(config: &tetris::Config, position: &tetris::notation::Position, drill: &Option<tetris::practice::Drill>) -> tetris::game::Game {
+    match drill {
+        Some(drill) => drill.start(config),
+        None => tetris::game::Game::from_position(config, position),
+    }
+}
+
+/// Boards for practice are either a fumen ("v115@...") or a text grid
+fn parse_board(text: &str, config: &tetris::Config) -> Result<tetris::notation::Position, String> {
+    let (width, height) = (config.width as usize, config.height as usize);
+    if text.contains('@') {
+        tetris::notation::Position::from_fumen(text, width, height)
+    } else {
+        let stack = tetris::notation::parse_grid(text, width, height)?;
+        Ok(tetris::notation::Position::new(stack, Vec::new()))
     }
 }
 
 impl TetrisApp {
+    /// The theme the player picked, or the first one
+    fn theme(&self) -> &theme::Theme {
+        self.themes.iter().find(|theme| theme.name == self.player.theme).unwrap_or(&self.themes[0])
+    }
+
     fn save(&mut self, game: &tetris::game::Game) {
-        self.requests.push(self.server.upload_replay(&self.player.name, game.replay()));
+        if let Err(err) = self.library.add(&self.player.name, game.replay()) {
+            println!("Can't keep the game in the library: {}", err);
+        }
+        self.sync();
+    }
+
+    /// Uploads the library games the server doesn't have yet
+    fn sync(&mut self) {
+        self.sync_timer = 0.0;
+        for (id, game, replay) in self.library.pending() {
+            if !self.uploads.iter().any(|upload| upload.0 == id) {
+                self.uploads.push((id, self.server.upload_replay(&game.name(), replay)));
+            }
+        }
+    }
+
+    /// Runs the frames that are due since the last call, returns true if the game got lost
+    fn advance_game(&self, game: &mut tetris::game::Game, dtime: &mut f32, dt: f32) -> bool {
+        let mut died = false;
+
+        // advance timer
+        let frame = game.config().frame_time();
+        *dtime -= dt.min(0.1) - frame;
+
+        let mut frames = 1;
+        while *dtime < -frame {
+            frames += 1;
+            *dtime += frame;
+        }
+        while *dtime > frame {
+            frames -= 1;
+            *dtime -= frame;
+        }
+
+        for _ in 0..frames {
+            if let Some(tetris::game::Outcome::Death) = game.frame() {
+                died = true;
+            }
+        }
+
+        // also picks up the rotations from the key handler
+        let events = game.take_events();
+        if self.player.sound {
+            self.sound.play(&events);
+        }
+
+        died
+    }
+
+    fn practice_menu(&self) -> State {
+        State::Practice {
+            game: None,
+            position: tetris::notation::Position::new(tetris::stack::Stack::new(self.config.width as usize, self.config.height as usize), Vec::new()),
+            drill: None,
+            result: tetris::practice::Judgement::Playing,
+            slots: Vec::new(),
+            board: String::new(),
+            error: String::new(),
+            dtime: 0.0,
+            paused: false,
+        }
+    }
+
+    /// Practice on the given board, a drill also judges the outcome
+    fn practice(&mut self, position: tetris::notation::Position, drill: Option<tetris::practice::Drill>) -> State {
+        self.renderer.gen_new_colors();
+        self.save_player_data();
+        State::Practice {
+            game: Some(practice_game(&self.config, &position, &drill)),
+            position,
+            drill,
+            result: tetris::practice::Judgement::Playing,
+            slots: vec!(None, None, None),
+            board: String::new(),
+            error: String::new(),
+            dtime: 0.0,
+            paused: false,
+        }
     }
 
     fn about_button<'ui>(&self, ui: &'ui imgui::Ui) -> bool {
@@ -151,8 +333,9 @@ impl TetrisApp {
 
     fn process_finished_requests(&mut self) {
         let mut answers = Vec::new();
+        let mut uploaded = Vec::new();
 
-        self.requests.retain(|request| {
+        fn check(request: &client::Request, answers: &mut Vec<tetris::networking::ServerAnswer>) -> bool {
             match request.response() {
                 client::Response::Waiting => true,
                 client::Response::HttpError(err) => {
@@ -168,7 +351,27 @@ impl TetrisApp {
                     false
                 }
             }
+        }
+
+        self.requests.retain(|request| check(request, &mut answers));
+
+        // an upload that failed stays pending, one the server answered in any way is done
+        self.uploads.retain(|(id, request)| {
+            let count = answers.len();
+            let waiting = check(request, &mut answers);
+            if answers.len() > count {
+                uploaded.push(*id);
+            }
+            waiting
         });
+        for id in uploaded {
+            self.library.uploaded(id);
+        }
+
+        // the server is reachable, send what it missed
+        if !answers.is_empty() && !self.library.pending().is_empty() {
+            self.sync();
+        }
 
         for msg in answers {
             match msg {
@@ -190,6 +393,12 @@ impl TetrisApp {
                 tetris::networking::ServerAnswer::UploadResult(result) => {
                     self.request_highscores();
                 }
+                tetris::networking::ServerAnswer::LiveGames(data) => {
+                    self.live_games = data;
+                }
+                // broadcasts and spectators wait for their own answers
+                tetris::networking::ServerAnswer::LiveUpdated { .. } |
+                tetris::networking::ServerAnswer::LiveData { .. } => {}
             };
         }
     }
@@ -221,29 +430,76 @@ impl TetrisApp {
     }
 
     fn check_player_data(&mut self) {
-        // TODO non-emscripten: load from local settings file?
         #[cfg(target_os = "emscripten")] {
             if let Some(data) = self.load_player.consume(|data| {
                 String::from_utf8(data).ok().and_then(|data| tetris::networking::decode(&data))
             }, || { None }) {
                 if let Some(data) = data {
-                    self.player = data;
-                    self.config.level = self.player.level;
-                    self.renderer.ghost_piece = self.player.ghost;
-                    self.renderer.threed = self.player.render3d;
+                    self.set_player(data);
                 }
             }
         }
     }
 
+    fn set_player(&mut self, mut player: PlayerOptions) {
+        if let Some(keys) = player.legacy_keys.take() {
+            let keyboard = input::profile_mut(&mut player.profiles, input::KEYBOARD);
+            for (action, key) in [(input::Action::Left, keys.left), (input::Action::Right, keys.right),
+                                  (input::Action::Down, keys.drop), (input::Action::RotateLeft, keys.rotl),
+                                  (input::Action::RotateRight, keys.rotr), (input::Action::Pause, keys.pause)].iter() {
+                keyboard.bind(*action, input::Binding::Key(*key));
+            }
+        }
+        self.player = player;
+        self.config = tetris::Config::with_curve(self.player.curve.clone());
+        self.config.level = self.player.level;
+        self.renderer.ghost_piece = self.player.ghost;
+        self.renderer.threed = self.player.render3d;
+        let theme = self.theme().clone();
+        self.renderer.set_theme(&theme);
+        self.compare_renderer.set_theme(&theme);
+    }
+
     fn save_player_data(&mut self) {
         #[cfg(target_os = "emscripten")]
             emscripten_util::localstorage::store("TETRIS", "player", tetris::networking::encode(&self.player).as_bytes());
+        #[cfg(not(target_os = "emscripten"))] {
+            if let Some(path) = player_file() {
+                if let Err(err) = std::fs::write(&path, tetris::networking::encode(&self.player)) {
+                    println!("Can't save settings to {:?}: {}", path, err);
+                }
+            }
+        }
+    }
+
+    /// Passes the actions that start or stop with the changes on to the game
+    fn act(&mut self, changes: &[input::Change]) {
+        for (action, pressed) in self.input.actions(&self.player.profiles, changes) {
+            match self.ui.as_mut().unwrap() {
+                State::Game { ref mut game, ref mut paused, .. } |
+                State::Practice { game: Some(ref mut game), ref mut paused, .. } => match action {
+                    input::Action::Pause => if pressed { *paused = !*paused },
+                    // letting go still counts while paused
+                    _ if *paused && pressed => {}
+                    input::Action::Left => game.left(pressed),
+                    input::Action::Right => game.right(pressed),
+                    input::Action::Down => game.down(pressed),
+                    input::Action::RotateLeft => if pressed { game.rotate(false) },
+                    input::Action::RotateRight => if pressed { game.rotate(true) },
+                },
+                _ => {}
+            }
+        }
     }
 }
 
 impl webrunner::WebApp for TetrisApp {
-    fn new(windowsize: (u32, u32)) -> Self {
+    fn new(context: &tinygl::Context, windowsize: (u32, u32)) -> Self {
+        let themes = theme::load();
+        let (field, next, info) = layout(&themes[0], None);
+        let right = layout(&themes[0], Some(true));
+        let stats = themes[0].layout.stats;
+
         let mut ret = TetrisApp {
             windowsize: (windowsize.0 as f32, windowsize.1 as f32),
             fixedsize: (710.0, 650.0),
@@ -252,40 +508,57 @@ impl webrunner::WebApp for TetrisApp {
             ui: Some(State::MainMenu),
             config: tetris::Config::new(),
             player: PlayerOptions {
-                left: Keycode::Left as i32,
-                right: Keycode::Right as i32,
-                drop: Keycode::Down as i32,
-                rotl: Keycode::Y as i32,
-                rotr: Keycode::X as i32,
-                pause: Keycode::Return as i32,
+                legacy_keys: None,
+                profiles: input::default_profiles(),
                 level: 0,
                 name: String::from("Your name please?"),
                 sound: true,
                 ghost: true,
                 render3d: true,
+                curve: tetris::LevelCurve::Nes,
+                broadcast: false,
+                theme: themes[0].name.clone(),
             },
-            renderer: renderer::Renderer::new(
-                renderer::Rectangle::new(-150.0, -300.0, 300.0, 600.0),
-                renderer::Rectangle::new(220.0, -300.0, 120.0, 120.0),
-                renderer::Rectangle::new(220.0, -120.0, 180.0, 200.0),
-                renderer::Rectangle::new(-400.0, -300.0, 300.0, 400.0),
-                ZFAR
-            ),
-            rotl: false,
-            rotr: false,
+            context: context.clone(),
+            renderer: renderer::Renderer::new(context, field, next, info, stats, ZFAR),
+            compare_renderer: renderer::Renderer::new(context, right.0, right.1, right.2, stats, ZFAR),
+            sound: sound::Sound::new(),
+            input: input::Input::new(),
             server: client::ServerConfig::new(),
             requests: Vec::new(),
+            uploads: Vec::new(),
+            sync_timer: 0.0,
+            abandoned: Vec::new(),
             #[cfg(target_os = "emscripten")] load_idtag: emscripten_util::localstorage::load("TETRIS", "idtag"),
             #[cfg(target_os = "emscripten")] load_player: emscripten_util::localstorage::load("TETRIS", "player"),
             scores_global: Vec::new(),
             scores_local: Vec::new(),
             last_global: Vec::new(),
             last_local: Vec::new(),
+            live_games: Vec::new(),
+            live_timer: 0.0,
             replays: HashMap::new(),
+            library: library::Library::open(),
+            drills: tetris::practice::Drill::builtin(),
+            themes,
             fpswidget: appbase::fpswidget::FpsWidget::new(180),
         };
 
+        ret.compare_renderer.name = "compare";
+        ret.compare_renderer.show_stats = false;
+        let theme = ret.themes[0].clone();
+        ret.renderer.set_theme(&theme);
+        ret.compare_renderer.set_theme(&theme);
+
         #[cfg(not(target_os = "emscripten"))] ret.server.set_idtag(&client::gen_idtag());
+        #[cfg(not(target_os = "emscripten"))] {
+            let player = player_file().and_then(|path| std::fs::read_to_string(path).ok())
+                .and_then(|data| tetris::networking::decode(&data));
+            if let Some(player) = player {
+                ret.set_player(player);
+            }
+        }
+        #[cfg(target_os = "emscripten")] emscripten_util::fileload::start_upload(HTML_INPUT_REPLAY);
         ret.request_highscores();
 
         ret
@@ -302,66 +575,108 @@ impl webrunner::WebApp for TetrisApp {
     fn render(&mut self, dt: f32) {
         self.fpswidget.push(dt);
 
-        unsafe {
-            gl::ClearColor(0.0, 0.15, 0.2, 1.0);
-            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
-        }
+        tinygl::clear_color(&self.context, 0.0, 0.15, 0.2, 1.0);
+        tinygl::clear(&self.context, gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        self.renderer.render_backdrop(dt);
 
         self.renderer.clear();
+        self.compare_renderer.clear();
+
+        // two replays side by side share the screen
+        let compare = match self.ui.as_ref() {
+            Some(State::Replay(view)) => view.compare.is_some(),
+            _ => false,
+        };
+        let (field, next, info) = layout(self.theme(), if compare { Some(false) } else { None });
+        self.renderer.set_layout(field, next, info);
+        self.renderer.show_stats = !compare;
+        self.compare_renderer.ghost_piece = self.renderer.ghost_piece;
+        self.compare_renderer.threed = self.renderer.threed;
 
         // go through server responses
         self.process_finished_requests();
         self.check_idtag();
+        self.library.poll();
+
+        // retry the uploads now and then, in case the server only comes back later
+        self.sync_timer += dt;
+        if self.sync_timer > SYNC_INTERVAL && self.uploads.is_empty() {
+            self.sync();
+        }
+
+        for (broadcast, replay) in &mut self.abandoned {
+            broadcast.update(&self.server, replay, true, dt);
+        }
+        self.abandoned.retain(|(broadcast, _)| !broadcast.done());
 
         // Advance running game?
         let mut bg = false;
         self.ui = Some(match self.ui.take().unwrap() {
-            State::Game{mut game, paused, mut finished, mut dtime} => {
-                if !finished && !paused {
-                    // advance timer
-                    dtime -= dt.min(0.1) - FRAME;
-
-                    let mut frames = 1;
-                    while dtime < -FRAME {
-                        frames += 1;
-                        dtime += FRAME;
-                    }
-                    while dtime > FRAME {
-                        frames -= 1;
-                        dtime -= FRAME;
-                    }
-
-                    for i in 0..frames {
-                        if let Some(ret) = game.frame() {
-                            match ret {
-                                tetris::game::Outcome::Death => {
-                                    self.save(&game);
-                                    finished = true;
-                                }
-                                tetris::game::Outcome::HorizonalMove => {
-                                    if self.player.sound { play_sound("blip"); }
-                                }
-                                tetris::game::Outcome::Merge => {
-                                    if self.player.sound { play_sound("deww"); }
-                                }
-                                tetris::game::Outcome::Clear(..) => {
-                                    if self.player.sound { play_sound("dabbedi"); }
+            State::Game{mut game, paused, mut finished, mut dtime, mut broadcast} => {
+                if !finished && !paused && self.advance_game(&mut game, &mut dtime, dt) {
+                    self.save(&game);
+                    finished = true;
+                }
+                if let Some(broadcast) = broadcast.as_mut() {
+                    broadcast.update(&self.server, game.replay(), finished, dt);
+                }
+
+                self.renderer.set_pieces(&game.config().pieces);
+                self.renderer.set_state(game.timestamp(), game.snapshot());
+                State::Game{game, paused, finished, dtime, broadcast}
+            },
+            mut state @ State::Practice{..} => {
+                if let State::Practice{game, drill, result, dtime, paused, ..} = &mut state {
+                    match game.as_mut() {
+                        Some(game) => {
+                            // practice games don't get uploaded, they can be undone after all
+                            if !*paused && *result == tetris::practice::Judgement::Playing && !game.lost() {
+                                self.advance_game(game, dtime, dt);
+                                if let Some(drill) = drill.as_ref() {
+                                    *result = drill.judge(game);
                                 }
                             }
+                            self.renderer.set_pieces(&game.config().pieces);
+                            self.renderer.set_state(game.timestamp(), game.snapshot());
                         }
+                        None => bg = true,
                     }
                 }
-
-                self.renderer.set_state(game.timestamp(), game.snapshot());
-                State::Game{game, paused, finished, dtime}
+                state
+            },
+            State::Replay(mut view) => {
+                view.advance(dt);
+                self.renderer.set_pieces(&view.replayer.config().pieces);
+                self.renderer.set_state(view.replayer.timestamp(), view.replayer.snapshot());
+                if let Some(compare) = view.compare.as_ref() {
+                    self.compare_renderer.set_pieces(&compare.replayer.config().pieces);
+                    self.compare_renderer.set_state(compare.replayer.timestamp(), compare.replayer.snapshot());
+                }
+                State::Replay(view)
             },
-            State::Replay{mut replayer} => {
-                let adv = dt * replayer.speed;
-                replayer.advance(adv);
-                self.renderer.set_state(replayer.timestamp(), replayer.snapshot());
-                State::Replay{replayer}
+            State::Spectate(mut spectator) => {
+                spectator.update(&self.server, dt);
+                match spectator.replayer.as_ref() {
+                    Some(replayer) => {
+                        self.renderer.set_pieces(&replayer.config().pieces);
+                        self.renderer.set_state(replayer.timestamp(), replayer.snapshot());
+                    }
+                    None => bg = true,
+                }
+                State::Spectate(spectator)
+            },
+            state @ State::Highscores{..} => {
+                // keep the list of live games fresh while it's shown
+                if let State::Highscores{source: Scores::Live, ..} = state {
+                    self.live_timer += dt;
+                    if self.live_timer > LIVE_INTERVAL {
+                        self.live_timer = 0.0;
+                        self.requests.push(self.server.request_live_games());
+                    }
+                }
+                bg = true;
+                state
             },
-            State::Highscores{selected, sort_by_score, global} => { bg = true; State::Highscores{selected, sort_by_score, global} },
             State::MainMenu => { bg = true; State::MainMenu },
             State::PreGame{keyconfig} => { bg = true; State::PreGame{keyconfig} },
             State::About => { bg = true; State::About },
@@ -381,6 +696,7 @@ impl webrunner::WebApp for TetrisApp {
             self.renderer.render_background(dt, &proj);
         } else {
             self.renderer.render(&proj);
+            self.compare_renderer.render(&proj);
         }
     }
 
@@ -404,12 +720,43 @@ impl webrunner::WebApp for TetrisApp {
                         ret = State::PreGame{keyconfig: None};
                     }
                 });
+                self.window(ui, "mainmenu_practice", (-0.5 * mbw, mby), (mbw, mbh)).build(|| {
+                    ui.set_window_font_scale(2.0 * self.ui_scale);
+                    ui.set_cursor_pos([20.0 * self.ui_scale, 20.0 * self.ui_scale]);
+                    if ui.button_with_size("Practice", [(mbw - 40.0)* self.ui_scale, (mbh - 40.0) * self.ui_scale]) {
+                        self.check_player_data();
+                        ret = self.practice_menu();
+                    }
+                });
                 self.window(ui, "mainmenu_highscores", (mb2x, mby), (mbw, mbh)).build(|| {
                     ui.set_window_font_scale(2.0 * self.ui_scale);
                     ui.set_cursor_pos([20.0 * self.ui_scale, 20.0 * self.ui_scale]);
                     if ui.button_with_size("Highscores", [(mbw - 40.0) * self.ui_scale, (mbh - 40.0) * self.ui_scale]) {
-                        ret = State::Highscores{selected: None, sort_by_score: true, global: true};
+                        ret = State::Highscores {
+                            selected: None,
+                            sort_by_score: true,
+                            source: Scores::Global,
+                            file: String::new(),
+                            message: String::new(),
+                        };
+                    }
+                });
+                self.window(ui, "mainmenu_theme", (-0.5 * mbw, mby + mbh + 20.0), (mbw, 70.0)).build(|| {
+                    // the stored settings have the theme picked last time
+                    self.check_player_data();
+                    ui.set_window_font_scale(1.2 * self.ui_scale);
+                    ui.text("Theme");
+                    let names: Vec<&str> = self.themes.iter().map(|theme| theme.name.as_str()).collect();
+                    let mut current = names.iter().position(|name| *name == self.theme().name).unwrap_or(0);
+                    let width = ui.push_item_width((mbw - 20.0) * self.ui_scale);
+                    if ui.combo_simple_string("##mainmenutheme", &mut current, &names) {
+                        let theme = self.themes[current].clone();
+                        self.player.theme = theme.name.clone();
+                        self.renderer.set_theme(&theme);
+                        self.compare_renderer.set_theme(&theme);
+                        self.save_player_data();
                     }
+                    width.end();
                 });
                 if self.about_button(ui) {
                     ret = State::About;
@@ -417,7 +764,7 @@ impl webrunner::WebApp for TetrisApp {
                 ret
             },
 
-            State::Highscores{mut selected, mut sort_by_score, mut global} => {
+            State::Highscores{mut selected, mut sort_by_score, mut source, mut file, mut message} => {
                 let mut ret = None;
 
                 self.window(ui, "highscores_back", (mb2x, mby), (mbw, mbh)).build(|| {
@@ -430,10 +777,17 @@ impl webrunner::WebApp for TetrisApp {
 
                 self.window(ui, "highscores_list", (mb1x, mby - 150.0), (mb2x - mb1x, mbh + 300.0)).build(|| {
                     // get high-score list
-                    let scores = if global {
-                        if sort_by_score { &self.scores_global } else { &self.last_global }
-                    } else {
-                        if sort_by_score { &self.scores_local } else { &self.last_local }
+                    let scores = match source {
+                        Scores::Global => if sort_by_score { self.scores_global.clone() } else { self.last_global.clone() },
+                        Scores::Player => if sort_by_score { self.scores_local.clone() } else { self.last_local.clone() },
+                        Scores::Library => self.library.games(sort_by_score),
+                        Scores::Live => {
+                            let mut games = self.live_games.clone();
+                            if !sort_by_score {
+                                games.sort_by(|a, b| b.utc().cmp(&a.utc()));
+                            }
+                            games
+                        }
                     };
 
                     ui.set_window_font_scale(1.2 * self.ui_scale);
@@ -447,22 +801,107 @@ impl webrunner::WebApp for TetrisApp {
                         if idx < scores.len() {
                             ui.same_line_with_pos(0.5 * (mb2x - mb1x - 100.0) * self.ui_scale);
                             let replay_id = scores[idx].replay();
-                            let replay = self.replays.get(&replay_id);
-                            let btn = if replay.is_some() { "Replay##highscores" } else { "Downloading...##nighscores" };
-                            if ui.button_with_size(btn, [100.0 * self.ui_scale, 30.0 * self.ui_scale]) {
-                                if let Some(replay) = replay {
-                                    ret = Some(State::Replay {
-                                        replayer: tetris::replay::Replayer::new(replay)
-                                    });
+                            let replay = match source {
+                                Scores::Library => self.library.get(replay_id).map(|entry| &entry.replay),
+                                _ => self.replays.get(&replay_id),
+                            };
+                            if source == Scores::Live {
+                                if ui.button_with_size("Watch##highscores", [100.0 * self.ui_scale, 30.0 * self.ui_scale]) {
+                                    ret = Some(State::Spectate(live::Spectator::new(&self.server, &scores[idx])));
+                                }
+                            } else {
+                                let btn = if replay.is_some() { "Replay##highscores" } else { "Downloading...##nighscores" };
+                                if ui.button_with_size(btn, [100.0 * self.ui_scale, 30.0 * self.ui_scale]) {
+                                    if let Some(replay) = replay {
+                                        match replayview::ReplayView::new(replay) {
+                                            Ok(view) => ret = Some(State::Replay(view)),
+                                            Err(err) => message = format!("Can't play the replay: {}", err),
+                                        }
+                                    }
                                 }
                             }
                         }
                     }
 
                     ui.same_line_with_pos((mb2x - mb1x - 120.0) * self.ui_scale);
-                    let btn = if global { "Global##highscores" } else { "Local##highscores" };
+                    let btn = match source {
+                        Scores::Global => "Global##highscores",
+                        Scores::Player => "Mine##highscores",
+                        Scores::Library => "Offline##highscores",
+                        Scores::Live => "Live##highscores",
+                    };
                     if ui.button_with_size(btn, [100.0 * self.ui_scale, 30.0 * self.ui_scale]) {
-                        global = !global;
+                        source = match source {
+                            Scores::Global => Scores::Player,
+                            Scores::Player => Scores::Library,
+                            Scores::Library => Scores::Live,
+                            Scores::Live => Scores::Global,
+                        };
+                        // the live games get fetched right away
+                        self.live_timer = LIVE_INTERVAL;
+                        selected = None;
+                        message.clear();
+                    }
+
+                    if source == Scores::Library {
+                        let bw = [100.0 * self.ui_scale, 30.0 * self.ui_scale];
+                        let selected_id = selected.and_then(|idx| scores.get(idx)).map(|game| game.replay());
+
+                        ui.set_cursor_pos([20.0 * self.ui_scale, ui.cursor_pos()[1]]);
+                        #[cfg(not(target_os = "emscripten"))] {
+                            let width = ui.push_item_width(140.0 * self.ui_scale);
+                            ui.input_text("##highscores_file", &mut file).hint("replay file").build();
+                            width.end();
+                            ui.same_line();
+                            if ui.button_with_size("Import##highscores", bw) {
+                                let date = std::fs::metadata(&file).and_then(|meta| meta.modified())
+                                    .map(chrono::DateTime::<chrono::Utc>::from)
+                                    .unwrap_or_else(|_| chrono::Utc::now());
+                                message = match std::fs::read_to_string(&file) {
+                                    Ok(text) => self.library.import(&file, &text, date).map(|_| format!("Imported {}", file)).unwrap_or_else(|err| err),
+                                    Err(err) => format!("Can't read {}: {}", file, err),
+                                };
+                            }
+                        }
+                        #[cfg(target_os = "emscripten")] {
+                            // the file dialog opens from the invisible <input> on top of the button
+                            emscripten_util::set_overlay_position(HTML_INPUT_REPLAY, (ui.cursor_screen_pos()[0], ui.cursor_screen_pos()[1]), (bw[0], bw[1]));
+                            ui.button_with_size("Import##highscores", bw);
+                            if let Some((name, data, modified)) = emscripten_util::fileload::get_result_with_date(HTML_INPUT_REPLAY) {
+                                let date = chrono::TimeZone::timestamp_millis(&chrono::Utc, modified as i64);
+                                message = String::from_utf8(data).ok()
+                                    .ok_or_else(|| format!("{} is no replay", name))
+                                    .and_then(|text| self.library.import(&name, &text, date))
+                                    .map(|_| format!("Imported {}", name)).unwrap_or_else(|err| err);
+                            }
+                        }
+
+                        if let Some(id) = selected_id {
+                            ui.same_line();
+                            if ui.button_with_size("Export##highscores", bw) {
+                                let name = format!("tetris-{}.replay", id);
+                                let text = self.library.export(id).unwrap_or_default();
+                                #[cfg(not(target_os = "emscripten"))] {
+                                    let path = if file.is_empty() { name } else { file.clone() };
+                                    message = match std::fs::write(&path, text) {
+                                        Ok(()) => format!("Saved {}", path),
+                                        Err(err) => format!("Can't write {}: {}", path, err),
+                                    };
+                                }
+                                #[cfg(target_os = "emscripten")] emscripten_util::fileload::download(&name, &text.into_bytes());
+                            }
+                            ui.same_line();
+                            if ui.button_with_size("Delete##highscores", bw) {
+                                self.library.remove(id);
+                                selected = None;
+                            }
+                        }
+
+                        if !message.is_empty() {
+                            ui.text(message.as_str());
+                        }
+                    } else {
+                        #[cfg(target_os = "emscripten")] emscripten_util::set_overlay_position(HTML_INPUT_REPLAY, (0.0, 0.0), (0.0, 0.0));
                     }
 
                     ui.columns(5, "High-Scores List", true);
@@ -488,7 +927,7 @@ impl webrunner::WebApp for TetrisApp {
 
                             // start to download replay, if it isn't available yet
                             let replay = self.replays.get(&score.1.replay());
-                            if replay.is_none() {
+                            if replay.is_none() && source != Scores::Library && source != Scores::Live {
                                 self.requests.push(self.server.request_replay(score.1.replay()));
                             }
                         }
@@ -505,7 +944,11 @@ impl webrunner::WebApp for TetrisApp {
                     ret = Some(State::About);
                 }
 
-                ret.unwrap_or(State::Highscores{selected, sort_by_score, global})
+                if ret.is_some() {
+                    #[cfg(target_os = "emscripten")] emscripten_util::set_overlay_position(HTML_INPUT_REPLAY, (0.0, 0.0), (0.0, 0.0));
+                }
+
+                ret.unwrap_or(State::Highscores{selected, sort_by_score, source, file, message})
             }
             State::PreGame{mut keyconfig} => {
                 let mut ret = None;
@@ -516,11 +959,18 @@ impl webrunner::WebApp for TetrisApp {
                     if ui.button_with_size("Start", [(mbw - 40.0)* self.ui_scale, (mbh - 40.0) * self.ui_scale]) {
                         self.renderer.gen_new_colors();
                         self.save_player_data();
+                        let game = tetris::game::Game::new(&self.config);
+                        let broadcast = if self.player.broadcast {
+                            Some(live::Broadcast::start(&self.server, &self.player.name, game.replay()))
+                        } else {
+                            None
+                        };
                         ret = Some(State::Game {
-                            game: tetris::game::Game::new(&self.config),
+                            game,
                             paused: false,
                             finished: false,
                             dtime: 0.0,
+                            broadcast,
                         });
                     }
                 });
@@ -534,7 +984,7 @@ impl webrunner::WebApp for TetrisApp {
                     }
                 });
 
-                let optionswin = ((mb1x + mbw, mby - 120.0), (mb2x - mb1x - mbw, mbh + 240.0));
+                let optionswin = ((mb1x + mbw, mby - 200.0), (mb2x - mb1x - mbw, mbh + 440.0));
                 self.window(ui, "pregame_options", optionswin.0, optionswin.1).build(|| {
                     ui.set_window_font_scale(1.5 * self.ui_scale);
                     if keyconfig.is_none() {
@@ -563,25 +1013,107 @@ impl webrunner::WebApp for TetrisApp {
 
                         ui.set_cursor_pos([20.0 * self.ui_scale, 255.0 * self.ui_scale]);
                         ui.checkbox("Play Sounds", &mut self.player.sound);
+                        ui.same_line();
+                        ui.checkbox("Go Live", &mut self.player.broadcast);
+
+                        ui.set_cursor_pos([20.0 * self.ui_scale, 300.0 * self.ui_scale]);
+                        ui.text("Speed Curve");
+
+                        ui.set_cursor_pos([20.0 * self.ui_scale, 325.0 * self.ui_scale]);
+                        ui.push_item_width(mb2x - mb1x - mbw);
+                        let mut curve = match self.config.curve {
+                            tetris::LevelCurve::Pal => 1,
+                            tetris::LevelCurve::Guideline => 2,
+                            tetris::LevelCurve::TwentyG => 3,
+                            _ => 0,
+                        };
+                        if ui.combo_simple_string("##pregamecurve", &mut curve, &CURVES) {
+                            let level = self.config.level;
+                            self.config = tetris::Config {
+                                level,
+                                width: self.config.width,
+                                height: self.config.height,
+                                pieces: self.config.pieces.clone(),
+                                ..tetris::Config::with_curve(match curve {
+                                    1 => tetris::LevelCurve::Pal,
+                                    2 => tetris::LevelCurve::Guideline,
+                                    3 => tetris::LevelCurve::TwentyG,
+                                    _ => tetris::LevelCurve::Nes,
+                                })
+                            };
+                        }
+                        self.player.curve = self.config.curve.clone();
+
+                        ui.set_cursor_pos([20.0 * self.ui_scale, 370.0 * self.ui_scale]);
+                        ui.text("Pieces");
+
+                        ui.set_cursor_pos([20.0 * self.ui_scale, 395.0 * self.ui_scale]);
+                        ui.push_item_width(mb2x - mb1x - mbw);
+                        let mut pieces = match self.config.pieces {
+                            tetris::PieceSet::Pentomino => 1,
+                            tetris::PieceSet::Big => 2,
+                            _ => 0,
+                        };
+                        if ui.combo_simple_string("##pregamepieces", &mut pieces, &PIECE_SETS) {
+                            self.config.pieces = match pieces {
+                                1 => tetris::PieceSet::Pentomino,
+                                2 => tetris::PieceSet::Big,
+                                _ => tetris::PieceSet::Tetromino,
+                            };
+                        }
+
+                        ui.set_cursor_pos([20.0 * self.ui_scale, 440.0 * self.ui_scale]);
+                        ui.text("Board Size");
+
+                        ui.set_cursor_pos([20.0 * self.ui_scale, 465.0 * self.ui_scale]);
+                        ui.push_item_width(0.5 * (mb2x - mb1x - mbw) - 25.0 * self.ui_scale);
+                        ui.slider("##pregamewidth", 4, 30, &mut self.config.width);
+                        ui.same_line();
+                        ui.slider("##pregameheight", 10, 40, &mut self.config.height);
                     } else {
                         let mut keynum = *keyconfig.as_ref().unwrap();
+                        let actions = input::ACTIONS.len() as i32;
+                        let scale = self.ui_scale;
 
-                        {
-                            let mut keychoice = |y, name, key: &mut i32, index, scale| {
-                                ui.set_cursor_pos([30.0 * scale, y * scale]);
-                                ui.text(name);
-                                ui.set_cursor_pos([170.0 * scale, y * scale - 10.0]);
-                                let buttonstr = if keynum == index { String::from("...") } else { format!("{:?}", Keycode::from_i32(*key).unwrap_or(Keycode::Escape)) };
-                                if ui.button_with_size(format!("{}", buttonstr), [100.0 * scale, 30.0 * scale]) {
+                        // keyboard and the controller that was used last, or the general profile
+                        let controller = self.input.shown_controller();
+                        let devices = [input::KEYBOARD, controller.as_str()];
+
+                        ui.set_window_font_scale(1.2 * scale);
+                        for (column, device) in devices.iter().enumerate() {
+                            ui.set_cursor_pos([(120.0 + 90.0 * column as f32) * scale, 20.0 * scale]);
+                            ui.text(device);
+                        }
+
+                        for (row, (action, name)) in input::ACTIONS.iter().enumerate() {
+                            let y = 60.0 + 40.0 * row as f32;
+                            ui.set_cursor_pos([20.0 * scale, y * scale]);
+                            ui.text(name);
+
+                            for (column, device) in devices.iter().enumerate() {
+                                let index = row as i32 + column as i32 * actions;
+                                let buttonstr = if keynum == index {
+                                    String::from("...")
+                                } else {
+                                    input::profile(&self.player.profiles, device).map_or(String::new(), |profile| {
+                                        profile.bindings(*action).iter().map(|binding| binding.name()).collect::<Vec<String>>().join(" ")
+                                    })
+                                };
+                                ui.set_cursor_pos([(120.0 + 90.0 * column as f32) * scale, y * scale - 8.0]);
+                                if ui.button_with_size(format!("{}##binding{}", buttonstr, index), [85.0 * scale, 30.0 * scale]) {
                                     keynum = if keynum == index { -1 } else { index };
                                 }
-                            };
-                            keychoice(30.0,  "Left",         &mut self.player.left,  0, self.ui_scale);
-                            keychoice(70.0,  "Right",        &mut self.player.right, 1, self.ui_scale);
-                            keychoice(110.0, "Down",         &mut self.player.drop,  2, self.ui_scale);
-                            keychoice(150.0, "Rotate Left",  &mut self.player.rotl,  3, self.ui_scale);
-                            keychoice(190.0, "Rotate Right", &mut self.player.rotr,  4, self.ui_scale);
-                            keychoice(230.0, "Pause",        &mut self.player.pause, 5, self.ui_scale);
+                            }
+                        }
+
+                        ui.set_cursor_pos([20.0 * scale, 320.0 * scale]);
+                        ui.text("Stick Deadzone (%)");
+
+                        ui.set_cursor_pos([20.0 * scale, 345.0 * scale]);
+                        ui.push_item_width(mb2x - mb1x - mbw);
+                        let mut deadzone = input::profile(&self.player.profiles, &controller).map_or(50, |profile| profile.deadzone);
+                        if ui.slider("##pregamedeadzone", 10, 90, &mut deadzone) {
+                            input::profile_mut(&mut self.player.profiles, &controller).deadzone = deadzone;
                         }
 
                         keyconfig = Some(keynum);
@@ -589,7 +1121,8 @@ impl webrunner::WebApp for TetrisApp {
 
                     let buttonstr = if keyconfig.is_some() { "Game Settings" } else { "Controls" };
                     let buttonw = 200.0;
-                    ui.set_cursor_pos([0.5 * self.ui_scale * ((optionswin.1).0 - buttonw), 295.0 * self.ui_scale]);
+                    ui.set_window_font_scale(1.5 * self.ui_scale);
+                    ui.set_cursor_pos([0.5 * self.ui_scale * ((optionswin.1).0 - buttonw), ((optionswin.1).1 - 60.0) * self.ui_scale]);
                     if ui.button_with_size(format!("{}", buttonstr), [buttonw * self.ui_scale, 40.0 * self.ui_scale]) {
                         keyconfig = match keyconfig {
                             None => Some(-1),
@@ -604,7 +1137,7 @@ impl webrunner::WebApp for TetrisApp {
 
                 ret.unwrap_or(State::PreGame{keyconfig})
             }
-            State::Game{game, paused, mut finished, dtime} => {
+            State::Game{game, paused, mut finished, dtime, broadcast} => {
                 self.renderer.do_ui(ui, self.ui_center, self.ui_scale);
                 let mut ret = None;
 
@@ -627,9 +1160,7 @@ impl webrunner::WebApp for TetrisApp {
                     ui.new_line();
 
                     if finished && ui.button_with_size("Watch Replay##aftergamefinished", [140.0 * self.ui_scale, 40.0 * self.ui_scale]) {
-                        ret = Some(State::Replay {
-                            replayer: tetris::replay::Replayer::new(game.replay())
-                        });
+                        ret = replayview::ReplayView::new(game.replay()).ok().map(State::Replay);
                     }
                 });
                 border.pop();
@@ -642,39 +1173,274 @@ impl webrunner::WebApp for TetrisApp {
                     });
                 }
 
-                ret.unwrap_or(State::Game{game, paused, finished, dtime})
+                match ret {
+                    Some(state) => {
+                        // spectators still get told that the game is over
+                        if let Some(broadcast) = broadcast {
+                            self.abandoned.push((broadcast, game.replay().clone()));
+                        }
+                        state
+                    }
+                    None => State::Game{game, paused, finished, dtime, broadcast},
+                }
             }
-            State::Replay{mut replayer} => {
+            State::Replay(mut view) => {
                 self.renderer.do_ui(ui, self.ui_center, self.ui_scale);
+                self.compare_renderer.do_ui(ui, self.ui_center, self.ui_scale);
                 let mut ret = None;
+                let scale = self.ui_scale;
 
-                self.window(ui, "Replayer UI#window", (200.0, 100.0), (200.0, 200.0)).build(|| {
-                    ui.set_window_font_scale(1.2 * self.ui_scale);
-                    ui.text("Speed");
-                    ui.slider("##replayspeedslider", -20.0, 20.0, &mut replayer.speed);
-                    ui.new_line();
+                // side by side the controls go between the boards
+                let (pos, size) = if view.compare.is_some() { ((-95.0, -110.0), (190.0, 435.0)) } else { ((200.0, 85.0), (200.0, 240.0)) };
+                self.window(ui, "Replayer UI#window", pos, size).build(|| {
+                    ui.set_window_font_scale(scale);
+                    let width = (size.0 - 20.0) * scale;
+                    let bw = [0.25 * width - 4.0, 22.0 * scale];
 
-                    ui.text("Time");
-                    let mut curr = replayer.frame();
-                    ui.slider("##replaytimeslider", 0.0, replayer.length(), &mut curr);
-                    replayer.jump(curr);
+                    ui.text("Speed");
+                    let item_width = ui.push_item_width(width);
+                    ui.slider("##replayspeedslider", -20.0, 20.0, &mut view.replayer.speed);
+                    item_width.end();
+
+                    ui.text(format!("Time {:.1}s  Frame {}", view.replayer.frame(), view.replayer.timestamp()));
+                    view.timeline_ui(ui, [width, 16.0 * scale]);
+
+                    if ui.button_with_size("|<##replay", bw) { view.step_piece(false); }
+                    ui.same_line();
+                    if ui.button_with_size("<##replay", bw) { view.step_frames(-1); }
+                    ui.same_line();
+                    if ui.button_with_size(">##replay", bw) { view.step_frames(1); }
+                    ui.same_line();
+                    if ui.button_with_size(">|##replay", bw) { view.step_piece(true); }
+
+                    if ui.button_with_size("A##replayloop", bw) { view.mark(false); }
+                    ui.same_line();
+                    if ui.button_with_size("B##replayloop", bw) { view.mark(true); }
+                    ui.same_line();
+                    if ui.button_with_size("Clear##replayloop", [2.0 * bw[0] + 8.0, bw[1]]) { view.clear_loop(); }
+
+                    ui.checkbox("Keys##replay", &mut view.inputs);
+                    if view.inputs {
+                        ui.same_line();
+                        view.inputs_ui(ui);
+                    }
 
-                    ui.new_line();
+                    let hw = [0.5 * width - 4.0, bw[1]];
+                    if ui.button_with_size("Analysis##replay", hw) {
+                        view.analysis = !view.analysis;
+                    }
+                    ui.same_line();
+                    if view.compare.is_some() {
+                        if ui.button_with_size("Single##replay", hw) {
+                            view.compare = None;
+                        }
+                    } else if ui.button_with_size("Compare##replay", hw) {
+                        view.choosing = !view.choosing;
+                    }
+                    if let Some(compare) = view.compare.as_ref() {
+                        ui.text(format!("vs. {}", compare.name));
+                    }
 
-                    if ui.button_with_size("Back##tomainmenu", [140.0 * self.ui_scale, 40.0 * self.ui_scale]) {
+                    if ui.button_with_size("Back##tomainmenu", [width, 30.0 * scale]) {
                         ret = Some(State::MainMenu);
                     }
                 });
 
-                if replayer.paused {
-                    self.window(ui, "pausedplayinggame", (-100.0, -50.0), (200.0, 100.0)).build(|| {
+                if view.choosing {
+                    let mut chosen = None;
+                    self.window(ui, "replay_compare", (-150.0, -200.0), (300.0, 400.0)).build(|| {
+                        ui.set_window_font_scale(1.1 * scale);
+                        ui.text("Compare with");
+                        ui.separator();
+                        for game in self.library.games(true) {
+                            let label = format!("{}  {}  {}##compare{}", game.score(), game.name(), game.time_str(), game.replay());
+                            if ui.selectable(label) {
+                                chosen = Some(game);
+                            }
+                        }
+                    });
+                    if let Some(game) = chosen {
+                        if let Some(entry) = self.library.get(game.replay()) {
+                            if let Err(err) = view.compare_with(&format!("{} ({})", game.name(), game.score()), &entry.replay) {
+                                println!("Can't compare with {}: {}", game.name(), err);
+                            }
+                        }
+                        view.choosing = false;
+                    }
+                }
+
+                if view.analysis {
+                    let timeline = &view.timeline;
+                    let timestamp = view.replayer.timestamp();
+                    self.window(ui, "replay_analysis", (-400.0, 110.0), (300.0, 215.0)).build(|| {
+                        ui.set_window_font_scale(1.1 * self.ui_scale);
+
+                        if let Some(p) = timeline.at(timestamp) {
+                            ui.text(format!("Piece #{}: {}", p.index + 1, p.piece.symbol()));
+                            ui.text(format!("Placement: {:.2}s", p.placement_time() as f32 / timeline.fps));
+                            ui.text(format!("Inputs: {} (optimal {})", p.inputs, p.optimal_inputs));
+                            ui.text(format!("Height: {}  Holes: {}  Well: {}", p.height, p.holes, p.well));
+                            ui.text(if p.tetris_ready { "Tetris ready" } else { "Not tetris ready" });
+                        }
+
+                        ui.separator();
+                        let summary = timeline.summary();
+                        ui.text(format!("Avg. placement: {:.2}s", summary.average_placement_time / timeline.fps));
+                        ui.text(format!("Finesse faults: {} / {} inputs", summary.finesse_faults, summary.inputs));
+                        ui.text(format!("Burned: {}  Tetris lines: {}", summary.burned_lines, summary.tetris_lines));
+                        ui.text(format!("Tetris ready: {}%", (100.0 * summary.tetris_readiness) as i32));
+                    });
+                }
+
+                if view.replayer.paused {
+                    let pos = if view.compare.is_some() { (-95.0, -200.0) } else { (-100.0, -50.0) };
+                    self.window(ui, "pausedplayinggame", pos, (200.0, 100.0)).build(|| {
                         ui.set_window_font_scale(2.5 * self.ui_scale);
                         ui.new_line(); ui.text("  Paused")
                     });
                 }
 
-                ret.unwrap_or(State::Replay{replayer})
+                ret.unwrap_or(State::Replay(view))
             },
+            State::Spectate(spectator) => {
+                self.renderer.do_ui(ui, self.ui_center, self.ui_scale);
+                let mut ret = None;
+
+                self.window(ui, "Spectator UI#window", (200.0, 150.0), (200.0, 200.0)).build(|| {
+                    ui.set_window_font_scale(1.5 * self.ui_scale);
+                    ui.text(&spectator.name);
+                    ui.text(if spectator.finished { "Finished" } else { "Live" });
+                    if let Some(error) = spectator.error.as_ref() {
+                        ui.text_wrapped(format!("Can't follow the game: {}", error));
+                    } else if spectator.replayer.is_none() {
+                        ui.text(if spectator.finished { "Game is gone" } else { "Waiting..." });
+                    }
+
+                    ui.new_line();
+                    if ui.button_with_size("Back##spectate", [140.0 * self.ui_scale, 40.0 * self.ui_scale]) {
+                        ret = Some(State::Highscores {
+                            selected: None,
+                            sort_by_score: true,
+                            source: Scores::Live,
+                            file: String::new(),
+                            message: String::new(),
+                        });
+                    }
+                });
+
+                ret.unwrap_or(State::Spectate(spectator))
+            },
+            mut state @ State::Practice{..} => {
+                let mut ret = None;
+
+                if let State::Practice{game, position, drill, result, slots, board, error, paused, ..} = &mut state {
+                    if let Some(game) = game.as_mut() {
+                        self.renderer.do_ui(ui, self.ui_center, self.ui_scale);
+
+                        self.window(ui, "practice_ui", (200.0, 85.0), (200.0, 240.0)).build(|| {
+                            ui.set_window_font_scale(1.2 * self.ui_scale);
+                            let bw = [140.0 * self.ui_scale, 25.0 * self.ui_scale];
+
+                            if let Some(drill) = drill.as_ref() {
+                                ui.text(&drill.goal.to_string());
+                            }
+
+                            if ui.button_with_size("Undo##practice", bw) {
+                                game.undo();
+                                *result = judge(drill, game);
+                            }
+                            if ui.button_with_size("Restart##practice", bw) {
+                                *game = practice_game(&self.config, position, drill);
+                                *result = judge(drill, game);
+                            }
+
+                            for (i, slot) in slots.iter_mut().enumerate() {
+                                if ui.button_with_size(format!("Save {}##practice", i + 1), [68.0 * self.ui_scale, bw[1]]) {
+                                    *slot = Some(game.clone());
+                                }
+                                ui.same_line();
+                                if ui.button_with_size(format!("Load {}##practice", i + 1), [68.0 * self.ui_scale, bw[1]]) {
+                                    if let Some(saved) = slot.as_ref() {
+                                        *game = saved.clone();
+                                        *result = judge(drill, game);
+                                    }
+                                }
+                            }
+
+                            if ui.button_with_size("Back##practice", bw) {
+                                ret = Some(self.practice_menu());
+                            }
+                        });
+
+                        let status = match *result {
+                            tetris::practice::Judgement::Success => Some("  Success!"),
+                            tetris::practice::Judgement::Failed => Some("   Failed"),
+                            tetris::practice::Judgement::Playing if game.lost() => Some("Game Over"),
+                            tetris::practice::Judgement::Playing if *paused => Some("  Paused"),
+                            tetris::practice::Judgement::Playing => None,
+                        };
+                        if let Some(status) = status {
+                            self.window(ui, "pausedplayinggame", (-100.0, -50.0), (200.0, 100.0)).build(|| {
+                                ui.set_window_font_scale(2.5 * self.ui_scale);
+                                ui.new_line(); ui.text(status)
+                            });
+                        }
+                    } else {
+                        self.window(ui, "practice_back", (mb2x, mby), (mbw, mbh)).build(|| {
+                            ui.set_window_font_scale(2.0 * self.ui_scale);
+                            ui.set_cursor_pos([20.0 * self.ui_scale, 20.0 * self.ui_scale]);
+                            if ui.button_with_size("Back", [(mbw - 40.0)* self.ui_scale, (mbh - 40.0) * self.ui_scale]) {
+                                ret = Some(State::MainMenu);
+                            }
+                        });
+
+                        self.window(ui, "practice_list", (mb1x, mby - 150.0), (mb2x - mb1x, mbh + 300.0)).build(|| {
+                            ui.set_window_font_scale(1.2 * self.ui_scale);
+                            let bw = [(mb2x - mb1x - 40.0) * self.ui_scale, 30.0 * self.ui_scale];
+                            let mut start = None;
+
+                            ui.set_cursor_pos([20.0 * self.ui_scale, 20.0 * self.ui_scale]);
+                            if ui.button_with_size("Free Play##practice", bw) {
+                                start = Some((position.clone(), None));
+                            }
+
+                            ui.separator();
+                            ui.text("Drills");
+                            for (i, drill) in self.drills.iter().enumerate() {
+                                ui.set_cursor_pos([20.0 * self.ui_scale, ui.cursor_pos()[1]]);
+                                if ui.button_with_size(format!("{} ({})##drill{}", drill.name, drill.goal, i), bw) {
+                                    start = Some((drill.position.clone(), Some(drill.clone())));
+                                }
+                            }
+
+                            ui.separator();
+                            ui.text("Your own board, as fumen or text grid:");
+                            ui.set_cursor_pos([20.0 * self.ui_scale, ui.cursor_pos()[1]]);
+                            ui.input_text_multiline("##practiceboard", board, [bw[0], 100.0 * self.ui_scale]).build();
+                            ui.set_cursor_pos([20.0 * self.ui_scale, ui.cursor_pos()[1]]);
+                            if ui.button_with_size("Play this board##practice", bw) {
+                                match parse_board(board, &self.config) {
+                                    Ok(position) => start = Some((position, None)),
+                                    Err(err) => *error = err,
+                                }
+                            }
+                            if !error.is_empty() {
+                                ui.text(error.as_str());
+                            }
+
+                            if let Some((position, drill)) = start {
+                                ret = Some(self.practice(position, drill));
+                            }
+                        });
+
+                        if self.about_button(ui) {
+                            ret = Some(State::About);
+                        }
+                    }
+                }
+
+                ret.unwrap_or(state)
+            }
             State::About => {
                 let sz = (300.0, 320.0);
                 let mut ret = State::About;
@@ -712,76 +1478,80 @@ impl webrunner::WebApp for TetrisApp {
     }
 
     fn event(&mut self, event: &Event) {
-        match event {
-            Event::KeyDown{keycode, keymod, .. } => {
-                let ctrl = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+        if let Event::KeyDown{keycode, keymod, .. } = event {
+            let ctrl = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
 
-                fn adjust_speed(value: &mut f32, delta: f32) {
-                    let v = value.signum() * value.abs().sqrt() + delta;
-                    *value = v.signum() * v.powi(2).min(20.0);
+            fn adjust_speed(value: &mut f32, delta: f32) {
+                let v = value.signum() * value.abs().sqrt() + delta;
+                *value = v.signum() * v.powi(2).min(20.0);
+            }
+
+            // undo and save states, only while practicing
+            if let State::Practice{game: Some(ref mut game), ref drill, ref mut result, ref mut slots, ..} = self.ui.as_mut().unwrap() {
+                let slot = match keycode.unwrap() {
+                    Keycode::Num1 => Some(0),
+                    Keycode::Num2 => Some(1),
+                    Keycode::Num3 => Some(2),
+                    _ => None,
+                };
+                if keycode.unwrap() == Keycode::Backspace {
+                    game.undo();
+                    *result = judge(drill, game);
+                } else if let Some(slot) = slot.filter(|slot| *slot < slots.len()) {
+                    if ctrl {
+                        slots[slot] = Some(game.clone());
+                    } else if let Some(saved) = slots[slot].as_ref() {
+                        *game = saved.clone();
+                        *result = judge(drill, game);
+                    }
                 }
+            }
 
-                match &mut self.ui.as_mut().unwrap() {
-                    State::Game{ref mut game, ref mut paused, ..} => {
-                        let key =  keycode.unwrap() as i32;
-                        if !*paused {
-                            if key == self.player.left { game.left(true) }
-                            if key == self.player.right { game.right(true) }
-                            if key == self.player.drop { game.down(true) }
-                            if key == self.player.rotl && !self.rotl {
-                                self.rotl = true;
-                                if self.player.sound { play_sound("rerr"); }
-                                game.rotate(false);
-                            }
-                            if key == self.player.rotr && !self.rotr {
-                                self.rotr = true;
-                                if self.player.sound { play_sound("rerr"); }
-                                game.rotate(true);
-                            }
-                        }
-                        if key == self.player.pause { *paused = !*paused }
-                    }
-                    State::Replay { ref mut replayer } => match keycode.unwrap() {
-                        Keycode::Left => replayer.advance(if ctrl { -10.0 } else { -1.0 }),
-                        Keycode::Right => replayer.advance(if ctrl { 10.0 } else { 1.0 }),
-                        Keycode::Up => adjust_speed(&mut replayer.speed, 0.1),
-                        Keycode::Down => adjust_speed(&mut replayer.speed, -0.1),
-                        Keycode::Return => replayer.paused = !replayer.paused,
-                        _ => {}
-                    }
-                    State::PreGame { ref mut keyconfig } => {
-                        if keyconfig.is_some() {
-                            if let Some(num) = keyconfig.replace(-1) {
-                                match num {
-                                    0 => self.player.left = keycode.unwrap() as i32,
-                                    1 => self.player.right = keycode.unwrap() as i32,
-                                    2 => self.player.drop = keycode.unwrap() as i32,
-                                    3 => self.player.rotl = keycode.unwrap() as i32,
-                                    4 => self.player.rotr = keycode.unwrap() as i32,
-                                    5 => self.player.pause = keycode.unwrap() as i32,
-                                    _ => {}
-                                }
-                            }
-                        }
-;                    }
+            if let State::Replay(ref mut view) = self.ui.as_mut().unwrap() {
+                match keycode.unwrap() {
+                    Keycode::Left => view.replayer.advance(if ctrl { -10.0 } else { -1.0 }),
+                    Keycode::Right => view.replayer.advance(if ctrl { 10.0 } else { 1.0 }),
+                    Keycode::Up => adjust_speed(&mut view.replayer.speed, 0.1),
+                    Keycode::Down => adjust_speed(&mut view.replayer.speed, -0.1),
+                    Keycode::Return => view.replayer.paused = !view.replayer.paused,
+                    // frames, or whole pieces with ctrl
+                    Keycode::Comma if ctrl => view.step_piece(false),
+                    Keycode::Period if ctrl => view.step_piece(true),
+                    Keycode::Comma => view.step_frames(-1),
+                    Keycode::Period => view.step_frames(1),
+                    Keycode::A => view.mark(false),
+                    Keycode::B => view.mark(true),
                     _ => {}
                 }
-            },
-            Event::KeyUp{keycode, .. } => {
-                match &mut self.ui.as_mut().unwrap() {
-                    State::Game { ref mut game, .. } => {
-                        let key =  keycode.unwrap() as i32;
-                        if key == self.player.left { game.left(false) }
-                        if key == self.player.right { game.right(false) }
-                        if key == self.player.drop { game.down(false) }
-                        if key == self.player.rotl { self.rotl = false; }
-                        if key == self.player.rotr { self.rotr = false; }
-                    },
-                    _ => {}
+                view.sync();
+            }
+        }
+
+        // before the event makes its controller the last one used
+        let controller = self.input.shown_controller();
+        let changes = self.input.changes(&self.player.profiles, event);
+
+        // the controls page waits for a key or button to bind, into the profile of its column
+        if let State::PreGame { keyconfig: Some(ref mut keynum) } = self.ui.as_mut().unwrap() {
+            if *keynum >= 0 {
+                let actions = input::ACTIONS.len() as i32;
+                let keyboard = *keynum < actions;
+                if let Some(change) = changes.iter().find(|change| change.pressed && (change.device == input::KEYBOARD) == keyboard) {
+                    let action = input::ACTIONS[(*keynum % actions) as usize].0;
+                    let device = if keyboard { input::KEYBOARD } else { controller.as_str() };
+                    input::profile_mut(&mut self.player.profiles, device).bind(action, change.binding.clone());
+                    *keynum = -1;
                 }
-            },
-            _ => {}
+                return;
+            }
         }
+
+        self.act(&changes);
+    }
+
+    fn controller(&mut self, instance: u32, name: Option<&str>) {
+        let changes = self.input.controller(instance, name);
+        self.act(&changes);
     }
 }
 
@@ -7,7 +7,10 @@ use rand::{Rng,SeedableRng};
 use tetris::piece;
 use tetris::state::*;
 
+use theme::{self, Theme};
+
 #[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Serialize, Deserialize)]
 pub struct Rectangle {
     pub x: f32,
     pub y: f32,
@@ -39,6 +42,7 @@ struct FallingPiece {
 }
 
 pub struct Renderer {
+    context: tinygl::Context,
     timestamp: i32,
     state: Option<Snapshot>,
 
@@ -50,36 +54,55 @@ pub struct Renderer {
 
     pub ghost_piece: bool,
     pub threed: bool,
+    pub show_stats: bool,
+    /// tells the imgui windows of several renderers apart
+    pub name: &'static str,
     tile_size: f32,
 
-    square: tinygl::VertexBuffer,
-    cube_vertices: tinygl::VertexBuffer,
-    cube_normals: tinygl::VertexBuffer,
-    cube_indices: tinygl::IndexBuffer,
+    square: tinygl::Mesh,
+    cube: tinygl::Mesh,
+    // the slot of the per block buffer in the cube mesh
+    cube_blocks: usize,
     program: tinygl::Program,
     block_program: tinygl::Program,
+    backdrop_program: tinygl::Program,
+
+    theme: Theme,
 
-    piece_colors: Vec<[Vector3<f32>; 7]>,
+    pieces: tetris::PieceSet,
+    // per level, indexed by piece::Type::to_int
+    piece_colors: Vec<Vec<Vector3<f32>>>,
 
     background: Vec<FallingPiece>,
     background_timer: f32,
+    backdrop_time: f32,
 }
 
+// one buffer per piece type, indexed by piece::Type::to_int
 struct BlockBuffers {
-    data: [Vec<f32>; 7],
+    data: Vec<Vec<f32>>,
 }
 
 impl BlockBuffers {
     fn new() -> Self {
         BlockBuffers {
-            data: [Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new(), Vec::new()],
+            data: Vec::new(),
         }
     }
 
-    fn vertex_size() -> i32 { 5 }
+    fn layout() -> tinygl::VertexLayout {
+        tinygl::VertexLayout::new()
+            .attribute("position", tinygl::Format::Vec3)
+            .attribute("size", tinygl::Format::Float)
+            .attribute("alpha", tinygl::Format::Float)
+            .instanced(1)
+    }
 
     fn block(&mut self, block: piece::Type, pos: (f32, f32, f32), sz: f32, alpha: f32) {
-        let idx = block as usize;
+        let idx = block.to_int() as usize;
+        if self.data.len() <= idx {
+            self.data.resize(idx + 1, Vec::new());
+        }
         self.data[idx].push(pos.0);
         self.data[idx].push(pos.1);
         self.data[idx].push(-pos.2);
@@ -88,25 +111,14 @@ impl BlockBuffers {
     }
 
     fn piece(&mut self, piece: piece::Piece, x: f32, y: f32, z: f32, sz: f32, ymax: f32, alpha: f32) {
-        let blocks = piece.blocks();
-        for i in 0..4 {
-            for j in 0..4 {
-                let y = y - j as f32 * sz;
-                if y < ymax && blocks[4*j + i] {
-                    self.block(piece.get_type(), (x + i as f32 * sz, y, z), sz, alpha);
-                }
+        for (i, j) in piece.cells() {
+            let y = y - j as f32 * sz;
+            if y < ymax {
+                self.block(piece.get_type(), (x + i as f32 * sz, y, z), sz, alpha);
             }
         }
     }
 
-    fn vbo(&self, idx: usize) -> Option<(tinygl::VertexBuffer, usize)> {
-        if self.data[idx].is_empty() {
-            None
-        } else {
-            let count = self.data[idx].len() / Self::vertex_size() as usize;
-            Some((tinygl::VertexBuffer::from(&self.data[idx]), count))
-        }
-    }
 }
 
 impl Renderer {
@@ -114,17 +126,37 @@ impl Renderer {
         self.program.uniform("pos", tinygl::Uniform::Vec3(Vector3::new(pos.x, pos.y, z)));
         self.program.uniform("size", tinygl::Uniform::Vec2(Vector2::new(pos.w, pos.h)));
         self.program.uniform("color", tinygl::Uniform::Vec4(color));
-        unsafe { gl::DrawArrays(gl::TRIANGLES, 0, 6) }
+        if let Err(err) = self.square.draw(&self.program, gl::TRIANGLES) {
+            self.context.warn(err);
+        }
     }
 
     fn draw_block(&self, buffers: &mut BlockBuffers, piece: piece::Type, x: i32, y: i32, z: f32, alpha: f32) {
+        let field = self.field();
         buffers.block(piece,
-                      (self.pos_field.x + self.tile_size * x as f32,
-                      self.pos_field.bottom() - self.tile_size * (y + 1) as f32, z),
+                      (field.x + self.tile_size * x as f32,
+                      field.bottom() - self.tile_size * (y + 1) as f32, z),
                       self.tile_size, alpha
         );
     }
 
+    /// Distance between the rows of the piece statistics and their font size, seven pieces fill the area
+    fn stats_layout(&self) -> (f32, f32) {
+        let count = self.state.as_ref().map_or(7, |state| state.stats().types().len()).max(7);
+        let spacing = 80.0 * 7.0 / count as f32;
+        (spacing, 1.5 * (spacing / 80.0).max(0.5))
+    }
+
+    /// The part of the field area the board takes up, centered horizontally and at the bottom
+    fn field(&self) -> Rectangle {
+        let (width, height) = match self.state.as_ref() {
+            Some(state) => (state.stack().width() as f32, state.stack().height() as f32),
+            None => (10.0, 20.0),
+        };
+        let (w, h) = (width * self.tile_size, height * self.tile_size);
+        Rectangle::new(self.pos_field.x + 0.5 * (self.pos_field.w - w), self.pos_field.bottom() - h, w, h)
+    }
+
     fn gen_level_base_color<F>(rnd: &F) -> Vector3<f32> where F: Fn(f32, f32) -> f32 {
         let hue = rnd(0.0, 360.0);
         let mut saturation = rnd(0.4, 0.9);
@@ -137,12 +169,13 @@ impl Renderer {
         return col;
     }
 
-    fn gen_level_colors() -> Vec<[Vector3<f32>; 7]> {
+    /// The colors of the theme, random ones if it has none
+    fn gen_level_colors(pieces: &tetris::PieceSet, theme: &Theme) -> Vec<Vec<Vector3<f32>>> {
         let rng = std::cell::RefCell::new(rand::rngs::OsRng::new().unwrap());
         let rnd = |min: f32, max: f32| { min + (max-min) * rng.borrow_mut().gen::<f32>() };
 
-        // level 0 base colors
-        let base = [
+        // level 0 base colors of the standard pieces
+        let standard = [
             Vector3::new(1.0, 1.0, 0.0),
             Vector3::new(0.0, 1.0, 1.0),
             Vector3::new(1.0, 0.0, 1.0),
@@ -152,30 +185,56 @@ impl Renderer {
             Vector3::new(1.0, 0.0, 0.0),
         ];
 
+        // the piece set's own colors, spread out hues for custom pieces without one
+        let shapes = pieces.shapes();
+        let count = shapes.iter().map(|shape| shape.tp.to_int() as usize + 1).max().unwrap_or(0).max(standard.len());
+        let mut base: Vec<Option<Vector3<f32>>> = vec!(None; count);
+        base[..standard.len()].copy_from_slice(&standard.iter().map(|c| Some(*c)).collect::<Vec<_>>());
+        for shape in shapes {
+            let idx = shape.tp.to_int() as usize;
+            base[idx] = match (shape.color, shape.tp) {
+                (Some(c), _) => Some(Vector3::new(c.0, c.1, c.2)),
+                (None, piece::Type::Custom(i)) => Some(util3d::hsv((i as f32 * 137.5) % 360.0, 0.8, 1.0)),
+                (None, _) => base[idx],
+            };
+        }
+
         let mut piece_colors = Vec::new();
 
         for lvl in 0..100 {
+            if let Some(colors) = theme.level_colors(lvl) {
+                // pieces the theme has no color for keep their own
+                piece_colors.push(base.iter().enumerate()
+                    .map(|(i, base)| colors.get(i).cloned().or(*base).unwrap_or(Vector3::new(0.4, 0.4, 0.4)))
+                    .collect());
+                continue;
+            }
+
             let col = Self::gen_level_base_color(&rnd);
             let ratio = (1.0 - 0.1 * lvl as f32).max(0.17);
             let fac = 0.7;
-            piece_colors.push([
-                fac * (ratio * base[0] + (1.0 - ratio) * col),
-                fac * (ratio * base[1] + (1.0 - ratio) * col),
-                fac * (ratio * base[2] + (1.0 - ratio) * col),
-                fac * (ratio * base[3] + (1.0 - ratio) * col),
-                fac * (ratio * base[4] + (1.0 - ratio) * col),
-                fac * (ratio * base[5] + (1.0 - ratio) * col),
-                fac * (ratio * base[6] + (1.0 - ratio) * col),
-            ]);
+            piece_colors.push(base.iter()
+                .map(|base| match base {
+                    Some(base) => fac * (ratio * base + (1.0 - ratio) * col),
+                    None => Vector3::new(0.4, 0.4, 0.4),
+                })
+                .collect());
         };
 
         piece_colors
     }
 
-    pub fn new(pos_field: Rectangle, pos_next: Rectangle, pos_info: Rectangle, pos_stats: Rectangle, z: f32) -> Self {
-        let cube = tinygl::shapes::Cube::new(1);
+    pub fn new(context: &tinygl::Context, pos_field: Rectangle, pos_next: Rectangle, pos_info: Rectangle, pos_stats: Rectangle, z: f32) -> Self {
+        let mut cube = tinygl::shapes::Cube::new(1).mesh(context);
+        // refilled for every piece type, a field full of blocks fits
+        let layout = BlockBuffers::layout();
+        let cube_blocks = cube.add_buffer(tinygl::VertexBuffer::stream(context, 256 * layout.stride()), layout);
+        let mut square = tinygl::Mesh::new(context);
+        square.add_buffer(tinygl::VertexBuffer::from::<f32>(context, &[0.0, 0.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0]),
+                          tinygl::VertexLayout::new().attribute("vertex", tinygl::Format::Vec2));
 
         Renderer {
+            context: context.clone(),
             timestamp: 0,
             state: None,
 
@@ -186,13 +245,16 @@ impl Renderer {
 
             ghost_piece: false,
             threed: false,
+            show_stats: true,
+            name: "game",
             tile_size: pos_field.w / 10.0,
             z,
 
             background: Vec::new(),
             background_timer: 0.0,
+            backdrop_time: 0.0,
 
-            program: tinygl::Program::new_versioned("
+            program: tinygl::Program::new_versioned(context, "
                 attribute vec2 vertex;
                 uniform vec3 pos;
                 uniform vec2 size;
@@ -207,7 +269,7 @@ impl Renderer {
                 }
                 ", 100),
 
-            block_program: tinygl::Program::new_versioned("
+            block_program: tinygl::Program::new_versioned(context, "
                 attribute vec3 vertex;
                 attribute vec3 normal;
                 attribute vec3 position;
@@ -219,6 +281,7 @@ impl Renderer {
                 varying float v_alpha;
                 varying vec3 v_normal;
                 varying vec3 v_position;
+                varying vec3 v_local;
 
                 void main() {
                     v_alpha = alpha;
@@ -226,34 +289,104 @@ impl Renderer {
                     vec3 pos = position + 0.5 * size * (v + vec3(1.0, 1.0, -1.0));
                     v_normal = normal;
                     v_position = pos;
+                    v_local = vertex;
                     gl_Position = view * vec4(pos, 1.0);
                 }
                 ", "
                 uniform vec3 color;
+                // 0 flat, 1 beveled, 2 textured
+                uniform int style;
 
                 varying float v_alpha;
                 varying vec3 v_normal;
                 varying vec3 v_position;
+                varying vec3 v_local;
 
                 void main() {
                     float light = max(dot(-v_normal, normalize(v_position)), 0.1);
-                    gl_FragColor = vec4(light * color, v_alpha);
+                    vec3 c = color;
+                    if (style == 1) {
+                        // y points down, the upper left edges catch the light
+                        vec2 p = v_local.xy;
+                        if (max(abs(p.x), abs(p.y)) > 0.7) {
+                            c *= p.x + p.y < 0.0 ? 1.4 : 0.55;
+                        }
+                    } else if (style == 2) {
+                        c *= 0.8 + 0.2 * sin(12.0 * (v_local.x + v_local.y)) * cos(9.0 * v_local.x);
+                    }
+                    gl_FragColor = vec4(light * c, v_alpha);
                 }
                 ", 100),
 
-            square: tinygl::VertexBuffer::from::<f32>(&vec!(0.0, 0.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0)),
+            backdrop_program: tinygl::Program::new_versioned(context, "
+                attribute vec2 vertex;
+                varying vec2 v_pos;
+                void main() {
+                    v_pos = vertex;
+                    gl_Position = vec4(2.0 * vertex - 1.0, 0.0, 1.0);
+                }
+                ", "
+                uniform vec3 color1;
+                uniform vec3 color2;
+                // 0 plain, 1 gradient, 2 plasma
+                uniform int shader;
+                uniform float time;
+                varying vec2 v_pos;
+                void main() {
+                    float t = 0.0;
+                    if (shader == 1) {
+                        t = 1.0 - v_pos.y;
+                    } else if (shader == 2) {
+                        t = 0.5 + 0.25 * (sin(6.0 * v_pos.x + time) + sin(5.0 * v_pos.y - 0.7 * time + 3.0 * sin(2.0 * v_pos.x + 0.3 * time)));
+                    }
+                    gl_FragColor = vec4(mix(color1, color2, t), 1.0);
+                }
+                ", 100),
+
+            square,
+            cube,
+            cube_blocks,
+
+            pieces: tetris::PieceSet::Tetromino,
+            piece_colors: Self::gen_level_colors(&tetris::PieceSet::Tetromino, &Theme::default()),
+            theme: Theme::default(),
+        }
+    }
 
-            cube_vertices: cube.vertices(),
-            cube_normals: cube.normals(),
-            cube_indices: cube.indices(),
+    /// Colors, blocks and background of the theme, and its layout for a single board
+    pub fn set_theme(&mut self, theme: &Theme) {
+        self.theme = theme.clone();
+        self.pos_stats = theme.layout.stats;
+        self.set_layout(theme.layout.field, theme.layout.next, theme.layout.info);
+        self.piece_colors = Self::gen_level_colors(&self.pieces, &self.theme);
+    }
 
-            piece_colors: Self::gen_level_colors(),
+    /// Moves the board, next piece box and score display
+    pub fn set_layout(&mut self, pos_field: Rectangle, pos_next: Rectangle, pos_info: Rectangle) {
+        self.pos_field = pos_field;
+        self.pos_next = pos_next;
+        self.pos_info = pos_info;
+        if let Some(state) = self.state.as_ref() {
+            let (width, height) = (state.stack().width() as f32, state.stack().height() as f32);
+            self.tile_size = (self.pos_field.w / width).min(self.pos_field.h / height);
         }
     }
 
     pub fn set_state(&mut self, timestamp: i32, state: &Snapshot) {
         self.timestamp = timestamp;
         self.state = Some(state.clone());
+
+        // fit the board into the field area
+        let (width, height) = (state.stack().width() as f32, state.stack().height() as f32);
+        self.tile_size = (self.pos_field.w / width).min(self.pos_field.h / height);
+    }
+
+    /// Picks the colors for the pieces of the game that is shown next
+    pub fn set_pieces(&mut self, pieces: &tetris::PieceSet) {
+        if self.pieces != *pieces {
+            self.pieces = pieces.clone();
+            self.piece_colors = Self::gen_level_colors(pieces, &self.theme);
+        }
     }
 
     pub fn clear(&mut self) {
@@ -261,7 +394,7 @@ impl Renderer {
     }
 
     pub fn gen_new_colors(&mut self) {
-        self.piece_colors = Self::gen_level_colors();
+        self.piece_colors = Self::gen_level_colors(&self.pieces, &self.theme);
     }
 
     fn collect_blocks(&self) -> BlockBuffers {
@@ -293,87 +426,82 @@ impl Renderer {
         });
 
         // add curr piece - only if no burn animation is running!
+        let field = self.field();
         if let Some(piece) = state.piece() {
             buffers.piece(piece.0,
-                          self.pos_field.x + self.tile_size * piece.1 as f32,
-                          self.pos_field.bottom() - self.tile_size * (piece.2 + 1) as f32, self.z,
-                          self.tile_size, self.pos_field.bottom(), 1.0);
+                          field.x + self.tile_size * piece.1 as f32,
+                          field.bottom() - self.tile_size * (piece.2 + 1) as f32, self.z,
+                          self.tile_size, field.bottom(), 1.0);
         }
 
         // add ghost piece
         if self.ghost_piece {
             if let Some(piece) = state.ghost_piece() {
                 buffers.piece(piece.0,
-                              self.pos_field.x + self.tile_size * piece.1 as f32,
-                              self.pos_field.bottom() - self.tile_size * (piece.2 + 1) as f32, self.z,
-                              self.tile_size, self.pos_field.bottom(), 0.4);
+                              field.x + self.tile_size * piece.1 as f32,
+                              field.bottom() - self.tile_size * (piece.2 + 1) as f32, self.z,
+                              self.tile_size, field.bottom(), 0.4);
             }
         }
 
-        // add next piece
-        let ofs = state.next_piece().get_type().offset();
-        buffers.piece(state.next_piece(),
-                      self.pos_next.x + ofs.0 * self.tile_size,
-                      self.pos_next.bottom() + (ofs.1 - 1.0) * self.tile_size, self.z,
-                      self.tile_size, 0.0, 1.0);
+        // add next piece, big ones get shrunk to fit the box
+        let next = state.next_piece();
+        let ofs = next.offset();
+        let tile = self.pos_next.w / next.size().max(4) as f32;
+        buffers.piece(next,
+                      self.pos_next.x + ofs.0 * tile,
+                      self.pos_next.bottom() + (ofs.1 - 1.0) * tile, self.z,
+                      tile, 0.0, 1.0);
 
         // add stats pieces
-        for i in 0..7 {
-            let y = self.pos_stats.y + 80.0 * i as f32;
-            let pc = piece::Piece::new(piece::Type::from_int(i), 2);
-            let ofs = pc.get_type().offset();
+        let (spacing, _) = self.stats_layout();
+        let types = if self.show_stats { state.stats().types() } else { &[] };
+        for (i, tp) in types.iter().enumerate() {
+            let y = self.pos_stats.y + spacing * i as f32;
+            let pc = self.pieces.piece(*tp);
+            let ofs = pc.offset();
+            let tile = 20.0 * spacing / 80.0 * 4.0 / pc.size().max(4) as f32;
             buffers.piece(pc,
-                          self.pos_stats.x + 40.0 + ofs.0 * 20.0,
-                          y + (ofs.1 + 3.0) * 20.0, self.z,
-                          20.0, 10000.0, 1.0);
+                          self.pos_stats.x + 40.0 + ofs.0 * tile,
+                          y + (ofs.1 + 3.0) * tile, self.z,
+                          tile, 10000.0, 1.0);
         }
 
         buffers
     }
 
-    fn render_blocks(&self, view: &cgmath::Matrix4<f32>, buffers: BlockBuffers, palette: usize) {
+    fn render_blocks(&mut self, view: &cgmath::Matrix4<f32>, buffers: BlockBuffers, palette: usize) {
         let threed = if self.threed { 1.0 } else { 0.0 };
         let scale = if self.threed { 0.9 } else { 0.95 };
         let model = cgmath::Matrix4::from_nonuniform_scale(scale, scale, threed * scale);
         let model = cgmath::Matrix4::from_translation(cgmath::Vector3::new(0.0, 0.0, (1.0 - threed) * scale)) * model;
 
-        unsafe {
-            gl::Enable(gl::BLEND);
-            gl::Enable(gl::CULL_FACE);
-            gl::Enable(gl::DEPTH_TEST);
-            gl::Disable(gl::STENCIL_TEST);
-        }
+        tinygl::enable(&self.context, gl::BLEND);
+        tinygl::enable(&self.context, gl::CULL_FACE);
+        tinygl::enable(&self.context, gl::DEPTH_TEST);
+        tinygl::disable(&self.context, gl::STENCIL_TEST);
 
-        self.cube_indices.bind();
         self.block_program.bind();
         self.block_program.uniform("model", tinygl::Uniform::Mat4(model));
         self.block_program.uniform("view", tinygl::Uniform::Mat4(*view));
-        self.block_program.vertex_attrib_buffer("vertex", &self.cube_vertices, 3, gl::FLOAT, false, 12, 0);
-        self.block_program.vertex_attrib_divisor("vertex", 0);
-        self.block_program.vertex_attrib_buffer("normal", &self.cube_normals, 3, gl::FLOAT, false, 12, 0);
-        self.block_program.vertex_attrib_divisor("normal", 0);
-        self.block_program.vertex_attrib_divisor("position", 1);
-        self.block_program.vertex_attrib_divisor("size", 1);
-        self.block_program.vertex_attrib_divisor("alpha", 1);
-
+        self.block_program.uniform("style", tinygl::Uniform::Signed(match self.theme.blocks {
+            theme::BlockStyle::Flat => 0,
+            theme::BlockStyle::Beveled => 1,
+            theme::BlockStyle::Textured => 2,
+        }));
         let palette = palette.min(self.piece_colors.len() - 1);
         let colors = &self.piece_colors[palette];
 
-        for i in 0..7 {
-            if let Some(vbo) = buffers.vbo(i) {
-                self.block_program.uniform("color", tinygl::Uniform::Vec3(colors[i]));
-                self.block_program.vertex_attrib_buffer("position", &vbo.0, 3, gl::FLOAT, false, 4 * BlockBuffers::vertex_size(), 0);
-                self.block_program.vertex_attrib_buffer("size", &vbo.0, 1, gl::FLOAT, false, 4 * BlockBuffers::vertex_size(), 12);
-                self.block_program.vertex_attrib_buffer("alpha", &vbo.0, 1, gl::FLOAT, false, 4 * BlockBuffers::vertex_size(), 16);
-                unsafe { gl::DrawElementsInstanced(gl::TRIANGLES, self.cube_indices.count() as i32, gl::UNSIGNED_SHORT, std::ptr::null(), vbo.1 as i32) }
+        for (i, data) in buffers.data.iter().enumerate() {
+            if !data.is_empty() {
+                let color = colors.get(i).cloned().unwrap_or(Vector3::new(0.4, 0.4, 0.4));
+                self.block_program.uniform("color", tinygl::Uniform::Vec3(color));
+                self.cube.buffer_mut(self.cube_blocks).update(data);
+                if let Err(err) = self.cube.draw(&self.block_program, gl::TRIANGLES) {
+                    self.context.warn(err);
+                }
             }
         }
-
-        self.block_program.vertex_attrib_divisor("position", 0);
-        self.block_program.vertex_attrib_divisor("size", 0);
-        self.block_program.vertex_attrib_divisor("alpha", 0);
-
-        self.block_program.disable_all_vertex_attribs();
     }
 
     pub fn render(&mut self, view: &cgmath::Matrix4<f32>) {
@@ -381,31 +509,53 @@ impl Renderer {
             return;
         }
 
-        unsafe { gl::Enable(gl::DEPTH_TEST) }
-        unsafe { gl::Enable(gl::BLEND) }
+        tinygl::enable(&self.context, gl::DEPTH_TEST);
+        tinygl::enable(&self.context, gl::BLEND);
 
         // draw squares for stack / next piece
         self.program.bind();
         self.program.uniform("view", tinygl::Uniform::Mat4(*view));
-        self.program.vertex_attrib_divisor("vertex", 0);
-        self.program.vertex_attrib_buffer("vertex", &self.square, 2, gl::FLOAT, false, 8, 0);
-        unsafe { gl::DepthFunc(gl::ALWAYS) }
-        self.draw_square(self.pos_field.expanded(50.0), self.z - 100.0, Vector4::new(0.2, 0.0, 0.0, 0.0));
+        tinygl::depth_func(&self.context, gl::ALWAYS);
+        self.draw_square(self.field().expanded(50.0), self.z - 100.0, Vector4::new(0.2, 0.0, 0.0, 0.0));
         self.draw_square(self.pos_next.scaled(1.3), 1.3 * self.z, Vector4::new(0.0, 0.0, 0.0, 1.0));
-        self.draw_square(self.pos_field.scaled(1.3), 1.3 * self.z, Vector4::new(0.0, 0.0, 0.0, 1.0));
-        self.program.disable_all_vertex_attribs();
+        self.draw_square(self.field().scaled(1.3), 1.3 * self.z, Vector4::new(0.0, 0.0, 0.0, 1.0));
 
         let buffers = self.collect_blocks();
-        unsafe { gl::DepthFunc(gl::LEQUAL) }
+        tinygl::depth_func(&self.context, gl::LEQUAL);
         self.render_blocks(view, buffers, self.state.as_ref().unwrap().level() as usize);
     }
 
+    /// Fills the screen with the background of the theme
+    pub fn render_backdrop(&mut self, dt: f32) {
+        self.backdrop_time += dt;
+        let background = &self.theme.background;
+
+        tinygl::disable(&self.context, gl::DEPTH_TEST);
+        tinygl::disable(&self.context, gl::CULL_FACE);
+
+        self.backdrop_program.bind();
+        self.backdrop_program.uniform("color1", tinygl::Uniform::Vec3(theme::color(&background.colors[0])));
+        self.backdrop_program.uniform("color2", tinygl::Uniform::Vec3(theme::color(&background.colors[1])));
+        self.backdrop_program.uniform("shader", tinygl::Uniform::Signed(match background.shader {
+            theme::Shader::Plain => 0,
+            theme::Shader::Gradient => 1,
+            theme::Shader::Plasma => 2,
+        }));
+        self.backdrop_program.uniform("time", tinygl::Uniform::Float(self.backdrop_time));
+        if let Err(err) = self.square.draw(&self.backdrop_program, gl::TRIANGLES) {
+            self.context.warn(err);
+        }
+    }
+
     pub fn render_background(&mut self, dt: f32, view: &cgmath::Matrix4<f32>) {
         let mut buffers = BlockBuffers::new();
 
         // add new block every N secs
         self.background_timer += dt;
-        if self.background_timer > 0.2 && self.background.len() < 150 {
+        if !self.theme.background.pieces {
+            self.background.clear();
+            self.background_timer = 0.0;
+        } else if self.background_timer > 0.2 && self.background.len() < 150 {
             self.background_timer -= 0.2;
             let size = 10.0 + 30.0 * rand::random::<f32>();
             let x = -600.0 + 1200.0 * rand::random::<f32>();
@@ -432,9 +582,9 @@ impl Renderer {
         let unixtime = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
         let palette = (unixtime / 20) % 30;
 
-        unsafe { gl::Enable(gl::DEPTH_TEST) }
-        unsafe { gl::Enable(gl::BLEND) }
-        unsafe { gl::DepthFunc(gl::LEQUAL) }
+        tinygl::enable(&self.context, gl::DEPTH_TEST);
+        tinygl::enable(&self.context, gl::BLEND);
+        tinygl::depth_func(&self.context, gl::LEQUAL);
         self.render_blocks(view, buffers, palette as usize);
     }
 
@@ -445,7 +595,7 @@ impl Renderer {
 
         let state = self.state.as_ref().unwrap();
 
-        staticwindow(ui, "scores",
+        staticwindow(ui, &format!("scores#{}", self.name),
                      (offset.0 + self.pos_info.x * scale, offset.1 + self.pos_info.y * scale),
                      (self.pos_info.w * scale, self.pos_info.h * scale),
                      (0.0, 0.0, 0.0, 0.0), || {
@@ -456,9 +606,14 @@ impl Renderer {
                 ui.text(format!("Tetris: {}%", (100.0 * state.tetris_rate()) as i32));
             });
 
-        for i in 0..7 {
-            let stats = state.stats().get(piece::Type::from_int(i));
-            let y = self.pos_stats.y + 80.0 * i as f32 + 20.0;
+        if !self.show_stats {
+            return;
+        }
+
+        let (spacing, font) = self.stats_layout();
+        for (i, tp) in state.stats().types().iter().enumerate() {
+            let stats = state.stats().get(*tp);
+            let y = self.pos_stats.y + spacing * i as f32 + 20.0 * spacing / 80.0;
 
             // choose color
             let step = 10;
@@ -474,17 +629,17 @@ impl Renderer {
 
             staticwindow(ui, &format!("droughtstats#window{}", i),
                          (offset.0 + self.pos_stats.x * scale, offset.1 + y * scale),
-                         (40.0 * scale, 60.0 * scale),
+                         (40.0 * scale, 0.75 * spacing * scale),
                          (0.0, 0.0, 0.0, 0.0), || {
-                    ui.set_window_font_scale(1.5 * scale);
+                    ui.set_window_font_scale(font * scale);
                     ui.text_colored(col, format!("{}", stats.1));
                 });
 
             staticwindow(ui, &format!("countstats#window{}", i),
                          (offset.0 + (self.pos_stats.x + 140.0) * scale, offset.1 + y * scale),
-                         (40.0 * scale, 60.0 * scale),
+                         (40.0 * scale, 0.75 * spacing * scale),
                          (0.0, 0.0, 0.0, 0.0), || {
-                    ui.set_window_font_scale(1.5 * scale);
+                    ui.set_window_font_scale(font * scale);
                     ui.text(format!("{}", stats.0));
                 });
         }
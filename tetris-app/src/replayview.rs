@@ -0,0 +1,180 @@
+//! Watching a replay: stepping by frames or pieces, a timeline with the tetrises, burns and
+//! level ups, an A/B loop, the keys the player pressed, and a second replay side by side.
+
+use imgui::*;
+
+use tetris::analysis::{self, Marker, Timeline};
+use tetris::replay::{Replay, Replayer};
+
+/// Frames a key of the input overlay stays lit after it got pressed
+const INPUT_FRAMES: i32 = 4;
+
+/// The replay shown next to the watched one, it follows piece by piece
+pub struct Compare {
+    pub name: String,
+    pub replayer: Replayer,
+    pub timeline: Timeline,
+}
+
+pub struct ReplayView {
+    pub replayer: Replayer,
+    pub timeline: Timeline,
+    /// show the per-piece statistics
+    pub analysis: bool,
+    /// show the keys pressed
+    pub inputs: bool,
+    /// loop points in seconds, the part between them repeats once both are set
+    pub loop_start: Option<f32>,
+    pub loop_end: Option<f32>,
+    pub compare: Option<Compare>,
+    /// the list of games to compare with is open
+    pub choosing: bool,
+}
+
+impl ReplayView {
+    pub fn new(replay: &Replay) -> Result<Self, String> {
+        let replayer = Replayer::new(replay)?;
+        Ok(ReplayView {
+            timeline: Timeline::new(replay, replayer.history()),
+            replayer,
+            analysis: false,
+            inputs: true,
+            loop_start: None,
+            loop_end: None,
+            compare: None,
+            choosing: false,
+        })
+    }
+
+    pub fn compare_with(&mut self, name: &str, replay: &Replay) -> Result<(), String> {
+        let replayer = Replayer::new(replay)?;
+        self.compare = Some(Compare {
+            name: String::from(name),
+            timeline: Timeline::new(replay, replayer.history()),
+            replayer,
+        });
+        self.sync();
+        Ok(())
+    }
+
+    pub fn advance(&mut self, dt: f32) {
+        let adv = dt * self.replayer.speed;
+        self.replayer.advance(adv);
+        self.sync();
+    }
+
+    /// Puts the other replay at the same point into the same piece
+    pub fn sync(&mut self) {
+        if let Some(compare) = self.compare.as_mut() {
+            let frame = self.timeline.sync(&compare.timeline, self.replayer.timestamp());
+            compare.replayer.jump_frame(frame);
+        }
+    }
+
+    pub fn jump(&mut self, time: f32) {
+        self.replayer.jump(time);
+        self.sync();
+    }
+
+    /// Single frames, pauses the replay
+    pub fn step_frames(&mut self, frames: i32) {
+        self.replayer.paused = true;
+        self.replayer.step(frames);
+        self.sync();
+    }
+
+    /// To the spawn of the next piece, or back to the spawn of this or the previous one
+    pub fn step_piece(&mut self, forward: bool) {
+        let now = self.replayer.timestamp();
+        let mut spawns = self.timeline.placements.iter().map(|p| p.spawned);
+        let frame = if forward {
+            spawns.find(|t| *t > now)
+        } else {
+            spawns.filter(|t| *t < now).last()
+        };
+
+        self.replayer.paused = true;
+        if let Some(frame) = frame {
+            self.replayer.jump_frame(frame);
+        }
+        self.sync();
+    }
+
+    /// Sets the start or the end of the loop to now
+    pub fn mark(&mut self, end: bool) {
+        let now = self.replayer.frame();
+        if end {
+            self.loop_end = Some(now);
+        } else {
+            self.loop_start = Some(now);
+        }
+        self.replayer.looped = match (self.loop_start, self.loop_end) {
+            (Some(a), Some(b)) if a != b => Some((a.min(b), a.max(b))),
+            _ => None,
+        };
+    }
+
+    pub fn clear_loop(&mut self) {
+        self.loop_start = None;
+        self.loop_end = None;
+        self.replayer.looped = None;
+    }
+
+    /// The bar to scrub through the replay, with the markers and the loop on it
+    pub fn timeline_ui(&mut self, ui: &Ui, size: [f32; 2]) {
+        let pos = ui.cursor_screen_pos();
+        let length = self.replayer.length().max(0.001);
+        let fps = self.replayer.config().fps();
+        let x = |time: f32| pos[0] + size[0] * (time / length).max(0.0).min(1.0);
+
+        ui.invisible_button("##replaytimeline", size);
+        if ui.is_item_active() {
+            let time = (ui.io().mouse_pos[0] - pos[0]) / size[0] * length;
+            self.jump(time);
+        }
+
+        let draw = ui.get_window_draw_list();
+        let bottom = pos[1] + size[1];
+        draw.add_rect(pos, [pos[0] + size[0], bottom], [0.2, 0.2, 0.2, 1.0]).filled(true).build();
+
+        if let Some(start) = self.loop_start {
+            let end = self.loop_end.unwrap_or(start);
+            draw.add_rect([x(start.min(end)), pos[1]], [x(start.max(end)) + 1.0, bottom], [1.0, 1.0, 1.0, 0.25]).filled(true).build();
+        }
+
+        for (frame, marker) in self.timeline.markers() {
+            let color = match marker {
+                Marker::Tetris => [0.3, 0.9, 1.0, 1.0],
+                Marker::Burn => [1.0, 0.4, 0.2, 1.0],
+                Marker::LevelUp => [1.0, 1.0, 0.3, 1.0],
+            };
+            let top = if marker == Marker::LevelUp { pos[1] } else { pos[1] + 0.4 * size[1] };
+            let mx = x(frame as f32 / fps);
+            draw.add_line([mx, top], [mx, bottom], color).build();
+        }
+
+        let now = x(self.replayer.frame());
+        draw.add_line([now, pos[1] - 2.0], [now, bottom + 2.0], [1.0, 1.0, 1.0, 1.0]).thickness(2.0).build();
+    }
+
+    /// The d-pad and rotation buttons, lit for the keys pressed just now
+    pub fn inputs_ui(&self, ui: &Ui) {
+        let now = self.replayer.timestamp();
+        let keys = analysis::inputs(self.replayer.history(), now - INPUT_FRAMES, now);
+
+        let buttons = [
+            ("<", keys.left),
+            ("v", keys.down),
+            (">", keys.right),
+            ("B", keys.rotate == Some(false)),
+            ("A", keys.rotate == Some(true)),
+        ];
+        for (i, (label, pressed)) in buttons.iter().enumerate() {
+            if i > 0 {
+                ui.same_line();
+            }
+            let color = if *pressed { [1.0, 1.0, 0.3, 1.0] } else { [0.4, 0.4, 0.4, 1.0] };
+            ui.text_colored(color, label);
+        }
+    }
+}
@@ -0,0 +1,101 @@
+//! Sound effects for the events of a game. The web build plays the <audio> elements of
+//! index.html, the desktop build loads the same files through SDL2_mixer.
+
+use tetris::game::Event;
+
+#[cfg(not(target_os = "emscripten"))]
+use std::collections::HashMap;
+
+#[cfg(not(target_os = "emscripten"))]
+const SOUNDS: [&str; 4] = ["blip", "rerr", "deww", "dabbedi"];
+
+/// The sounds for a batch of events, a piece that clears lines only plays the clear
+fn sounds(events: &[Event]) -> Vec<&'static str> {
+    let mut ret = Vec::new();
+    for (i, event) in events.iter().enumerate() {
+        let clears = matches!(events.get(i + 1), Some(Event::Clear(_)));
+        match event {
+            Event::Shift(_) => ret.push("blip"),
+            Event::Rotate { .. } => ret.push("rerr"),
+            Event::Lock(_) if !clears => ret.push("deww"),
+            Event::Clear(_) => ret.push("dabbedi"),
+            _ => {}
+        }
+    }
+    ret
+}
+
+pub struct Sound {
+    #[cfg(not(target_os = "emscripten"))]
+    chunks: HashMap<&'static str, sdl2::mixer::Chunk>,
+    // keeps the decoders loaded
+    #[cfg(not(target_os = "emscripten"))]
+    _mixer: Option<sdl2::mixer::Sdl2MixerContext>,
+}
+
+impl Sound {
+    #[cfg(target_os = "emscripten")]
+    pub fn new() -> Self {
+        Sound {}
+    }
+
+    /// Opens the audio device, without one or without the sound files the game stays silent
+    #[cfg(not(target_os = "emscripten"))]
+    pub fn new() -> Self {
+        let mixer = sdl2::mixer::init(sdl2::mixer::InitFlag::MP3).ok();
+
+        let chunks = match sdl2::mixer::open_audio(44100, sdl2::mixer::DEFAULT_FORMAT, 2, 1024) {
+            Ok(()) => {
+                sdl2::mixer::allocate_channels(8);
+                SOUNDS.iter().filter_map(|name| Self::load(name).map(|chunk| (*name, chunk))).collect()
+            }
+            Err(err) => {
+                println!("No audio: {}", err);
+                HashMap::new()
+            }
+        };
+
+        Sound {
+            chunks,
+            _mixer: mixer,
+        }
+    }
+
+    /// Sound files are looked for in the working directory, then next to the executable
+    #[cfg(not(target_os = "emscripten"))]
+    fn load(name: &str) -> Option<sdl2::mixer::Chunk> {
+        let file = format!("{}.mp3", name);
+        let beside_exe = std::env::current_exe().ok()
+            .and_then(|exe| exe.parent().map(|dir| dir.join(&file)));
+
+        let ret = sdl2::mixer::Chunk::from_file(&file)
+            .or_else(|err| beside_exe.ok_or(err).and_then(sdl2::mixer::Chunk::from_file));
+        if let Err(err) = &ret {
+            println!("Can't load {}: {}", file, err);
+        }
+        ret.ok()
+    }
+
+    pub fn play(&self, events: &[Event]) {
+        for name in sounds(events) {
+            self.play_sound(name);
+        }
+    }
+
+    #[cfg(target_os = "emscripten")]
+    fn play_sound(&self, tagname: &str) {
+        emscripten_util::run_javascript(&(String::from("{
+            let element = document.getElementById('") + tagname + "');
+            element.currentTime = 0;
+            element.play();
+        }"));
+    }
+
+    #[cfg(not(target_os = "emscripten"))]
+    fn play_sound(&self, name: &str) {
+        if let Some(chunk) = self.chunks.get(name) {
+            // all channels busy just drops the sound
+            let _ = sdl2::mixer::Channel::all().play(chunk, 0);
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! Looks of the game: piece colors per level, how the blocks are drawn, the background and
+//! where the board, next piece, score and statistics go. The themes come from themes.json,
+//! the desktop build also picks up a themes.json of the player in the data directory.
+
+use cgmath::Vector3;
+
+use renderer::Rectangle;
+
+const BUILTIN: &str = include_str!("../themes.json");
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum BlockStyle {
+    /// plain lit cubes
+    #[default]
+    Flat,
+    /// light and dark edges like the NES blocks
+    Beveled,
+    /// a pattern on each block
+    Textured,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Shader {
+    /// the first color only
+    Plain,
+    /// from the first color at the top to the second one at the bottom
+    Gradient,
+    /// waves of both colors
+    Plasma,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Background {
+    pub shader: Shader,
+    /// "#rrggbb"
+    pub colors: [String; 2],
+    /// pieces falling behind the menus
+    pub pieces: bool,
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background {
+            shader: Shader::Plain,
+            colors: [String::from("#002633"), String::from("#000000")],
+            pieces: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Layout {
+    pub field: Rectangle,
+    pub next: Rectangle,
+    pub info: Rectangle,
+    pub stats: Rectangle,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout {
+            field: Rectangle::new(-150.0, -300.0, 300.0, 600.0),
+            next: Rectangle::new(220.0, -300.0, 120.0, 120.0),
+            info: Rectangle::new(220.0, -120.0, 180.0, 200.0),
+            stats: Rectangle::new(-400.0, -300.0, 300.0, 400.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Theme {
+    pub name: String,
+    /// piece colors per level as "#rrggbb" in the order O I T L J S Z, the levels repeat after
+    /// the last one. Without any every game gets random colors.
+    #[serde(default)]
+    pub palette: Vec<Vec<String>>,
+    #[serde(default)]
+    pub blocks: BlockStyle,
+    #[serde(default)]
+    pub background: Background,
+    #[serde(default)]
+    pub layout: Layout,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            name: String::from("Classic"),
+            palette: Vec::new(),
+            blocks: BlockStyle::default(),
+            background: Background::default(),
+            layout: Layout::default(),
+        }
+    }
+}
+
+/// "#rrggbb" to a color, black if it isn't one
+pub fn color(hex: &str) -> Vector3<f32> {
+    let hex = hex.trim_start_matches('#');
+    let channel = |i: usize| hex.get(2 * i..2 * i + 2)
+        .and_then(|c| u8::from_str_radix(c, 16).ok())
+        .map_or(0.0, |c| c as f32 / 255.0);
+    if hex.len() == 6 {
+        Vector3::new(channel(0), channel(1), channel(2))
+    } else {
+        Vector3::new(0.0, 0.0, 0.0)
+    }
+}
+
+impl Theme {
+    /// The colors of a level, None for a random palette
+    pub fn level_colors(&self, level: usize) -> Option<Vec<Vector3<f32>>> {
+        if self.palette.is_empty() {
+            return None;
+        }
+        let colors = &self.palette[level % self.palette.len()];
+        Some(colors.iter().map(|hex| color(hex)).collect())
+    }
+}
+
+#[cfg(not(target_os = "emscripten"))]
+fn user_file() -> Option<std::path::PathBuf> {
+    sdl2::filesystem::pref_path("tetris", "tetris-app").ok().map(|dir| std::path::Path::new(&dir).join("themes.json"))
+}
+
+fn parse(data: &str, source: &str) -> Vec<Theme> {
+    serde_json::from_str(data).unwrap_or_else(|err| {
+        println!("Can't read the themes of {}: {}", source, err);
+        Vec::new()
+    })
+}
+
+/// The built-in themes, followed by the ones of the player. A theme of the player with the
+/// name of a built-in one replaces it.
+pub fn load() -> Vec<Theme> {
+    let mut themes = parse(BUILTIN, "the game");
+    if themes.is_empty() {
+        themes.push(Theme::default());
+    }
+
+    #[cfg(not(target_os = "emscripten"))] {
+        let data = user_file().and_then(|path| std::fs::read_to_string(path).ok());
+        for theme in data.map_or(Vec::new(), |data| parse(&data, "the player")) {
+            match themes.iter_mut().find(|t| t.name == theme.name) {
+                Some(existing) => *existing = theme,
+                None => themes.push(theme),
+            }
+        }
+    }
+
+    themes
+}
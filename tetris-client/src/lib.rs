@@ -1,3 +1,12 @@
+//! Communication with the highscore and replay server, shared by the app and the command line tools.
+
+extern crate base64;
+extern crate bincode;
+extern crate rand;
+extern crate serde;
+extern crate tetris;
+extern crate webutil;
+
 use webutil::httpclient;
 use webutil::curve25519;
 
@@ -133,4 +142,33 @@ impl ServerConfig {
             ids: vec!(id)
         })
     }
+
+    /// Announces a game that just started, the replay has the board and nothing else yet
+    pub fn start_live(&self, name: &str, replay: &tetris::replay::Replay) -> Request {
+        self.post(ServerMessage::StartLive {
+            name: name.to_string(),
+            idtag: self.idtag.clone(),
+            replay: replay.clone(),
+        })
+    }
+
+    pub fn update_live(&self, session: usize, delta: tetris::replay::ReplayDelta, finished: bool) -> Request {
+        self.post(ServerMessage::UpdateLive {
+            session,
+            idtag: self.idtag.clone(),
+            delta,
+            finished,
+        })
+    }
+
+    pub fn request_live_games(&self) -> Request {
+        self.post(ServerMessage::RequestLiveGames)
+    }
+
+    pub fn watch_live(&self, session: usize, from: usize) -> Request {
+        self.post(ServerMessage::WatchLive {
+            session,
+            from,
+        })
+    }
 }
\ No newline at end of file
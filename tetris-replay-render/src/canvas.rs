@@ -0,0 +1,154 @@
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Color {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+impl Color {
+    pub fn new(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Color { r, g, b, a }
+    }
+
+    pub fn rgb(r: f32, g: f32, b: f32) -> Self {
+        Color { r, g, b, a: 1.0 }
+    }
+
+    pub fn scaled(&self, fac: f32) -> Self {
+        Color { r: self.r * fac, g: self.g * fac, b: self.b * fac, a: self.a }
+    }
+}
+
+/// RGBA8 image that the replay frames are rasterized into
+pub struct Canvas {
+    width: usize,
+    height: usize,
+    data: Vec<u8>,
+}
+
+// 3x5 bitmap font, one byte per row, bit 2 is the left-most column
+const GLYPH_WIDTH: i32 = 3;
+const GLYPH_HEIGHT: i32 = 5;
+
+fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        '0' => [7, 5, 5, 5, 7],
+        '1' => [2, 6, 2, 2, 7],
+        '2' => [7, 1, 7, 4, 7],
+        '3' => [7, 1, 7, 1, 7],
+        '4' => [5, 5, 7, 1, 1],
+        '5' => [7, 4, 7, 1, 7],
+        '6' => [7, 4, 7, 5, 7],
+        '7' => [7, 1, 1, 1, 1],
+        '8' => [7, 5, 7, 5, 7],
+        '9' => [7, 5, 7, 1, 7],
+        'A' => [2, 5, 7, 5, 5],
+        'B' => [6, 5, 6, 5, 6],
+        'C' => [3, 4, 4, 4, 3],
+        'D' => [6, 5, 5, 5, 6],
+        'E' => [7, 4, 6, 4, 7],
+        'F' => [7, 4, 6, 4, 4],
+        'G' => [3, 4, 5, 5, 3],
+        'H' => [5, 5, 7, 5, 5],
+        'I' => [7, 2, 2, 2, 7],
+        'J' => [1, 1, 1, 5, 2],
+        'K' => [5, 5, 6, 5, 5],
+        'L' => [4, 4, 4, 4, 7],
+        'M' => [5, 7, 7, 5, 5],
+        'N' => [6, 5, 5, 5, 5],
+        'O' => [2, 5, 5, 5, 2],
+        'P' => [6, 5, 6, 4, 4],
+        'Q' => [2, 5, 5, 6, 3],
+        'R' => [6, 5, 6, 5, 5],
+        'S' => [3, 4, 2, 1, 6],
+        'T' => [7, 2, 2, 2, 2],
+        'U' => [5, 5, 5, 5, 7],
+        'V' => [5, 5, 5, 5, 2],
+        'W' => [5, 5, 7, 7, 5],
+        'X' => [5, 5, 2, 5, 5],
+        'Y' => [5, 5, 2, 2, 2],
+        'Z' => [7, 1, 2, 4, 7],
+        '%' => [5, 1, 2, 4, 5],
+        ':' => [0, 2, 0, 2, 0],
+        '-' => [0, 0, 7, 0, 0],
+        '.' => [0, 0, 0, 0, 2],
+        '/' => [1, 1, 2, 4, 4],
+        _ => [0, 0, 0, 0, 0],
+    }
+}
+
+impl Canvas {
+    pub fn new(width: usize, height: usize) -> Self {
+        Canvas {
+            width,
+            height,
+            data: vec![0; width * height * 4],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn data(&self) -> &Vec<u8> {
+        &self.data
+    }
+
+    pub fn clear(&mut self, color: Color) {
+        let px = Self::to_rgba8(color);
+        for chunk in self.data.chunks_mut(4) {
+            chunk.copy_from_slice(&px);
+        }
+    }
+
+    fn to_rgba8(color: Color) -> [u8; 4] {
+        let conv = |v: f32| (v.max(0.0).min(1.0) * 255.0 + 0.5) as u8;
+        [conv(color.r), conv(color.g), conv(color.b), conv(color.a)]
+    }
+
+    fn blend(&mut self, x: usize, y: usize, color: Color) {
+        let idx = 4 * (x + self.width * y);
+        let a = color.a.max(0.0).min(1.0);
+        let src = Self::to_rgba8(Color::new(color.r, color.g, color.b, 1.0));
+        for c in 0..3 {
+            let dst = self.data[idx + c] as f32;
+            self.data[idx + c] = (a * src[c] as f32 + (1.0 - a) * dst + 0.5) as u8;
+        }
+        self.data[idx + 3] = 255;
+    }
+
+    /// Fills the axis-aligned rectangle, coordinates are rounded to the pixel grid
+    pub fn fill_rect(&mut self, x: f32, y: f32, w: f32, h: f32, color: Color) {
+        let x0 = (x.round() as i64).max(0).min(self.width as i64) as usize;
+        let y0 = (y.round() as i64).max(0).min(self.height as i64) as usize;
+        let x1 = ((x + w).round() as i64).max(0).min(self.width as i64) as usize;
+        let y1 = ((y + h).round() as i64).max(0).min(self.height as i64) as usize;
+
+        for py in y0..y1 {
+            for px in x0..x1 {
+                self.blend(px, py, color);
+            }
+        }
+    }
+
+    /// Renders text with the built-in font, every font pixel is `size` canvas pixels wide
+    pub fn text(&mut self, x: f32, y: f32, size: f32, text: &str, color: Color) {
+        let mut x = x;
+        for c in text.chars() {
+            let rows = glyph(c);
+            for j in 0..GLYPH_HEIGHT {
+                for i in 0..GLYPH_WIDTH {
+                    if rows[j as usize] & (1 << (GLYPH_WIDTH - 1 - i)) != 0 {
+                        self.fill_rect(x + i as f32 * size, y + j as f32 * size, size, size, color);
+                    }
+                }
+            }
+            x += (GLYPH_WIDTH + 1) as f32 * size;
+        }
+    }
+}
@@ -0,0 +1,170 @@
+extern crate tetris;
+extern crate tetris_client as client;
+extern crate util3d;
+extern crate png;
+extern crate gif;
+
+mod canvas;
+mod scene;
+mod output;
+
+use tetris::replay::{Replay, Replayer};
+use output::FrameSink;
+
+const USAGE: &str = "Usage: tetris-replay-render (<replay-file> | --server <id>) [options]
+
+Options:
+    --png <dir>       write the frames as PNG images into <dir>
+    --gif <file>      write the frames into an animated GIF
+    --fps <n>         frames per second of the output (default: 25)
+    --scale <f>       size of the output, relative to 710x650 (default: 0.5)
+    --from <secs>     start of the rendered range (default: 0)
+    --to <secs>       end of the rendered range (default: end of replay)
+    --ghost           draw the ghost piece";
+
+struct Options {
+    file: Option<String>,
+    server_id: Option<usize>,
+    png: Option<String>,
+    gif: Option<String>,
+    fps: f32,
+    scale: f32,
+    from: f32,
+    to: Option<f32>,
+    ghost: bool,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut ret = Options {
+        file: None,
+        server_id: None,
+        png: None,
+        gif: None,
+        fps: 25.0,
+        scale: 0.5,
+        from: 0.0,
+        to: None,
+        ghost: false,
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("Missing value for {}", name));
+        let number = |name: &str, value: String| value.parse::<f32>().map_err(|_| format!("Invalid value for {}: {}", name, value));
+
+        match arg.as_str() {
+            "--server" => {
+                let id = value("--server")?;
+                ret.server_id = Some(id.parse().map_err(|_| format!("Invalid replay ID: {}", id))?);
+            }
+            "--png" => ret.png = Some(value("--png")?),
+            "--gif" => ret.gif = Some(value("--gif")?),
+            "--fps" => ret.fps = number("--fps", value("--fps")?)?,
+            "--scale" => ret.scale = number("--scale", value("--scale")?)?,
+            "--from" => ret.from = number("--from", value("--from")?)?,
+            "--to" => ret.to = Some(number("--to", value("--to")?)?),
+            "--ghost" => ret.ghost = true,
+            "-h" | "--help" => return Err(String::new()),
+            _ if arg.starts_with("--") => return Err(format!("Unknown option: {}", arg)),
+            _ => ret.file = Some(arg),
+        }
+    }
+
+    if ret.file.is_some() == ret.server_id.is_some() {
+        return Err(String::from("Need either a replay file or a server replay ID"));
+    }
+    if ret.png.is_none() && ret.gif.is_none() {
+        return Err(String::from("Need at least one of --png or --gif"));
+    }
+    if ret.fps <= 0.0 || ret.fps > 60.0 {
+        return Err(format!("FPS needs to be in (0, 60], got {}", ret.fps));
+    }
+    if ret.scale <= 0.0 {
+        return Err(format!("Invalid scale: {}", ret.scale));
+    }
+
+    Ok(ret)
+}
+
+fn load_file(path: &str) -> Result<Replay, String> {
+    let data = std::fs::read_to_string(path).map_err(|err| format!("Can't read {}: {}", path, err))?;
+    Replay::from_text(&data).ok_or(format!("{} doesn't contain a valid replay", path))
+}
+
+fn load_from_server(id: usize) -> Result<Replay, String> {
+    let server = client::ServerConfig::new();
+    let request = server.request_replay(id);
+
+    match request.response() {
+        client::Response::Success(tetris::networking::ServerAnswer::ReplayList { data }) => {
+            data.into_iter()
+                .find(|entry| entry.0 == id)
+                .map(|entry| entry.1)
+                .ok_or(format!("Server didn't send replay {}", id))
+        }
+        client::Response::Success(answer) => Err(format!("Unexpected server answer: {:?}", answer)),
+        client::Response::Waiting => Err(String::from("No answer from server")),
+        client::Response::HttpError(err) => Err(format!("HTTP error: {}", err)),
+        client::Response::ParseError(err) => Err(format!("Parse error: {}", err)),
+    }
+}
+
+fn run(options: Options) -> Result<(), String> {
+    let replay = match options.server_id {
+        Some(id) => load_from_server(id)?,
+        None => load_file(options.file.as_ref().unwrap())?,
+    };
+
+    let mut scene = scene::Scene::new(options.scale);
+    scene.ghost_piece = options.ghost;
+    scene.pieces = replay.config().pieces.clone();
+    let mut canvas = canvas::Canvas::new(scene.size().0, scene.size().1);
+
+    let mut sinks: Vec<Box<dyn FrameSink>> = Vec::new();
+    if let Some(dir) = &options.png {
+        sinks.push(Box::new(output::PngSequence::new(dir)?));
+    }
+    if let Some(file) = &options.gif {
+        sinks.push(Box::new(output::GifAnimation::new(file, scene.size(), options.fps)?));
+    }
+
+    let mut replayer = Replayer::new(&replay)?;
+    let end = options.to.unwrap_or(replayer.length()).min(replayer.length());
+    let mut frames = 0;
+
+    let mut time = options.from.max(0.0);
+    while time <= end {
+        replayer.jump(time);
+        scene.render(&mut canvas, replayer.timestamp(), replayer.snapshot());
+        for sink in &mut sinks {
+            sink.push(&canvas)?;
+        }
+        frames += 1;
+        time = options.from.max(0.0) + frames as f32 / options.fps;
+    }
+
+    let input = match options.server_id {
+        Some(id) => format!("replay {}", id),
+        None => options.file.clone().unwrap(),
+    };
+    println!("Rendered {} frames ({:.1}s of {}, which lasts {:.1}s)", frames, end - options.from.max(0.0), input, replay.duration());
+    Ok(())
+}
+
+fn main() {
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(err) => {
+            if !err.is_empty() {
+                eprintln!("{}\n", err);
+            }
+            eprintln!("{}", USAGE);
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(err) = run(options) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,75 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+
+use png::HasParameters;
+
+use canvas::Canvas;
+
+pub trait FrameSink {
+    fn push(&mut self, canvas: &Canvas) -> Result<(), String>;
+}
+
+pub fn write_png(path: &PathBuf, canvas: &Canvas) -> Result<(), String> {
+    let file = File::create(path).map_err(|err| format!("Can't create {}: {}", path.display(), err))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), canvas.width() as u32, canvas.height() as u32);
+    encoder.set(png::ColorType::RGBA).set(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header().map_err(|err| format!("PNG error: {}", err))?;
+    writer.write_image_data(canvas.data()).map_err(|err| format!("PNG error: {}", err))
+}
+
+/// Writes every frame into `<dir>/frame_00000.png`, `<dir>/frame_00001.png`, ...
+pub struct PngSequence {
+    dir: PathBuf,
+    index: usize,
+}
+
+impl PngSequence {
+    pub fn new(dir: &str) -> Result<Self, String> {
+        std::fs::create_dir_all(dir).map_err(|err| format!("Can't create {}: {}", dir, err))?;
+        Ok(PngSequence {
+            dir: PathBuf::from(dir),
+            index: 0,
+        })
+    }
+}
+
+impl FrameSink for PngSequence {
+    fn push(&mut self, canvas: &Canvas) -> Result<(), String> {
+        let path = self.dir.join(format!("frame_{:05}.png", self.index));
+        self.index += 1;
+        write_png(&path, canvas)
+    }
+}
+
+/// Animated, endlessly looping GIF
+pub struct GifAnimation {
+    encoder: gif::Encoder<BufWriter<File>>,
+    delay: u16,
+}
+
+impl GifAnimation {
+    pub fn new(path: &str, size: (usize, usize), fps: f32) -> Result<Self, String> {
+        let file = File::create(path).map_err(|err| format!("Can't create {}: {}", path, err))?;
+        let mut encoder = gif::Encoder::new(BufWriter::new(file), size.0 as u16, size.1 as u16, &[])
+            .map_err(|err| format!("GIF error: {}", err))?;
+
+        // png::HasParameters and gif::SetParameter both provide set()
+        gif::SetParameter::set(&mut encoder, gif::Repeat::Infinite).map_err(|err| format!("GIF error: {}", err))?;
+
+        Ok(GifAnimation {
+            encoder,
+            delay: (100.0 / fps).round().max(1.0) as u16,
+        })
+    }
+}
+
+impl FrameSink for GifAnimation {
+    fn push(&mut self, canvas: &Canvas) -> Result<(), String> {
+        let mut pixels = canvas.data().clone();
+        let mut frame = gif::Frame::from_rgba_speed(canvas.width() as u16, canvas.height() as u16, &mut pixels, 10);
+        frame.delay = self.delay;
+        self.encoder.write_frame(&frame).map_err(|err| format!("GIF error: {}", err))
+    }
+}
@@ -0,0 +1,288 @@
+use tetris::piece;
+use tetris::state::*;
+
+use canvas::{Canvas, Color};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Rectangle {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+impl Rectangle {
+    pub fn new(x: f32, y: f32, w: f32, h: f32) -> Self {
+        Rectangle { x, y, w, h }
+    }
+    fn bottom(&self) -> f32 {
+        self.y + self.h
+    }
+    fn right(&self) -> f32 {
+        self.x + self.w
+    }
+    fn scaled(&self, rel: f32) -> Self {
+        Rectangle { x: self.x * rel, y: self.y * rel, w: self.w * rel, h: self.h * rel }
+    }
+    fn translated(&self, dx: f32, dy: f32) -> Self {
+        Rectangle { x: self.x + dx, y: self.y + dy, w: self.w, h: self.h }
+    }
+}
+
+/// Draws snapshots into a canvas, using the same layout as `tetris-app`'s renderer
+pub struct Scene {
+    size: (usize, usize),
+    scale: f32,
+
+    pos_field: Rectangle,
+    pos_next: Rectangle,
+    pos_info: Rectangle,
+    pos_stats: Rectangle,
+
+    pub ghost_piece: bool,
+    /// shapes for the statistics panel and colors of custom pieces
+    pub pieces: tetris::PieceSet,
+}
+
+impl Scene {
+    /// The scene will cover the 710x650 area of the app's fixed-size UI, times `scale`
+    pub fn new(scale: f32) -> Self {
+        let fixedsize = (710.0, 650.0);
+        let center = (0.5 * fixedsize.0 * scale, 0.5 * fixedsize.1 * scale);
+        let place = |rect: Rectangle| rect.scaled(scale).translated(center.0, center.1);
+
+        let pos_field = place(Rectangle::new(-150.0, -300.0, 300.0, 600.0));
+
+        Scene {
+            size: ((fixedsize.0 * scale).round() as usize, (fixedsize.1 * scale).round() as usize),
+            scale,
+            pos_field,
+            pos_next: place(Rectangle::new(220.0, -300.0, 120.0, 120.0)),
+            pos_info: place(Rectangle::new(220.0, -120.0, 180.0, 200.0)),
+            pos_stats: place(Rectangle::new(-400.0, -300.0, 300.0, 400.0)),
+            ghost_piece: false,
+            pieces: tetris::PieceSet::Tetromino,
+        }
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        self.size
+    }
+
+    /// Deterministic per-level palette, so that the same replay always renders the same images,
+    /// indexed by `piece::Type::to_int`
+    pub fn level_colors(level: i32, pieces: &tetris::PieceSet) -> Vec<Color> {
+        let mut base = vec!(
+            (1.0, 1.0, 0.0),
+            (0.0, 1.0, 1.0),
+            (1.0, 0.0, 1.0),
+            (1.0, 0.5, 0.0),
+            (0.0, 0.0, 1.0),
+            (0.0, 1.0, 0.0),
+            (1.0, 0.0, 0.0),
+        );
+
+        // the piece set's own colors, spread out hues for custom pieces without one
+        for shape in pieces.shapes() {
+            let idx = shape.tp.to_int() as usize;
+            if base.len() <= idx {
+                base.resize(idx + 1, (0.4, 0.4, 0.4));
+            }
+            base[idx] = match (shape.color, shape.tp) {
+                (Some(c), _) => c,
+                (None, piece::Type::Custom(i)) => {
+                    let c = util3d::hsv((i as f32 * 137.5) % 360.0, 0.8, 1.0);
+                    (c.x, c.y, c.z)
+                }
+                (None, _) => base[idx],
+            };
+        }
+
+        let lvl = level.max(0);
+        let col = util3d::hsv((lvl * 67 % 360) as f32, 0.65, 1.0);
+        let ratio = (1.0 - 0.1 * lvl as f32).max(0.17);
+        let fac = 0.7;
+
+        let mix = |b: (f32, f32, f32)| Color::rgb(
+            fac * (ratio * b.0 + (1.0 - ratio) * col.x),
+            fac * (ratio * b.1 + (1.0 - ratio) * col.y),
+            fac * (ratio * b.2 + (1.0 - ratio) * col.z),
+        );
+
+        base.into_iter().map(mix).collect()
+    }
+
+    /// The part of the field area the board takes up, and the size of its blocks
+    fn field(&self, state: &Snapshot) -> (Rectangle, f32) {
+        let (width, height) = (state.stack().width() as f32, state.stack().height() as f32);
+        let tile = (self.pos_field.w / width).min(self.pos_field.h / height);
+        let (w, h) = (width * tile, height * tile);
+        (Rectangle::new(self.pos_field.x + 0.5 * (self.pos_field.w - w), self.pos_field.bottom() - h, w, h), tile)
+    }
+
+    fn block(canvas: &mut Canvas, clip: Option<Rectangle>, x: f32, y: f32, sz: f32, color: Color) {
+        if let Some(clip) = clip {
+            if x < clip.x - 0.5 || y < clip.y - 0.5 || x + sz > clip.right() + 0.5 || y + sz > clip.bottom() + 0.5 {
+                return;
+            }
+        }
+
+        let border = 0.05 * sz;
+        canvas.fill_rect(x, y, sz, sz, color.scaled(0.6));
+        canvas.fill_rect(x + border, y + border, sz - 2.0 * border, sz - 2.0 * border, color);
+    }
+
+    fn piece(canvas: &mut Canvas, clip: Option<Rectangle>, piece: piece::Piece, x: f32, y: f32, sz: f32, color: Color) {
+        for (i, j) in piece.cells() {
+            Self::block(canvas, clip, x + i as f32 * sz, y - j as f32 * sz, sz, color);
+        }
+    }
+
+    pub fn render(&self, canvas: &mut Canvas, timestamp: i32, state: &Snapshot) {
+        let colors = Self::level_colors(state.level(), &self.pieces);
+        let color = |tp: piece::Type| match tp {
+            piece::Type::Garbage => Color::rgb(0.4, 0.4, 0.4),
+            _ => colors.get(tp.to_int() as usize).cloned().unwrap_or(Color::rgb(0.4, 0.4, 0.4)),
+        };
+        let (pos_field, tile_size) = self.field(state);
+
+        canvas.clear(Color::rgb(0.0, 0.15, 0.2));
+        canvas.fill_rect(pos_field.x, pos_field.y, pos_field.w, pos_field.h, Color::rgb(0.0, 0.0, 0.0));
+        canvas.fill_rect(self.pos_next.x, self.pos_next.y, self.pos_next.w, self.pos_next.h, Color::rgb(0.0, 0.0, 0.0));
+
+        // compute line burn
+        let t = state.are_duration()
+            .map(|d| (timestamp - state.timestamp()) as f32 / d as f32)
+            .unwrap_or(1.0);
+        let burn = state.animation().map(|a| a.0);
+
+        // stack
+        let stack = state.stack().blocks();
+        let field = Some(pos_field);
+        stack.for_each(&mut |x, y, tp| {
+            if *tp != piece::Type::None {
+                let visible = match &burn {
+                    Some(burn) => !burn.contains(&y) || t < 2.0 * (0.5 - x as f32 / (stack.width() - 1) as f32).abs(),
+                    None => true,
+                };
+                if visible {
+                    Self::block(canvas, field,
+                                pos_field.x + tile_size * x as f32,
+                                pos_field.bottom() - tile_size * (y + 1) as f32,
+                                tile_size, color(*tp));
+                }
+            }
+        });
+
+        // ghost piece
+        if self.ghost_piece {
+            if let Some(piece) = state.ghost_piece() {
+                let col = color(piece.0.get_type());
+                Self::piece(canvas, field, piece.0,
+                            pos_field.x + tile_size * piece.1 as f32,
+                            pos_field.bottom() - tile_size * (piece.2 + 1) as f32,
+                            tile_size, Color::new(col.r, col.g, col.b, 0.4));
+            }
+        }
+
+        // current piece
+        if let Some(piece) = state.piece() {
+            Self::piece(canvas, field, piece.0,
+                        pos_field.x + tile_size * piece.1 as f32,
+                        pos_field.bottom() - tile_size * (piece.2 + 1) as f32,
+                        tile_size, color(piece.0.get_type()));
+        }
+
+        // next piece, big ones get shrunk to fit the box
+        let next = state.next_piece();
+        let ofs = next.offset();
+        let tile = self.pos_next.w / next.size().max(4) as f32;
+        Self::piece(canvas, None, next,
+                    self.pos_next.x + ofs.0 * tile,
+                    self.pos_next.bottom() + (ofs.1 - 1.0) * tile,
+                    tile, color(next.get_type()));
+
+        // info panel
+        let white = Color::rgb(1.0, 1.0, 1.0);
+        let fontsize = (3.0 * self.scale).max(1.0);
+        let lines = [
+            format!(" LEVEL: {}", state.level()),
+            format!(" SCORE: {}", state.score()),
+            format!(" LINES: {}", state.lines()),
+            format!("TETRIS: {}%", (100.0 * state.tetris_rate()) as i32),
+        ];
+        for (i, line) in lines.iter().enumerate() {
+            canvas.text(self.pos_info.x, self.pos_info.y + (10.0 + 30.0 * i as f32) * self.scale, fontsize, line, white);
+        }
+
+        // stats panel: drought | piece | count, more than seven pieces get squeezed together
+        let types = state.stats().types();
+        let spacing = 80.0 * 7.0 / types.len().max(7) as f32;
+        for (i, tp) in types.iter().cloned().enumerate() {
+            let stats = state.stats().get(tp);
+            let y = self.pos_stats.y + spacing * self.scale * i as f32;
+
+            let pc = self.pieces.piece(tp);
+            let ofs = pc.offset();
+            let sz = 20.0 * spacing / 80.0 * 4.0 / pc.size().max(4) as f32 * self.scale;
+            Self::piece(canvas, None, pc,
+                        self.pos_stats.x + 40.0 * self.scale + ofs.0 * sz,
+                        y + (ofs.1 + 3.0) * sz,
+                        sz, color(tp));
+
+            // same drought color ramp as in the app
+            let step = 10;
+            let drought = if stats.1 < step {
+                Color::new(1.0, 1.0, 1.0, stats.1 as f32 / step as f32)
+            } else if stats.1 < 2 * step {
+                Color::rgb(1.0, 1.0, 2.0 - stats.1 as f32 / step as f32)
+            } else if stats.1 < 3 * step {
+                Color::rgb(1.0, 3.0 - stats.1 as f32 / step as f32, 0.0)
+            } else {
+                Color::rgb(1.0, 0.0, 0.0)
+            };
+
+            let texty = y + 30.0 * spacing / 80.0 * self.scale;
+            canvas.text(self.pos_stats.x, texty, fontsize, &stats.1.to_string(), drought);
+            canvas.text(self.pos_stats.x + 140.0 * self.scale, texty, fontsize, &stats.0.to_string(), white);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tetris::replay::{Replay, Replayer};
+
+    const REPLAY: &str = include_str!("../../tetris/tests/replays/nes_level0.txt");
+
+    // FNV-1a of the pixels
+    fn checksum(canvas: &Canvas) -> u64 {
+        canvas.data().iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+    }
+
+    fn render(scene: &Scene, replayer: &mut Replayer, time: f32) -> u64 {
+        let mut canvas = Canvas::new(scene.size().0, scene.size().1);
+        replayer.jump(time);
+        scene.render(&mut canvas, replayer.timestamp(), replayer.snapshot());
+        checksum(&canvas)
+    }
+
+    #[test]
+    fn renders_the_same_frames() {
+        let replay = Replay::from_text(REPLAY).unwrap();
+        let scene = Scene::new(0.5);
+        let mut replayer = Replayer::new(&replay).unwrap();
+        let times = [0.0, 5.0, 20.0, replayer.length()];
+
+        let forward: Vec<u64> = times.iter().map(|time| render(&scene, &mut replayer, *time)).collect();
+        // jumping back from the end of a second replayer doesn't change a pixel
+        let mut again = Replayer::new(&replay).unwrap();
+        let mut backward: Vec<u64> = times.iter().rev().map(|time| render(&scene, &mut again, *time)).collect();
+        backward.reverse();
+        assert_eq!(forward, backward);
+
+        // has to change whenever the look of the frames does
+        assert_eq!(forward, vec!(15472587168776783049, 11525608428989401117, 6025101934922571935, 11011863273141980086));
+    }
+}
@@ -4,6 +4,7 @@ extern crate tetris;
 extern crate serde;
 extern crate base64;
 extern crate bincode;
+extern crate serde_json;
 #[macro_use] extern crate serde_derive;
 
 extern crate rusqlite;
@@ -18,6 +19,9 @@ use rusqlite::{Connection, NO_PARAMS};
 
 use chrono::TimeZone;
 
+/// Seconds without an update after which a live game counts as abandoned
+const LIVE_TIMEOUT: i64 = 60;
+
 fn open_database(path: &str) -> Result<Connection, String> {
     let exists = std::path::Path::new(path).exists();
 
@@ -40,9 +44,64 @@ fn open_database(path: &str) -> Result<Connection, String> {
         ).map_err(|err| String::from("Creation failed: ") + err.description())?;
     }
 
+    // games that are going on right now, newer than the database itself maybe
+    db.execute(
+        "CREATE TABLE IF NOT EXISTS live (
+            id          INTEGER PRIMARY KEY,
+            name        TEXT NOT NULL,
+            idtag       TEXT NOT NULL,
+            started     INTEGER,
+            updated     INTEGER,
+            score       INTEGER,
+            level       INTEGER,
+            finished    INTEGER,
+            game        BLOB
+        )",
+        NO_PARAMS
+    ).map_err(|err| String::from("Creation failed: ") + err.description())?;
+
     Ok(db)
 }
 
+/// Replays are stored as JSON so that the config can grow, rows from before level curves
+/// were bincode encoded
+fn deserialize_replay(data: &[u8]) -> Option<tetris::replay::Replay> {
+    serde_json::from_slice::<tetris::replay::Replay>(data).ok()
+        .or_else(|| bincode::deserialize::<tetris::replay::LegacyReplay>(data).ok().map(|replay| replay.into()))
+}
+
+/// The live game of a player, the idtag has to match the one it was started with
+fn load_live(db: &Connection, session: usize, idtag: Option<&str>) -> Result<(tetris::replay::Replay, bool), String> {
+    let (game, owner, finished): (Vec<u8>, String, i32) = db
+        .query_row_and_then("SELECT game, idtag, finished FROM live WHERE id = ?1", &[&(session as i32)],
+                            |row| Ok((row.get_checked(0)?, row.get_checked(1)?, row.get_checked(2)?)))
+        .map_err(|err: rusqlite::Error| String::from("SELECT failed: ") + &err.description())?;
+
+    if idtag.map_or(false, |idtag| idtag != owner) {
+        return Err(String::from("Not your game"));
+    }
+
+    let replay = deserialize_replay(&game).ok_or(String::from("Invalid replay data"))?;
+    Ok((replay, finished != 0))
+}
+
+/// Stores what the live game got up to, with its score and level for the list of live games
+fn store_live(db: &Connection, session: usize, replay: &tetris::replay::Replay, finished: bool) -> Result<(), String> {
+    let mut replayer = tetris::replay::Replayer::new(replay)?;
+    let len = replayer.length();
+    replayer.jump(len);
+    let state = replayer.snapshot();
+
+    let now = chrono::Utc::now().timestamp();
+    let game = serde_json::to_vec(replay).unwrap();
+    db.execute(
+        "UPDATE live SET updated = ?1, score = ?2, level = ?3, finished = ?4, game = ?5 WHERE id = ?6",
+        &[&now, &state.score(), &state.level(), &(finished as i32), &game as &ToSql, &(session as i32)]
+    ).map_err(|err| String::from("UPDATE failed: ") + &err.description())?;
+
+    Ok(())
+}
+
 fn process(message: ServerMessage) -> Result<ServerAnswer, String> {
     // open SQLite connection
     let db = open_database("/var/tetris/tetris.sqlite")?;
@@ -50,13 +109,13 @@ fn process(message: ServerMessage) -> Result<ServerAnswer, String> {
     let ret = match message {
         ServerMessage::UploadReplay { name, idtag, replay } => {
             // re-play to find out score and final level
-            let mut replayer = tetris::replay::Replayer::new(&replay);
+            let mut replayer = tetris::replay::Replayer::new(&replay)?;
             let len = replayer.length();
             replayer.jump(len);
             let state = replayer.snapshot();
 
             let now = chrono::Utc::now().timestamp();
-            let game = bincode::serialize(&replay).unwrap();
+            let game = serde_json::to_vec(&replay).unwrap();
 
             // get new ID
             let id: i32 = db.query_row_and_then(
@@ -99,27 +158,32 @@ fn process(message: ServerMessage) -> Result<ServerAnswer, String> {
                 .map_err(|err| String::from("SELECT failed: ") + &err.description())?;
 
             let iter = stmt
-                .query_map(NO_PARAMS, |row| {
+                .query_map(NO_PARAMS, |row| -> Result<tetris::PlayedGame, String> {
                     let id: i32 = row.get(0);
                     let ts = chrono::Utc.timestamp(row.get(2), 0);
                     let replay: Vec<u8> = row.get(5);
-                    let replay = bincode::deserialize::<tetris::replay::Replay>(&replay).unwrap();
+                    let replay = deserialize_replay(&replay).ok_or(format!("Replay {} has invalid data", id))?;
 
-                    tetris::PlayedGame::new(
+                    Ok(tetris::PlayedGame::new(
                         id as usize,
                         ts,
                         row.get(1),
                         row.get(4),
                         replay.config().level,
                         row.get(3),
-                        replay.frames() as f32 / 1000.0
-                    )
+                        replay.duration()
+                    ))
                 })
                 .map_err(|err| String::from("query_map failed: ") + &err.description())?;
 
+            // like with the live games, broken rows only show up in the log
             let mut ret = Vec::new();
             for game in iter {
-                ret.push(game.unwrap());
+                match game {
+                    Ok(Ok(game)) => ret.push(game),
+                    Ok(Err(err)) => eprintln!("{}", err),
+                    Err(err) => eprintln!("Skipping highscore: {}", err.description()),
+                }
             }
 
             ServerAnswer::HighscoreList {
@@ -138,12 +202,94 @@ fn process(message: ServerMessage) -> Result<ServerAnswer, String> {
                 .query_row_and_then("SELECT game FROM replay WHERE id = ?1", &[&id], |row| row.get_checked(0))
                 .map_err(|err| String::from("SELECT failed: ") + &err.description())?;
 
-            let replay = bincode::deserialize::<tetris::replay::Replay>(&replay).unwrap();
+            let replay = deserialize_replay(&replay).ok_or(String::from("Invalid replay data"))?;
 
             ServerAnswer::ReplayList {
                 data: vec!((id as usize, replay))
             }
         },
+
+        ServerMessage::StartLive { name, idtag, replay } => {
+            let now = chrono::Utc::now().timestamp();
+
+            // clients that went away don't say so
+            db.execute("DELETE FROM live WHERE updated < ?1", &[&(now - LIVE_TIMEOUT)])
+                .map_err(|err| String::from("DELETE failed: ") + &err.description())?;
+
+            let id: i32 = db.query_row_and_then(
+                "SELECT MAX(id) FROM live",
+                NO_PARAMS,
+                |row| row.get_checked(0)
+            ).unwrap_or(0) + 1;
+
+            db.execute(
+                "INSERT INTO live (id, name, idtag, started, updated, score, level, finished, game)
+                VALUES (?1, ?2, ?3, ?4, ?4, 0, ?5, 0, ?6)",
+                &[&id, &name as &ToSql, &idtag as &ToSql, &now, &replay.config().level, &serde_json::to_vec(&replay).unwrap()]
+            ).map_err(|err| String::from("INSERT failed: ") + &err.description())?;
+            store_live(&db, id as usize, &replay, false)?;
+
+            ServerAnswer::LiveUpdated { session: id as usize, entries: replay.position().0 }
+        },
+
+        ServerMessage::UpdateLive { session, idtag, delta, finished } => {
+            let (mut replay, _) = load_live(&db, session, Some(&idtag))?;
+
+            // a delta that doesn't fit gets dropped, the answer tells the client where to go on
+            if replay.apply(&delta).is_ok() {
+                store_live(&db, session, &replay, finished)?;
+            }
+
+            ServerAnswer::LiveUpdated { session, entries: replay.position().0 }
+        },
+
+        ServerMessage::RequestLiveGames => {
+            let since = chrono::Utc::now().timestamp() - LIVE_TIMEOUT;
+            let mut stmt = db
+                .prepare("SELECT id, name, started, score, level, game FROM live WHERE finished = 0 AND updated >= ?1 ORDER BY score DESC")
+                .map_err(|err| String::from("SELECT failed: ") + &err.description())?;
+
+            let iter = stmt
+                .query_map(&[&since], |row| -> Result<tetris::PlayedGame, String> {
+                    let id: i32 = row.get(0);
+                    let replay: Vec<u8> = row.get(5);
+                    let replay = deserialize_replay(&replay).ok_or(format!("Live game {} has invalid replay data", id))?;
+
+                    Ok(tetris::PlayedGame::new(
+                        id as usize,
+                        chrono::Utc.timestamp(row.get(2), 0),
+                        row.get(1),
+                        row.get(3),
+                        replay.config().level,
+                        row.get(4),
+                        replay.duration()
+                    ))
+                })
+                .map_err(|err| String::from("query_map failed: ") + &err.description())?;
+
+            // the answer goes to stdout, what's left out to the log of the web server
+            let mut ret = Vec::new();
+            for game in iter {
+                match game {
+                    Ok(Ok(game)) => ret.push(game),
+                    Ok(Err(err)) => eprintln!("{}", err),
+                    Err(err) => eprintln!("Skipping live game: {}", err.description()),
+                }
+            }
+
+            ServerAnswer::LiveGames(ret)
+        },
+
+        ServerMessage::WatchLive { session, from } => {
+            let (replay, finished) = load_live(&db, session, None)?;
+
+            ServerAnswer::LiveData {
+                session,
+                header: if from == 0 { Some(replay.header()) } else { None },
+                delta: replay.delta(from),
+                finished,
+            }
+        },
     };
 
     Ok(ret)
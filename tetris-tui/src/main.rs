@@ -0,0 +1,460 @@
+extern crate tetris;
+extern crate tetris_client as client;
+extern crate crossterm;
+
+mod screen;
+
+use std::time::{Duration, Instant};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+
+use tetris::replay::{Replay, Replayer};
+
+// Terminals don't report key releases. A press is a tap until the terminal starts repeating the
+// key, usually after 500 to 660ms, and the key counts as held until the repeats stop.
+const REPEAT_DELAY: i32 = 40;
+// most terminals repeat 25 to 30 times per second, slower ones about 10
+const REPEAT_INTERVAL: i32 = 8;
+
+const USAGE: &str = "Usage: tetris-tui [options]
+
+Options:
+    --level <n>       starting level (default: 0)
+    --curve <name>    speed curve: nes, pal, guideline or 20g (default: nes)
+    --pieces <name>   tetromino, pentomino, big or a file with custom pieces (default: tetromino)
+    --size <w>x<h>    board size (default: 10x20)
+    --name <name>     player name for uploaded games
+    --replay <file>   watch a replay file instead of playing
+    --server <id>     watch a replay from the server instead of playing
+
+Game:   Left/Right/Down move, Y/Z rotate left, X rotate right, P pause, Q quit
+Replay: Space pause, Left/Right seek (Ctrl: 10s), Up/Down speed, Q quit";
+
+struct Options {
+    level: i32,
+    curve: tetris::LevelCurve,
+    pieces: tetris::PieceSet,
+    size: (i32, i32),
+    name: String,
+    replay: Option<String>,
+    server_id: Option<usize>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Key {
+    Left,
+    Right,
+    Down,
+    RotateLeft,
+    RotateRight,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Hold {
+    Released,
+    /// frames since the press, the game gets to see it for one of them
+    Tapped(i32),
+    /// frames until it counts as released
+    Repeating(i32),
+}
+
+/// What a key event means for the game
+#[derive(Clone, Copy, PartialEq)]
+enum Press {
+    /// pressed, but released again after a frame unless it turns out to be held
+    Tap,
+    /// the first repeat, the key is held down from now on
+    Hold,
+    /// any later repeat
+    Repeat,
+}
+
+struct HeldKeys {
+    keys: [Hold; 5],
+}
+
+impl HeldKeys {
+    fn new() -> Self {
+        HeldKeys { keys: [Hold::Released; 5] }
+    }
+
+    fn press(&mut self, key: Key) -> Press {
+        let hold = &mut self.keys[key as usize];
+        let ret = match *hold {
+            Hold::Released => Press::Tap,
+            Hold::Tapped(_) => Press::Hold,
+            Hold::Repeating(_) => Press::Repeat,
+        };
+        *hold = if ret == Press::Tap { Hold::Tapped(0) } else { Hold::Repeating(REPEAT_INTERVAL) };
+        ret
+    }
+
+    /// Counts the frames, returns the keys the game should see released
+    fn tick(&mut self) -> Vec<Key> {
+        let keys = [Key::Left, Key::Right, Key::Down, Key::RotateLeft, Key::RotateRight];
+        let mut released = Vec::new();
+        for key in keys.iter() {
+            let hold = &mut self.keys[*key as usize];
+            *hold = match *hold {
+                Hold::Released => Hold::Released,
+                Hold::Tapped(frames) => {
+                    if frames == 1 {
+                        released.push(*key);
+                    }
+                    // no repeat came, so the next press is a tap again
+                    if frames + 1 >= REPEAT_DELAY { Hold::Released } else { Hold::Tapped(frames + 1) }
+                }
+                Hold::Repeating(frames) if frames > 1 => Hold::Repeating(frames - 1),
+                Hold::Repeating(_) => {
+                    released.push(*key);
+                    Hold::Released
+                }
+            };
+        }
+        released
+    }
+}
+
+enum Mode {
+    Game {
+        game: tetris::game::Game,
+        keys: HeldKeys,
+        paused: bool,
+        finished: bool,
+    },
+    Replay {
+        replayer: Replayer,
+    },
+}
+
+struct TetrisTui {
+    config: tetris::Config,
+    name: String,
+    server: client::ServerConfig,
+    mode: Mode,
+    message: String,
+    quit: bool,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut ret = Options {
+        level: 0,
+        curve: tetris::LevelCurve::Nes,
+        pieces: tetris::PieceSet::Tetromino,
+        size: (10, 20),
+        name: std::env::var("USER").unwrap_or(String::from("Terminal")),
+        replay: None,
+        server_id: None,
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("Missing value for {}", name));
+
+        match arg.as_str() {
+            "--level" => {
+                let level = value("--level")?;
+                ret.level = level.parse().map_err(|_| format!("Invalid level: {}", level))?;
+            }
+            "--curve" => {
+                let curve = value("--curve")?;
+                ret.curve = match curve.to_lowercase().as_str() {
+                    "nes" => tetris::LevelCurve::Nes,
+                    "pal" => tetris::LevelCurve::Pal,
+                    "guideline" => tetris::LevelCurve::Guideline,
+                    "20g" => tetris::LevelCurve::TwentyG,
+                    _ => return Err(format!("Unknown speed curve: {}", curve)),
+                };
+            }
+            "--pieces" => {
+                let pieces = value("--pieces")?;
+                ret.pieces = match pieces.to_lowercase().as_str() {
+                    "tetromino" => tetris::PieceSet::Tetromino,
+                    "pentomino" => tetris::PieceSet::Pentomino,
+                    "big" => tetris::PieceSet::Big,
+                    _ => {
+                        let data = std::fs::read_to_string(&pieces).map_err(|err| format!("Can't read {}: {}", pieces, err))?;
+                        tetris::PieceSet::parse(&data).map_err(|err| format!("{}: {}", pieces, err))?
+                    }
+                };
+            }
+            "--size" => {
+                let size = value("--size")?;
+                let mut dims = size.split('x').map(|dim| dim.parse::<i32>().ok().filter(|dim| tetris::BOARD_SIZES.contains(dim)));
+                ret.size = match (dims.next(), dims.next(), dims.next()) {
+                    (Some(Some(width)), Some(Some(height)), None) => (width, height),
+                    _ => return Err(format!("Invalid board size: {}", size)),
+                };
+            }
+            "--name" => ret.name = value("--name")?,
+            "--replay" => ret.replay = Some(value("--replay")?),
+            "--server" => {
+                let id = value("--server")?;
+                ret.server_id = Some(id.parse().map_err(|_| format!("Invalid replay ID: {}", id))?);
+            }
+            "-h" | "--help" => return Err(String::new()),
+            _ => return Err(format!("Unknown option: {}", arg)),
+        }
+    }
+
+    Ok(ret)
+}
+
+fn load_from_server(server: &client::ServerConfig, id: usize) -> Result<Replay, String> {
+    match server.request_replay(id).response() {
+        client::Response::Success(tetris::networking::ServerAnswer::ReplayList { data }) => {
+            data.into_iter()
+                .find(|entry| entry.0 == id)
+                .map(|entry| entry.1)
+                .ok_or(format!("Server didn't send replay {}", id))
+        }
+        client::Response::Success(answer) => Err(format!("Unexpected server answer: {:?}", answer)),
+        client::Response::Waiting => Err(String::from("No answer from server")),
+        client::Response::HttpError(err) => Err(format!("HTTP error: {}", err)),
+        client::Response::ParseError(err) => Err(format!("Parse error: {}", err)),
+    }
+}
+
+fn adjust_speed(value: &mut f32, delta: f32) {
+    let v = value.signum() * value.abs().sqrt() + delta;
+    *value = v.signum() * v.powi(2).min(20.0);
+}
+
+impl TetrisTui {
+    fn new_game(config: &tetris::Config) -> Mode {
+        Mode::Game {
+            game: tetris::game::Game::new(config),
+            keys: HeldKeys::new(),
+            paused: false,
+            finished: false,
+        }
+    }
+
+    fn upload(&mut self, replay: &Replay) {
+        self.message = match self.server.upload_replay(&self.name, replay).response() {
+            client::Response::Success(tetris::networking::ServerAnswer::UploadResult(Some(game))) => {
+                format!("Uploaded as #{}, score {}", game.replay(), game.score())
+            }
+            client::Response::Success(answer) => format!("Upload failed: {:?}", answer),
+            client::Response::Waiting => String::from("Upload failed: no answer from server"),
+            client::Response::HttpError(err) => format!("Upload failed: {}", err),
+            client::Response::ParseError(err) => format!("Upload failed: {}", err),
+        };
+    }
+
+    fn save(&mut self, replay: &Replay) {
+        let unixtime = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+        let path = format!("tetris-{}.replay", unixtime);
+        self.message = match std::fs::write(&path, replay.to_text()) {
+            Ok(()) => format!("Saved replay to {}", path),
+            Err(err) => format!("Can't write {}: {}", path, err),
+        };
+    }
+
+    fn key(&mut self, event: KeyEvent) {
+        let ctrl = event.modifiers.contains(KeyModifiers::CONTROL);
+        if event.code == KeyCode::Char('q') || event.code == KeyCode::Esc || (ctrl && event.code == KeyCode::Char('c')) {
+            self.quit = true;
+            return;
+        }
+
+        let mut new_mode = None;
+        let mut finished_replay = None;
+
+        match &mut self.mode {
+            Mode::Game { game, keys, paused, finished } => {
+                if *finished {
+                    match event.code {
+                        KeyCode::Char('n') => new_mode = Some(Self::new_game(&self.config)),
+                        KeyCode::Char('r') => new_mode = Replayer::new(game.replay()).ok().map(|replayer| Mode::Replay { replayer }),
+                        KeyCode::Char('s') | KeyCode::Char('u') => finished_replay = Some((event.code, game.replay().clone())),
+                        _ => {}
+                    }
+                } else if event.code == KeyCode::Char('p') || event.code == KeyCode::Enter {
+                    *paused = !*paused;
+                } else if !*paused {
+                    // a tap moves once, holding starts DAS once the terminal repeats the key.
+                    // Rotating twice with the same key needs a pause of the repeat delay.
+                    let key = match event.code {
+                        KeyCode::Left => Some(Key::Left),
+                        KeyCode::Right => Some(Key::Right),
+                        KeyCode::Down => Some(Key::Down),
+                        KeyCode::Char('y') | KeyCode::Char('z') => Some(Key::RotateLeft),
+                        KeyCode::Char('x') => Some(Key::RotateRight),
+                        _ => None,
+                    };
+                    if let Some(key) = key {
+                        match (key, keys.press(key)) {
+                            (_, Press::Repeat) => {}
+                            (Key::Left, _) => game.left(true),
+                            (Key::Right, _) => game.right(true),
+                            (Key::Down, _) => game.down(true),
+                            (Key::RotateLeft, Press::Tap) => game.rotate(false),
+                            (Key::RotateRight, Press::Tap) => game.rotate(true),
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            Mode::Replay { replayer } => match event.code {
+                KeyCode::Left => replayer.advance(if ctrl { -10.0 } else { -1.0 }),
+                KeyCode::Right => replayer.advance(if ctrl { 10.0 } else { 1.0 }),
+                KeyCode::Up => adjust_speed(&mut replayer.speed, 0.1),
+                KeyCode::Down => adjust_speed(&mut replayer.speed, -0.1),
+                KeyCode::Char(' ') | KeyCode::Enter => replayer.paused = !replayer.paused,
+                _ => {}
+            }
+        }
+
+        match finished_replay {
+            Some((KeyCode::Char('s'), replay)) => self.save(&replay),
+            Some((_, replay)) => self.upload(&replay),
+            None => {}
+        }
+
+        if let Some(mode) = new_mode {
+            self.message.clear();
+            self.mode = mode;
+        }
+    }
+
+    fn frame(&mut self) {
+        match &mut self.mode {
+            Mode::Game { game, keys, paused, finished } => {
+                if *paused || *finished {
+                    return;
+                }
+
+                for key in keys.tick() {
+                    match key {
+                        Key::Left => game.left(false),
+                        Key::Right => game.right(false),
+                        Key::Down => game.down(false),
+                        Key::RotateLeft | Key::RotateRight => {}
+                    }
+                }
+
+                if let Some(tetris::game::Outcome::Death) = game.frame() {
+                    *finished = true;
+                }
+                // nothing to play them on in a terminal
+                game.take_events();
+            }
+            Mode::Replay { replayer } => {
+                let dt = replayer.speed * replayer.config().frame_time();
+                replayer.advance(dt);
+            }
+        }
+    }
+
+    /// Seconds per frame of the game or replay that is running
+    fn frame_time(&self) -> f32 {
+        match &self.mode {
+            Mode::Game { game, .. } => game.config().frame_time(),
+            Mode::Replay { replayer } => replayer.config().frame_time(),
+        }
+    }
+
+    fn text(&self) -> String {
+        let mut ret = match &self.mode {
+            Mode::Game { game, paused, finished, .. } => {
+                let status = if *finished {
+                    "Game over! [n]ew game, [r]eplay, [s]ave replay, [u]pload, [q]uit"
+                } else if *paused {
+                    "Paused"
+                } else {
+                    ""
+                };
+                format!("{}\n{}", game.snapshot(), status)
+            }
+            Mode::Replay { replayer } => {
+                format!("{}\nReplay {:.1}s / {:.1}s, speed {:.2}{}",
+                        replayer.snapshot(), replayer.frame(), replayer.length(), replayer.speed,
+                        if replayer.paused { ", paused" } else { "" })
+            }
+        };
+        ret.push('\n');
+        ret.push_str(&self.message);
+        ret
+    }
+}
+
+fn run(options: Options) -> Result<(), String> {
+    let mut config = tetris::Config::with_curve(options.curve);
+    config.pieces = options.pieces;
+    config.width = options.size.0;
+    config.height = options.size.1;
+    config.level = options.level;
+
+    let mut server = client::ServerConfig::new();
+    server.set_idtag(&client::gen_idtag());
+
+    let mode = if let Some(id) = options.server_id {
+        Mode::Replay { replayer: Replayer::new(&load_from_server(&server, id)?)? }
+    } else if let Some(path) = &options.replay {
+        let data = std::fs::read_to_string(path).map_err(|err| format!("Can't read {}: {}", path, err))?;
+        let replay = Replay::from_text(&data).ok_or(format!("{} doesn't contain a valid replay", path))?;
+        Mode::Replay { replayer: Replayer::new(&replay)? }
+    } else {
+        TetrisTui::new_game(&config)
+    };
+
+    let mut app = TetrisTui {
+        config,
+        name: options.name,
+        server,
+        mode,
+        message: String::new(),
+        quit: false,
+    };
+
+    let mut screen = screen::Screen::new()?;
+    let mut next_frame = Instant::now();
+
+    while !app.quit {
+        // handle input until the next frame is due
+        loop {
+            let timeout = next_frame.checked_duration_since(Instant::now()).unwrap_or(Duration::from_secs(0));
+            if !event::poll(timeout).map_err(|err| err.to_string())? {
+                break;
+            }
+            if let Event::Key(key) = event::read().map_err(|err| err.to_string())? {
+                app.key(key);
+            }
+        }
+
+        // catch up if we fell behind, but don't try to make up for long stalls
+        let frame = Duration::from_micros((app.frame_time() * 1e6) as u64);
+        let now = Instant::now();
+        let mut frames = 0;
+        while next_frame <= now && frames < 10 {
+            app.frame();
+            next_frame += frame;
+            frames += 1;
+        }
+        if next_frame <= now {
+            next_frame = now + frame;
+        }
+
+        screen.draw(&app.text());
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(err) => {
+            if !err.is_empty() {
+                eprintln!("{}\n", err);
+            }
+            eprintln!("{}", USAGE);
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(err) = run(options) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,40 @@
+use std::io::{Stdout, Write};
+
+use crossterm::{cursor, terminal, QueueableCommand, ExecutableCommand};
+use crossterm::style::Print;
+
+/// Puts the terminal into raw mode on an alternate screen, and restores it when dropped
+pub struct Screen {
+    out: Stdout,
+}
+
+impl Screen {
+    pub fn new() -> Result<Self, String> {
+        let mut out = std::io::stdout();
+        terminal::enable_raw_mode().map_err(|err| format!("Can't enable raw mode: {}", err))?;
+        out.execute(terminal::EnterAlternateScreen)
+            .and_then(|out| out.execute(cursor::Hide))
+            .map_err(|err| format!("Can't set up terminal: {}", err))?;
+        Ok(Screen { out })
+    }
+
+    /// Replaces the screen contents with the given text
+    pub fn draw(&mut self, text: &str) {
+        let _ = self.out.queue(cursor::MoveTo(0, 0));
+        for (row, line) in text.lines().enumerate() {
+            let _ = self.out.queue(cursor::MoveTo(0, row as u16));
+            let _ = self.out.queue(Print(line));
+            let _ = self.out.queue(terminal::Clear(terminal::ClearType::UntilNewLine));
+        }
+        let _ = self.out.queue(terminal::Clear(terminal::ClearType::FromCursorDown));
+        let _ = self.out.flush();
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = self.out.execute(cursor::Show);
+        let _ = self.out.execute(terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
@@ -0,0 +1,8 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate tetris;
+
+// see tetris::sim::fuzz for how the bytes turn into a game
+fuzz_target!(|data: &[u8]| {
+    tetris::sim::fuzz(data);
+});
@@ -0,0 +1,414 @@
+//! Per-piece statistics of a played game, computed from its replay.
+
+use std::collections::VecDeque;
+
+use super::piece;
+use super::replay::{Replay, Replayer};
+use super::stack::Stack;
+use super::state::{GameHistory, Snapshot};
+
+/// Everything about a single placed piece, board metrics are taken after its lines got cleared
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Placement {
+    pub index: usize,
+    pub piece: piece::Type,
+    /// frame numbers of the spawn and the lock
+    pub spawned: i32,
+    pub locked: i32,
+    pub x: i32,
+    pub y: i32,
+    /// key presses that rotated or shifted the piece, holding a key for auto shift counts once
+    pub inputs: i32,
+    /// inputs needed by the shortest path to the same placement, auto shifting to a wall is one
+    pub optimal_inputs: i32,
+    pub finesse_faults: i32,
+    pub lines: i32,
+    pub level: i32,
+    pub score: i32,
+    pub height: i32,
+    pub holes: i32,
+    /// depth of the deepest well
+    pub well: i32,
+    /// an I piece would score a tetris
+    pub tetris_ready: bool,
+}
+
+impl Placement {
+    pub fn placement_time(&self) -> i32 {
+        self.locked - self.spawned
+    }
+
+    pub fn is_burn(&self) -> bool {
+        self.lines > 0 && self.lines < 4
+    }
+}
+
+/// Totals over all placements
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Summary {
+    pub pieces: usize,
+    pub average_placement_time: f32,
+    pub inputs: i32,
+    pub finesse_faults: i32,
+    pub burned_lines: i32,
+    pub tetris_lines: i32,
+    pub tetris_rate: f32,
+    /// share of placements that left the board tetris ready
+    pub tetris_readiness: f32,
+    pub max_height: i32,
+}
+
+/// Notable placements, to mark them on the timeline of a replay
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Marker {
+    Tetris,
+    Burn,
+    LevelUp,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Timeline {
+    /// frames per second of the timestamps
+    pub fps: f32,
+    /// the game started on
+    pub level: i32,
+    pub placements: Vec<Placement>,
+}
+
+fn column_heights(stack: &Stack) -> Vec<i32> {
+    let blocks = stack.blocks();
+    (0..blocks.width())
+        .map(|x| (0..blocks.height()).rev()
+            .find(|y| *blocks.at(x, *y) != piece::Type::None)
+            .map_or(0, |y| y as i32 + 1))
+        .collect()
+}
+
+/// Empty cells that have a block somewhere above them
+pub fn holes(stack: &Stack) -> i32 {
+    let blocks = stack.blocks();
+    column_heights(stack).iter().enumerate()
+        .map(|(x, h)| (0..*h as usize).filter(|y| *blocks.at(x, *y) == piece::Type::None).count() as i32)
+        .sum()
+}
+
+pub fn height(stack: &Stack) -> i32 {
+    column_heights(stack).into_iter().max().unwrap_or(0)
+}
+
+/// Depth of the deepest column that is lower than both of its neighbours, walls count as infinitely high
+pub fn well_depth(stack: &Stack) -> i32 {
+    let heights = column_heights(stack);
+    (0..heights.len())
+        .map(|x| {
+            let left = if x > 0 { heights[x - 1] } else { i32::MAX };
+            let right = heights.get(x + 1).cloned().unwrap_or(i32::MAX);
+            left.min(right).saturating_sub(heights[x]).min(stack.height())
+        })
+        .max()
+        .unwrap_or(0)
+        .max(0)
+}
+
+/// True if some column is open down to four rows that are full apart from that column
+pub fn tetris_ready(stack: &Stack) -> bool {
+    let blocks = stack.blocks();
+    let heights = column_heights(stack);
+    (0..blocks.width()).any(|well| {
+        let bottom = heights[well] as usize;
+        bottom + 4 <= blocks.height() && (bottom..bottom + 4).all(|y| {
+            (0..blocks.width()).all(|x| (x == well) == (*blocks.at(x, y) == piece::Type::None))
+        })
+    })
+}
+
+fn cells(piece: piece::Piece, x: i32, y: i32) -> Vec<(i32, i32)> {
+    let mut ret: Vec<(i32, i32)> = piece.cells().map(|(i, j)| (x + i, y + j)).collect();
+    ret.sort();
+    ret
+}
+
+/// Fewest rotations and shifts that put the piece from its spawn position above its final place,
+/// or None if it can't get there without dropping first (tucks and spins). Holding a direction
+/// until the piece hits the wall or the stack is a single input.
+fn optimal_inputs(stack: &Stack, spawn: (piece::Piece, i32, i32), target: (piece::Piece, i32, i32)) -> Option<i32> {
+    let goal = cells(target.0, target.1, target.2);
+    let y = spawn.2;
+
+    let mut visited = vec!((spawn.0, spawn.1));
+    let mut queue = VecDeque::new();
+    queue.push_back((spawn.0, spawn.1, 0));
+
+    while let Some((piece, x, count)) = queue.pop_front() {
+        if cells(piece, x, target.2) == goal {
+            return Some(count);
+        }
+
+        let slide = |direction: i32| {
+            let mut nx = x;
+            while stack.fits(piece, nx + direction, y) {
+                nx += direction;
+            }
+            nx
+        };
+        let moves = [(piece.rotate(true), x), (piece.rotate(false), x), (piece, x - 1), (piece, x + 1), (piece, slide(-1)), (piece, slide(1))];
+        for (next, nx) in moves.iter() {
+            if stack.fits(*next, *nx, y) && !visited.contains(&(*next, *nx)) {
+                visited.push((*next, *nx));
+                queue.push_back((*next, *nx, count + 1));
+            }
+        }
+    }
+
+    None
+}
+
+impl Timeline {
+    pub fn from_replay(replay: &Replay) -> Result<Self, String> {
+        Ok(Self::new(replay, Replayer::new(replay)?.history()))
+    }
+
+    /// The history has to be the one of the replay, played to the end
+    pub fn new(replay: &Replay, history: &GameHistory) -> Self {
+        let inputs = replay.inputs_per_piece();
+        let frames = history.frames();
+        let mut placements = Vec::new();
+
+        // the frames between a spawn and the next ARE belong to one piece
+        let mut start = 0;
+        while start < frames.len() {
+            let end = match frames[start..].iter().position(|frame| frame.piece().is_none()) {
+                Some(len) => start + len,
+                None => break,
+            };
+
+            if end > start {
+                let index = placements.len();
+                placements.push(Self::placement(index, &frames[start..end], &frames[end], inputs.get(index).cloned().unwrap_or(0)));
+            }
+
+            start = end + frames[end..].iter().position(|frame| frame.piece().is_some()).unwrap_or(frames.len() - end);
+        }
+
+        Timeline { fps: history.config().fps(), level: history.config().level, placements }
+    }
+
+    fn placement(index: usize, moves: &[Snapshot], locked: &Snapshot, inputs: i32) -> Placement {
+        let spawn = moves[0].piece().unwrap();
+        let last = moves[moves.len() - 1].piece().unwrap();
+
+        let optimal = optimal_inputs(moves[0].stack(), spawn, last).unwrap_or(inputs).min(inputs);
+
+        // the stack of a line clear frame still shows the cleared lines
+        let (lines, stack) = match locked.animation() {
+            Some((rows, merged)) => (rows.len() as i32, merged.eliminate().0),
+            None => (0, locked.stack().clone()),
+        };
+
+        Placement {
+            index,
+            piece: spawn.0.get_type(),
+            spawned: moves[0].timestamp(),
+            locked: locked.timestamp(),
+            x: last.1,
+            y: last.2,
+            inputs,
+            optimal_inputs: optimal,
+            finesse_faults: inputs - optimal,
+            lines,
+            level: locked.level(),
+            score: locked.score(),
+            height: height(&stack),
+            holes: holes(&stack),
+            well: well_depth(&stack),
+            tetris_ready: tetris_ready(&stack),
+        }
+    }
+
+    /// The placement of the piece that is falling (or was placed last) at the given frame
+    pub fn at(&self, timestamp: i32) -> Option<&Placement> {
+        self.placements.iter().take_while(|p| p.spawned <= timestamp).last()
+    }
+
+    /// Tetrises, burns and level ups by the frame of their lock
+    pub fn markers(&self) -> Vec<(i32, Marker)> {
+        let mut ret = Vec::new();
+        let mut level = self.level;
+        for p in &self.placements {
+            if p.lines >= 4 {
+                ret.push((p.locked, Marker::Tetris));
+            } else if p.is_burn() {
+                ret.push((p.locked, Marker::Burn));
+            }
+            if p.level > level {
+                ret.push((p.locked, Marker::LevelUp));
+            }
+            level = p.level;
+        }
+        ret
+    }
+
+    /// The frame of the other game that is as far into the piece with the same index as this
+    /// game is at `timestamp`, to show two games side by side. Past the last piece of the other
+    /// game that's the end of it.
+    pub fn sync(&self, other: &Timeline, timestamp: i32) -> i32 {
+        let (index, offset) = match self.at(timestamp) {
+            Some(p) => (p.index, timestamp - p.spawned),
+            None => return timestamp,
+        };
+        match other.placements.get(index) {
+            Some(p) => {
+                let next = other.placements.get(index + 1).map_or(i32::MAX, |next| next.spawned - 1);
+                (p.spawned + offset).min(next)
+            }
+            None => i32::MAX,
+        }
+    }
+
+    pub fn summary(&self) -> Summary {
+        let count = self.placements.len();
+        let sum = |f: &dyn Fn(&Placement) -> i32| self.placements.iter().map(f).sum::<i32>();
+
+        let lines = sum(&|p| p.lines);
+        let tetris_lines = sum(&|p| if p.lines == 4 { 4 } else { 0 });
+
+        Summary {
+            pieces: count,
+            average_placement_time: sum(&|p| p.placement_time()) as f32 / count.max(1) as f32,
+            inputs: sum(&|p| p.inputs),
+            finesse_faults: sum(&|p| p.finesse_faults),
+            burned_lines: lines - tetris_lines,
+            tetris_lines,
+            tetris_rate: tetris_lines as f32 / lines.max(1) as f32,
+            tetris_readiness: sum(&|p| if p.tetris_ready { 1 } else { 0 }) as f32 / count.max(1) as f32,
+            max_height: self.placements.iter().map(|p| p.height).max().unwrap_or(0),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        #[derive(Serialize)]
+        struct Output<'a> {
+            fps: f32,
+            summary: Summary,
+            placements: &'a Vec<Placement>,
+        }
+
+        serde_json::to_string_pretty(&Output { fps: self.fps, summary: self.summary(), placements: &self.placements }).unwrap()
+    }
+
+    /// One line per placement, with a header line
+    pub fn to_csv(&self) -> String {
+        let mut ret = String::from("index,piece,spawned,locked,x,y,inputs,optimal_inputs,finesse_faults,lines,level,score,height,holes,well,tetris_ready\n");
+        for p in &self.placements {
+            ret += &format!("{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
+                            p.index, p.piece.symbol(), p.spawned, p.locked, p.x, p.y,
+                            p.inputs, p.optimal_inputs, p.finesse_faults, p.lines, p.level, p.score,
+                            p.height, p.holes, p.well, p.tetris_ready);
+        }
+        ret
+    }
+}
+
+/// Keys pressed over some frames of a recorded game
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Keys {
+    pub left: bool,
+    pub right: bool,
+    pub down: bool,
+    /// rotated, clockwise or not
+    pub rotate: Option<bool>,
+}
+
+/// Keys that were pressed in the frames after `from` up to `to`, as far as the moves of the
+/// piece tell. Soft drop shows as the piece falling faster than gravity pulls it.
+pub fn inputs(history: &GameHistory, from: i32, to: i32) -> Keys {
+    let frames = history.frames();
+    let mut ret = Keys::default();
+
+    // go back to the start of the range, then on to the spawn of that piece to know when it last fell
+    let end = frames.iter().position(|frame| frame.timestamp() > to).unwrap_or(frames.len());
+    let mut start = end.saturating_sub(1);
+    while start > 0 && frames[start].timestamp() > from {
+        start -= 1;
+    }
+    while start > 0 && frames[start - 1].piece().is_some() {
+        start -= 1;
+    }
+
+    let mut fell = frames.get(start).map_or(0, |frame| frame.timestamp());
+    for pair in frames[start..end].windows(2) {
+        let (a, b) = match (pair[0].piece(), pair[1].piece()) {
+            (Some(a), Some(b)) => (a, b),
+            _ => {
+                fell = pair[1].timestamp();
+                continue;
+            }
+        };
+        let timestamp = pair[1].timestamp();
+
+        if b.2 < a.2 {
+            let gravity = history.config().gravity(pair[1].level());
+            if timestamp > from && timestamp - fell < gravity {
+                ret.down = true;
+            }
+            fell = timestamp;
+        }
+        if timestamp <= from {
+            continue;
+        }
+        if b.1 < a.1 {
+            ret.left = true;
+        }
+        if b.1 > a.1 {
+            ret.right = true;
+        }
+        if b.0 != a.0 {
+            ret.rotate = Some(a.0.rotate(true) == b.0);
+        }
+    }
+
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notation::parse_grid;
+
+    #[test]
+    fn board_metrics() {
+        let stack = parse_grid("
+            ...JJ.....
+            OOT.JLLLL.
+            OOTTJSSSS.
+            IIITIIIII.
+            IIIIIIIII.
+        ", 10, 20).unwrap();
+
+        assert_eq!(height(&stack), 5);
+        assert_eq!(holes(&stack), 1);
+        assert_eq!(well_depth(&stack), 4);
+        assert!(!tetris_ready(&stack));
+
+        let ready = parse_grid("
+            XXXXXXXXX.
+            XXXXXXXXX.
+            XXXXXXXXX.
+            XXXXXXXXX.
+        ", 10, 20).unwrap();
+        assert!(tetris_ready(&ready));
+    }
+
+    #[test]
+    fn level_up_markers() {
+        let placement = |index: usize, lines: i32, level: i32| Placement {
+            index, piece: piece::Type::I, spawned: index as i32 * 100, locked: index as i32 * 100 + 50, x: 0, y: 0,
+            inputs: 0, optimal_inputs: 0, finesse_faults: 0, lines, level, score: 0,
+            height: 0, holes: 0, well: 0, tetris_ready: false,
+        };
+
+        // the first placement already reaches the next level
+        let timeline = Timeline { fps: 60.0, level: 5, placements: vec!(placement(0, 4, 6), placement(1, 1, 6)) };
+        assert_eq!(timeline.markers(), vec!((50, Marker::Tetris), (50, Marker::LevelUp), (150, Marker::Burn)));
+    }
+}
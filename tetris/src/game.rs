@@ -1,6 +1,12 @@
+use std::collections::VecDeque;
+
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
 use super::piece;
 use super::stack;
 use super::state::*;
+use super::notation::Position;
 
 #[derive(PartialEq,Clone,Copy,Debug)]
 enum Move {
@@ -26,12 +32,48 @@ pub enum Outcome {
     Death,
 }
 
+/// The I piece hasn't come for this many pieces when `Event::Drought` gets sent
+pub const DROUGHT_WARNING: i32 = 20;
+
+/// Everything that happens during a game, in order, for sounds and other effects.
+/// Collected until the frontend picks them up with `Game::take_events`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// a new piece appeared at the top of the field
+    Spawn(piece::Type),
+    Rotate { clockwise: bool },
+    /// one column to the left (-1) or right (1)
+    Shift(i32),
+    /// the piece became part of the stack
+    Lock(piece::Type),
+    /// rows that got cleared, the number of lines is the length
+    Clear(Vec<i32>),
+    /// the new level
+    LevelUp(i32),
+    TopOut,
+    /// pieces since the last I piece, sent once it reaches `DROUGHT_WARNING`
+    Drought(i32),
+}
+
 impl std::fmt::Debug for Outcome {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{:?}", self)
     }
 }
 
+// state at the spawn of a piece, to undo moves
+#[derive(Clone)]
+struct Checkpoint {
+    frames: usize,
+    timestamp: i32,
+    drop_timer: i32,
+    queue: VecDeque<piece::Type>,
+    // so the pieces after the undo are the same ones again
+    rng: StdRng,
+    replay: (usize, i32),
+}
+
+#[derive(Clone)]
 pub struct Game {
     config: super::Config,
 
@@ -53,36 +95,77 @@ pub struct Game {
     down: i32,
     down_das: i32,
 
+    // predetermined pieces, used up before random ones get generated
+    queue: VecDeque<piece::Type>,
+    checkpoints: Vec<Checkpoint>,
+    rng: StdRng,
+    events: Vec<Event>,
+
     replay: super::replay::Replay,
 }
 
 impl Game {
-    fn gen_piece(last: piece::Type) -> piece::Piece {
-        let first = rand::random::<u32>() % 8;
+    // NES style: roll once more than there are pieces, re-roll on a repeat or the extra number
+    fn gen_piece(config: &super::Config, rng: &mut StdRng, last: piece::Type) -> piece::Piece {
+        let types = config.pieces.types();
+        let first = rng.gen_range(0, types.len() + 1);
 
-        let tp = if first < 7 && first != last as u32 {
-            piece::Type::from_int(first)
+        let tp = if first < types.len() && types[first] != last {
+            types[first]
         } else {
-            piece::Type::from_int(rand::random::<u32>() % 7)
+            types[rng.gen_range(0, types.len())]
         };
 
-        piece::Piece::new(tp, 2)
+        config.pieces.piece(tp)
+    }
+
+    fn next_piece(&mut self, last: piece::Type) -> piece::Piece {
+        match self.queue.pop_front() {
+            Some(tp) => self.config.pieces.piece(tp),
+            None => Self::gen_piece(&self.config, &mut self.rng, last),
+        }
     }
 
     pub fn new(config: &super::Config) -> Self {
-        let first = Self::gen_piece(piece::Type::None);
-        let second = Self::gen_piece(first.get_type());
+        Self::from_position(config, &Position::new(stack::Stack::new(config.width as usize, config.height as usize), Vec::new()))
+    }
+
+    /// Starts playing on the given board, with the queued pieces coming first
+    pub fn from_position(config: &super::Config, position: &Position) -> Self {
+        Self::with_seed(config, position, rand::random())
+    }
+
+    /// Like `from_position`, but the same seed always deals the same pieces
+    pub fn with_seed(config: &super::Config, position: &Position, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut queue: VecDeque<piece::Type> = position.queue.iter().cloned().collect();
+        let first = match queue.pop_front() {
+            Some(tp) => config.pieces.piece(tp),
+            None => Self::gen_piece(config, &mut rng, piece::Type::None),
+        };
+        let second = match queue.pop_front() {
+            Some(tp) => config.pieces.piece(tp),
+            None => Self::gen_piece(config, &mut rng, first.get_type()),
+        };
         let timestamp = 0;
 
-        let state = GameHistory::new(config, first, second);
-        let replay = super::replay::Replay::new(config, first.get_type(), second.get_type(), timestamp);
+        let state = GameHistory::with_stack(config, position.stack.clone(), first, second);
+        let mut replay = super::replay::Replay::new(config, first.get_type(), second.get_type(), timestamp);
+        replay.set_stack(&position.stack);
 
-        Game {
+        // the board might not even have room for the first piece
+        let lost = Self::blocked(state.snapshot());
+        let events = match lost {
+            Some(_) => vec!(Event::TopOut),
+            None => vec!(Event::Spawn(first.get_type())),
+        };
+
+        let mut ret = Game {
             config: config.clone(),
 
             state,
             timestamp,
-            lost: None,
+            lost,
 
             are: false,
             drop_timer: -90,
@@ -97,10 +180,60 @@ impl Game {
             down_pressed: false,
             down_das: 0,
 
+            queue,
+            checkpoints: Vec::new(),
+            rng,
+            events,
+
             replay,
+        };
+        ret.checkpoint();
+        ret
+    }
+
+    fn blocked(snapshot: &Snapshot) -> Option<(i32, i32)> {
+        match snapshot.piece() {
+            Some(piece) if !snapshot.stack().fits(piece.0, piece.1, piece.2) => Some((snapshot.score(), snapshot.level())),
+            _ => None,
         }
     }
 
+    fn checkpoint(&mut self) {
+        self.checkpoints.push(Checkpoint {
+            frames: self.state.frame_count(),
+            timestamp: self.timestamp,
+            drop_timer: self.drop_timer,
+            queue: self.queue.clone(),
+            rng: self.rng.clone(),
+            replay: self.replay.position(),
+        });
+    }
+
+    /// Steps back to the spawn of the previous piece, or of the current one while it's still falling
+    /// and there is no previous one. Also revives lost games, and drops the events that didn't
+    /// get picked up, they happened after the spawn.
+    pub fn undo(&mut self) {
+        let falling = self.lost.is_none() && !self.are;
+        if falling && self.checkpoints.len() > 1 {
+            self.checkpoints.pop();
+        }
+
+        let checkpoint = self.checkpoints.last().unwrap().clone();
+        self.state.truncate(checkpoint.frames);
+        self.timestamp = checkpoint.timestamp;
+        self.drop_timer = checkpoint.drop_timer;
+        self.queue = checkpoint.queue;
+        self.rng = checkpoint.rng;
+        self.replay.rewind(checkpoint.replay);
+        self.events.clear();
+
+        self.lost = Self::blocked(self.state.snapshot());
+        self.are = false;
+        self.movekey = self.new_movekey;
+        self.das = 0;
+        self.down = 0;
+    }
+
     fn try_move(&mut self, rotate: Option<bool>, dx: i32, dy: i32) -> bool {
         if self.lost.is_some() {
             return false;
@@ -157,7 +290,9 @@ impl Game {
     }
 
     pub fn rotate(&mut self, clockwise: bool) {
-        self.try_move(Some(clockwise), 0, 0);
+        if self.try_move(Some(clockwise), 0, 0) {
+            self.events.push(Event::Rotate { clockwise });
+        }
     }
 
     fn move_down(&mut self) -> Option<Outcome> {
@@ -174,12 +309,29 @@ impl Game {
         // try to drop piece one tile further and merge it if it doesn't work
         if !self.try_move(None, 0, -1) {
             // generate next piece
-            let next_piece = Self::gen_piece(self.state.snapshot().next_piece().get_type());
+            let last = self.state.snapshot().next_piece().get_type();
+            let next_piece = self.next_piece(last);
 
             // merge piece
+            let level = self.state.snapshot().level();
+            self.events.push(Event::Lock(curr_piece.unwrap().0.get_type()));
             self.replay.add_merge(self.timestamp, self.down, next_piece);
             self.state.merge(self.timestamp, next_piece, self.down);
 
+            if let Some((rows, _)) = self.state.snapshot().animation() {
+                self.events.push(Event::Clear(rows.clone()));
+            }
+            if self.state.snapshot().level() != level {
+                self.events.push(Event::LevelUp(self.state.snapshot().level()));
+            }
+
+            if self.config.is_killscreen(self.state.snapshot().level()) {
+                let last_breath = self.state.snapshot();
+                self.lost = Some((last_breath.score(), last_breath.level()));
+                self.events.push(Event::TopOut);
+                return Some(Outcome::Death);
+            }
+
             // adjust timers
             self.drop_timer = -self.state.snapshot().are_duration().unwrap();
             self.are = true;
@@ -215,7 +367,16 @@ impl Game {
                 if !self.state.start_new_piece(self.timestamp) {
                     let last_breath = self.state.snapshot();
                     self.lost = Some((last_breath.score(), last_breath.level()));
+                    self.events.push(Event::TopOut);
                     outcome = Some(Outcome::Death);
+                } else {
+                    let snapshot = self.state.snapshot();
+                    self.events.push(Event::Spawn(snapshot.piece().unwrap().0.get_type()));
+                    let drought = snapshot.stats().get(piece::Type::I).1;
+                    if drought == DROUGHT_WARNING {
+                        self.events.push(Event::Drought(drought));
+                    }
+                    self.checkpoint();
                 }
 
                 self.drop_timer = 0;   // re-set gravity timer
@@ -238,6 +399,7 @@ impl Game {
 
             if direction != 0 {
                 self.das = if self.try_move(None, direction, 0) {
+                    self.events.push(Event::Shift(direction));
                     outcome = Some(Outcome::HorizonalMove);
                     0
                 } else {
@@ -259,6 +421,7 @@ impl Game {
             // Try to move, if DAS is full
             if direction != 0 && self.das == self.config.das_initial {
                 if self.try_move(None, direction, 0) {
+                    self.events.push(Event::Shift(direction));
                     outcome = Some(Outcome::HorizonalMove);
                     self.das = self.config.das_initial - self.config.das_step;
                 }
@@ -268,9 +431,7 @@ impl Game {
         //
         // Compute gravity for current level
         //
-        let gravity = self.state.snapshot().level();
-        let gravity = gravity.min(self.config.gravity.len() as i32 - 1);
-        let gravity = *self.config.gravity.get(gravity as usize).unwrap();
+        let gravity = self.config.gravity(self.state.snapshot().level());
 
         //
         // update soft drop
@@ -292,6 +453,15 @@ impl Game {
             move_down = true;
         }
 
+        // 20G: sink as far as possible, only lock when the piece was resting already
+        if gravity == 0 && self.down <= 0 {
+            let mut sunk = false;
+            while self.try_move(None, 0, -1) {
+                sunk = true;
+            }
+            move_down = !sunk;
+        }
+
         if move_down {
             if let Some(downret) = self.move_down() {
                 outcome = Some(downret);
@@ -306,10 +476,27 @@ impl Game {
         self.state.snapshot()
     }
 
+    pub fn history(&self) -> &GameHistory {
+        &self.state
+    }
+
+    /// Events since the last call
+    pub fn take_events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.events)
+    }
+
     pub fn timestamp(&self) -> i32 {
         self.timestamp
     }
 
+    pub fn lost(&self) -> bool {
+        self.lost.is_some()
+    }
+
+    pub fn config(&self) -> &super::Config {
+        &self.config
+    }
+
     pub fn replay(&self) -> &super::replay::Replay {
         &self.replay
     }
@@ -0,0 +1,123 @@
+//! How fast pieces fall on each level, when the level goes up and how fast frames tick.
+
+/// NTSC NES frames per row, level 29 and above drop one row per frame
+const NES: [i32; 30] = [
+    48, 43, 38, 33, 28, 23, 18, 13, 8, 6,
+    5, 5, 5, 4, 4, 4, 3, 3, 3, 2,
+    2, 2, 2, 2, 2, 2, 2, 2, 2, 1,
+];
+
+/// PAL NES frames per row at 50 Hz, level 19 and above drop one row per frame
+const PAL: [i32; 20] = [
+    36, 32, 29, 25, 22, 18, 15, 11, 7, 5,
+    4, 4, 4, 3, 3, 3, 2, 2, 2, 1,
+];
+
+/// First guideline level, counting from 1, that is 20G
+const GUIDELINE_20G: i32 = 20;
+
+#[derive(Debug, PartialEq, Clone, Default)]
+#[derive(Serialize, Deserialize)]
+pub enum LevelCurve {
+    /// NTSC NES, 60 frames per second
+    #[default]
+    Nes,
+    /// PAL NES, 50 frames per second
+    Pal,
+    /// (0.8 - (level - 1) * 0.007) ^ (level - 1) seconds per row, counting levels from 1,
+    /// but at least a frame until 20G from level 20 on, ten lines per level
+    Guideline,
+    /// pieces fall to the bottom right away, see `LevelCurve::gravity`
+    TwentyG,
+    /// Frames per row for each level, the last entry holds for all levels above
+    Custom(Vec<i32>),
+}
+
+/// What happens once the game gets fast enough
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+#[derive(Serialize, Deserialize)]
+pub enum Killscreen {
+    /// levels keep going up forever
+    #[default]
+    None,
+    /// the level doesn't go above this one
+    Cap(i32),
+    /// the game is over as soon as this level is reached
+    End(i32),
+}
+
+impl LevelCurve {
+    pub fn fps(&self) -> f32 {
+        match self {
+            LevelCurve::Pal => 50.0,
+            _ => 60.0,
+        }
+    }
+
+    /// Frames it takes a piece to fall one row. 0 means 20G: the piece falls as far as it can
+    /// within the frame and locks on the first frame it starts out resting.
+    pub fn gravity(&self, level: i32) -> i32 {
+        let table = |table: &[i32]| table[(level.max(0) as usize).min(table.len() - 1)];
+
+        match self {
+            LevelCurve::Nes => table(&NES),
+            LevelCurve::Pal => table(&PAL),
+            LevelCurve::Guideline => {
+                let level = (level + 1).max(1);
+                if level >= GUIDELINE_20G {
+                    return 0;
+                }
+                // around level 15 this drops below a frame, which mustn't turn into 20G early
+                let level = level as f32;
+                let seconds = (0.8 - (level - 1.0) * 0.007).max(0.0).powf(level - 1.0);
+                ((seconds * self.fps()).round() as i32).max(1)
+            }
+            LevelCurve::TwentyG => 0,
+            LevelCurve::Custom(frames) if frames.is_empty() => 0,
+            LevelCurve::Custom(frames) => table(frames).max(0),
+        }
+    }
+
+    /// Lines to clear until the first level up when starting at `level`
+    pub fn transition(&self, level: i32) -> i32 {
+        match self {
+            LevelCurve::Guideline => self.lines_per_level(),
+            _ => (level * 10 + 10).min(100.max(level * 10 - 50)),
+        }
+    }
+
+    /// Lines between any later level ups
+    pub fn lines_per_level(&self) -> i32 {
+        10
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gravity() {
+        assert_eq!(LevelCurve::Nes.gravity(0), 48);
+        assert_eq!(LevelCurve::Nes.gravity(9), 6);
+        assert_eq!(LevelCurve::Nes.gravity(18), 3);
+        assert_eq!(LevelCurve::Nes.gravity(19), 2);
+        assert_eq!(LevelCurve::Nes.gravity(29), 1);
+        assert_eq!(LevelCurve::Nes.gravity(200), 1);
+
+        assert_eq!(LevelCurve::Pal.gravity(0), 36);
+        assert_eq!(LevelCurve::Pal.gravity(9), 5);
+        assert_eq!(LevelCurve::Pal.gravity(18), 2);
+        assert_eq!(LevelCurve::Pal.gravity(19), 1);
+        assert_eq!(LevelCurve::Pal.gravity(40), 1);
+
+        // level 0 here is guideline level 1
+        assert_eq!(LevelCurve::Guideline.gravity(0), 60);
+        assert_eq!(LevelCurve::Guideline.gravity(1), 48);
+        assert_eq!(LevelCurve::Guideline.gravity(9), 4);
+        assert_eq!(LevelCurve::Guideline.gravity(13), 1);
+        assert_eq!(LevelCurve::Guideline.gravity(18), 1);
+        assert_eq!(LevelCurve::Guideline.gravity(19), 0);
+        assert_eq!(LevelCurve::Guideline.gravity(30), 0);
+    }
+}
@@ -17,9 +17,21 @@ pub mod game;
 pub mod state;
 pub mod replay;
 pub mod networking;
+pub mod notation;
+pub mod practice;
+pub mod analysis;
+pub mod level;
+pub mod pieceset;
+pub mod sim;
 
 use chrono::{DateTime, Utc, Local, Timelike, Datelike};
 
+pub use level::{LevelCurve, Killscreen};
+pub use pieceset::PieceSet;
+
+/// Boards can be this many blocks wide and high
+pub const BOARD_SIZES: std::ops::RangeInclusive<i32> = 4..=100;
+
 #[derive(PartialEq,Clone)]
 #[derive(Serialize, Deserialize)]
 pub struct Config {
@@ -28,7 +40,12 @@ pub struct Config {
 
     pub level: i32,
 
-    pub gravity: Vec<i32>,
+    #[serde(default)]
+    pub curve: LevelCurve,
+    #[serde(default)]
+    pub killscreen: Killscreen,
+    #[serde(default)]
+    pub pieces: PieceSet,
 
     pub das_initial: i32,
     pub das_step: i32,
@@ -45,17 +62,9 @@ impl Config {
             width: 10,
             height: 20,
             level: 0,
-            gravity: vec!(48, 43, 38, 33,
-                          28, 23, 18, 13,
-                          8,
-                          6,
-                          5, 5, 5,
-                          4, 4, 4,
-                          3, 3, 3,
-                          2, 2, 2, 2, 2,
-                          2, 2, 2, 2, 2,
-                          1
-            ),
+            curve: LevelCurve::Nes,
+            killscreen: Killscreen::None,
+            pieces: PieceSet::Tetromino,
             das_initial: 16,
             das_step: 6,
             das_down: 2,
@@ -65,8 +74,100 @@ impl Config {
         }
     }
 
+    /// PAL NES timing, with its faster DAS
+    pub fn pal() -> Self {
+        Config {
+            curve: LevelCurve::Pal,
+            das_initial: 12,
+            das_step: 4,
+            ..Self::new()
+        }
+    }
+
+    pub fn with_curve(curve: LevelCurve) -> Self {
+        match curve {
+            LevelCurve::Pal => Self::pal(),
+            curve => Config { curve, ..Self::new() },
+        }
+    }
+
+    pub fn fps(&self) -> f32 {
+        self.curve.fps()
+    }
+
+    /// Configs from files or the network can have any board size
+    pub fn check_board(&self) -> Result<(), String> {
+        if BOARD_SIZES.contains(&self.width) && BOARD_SIZES.contains(&self.height) {
+            Ok(())
+        } else {
+            Err(format!("Invalid board size {}x{}", self.width, self.height))
+        }
+    }
+
+    /// Seconds per frame
+    pub fn frame_time(&self) -> f32 {
+        1.0 / self.fps()
+    }
+
+    pub fn gravity(&self, level: i32) -> i32 {
+        self.curve.gravity(level)
+    }
+
     pub fn transition(&self) -> i32 {
-        (self.level * 10 + 10).min(100.max(self.level * 10 - 50))
+        self.curve.transition(self.level)
+    }
+
+    /// The level after clearing enough lines on `level`
+    pub fn next_level(&self, level: i32) -> i32 {
+        match self.killscreen {
+            Killscreen::Cap(cap) => (level + 1).min(cap.max(level)),
+            _ => level + 1,
+        }
+    }
+
+    /// Whether reaching `level` ends the game
+    pub fn is_killscreen(&self, level: i32) -> bool {
+        match self.killscreen {
+            Killscreen::End(end) => level >= end && level > self.level,
+            _ => false,
+        }
+    }
+}
+
+/// Layout of `Config` before level curves, which binary encoded replays still use
+#[derive(Deserialize)]
+pub struct LegacyConfig {
+    width: i32,
+    height: i32,
+    level: i32,
+    gravity: Vec<i32>,
+    das_initial: i32,
+    das_step: i32,
+    das_down: i32,
+    are_base: i32,
+    are_max: i32,
+    line_clear: i32,
+}
+
+impl From<LegacyConfig> for Config {
+    fn from(legacy: LegacyConfig) -> Self {
+        // all of them used the NES table, but keep anything else as it was
+        let nes = (0..legacy.gravity.len() as i32).all(|level| LevelCurve::Nes.gravity(level) == legacy.gravity[level as usize]);
+
+        Config {
+            width: legacy.width,
+            height: legacy.height,
+            level: legacy.level,
+            curve: if nes { LevelCurve::Nes } else { LevelCurve::Custom(legacy.gravity) },
+            killscreen: Killscreen::None,
+            pieces: PieceSet::Tetromino,
+            das_initial: legacy.das_initial,
+            das_step: legacy.das_step,
+            das_down: legacy.das_down,
+            are_base: legacy.are_base,
+            are_max: legacy.are_max,
+            line_clear: legacy.line_clear,
+        }
     }
 }
 
@@ -6,12 +6,13 @@ pub fn encode<T: serde::Serialize>(message: &T) -> String {
     base64::encode(&serde_json::to_string(message).unwrap())
 }
 
+/// None for anything that isn't base64 encoded JSON of a `T`
 pub fn decode<T: serde::de::DeserializeOwned>(message: &str) -> Option<T> {
     let ret = match base64::decode(message).ok() {
         None => None,
         Some(data) => {
             // bincode::deserialize(&data).ok()
-            serde_json::from_str(&String::from_utf8(data).unwrap()).ok()
+            serde_json::from_slice(&data).ok()
         }
     };
 
@@ -33,7 +34,25 @@ pub enum ServerMessage {
     },
     RequestReplays {
         ids: Vec<usize>,
-    }
+    },
+    /// a game that just started, with what got recorded so far
+    StartLive {
+        name: String,
+        idtag: String,
+        replay: super::replay::Replay,
+    },
+    UpdateLive {
+        session: usize,
+        idtag: String,
+        delta: super::replay::ReplayDelta,
+        finished: bool,
+    },
+    RequestLiveGames,
+    WatchLive {
+        session: usize,
+        /// entries the spectator has already, the header comes along with the first ones
+        from: usize,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -52,5 +71,18 @@ pub enum ServerAnswer {
     },
     UploadResult(
         Option<super::PlayedGame>
-    )
+    ),
+    /// the entries the server has of the session, the next update starts there
+    LiveUpdated {
+        session: usize,
+        entries: usize,
+    },
+    /// games going on right now, the replay id is the session
+    LiveGames(Vec<super::PlayedGame>),
+    LiveData {
+        session: usize,
+        header: Option<super::replay::Replay>,
+        delta: super::replay::ReplayDelta,
+        finished: bool,
+    },
 }
\ No newline at end of file
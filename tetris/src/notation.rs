@@ -0,0 +1,692 @@
+//! Import and export of boards and piece queues.
+//!
+//! Boards can be written as a simple text grid (one line per row, top row first, using the piece
+//! letters, '.' for empty cells and 'X' for garbage), or in the fumen v115 encoding used by most
+//! tetris tools. Piece queues are strings of piece letters, the first one being the current piece.
+
+use std::collections::VecDeque;
+
+use array2d::Array2D;
+
+use super::piece;
+use super::stack::Stack;
+
+/// A board plus the pieces that will be played on it
+#[derive(Clone)]
+pub struct Position {
+    pub stack: Stack,
+    pub queue: Vec<piece::Type>,
+}
+
+impl Position {
+    pub fn new(stack: Stack, queue: Vec<piece::Type>) -> Self {
+        Position { stack, queue }
+    }
+
+    /// Reads the first page of a fumen, the queue is taken from a "#Q=[hold](current)next" comment
+    pub fn from_fumen(data: &str, width: usize, height: usize) -> Result<Self, String> {
+        if width != FUMEN_WIDTH {
+            return Err(format!("Fumen boards are {} blocks wide, not {}", FUMEN_WIDTH, width));
+        }
+
+        let page = decode_fumen(data, height)?.into_iter().next().ok_or("Fumen contains no pages")?;
+        let queue = match page.comment.as_ref().and_then(|comment| comment.find("#Q=").map(|idx| comment.split_at(idx + 3).1)) {
+            Some(quiz) => parse_quiz(quiz)?,
+            None => Vec::new(),
+        };
+
+        Ok(Position {
+            stack: page.field,
+            queue,
+        })
+    }
+
+    pub fn to_fumen(&self) -> Result<String, String> {
+        let comment = if self.queue.is_empty() {
+            None
+        } else {
+            Some(format!("#Q=[]({}){}", self.queue[0].symbol(), queue(&self.queue[1..])))
+        };
+
+        encode_fumen(&[Page {
+            field: self.stack.clone(),
+            operation: None,
+            comment,
+        }])
+    }
+}
+
+/// Parses a text grid, rows that are missing at the top of the board are left empty
+pub fn parse_grid(text: &str, width: usize, height: usize) -> Result<Stack, String> {
+    let rows: Vec<&str> = text.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .collect();
+
+    if rows.len() > height {
+        return Err(format!("Grid has {} rows, board only {}", rows.len(), height));
+    }
+
+    let mut blocks = Array2D::new(width, height, piece::Type::None);
+    for (j, row) in rows.iter().rev().enumerate() {
+        let cells: Vec<char> = row.chars().collect();
+        if cells.len() != width {
+            return Err(format!("Grid row '{}' is not {} blocks wide", row, width));
+        }
+        for (i, c) in cells.iter().enumerate() {
+            let tp = piece::Type::from_symbol(*c).ok_or(format!("Invalid block '{}' in grid", c))?;
+            blocks.set(i, j, tp);
+        }
+    }
+
+    Ok(Stack::from_blocks(blocks))
+}
+
+/// Text grid of the stack, without the empty rows at the top
+pub fn grid(stack: &Stack) -> String {
+    let blocks = stack.blocks();
+    let top = (0..blocks.height())
+        .filter(|y| (0..blocks.width()).any(|x| *blocks.at(x, *y) != piece::Type::None))
+        .max()
+        .map(|y| y + 1)
+        .unwrap_or(0);
+
+    let mut ret = String::new();
+    for y in (0..top).rev() {
+        for x in 0..blocks.width() {
+            ret.push(blocks.at(x, y).symbol());
+        }
+        ret.push('\n');
+    }
+    ret
+}
+
+pub fn parse_queue(text: &str) -> Result<Vec<piece::Type>, String> {
+    text.chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| match piece::Type::from_symbol(c) {
+            Some(piece::Type::None) | Some(piece::Type::Garbage) | None => Err(format!("Invalid piece '{}' in queue", c)),
+            Some(tp) => Ok(tp),
+        })
+        .collect()
+}
+
+pub fn queue(pieces: &[piece::Type]) -> String {
+    pieces.iter().map(|tp| tp.symbol()).collect()
+}
+
+// "[hold](current)next..." from a fumen quiz comment, the hold piece is not supported
+fn parse_quiz(quiz: &str) -> Result<Vec<piece::Type>, String> {
+    let quiz = quiz.split_whitespace().next().unwrap_or("");
+    let err = || format!("Invalid queue comment '{}'", quiz);
+
+    let rest = quiz.trim_start_matches('[');
+    let close = rest.find(']').ok_or_else(err)?;
+    if close > 0 {
+        return Err(String::from("Hold pieces are not supported"));
+    }
+    let rest = rest.split_at(close + 1).1;
+
+    let (current, rest) = if rest.starts_with('(') {
+        let close = rest.find(')').ok_or_else(err)?;
+        (&rest[1..close], rest.split_at(close + 1).1)
+    } else {
+        ("", rest)
+    };
+
+    let mut ret = parse_queue(current)?;
+    ret.append(&mut parse_queue(rest)?);
+    Ok(ret)
+}
+
+//
+// fumen v115
+//
+
+const FUMEN_WIDTH: usize = 10;
+const FUMEN_TOP: usize = 23;
+const FUMEN_BLOCKS: usize = FUMEN_WIDTH * (FUMEN_TOP + 1);
+
+const ENCODE_TABLE: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const COMMENT_TABLE: &str = " !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+const COMMENT_CHAR_VALUE: u32 = 96;
+
+/// Piece rotations, in the order in which fumen numbers them
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Rotation {
+    Reverse,
+    Right,
+    Spawn,
+    Left,
+}
+
+/// The piece shown on a fumen page, positioned by its rotation center
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Operation {
+    pub tp: piece::Type,
+    pub rotation: Rotation,
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Operation {
+    pub fn cells(&self) -> [(i32, i32); 4] {
+        let shape = match self.tp {
+            piece::Type::I => [(0, 0), (-1, 0), (1, 0), (2, 0)],
+            piece::Type::T => [(0, 0), (-1, 0), (1, 0), (0, 1)],
+            piece::Type::O => [(0, 0), (1, 0), (0, 1), (1, 1)],
+            piece::Type::L => [(0, 0), (-1, 0), (1, 0), (1, 1)],
+            piece::Type::J => [(0, 0), (-1, 0), (1, 0), (-1, 1)],
+            piece::Type::S => [(0, 0), (-1, 0), (0, 1), (1, 1)],
+            piece::Type::Z => [(0, 0), (1, 0), (0, 1), (-1, 1)],
+            _ => [(0, 0); 4],
+        };
+
+        let mut ret = [(0, 0); 4];
+        for (i, (x, y)) in shape.iter().enumerate() {
+            let (x, y) = match self.rotation {
+                Rotation::Spawn => (*x, *y),
+                Rotation::Right => (*y, -*x),
+                Rotation::Reverse => (-*x, -*y),
+                Rotation::Left => (-*y, *x),
+            };
+            ret[i] = (self.x + x, self.y + y);
+        }
+        ret
+    }
+
+    // fumen stores some rotations relative to a different block than the rotation center
+    fn position_offset(&self) -> (i32, i32) {
+        match (self.tp, self.rotation) {
+            (piece::Type::O, Rotation::Left) => (1, -1),
+            (piece::Type::O, Rotation::Reverse) => (1, 0),
+            (piece::Type::O, Rotation::Spawn) => (0, -1),
+            (piece::Type::I, Rotation::Reverse) => (1, 0),
+            (piece::Type::I, Rotation::Left) => (0, -1),
+            (piece::Type::S, Rotation::Spawn) => (0, -1),
+            (piece::Type::S, Rotation::Right) => (-1, 0),
+            (piece::Type::Z, Rotation::Spawn) => (0, -1),
+            (piece::Type::Z, Rotation::Left) => (1, 0),
+            _ => (0, 0),
+        }
+    }
+}
+
+pub struct Page {
+    /// The board, without the page's piece
+    pub field: Stack,
+    pub operation: Option<Operation>,
+    pub comment: Option<String>,
+}
+
+fn fumen_block(tp: piece::Type) -> u32 {
+    match tp {
+        piece::Type::None => 0,
+        piece::Type::I => 1,
+        piece::Type::L => 2,
+        piece::Type::O => 3,
+        piece::Type::Z => 4,
+        piece::Type::T => 5,
+        piece::Type::J => 6,
+        piece::Type::S => 7,
+        piece::Type::Garbage | piece::Type::Custom(_) => 8,
+    }
+}
+
+fn fumen_type(block: u32) -> Option<piece::Type> {
+    match block {
+        0 => Some(piece::Type::None),
+        1 => Some(piece::Type::I),
+        2 => Some(piece::Type::L),
+        3 => Some(piece::Type::O),
+        4 => Some(piece::Type::Z),
+        5 => Some(piece::Type::T),
+        6 => Some(piece::Type::J),
+        7 => Some(piece::Type::S),
+        8 => Some(piece::Type::Garbage),
+        _ => None,
+    }
+}
+
+fn fumen_rotation(rotation: u32) -> Rotation {
+    match rotation % 4 {
+        0 => Rotation::Reverse,
+        1 => Rotation::Right,
+        2 => Rotation::Spawn,
+        _ => Rotation::Left,
+    }
+}
+
+// fumen fields are stored top to bottom, the last row being the garbage row below the board
+type FumenField = [piece::Type; FUMEN_BLOCKS];
+
+fn field_index(x: i32, y: i32) -> Option<usize> {
+    if x < 0 || x >= FUMEN_WIDTH as i32 || y < -1 || y >= FUMEN_TOP as i32 {
+        None
+    } else {
+        Some((FUMEN_TOP as i32 - 1 - y) as usize * FUMEN_WIDTH + x as usize)
+    }
+}
+
+fn field_from_stack(stack: &Stack) -> Result<FumenField, String> {
+    let blocks = stack.blocks();
+    if blocks.width() != FUMEN_WIDTH || blocks.height() > FUMEN_TOP {
+        return Err(format!("Fumen boards are at most {}x{}", FUMEN_WIDTH, FUMEN_TOP));
+    }
+
+    let mut field = [piece::Type::None; FUMEN_BLOCKS];
+    blocks.for_each(&mut |x, y, tp| {
+        field[field_index(x, y).unwrap()] = *tp;
+    });
+    Ok(field)
+}
+
+fn field_to_stack(field: &FumenField, height: usize) -> Result<Stack, String> {
+    let mut blocks = Array2D::new(FUMEN_WIDTH, height, piece::Type::None);
+    for y in 0..FUMEN_TOP as i32 {
+        for x in 0..FUMEN_WIDTH as i32 {
+            let tp = field[field_index(x, y).unwrap()];
+            if y < height as i32 {
+                blocks.set(x as usize, y as usize, tp);
+            } else if tp != piece::Type::None {
+                return Err(format!("Fumen board is higher than {} rows", height));
+            }
+        }
+    }
+    Ok(Stack::from_blocks(blocks))
+}
+
+fn lock_piece(field: &mut FumenField, operation: &Operation) {
+    for (x, y) in operation.cells().iter() {
+        if let Some(idx) = field_index(*x, *y) {
+            field[idx] = operation.tp;
+        }
+    }
+
+    // clear full rows, the garbage row is not part of the board
+    let mut rows: Vec<[piece::Type; FUMEN_WIDTH]> = Vec::new();
+    for y in 0..FUMEN_TOP as i32 {
+        let mut row = [piece::Type::None; FUMEN_WIDTH];
+        for x in 0..FUMEN_WIDTH {
+            row[x] = field[field_index(x as i32, y).unwrap()];
+        }
+        if row.contains(&piece::Type::None) {
+            rows.push(row);
+        }
+    }
+    rows.resize(FUMEN_TOP, [piece::Type::None; FUMEN_WIDTH]);
+
+    for (y, row) in rows.iter().enumerate() {
+        for x in 0..FUMEN_WIDTH {
+            field[field_index(x as i32, y as i32).unwrap()] = row[x];
+        }
+    }
+}
+
+fn rise_garbage(field: &mut FumenField) {
+    for y in (0..FUMEN_TOP as i32).rev() {
+        for x in 0..FUMEN_WIDTH as i32 {
+            field[field_index(x, y).unwrap()] = field[field_index(x, y - 1).unwrap()];
+        }
+    }
+    for x in 0..FUMEN_WIDTH as i32 {
+        field[field_index(x, -1).unwrap()] = piece::Type::None;
+    }
+}
+
+fn mirror(field: &mut FumenField) {
+    for row in field.chunks_mut(FUMEN_WIDTH) {
+        row.reverse();
+    }
+}
+
+// same rules as JavaScript's escape(), which fumen applies to comments
+fn escape(text: &str) -> String {
+    let mut ret = String::new();
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() || "@*_+-./".contains(c) {
+            ret.push(c);
+        } else if (c as u32) < 256 {
+            ret.push_str(&format!("%{:02X}", c as u32));
+        } else {
+            ret.push_str(&format!("%u{:04X}", c as u32));
+        }
+    }
+    ret
+}
+
+fn unescape(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let hex = |from: usize, len: usize| -> Option<char> {
+        if from + len > chars.len() {
+            return None;
+        }
+        let digits: String = chars[from..from + len].iter().collect();
+        u32::from_str_radix(&digits, 16).ok().and_then(std::char::from_u32)
+    };
+
+    let mut ret = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] == '%' {
+            if i + 1 < chars.len() && chars[i + 1] == 'u' {
+                if let Some(c) = hex(i + 2, 4) {
+                    ret.push(c);
+                    i += 6;
+                    continue;
+                }
+            } else if let Some(c) = hex(i + 1, 2) {
+                ret.push(c);
+                i += 3;
+                continue;
+            }
+        }
+        ret.push(chars[i]);
+        i += 1;
+    }
+    ret
+}
+
+struct Values {
+    data: VecDeque<u32>,
+}
+
+impl Values {
+    fn poll(&mut self, digits: usize) -> Result<u32, String> {
+        let mut ret = 0;
+        let mut fac = 1;
+        for _ in 0..digits {
+            ret += fac * self.data.pop_front().ok_or("Fumen data ends unexpectedly")?;
+            fac *= ENCODE_TABLE.len() as u32;
+        }
+        Ok(ret)
+    }
+}
+
+fn push(data: &mut Vec<u32>, value: u32, digits: usize) {
+    let mut value = value;
+    for _ in 0..digits {
+        data.push(value % ENCODE_TABLE.len() as u32);
+        value /= ENCODE_TABLE.len() as u32;
+    }
+}
+
+/// Decodes all pages of a fumen ("v115@..."), the boards are cut to `height` rows
+pub fn decode_fumen(data: &str, height: usize) -> Result<Vec<Page>, String> {
+    let data = data.trim();
+    let data = match data.find('@') {
+        Some(idx) => {
+            let (version, rest) = data.split_at(idx);
+            if !version.ends_with("115") {
+                return Err(format!("Unsupported fumen version '{}'", version));
+            }
+            &rest[1..]
+        }
+        None => data,
+    };
+
+    let mut values = Values { data: VecDeque::new() };
+    for c in data.chars().filter(|c| *c != '?') {
+        let value = ENCODE_TABLE.find(c).ok_or(format!("Invalid character '{}' in fumen", c))?;
+        values.data.push_back(value as u32);
+    }
+
+    let mut pages = Vec::new();
+    let mut field = [piece::Type::None; FUMEN_BLOCKS];
+    let mut comment = None;
+    let mut repeat = 0;
+
+    while !values.data.is_empty() {
+        // field, as difference to the previous one
+        if repeat > 0 {
+            repeat -= 1;
+        } else {
+            let mut idx = 0;
+            while idx < FUMEN_BLOCKS {
+                let value = values.poll(2)?;
+                let diff = (value / FUMEN_BLOCKS as u32) as i32 - 8;
+                let count = (value % FUMEN_BLOCKS as u32) as usize + 1;
+
+                if diff == 0 && count == FUMEN_BLOCKS {
+                    repeat = values.poll(1)?;
+                }
+                if idx + count > FUMEN_BLOCKS {
+                    return Err(String::from("Invalid fumen field"));
+                }
+
+                for block in &mut field[idx..idx + count] {
+                    let new = fumen_block(*block) as i32 + diff;
+                    *block = fumen_type(new.max(0) as u32).filter(|_| new >= 0).ok_or("Invalid fumen block")?;
+                }
+                idx += count;
+            }
+        }
+
+        // piece and flags
+        let mut action = values.poll(3)?;
+        let tp = fumen_type(action % 8).ok_or("Invalid fumen piece")?;
+        action /= 8;
+        let rotation = fumen_rotation(action % 4);
+        action /= 4;
+        let location = (action % FUMEN_BLOCKS as u32) as i32;
+        action /= FUMEN_BLOCKS as u32;
+        let rise = action % 2 == 1;
+        action /= 2;
+        let mirrored = action % 2 == 1;
+        action /= 4; // colorize flag is irrelevant here
+        let has_comment = action % 2 == 1;
+        action /= 2;
+        let lock = action % 2 == 0;
+
+        let operation = match tp {
+            piece::Type::None | piece::Type::Garbage => None,
+            tp => {
+                let mut op = Operation {
+                    tp,
+                    rotation,
+                    x: location % FUMEN_WIDTH as i32,
+                    y: FUMEN_TOP as i32 - 1 - location / FUMEN_WIDTH as i32,
+                };
+                let ofs = op.position_offset();
+                op.x += ofs.0;
+                op.y += ofs.1;
+                Some(op)
+            }
+        };
+
+        // comments stay the same for the following pages, unless they get replaced
+        if has_comment {
+            let len = values.poll(2)? as usize;
+            let mut text = String::new();
+            while text.len() < len {
+                let mut value = values.poll(5)?;
+                for _ in 0..4 {
+                    if text.len() < len {
+                        let c = COMMENT_TABLE.chars().nth((value % COMMENT_CHAR_VALUE) as usize).ok_or("Invalid fumen comment")?;
+                        text.push(c);
+                    }
+                    value /= COMMENT_CHAR_VALUE;
+                }
+            }
+            comment = Some(unescape(&text));
+        }
+
+        pages.push(Page {
+            field: field_to_stack(&field, height)?,
+            operation,
+            comment: comment.clone(),
+        });
+
+        if lock {
+            if let Some(op) = operation.as_ref() {
+                lock_piece(&mut field, op);
+            }
+            if rise {
+                rise_garbage(&mut field);
+            }
+            if mirrored {
+                mirror(&mut field);
+            }
+        }
+    }
+
+    Ok(pages)
+}
+
+/// Encodes the pages as fumen, every page's piece is locked into the field of the next page
+pub fn encode_fumen(pages: &[Page]) -> Result<String, String> {
+    let mut data = Vec::new();
+    let mut prev = [piece::Type::None; FUMEN_BLOCKS];
+    let mut prev_comment = None;
+    let mut repeat_idx: Option<usize> = None;
+
+    for (index, page) in pages.iter().enumerate() {
+        let field = field_from_stack(&page.field)?;
+
+        // run-length encoded difference to the previous field
+        let mut runs: Vec<(u32, u32)> = Vec::new();
+        for i in 0..FUMEN_BLOCKS {
+            let diff = (fumen_block(field[i]) as i32 - fumen_block(prev[i]) as i32 + 8) as u32;
+            match runs.last_mut() {
+                Some(run) if run.0 == diff => run.1 += 1,
+                _ => runs.push((diff, 1)),
+            }
+        }
+
+        let unchanged = runs.len() == 1 && runs[0].0 == 8;
+        match repeat_idx {
+            Some(idx) if unchanged && data[idx] < ENCODE_TABLE.len() as u32 - 1 => data[idx] += 1,
+            _ => {
+                for run in &runs {
+                    push(&mut data, run.0 * FUMEN_BLOCKS as u32 + run.1 - 1, 2);
+                }
+                repeat_idx = None;
+                if unchanged {
+                    data.push(0);
+                    repeat_idx = Some(data.len() - 1);
+                }
+            }
+        }
+
+        // piece and flags
+        let comment = page.comment.as_ref().map(|c| escape(c));
+        let has_comment = comment != prev_comment && (index > 0 || comment.as_ref().is_some_and(|c| !c.is_empty()));
+
+        let mut action = 0;
+        if let Some(op) = page.operation.as_ref() {
+            let ofs = op.position_offset();
+            let x = op.x - ofs.0;
+            let y = op.y - ofs.1;
+            let location = field_index(x, y).filter(|_| y >= 0).ok_or("Fumen piece outside of the board")?;
+            let rotation = match op.rotation {
+                Rotation::Reverse => 0,
+                Rotation::Right => 1,
+                Rotation::Spawn => 2,
+                Rotation::Left => 3,
+            };
+            action = fumen_block(op.tp) + 8 * rotation + 32 * location as u32;
+        }
+        if index == 0 {
+            action += 4 * 32 * FUMEN_BLOCKS as u32; // colorize
+        }
+        if has_comment {
+            action += 8 * 32 * FUMEN_BLOCKS as u32;
+        }
+        push(&mut data, action, 3);
+
+        if has_comment {
+            let text: Vec<char> = comment.as_ref().map(|c| c.chars().take(4095).collect()).unwrap_or(Vec::new());
+            push(&mut data, text.len() as u32, 2);
+            for chunk in text.chunks(4) {
+                let mut value = 0;
+                let mut fac = 1;
+                for c in chunk {
+                    let idx = COMMENT_TABLE.find(*c).ok_or("Invalid character in fumen comment")?;
+                    value += fac * idx as u32;
+                    fac *= COMMENT_CHAR_VALUE;
+                }
+                push(&mut data, value, 5);
+            }
+            prev_comment = comment;
+        }
+
+        prev = field;
+        if let Some(op) = page.operation.as_ref() {
+            lock_piece(&mut prev, op);
+        }
+    }
+
+    let table: Vec<char> = ENCODE_TABLE.chars().collect();
+    let mut ret = String::from("v115@");
+    for (i, value) in data.iter().enumerate() {
+        // fumen inserts a '?' every 47 characters, for URLs
+        if i > 0 && i % 47 == 0 {
+            ret.push('?');
+        }
+        ret.push(table[*value as usize]);
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_fumen() {
+        let pages = decode_fumen("v115@vhAAgH", 20).unwrap();
+        assert_eq!(pages.len(), 1);
+        assert!(pages[0].operation.is_none());
+        assert_eq!(grid(&pages[0].field), "");
+
+        let empty = Position::new(Stack::new(10, 20), Vec::new());
+        assert_eq!(empty.to_fumen().unwrap(), "v115@vhAAgH");
+    }
+
+    #[test]
+    fn fumen_round_trip() {
+        let stack = parse_grid("
+            X.........
+            IJJJ..SSZZ
+            TTTX.SSOOZ
+            ITJJXXXOOZ
+        ", 10, 20).unwrap();
+        let position = Position::new(stack, parse_queue("TIJLOSZ").unwrap());
+
+        let fumen = position.to_fumen().unwrap();
+        let decoded = Position::from_fumen(&fumen, 10, 20).unwrap();
+        assert_eq!(grid(&decoded.stack), grid(&position.stack));
+        assert_eq!(queue(&decoded.queue), "TIJLOSZ");
+    }
+
+    #[test]
+    fn fumen_pieces_lock() {
+        let pages = [
+            Page {
+                field: parse_grid("IIIIIIII..", 10, 20).unwrap(),
+                operation: Some(Operation { tp: piece::Type::O, rotation: Rotation::Spawn, x: 8, y: 0 }),
+                comment: Some(String::from("(tetris)")),
+            },
+            Page {
+                field: parse_grid("IIIIIIII..", 10, 20).unwrap(),
+                operation: None,
+                comment: None,
+            },
+        ];
+
+        let fumen = encode_fumen(&pages).unwrap();
+        let decoded = decode_fumen(&fumen, 20).unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[0].operation, pages[0].operation);
+        assert_eq!(decoded[0].comment, Some(String::from("(tetris)")));
+        assert_eq!(grid(&decoded[1].field), "IIIIIIII..\n");
+    }
+
+    #[test]
+    fn grid_errors() {
+        assert!(parse_grid("..........\n", 10, 20).is_ok());
+        assert!(parse_grid(".........\n", 10, 20).is_err());
+        assert!(parse_grid("....Q.....\n", 10, 20).is_err());
+        assert!(parse_queue("TIX").is_err());
+    }
+}
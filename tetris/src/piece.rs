@@ -1,6 +1,7 @@
+use super::pieceset::{PieceSet, Shape};
 
-#[derive(Debug, Copy, Clone, PartialEq)]
-#[derive(FromPrimitive, ToPrimitive)]
+/// Piece types of the standard set come first, `Custom` ones are used by other piece sets
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[derive(Serialize, Deserialize)]
 pub enum Type {
     O,
@@ -11,34 +12,99 @@ pub enum Type {
     S,
     Z,
     None,
+    Garbage,
+    Custom(u8),
 }
 
+/// Symbols of the custom piece types, chosen to not clash with the standard ones
+const CUSTOM_SYMBOLS: &str = "0123456789abcdefghkmnpq";
+
 impl Type {
-    pub fn offset(&self) -> (f32, f32) {
-        match &self {
-            Type::O => (0.0, 0.0),
-            Type::I => (0.0, 0.5),
-            Type::T => (-0.5, 0.0),
-            Type::L => (-0.5, 0.0),
-            Type::J => (-0.5, 0.0),
-            Type::S => (-0.5, 0.0),
-            Type::Z => (-0.5, 0.0),
-            _ => (0.0, 0.0),
+    /// Number that fits into five bits, as stored in replays
+    pub fn to_int(&self) -> u32 {
+        match self {
+            Type::O => 0,
+            Type::I => 1,
+            Type::T => 2,
+            Type::L => 3,
+            Type::J => 4,
+            Type::S => 5,
+            Type::Z => 6,
+            Type::None => 7,
+            Type::Garbage => 8,
+            Type::Custom(i) => 9 + *i as u32,
         }
     }
 
     pub fn from_int(i: u32) -> Self {
-        num::FromPrimitive::from_u32(i).unwrap()
+        match i {
+            0 => Type::O,
+            1 => Type::I,
+            2 => Type::T,
+            3 => Type::L,
+            4 => Type::J,
+            5 => Type::S,
+            6 => Type::Z,
+            7 => Type::None,
+            8 => Type::Garbage,
+            _ => Type::Custom((i - 9) as u8),
+        }
+    }
+
+    /// Most custom types there can be
+    pub fn max_custom() -> usize {
+        CUSTOM_SYMBOLS.len()
+    }
+
+    /// Single character used for text output of boards, '.' for an empty cell and 'X' for garbage
+    pub fn symbol(&self) -> char {
+        match &self {
+            Type::O => 'O',
+            Type::I => 'I',
+            Type::T => 'T',
+            Type::L => 'L',
+            Type::J => 'J',
+            Type::S => 'S',
+            Type::Z => 'Z',
+            Type::None => '.',
+            Type::Garbage => 'X',
+            Type::Custom(i) => CUSTOM_SYMBOLS.chars().nth(*i as usize).unwrap_or('?'),
+        }
+    }
+
+    pub fn from_symbol(symbol: char) -> Option<Self> {
+        if let Some(i) = CUSTOM_SYMBOLS.find(symbol) {
+            return Some(Type::Custom(i as u8));
+        }
+
+        match symbol.to_ascii_uppercase() {
+            'O' => Some(Type::O),
+            'I' => Some(Type::I),
+            'T' => Some(Type::T),
+            'L' => Some(Type::L),
+            'J' => Some(Type::J),
+            'S' => Some(Type::S),
+            'Z' => Some(Type::Z),
+            '.' | '_' => Some(Type::None),
+            'X' | '#' => Some(Type::Garbage),
+            _ => None,
+        }
     }
 }
 
 pub type Orientation = u8;
 
+/// Pieces fit into a box of at most 8x8 cells
+pub const MAX_SIZE: i32 = 8;
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 #[derive(Serialize, Deserialize)]
 pub struct Piece {
     tp: Type,
-    orientation: Orientation
+    orientation: Orientation,
+    // one bit per cell for each orientation, bit x + 8 * y
+    states: [u64; 4],
+    size: u8,
 }
 
 impl Piece {
@@ -46,165 +112,222 @@ impl Piece {
         self.tp
     }
 
+    /// A piece of the standard set, `None`, `Garbage` and custom types have no blocks
     pub fn new(tp: Type, orientation: Orientation) -> Self {
-        Piece { tp, orientation }
+        match PieceSet::Tetromino.shape(tp) {
+            Some(shape) => Self::from_shape(shape, orientation),
+            None => Piece { tp, orientation, states: [0; 4], size: 4 },
+        }
+    }
+
+    pub fn from_shape(shape: &Shape, orientation: Orientation) -> Self {
+        let mut states = [0; 4];
+        for (i, state) in states.iter_mut().enumerate() {
+            if let Some(cells) = shape.rotations.get(i % shape.rotations.len().max(1)) {
+                for (x, y) in cells {
+                    *state |= 1 << (x + MAX_SIZE * y);
+                }
+            }
+        }
+
+        Piece {
+            tp: shape.tp,
+            orientation,
+            states,
+            size: shape.size as u8,
+        }
     }
 
     pub fn rotate(&self, clockwise: bool) -> Self {
         let delta = if clockwise { 3 } else { 1 };
         Piece {
-            tp: self.tp,
-            orientation: (self.orientation + delta) % 4
+            orientation: (self.orientation + delta) % 4,
+            ..*self
         }
     }
 
-    pub fn blocks(&self) -> [bool; 16] {
-        match &self.tp {
-            Type::None => [
-                false, false, false, false,
-                false, false, false, false,
-                false, false, false, false,
-                false, false, false, false,
-            ],
-
-            Type::O => [
-                false, false, false, false,
-                false, true,  true,  false,
-                false, true,  true,  false,
-                false, false, false, false,
-            ],
-
-            Type::I =>  match self.orientation % 4 {
-                0 | 2 => [
-                    false, false, false, false,
-                    false, false, false, false,
-                    true,  true,  true,  true,
-                    false, false, false, false,
-                ],
-                1 | 3 => [
-                    false, false, true , false,
-                    false, false, true , false,
-                    false, false, true , false,
-                    false, false, true , false,
-                ],
-                _ => unreachable!("Piece Orientation does not compute")
-            },
-
-            Type::T => match self.orientation % 4 {
-                0 => [
-                    false, false, false, false,
-                    false, false, false, false,
-                    false, true , true , true ,
-                    false, false, true , false,
-                ],
-                1 => [
-                    false, false, false, false,
-                    false, false, true , false,
-                    false, true , true , false,
-                    false, false, true , false,
-                ],
-                2 => [
-                    false, false, false, false,
-                    false, false, true , false,
-                    false, true , true , true ,
-                    false, false, false, false,
-                ],
-                3 => [
-                    false, false, false, false,
-                    false, false, true , false,
-                    false, false, true , true ,
-                    false, false, true , false,
-                ],
-                _ => unreachable!("Piece Orientation does not compute")
-            },
-
-            Type::J => match self.orientation % 4 {
-                0 => [
-                    false, false, false, false,
-                    false, false, false, false,
-                    false, true , true , true ,
-                    false, true , false, false,
-                ],
-                1 => [
-                    false, false, false, false,
-                    false, true , true , false,
-                    false, false, true , false,
-                    false, false, true , false,
-                ],
-                2 => [
-                    false, false, false, false,
-                    false, false, false, true,
-                    false, true , true , true ,
-                    false, false, false, false,
-                ],
-                3 => [
-                    false, false, false, false,
-                    false, false, true , false,
-                    false, false, true , false,
-                    false, false, true , true ,
-                ],
-                _ => unreachable!("Piece Orientation does not compute")
-            }
+    /// Edge length of the box the piece rotates in
+    pub fn size(&self) -> i32 {
+        self.size as i32
+    }
 
-            Type::L => match self.orientation % 4 {
-                0 => [
-                    false, false, false, false,
-                    false, false, false, false,
-                    false, true , true , true ,
-                    false, false, false, true ,
-                ],
-                1 => [
-                    false, false, false, false,
-                    false, false, true , false,
-                    false, false, true , false,
-                    false, true , true , false,
-                ],
-                2 => [
-                    false, false, false, false,
-                    false, true , false, false,
-                    false, true , true , true ,
-                    false, false, false, false,
-                ],
-                3 => [
-                    false, false, false, false,
-                    false, false, true , true ,
-                    false, false, true , false,
-                    false, false, true , false,
-                ],
-                _ => unreachable!("Piece Orientation does not compute")
-            }
+    /// Positions of the blocks within the box, y pointing up
+    pub fn cells(&self) -> impl Iterator<Item = (i32, i32)> {
+        let state = self.states[(self.orientation % 4) as usize];
+        (0..MAX_SIZE * MAX_SIZE)
+            .filter(move |i| state & (1 << i) != 0)
+            .map(|i| (i % MAX_SIZE, i / MAX_SIZE))
+    }
 
-            Type::S => match self.orientation % 4 {
-                0 | 2 => [
-                    false, false, false, false,
-                    false, true , true , false,
-                    false, false, true , true ,
-                    false, false, false, false,
-                ],
-                1 | 3 => [
-                    false, false, false, true ,
-                    false, false, true , true ,
-                    false, false, true , false,
-                    false, false, false, false,
-                ],
-                _ => unreachable!("Piece Orientation does not compute")
-            }
+    /// Shift that centers the blocks in the box, with y pointing down
+    pub fn offset(&self) -> (f32, f32) {
+        let (mut min, mut max) = ((MAX_SIZE, MAX_SIZE), (0, 0));
+        for (x, y) in self.cells() {
+            min = (min.0.min(x), min.1.min(y));
+            max = (max.0.max(x), max.1.max(y));
+        }
+        if max.0 < min.0 {
+            return (0.0, 0.0);
+        }
 
-            Type::Z => match self.orientation % 4 {
-                0 | 2 => [
-                    false, false, false, false,
-                    false, false, true , true ,
-                    false, true , true , false,
-                    false, false, false, false,
-                ],
-                1 | 3 => [
-                    false, false, true , false,
-                    false, false, true , true ,
-                    false, false, false, true ,
-                    false, false, false, false,
-                ],
-                _ => unreachable!("Piece Orientation does not compute")
-            }
+        let half = self.size as f32 / 2.0;
+        (half - (min.0 + max.0 + 1) as f32 / 2.0, (min.1 + max.1 + 1) as f32 / 2.0 - half)
+    }
+
+    /// Where the piece enters a board: centered, with its top row in the top row of the board
+    pub fn spawn_position(&self, width: i32, height: i32) -> (i32, i32) {
+        let top = self.cells().map(|(_, y)| y).max().unwrap_or(0);
+        (width / 2 - self.size() / 2, height - 1 - top)
+    }
+}
+
+/// Blocks of the standard pieces in a 4x4 box, row by row from the bottom
+pub fn tetromino_blocks(tp: Type, orientation: Orientation) -> [bool; 16] {
+    match tp {
+        Type::None | Type::Garbage | Type::Custom(_) => [
+            false, false, false, false,
+            false, false, false, false,
+            false, false, false, false,
+            false, false, false, false,
+        ],
+
+        Type::O => [
+            false, false, false, false,
+            false, true,  true,  false,
+            false, true,  true,  false,
+            false, false, false, false,
+        ],
+
+        Type::I =>  match orientation % 4 {
+            0 | 2 => [
+                false, false, false, false,
+                false, false, false, false,
+                true,  true,  true,  true,
+                false, false, false, false,
+            ],
+            1 | 3 => [
+                false, false, true , false,
+                false, false, true , false,
+                false, false, true , false,
+                false, false, true , false,
+            ],
+            _ => unreachable!("Piece Orientation does not compute")
+        },
+
+        Type::T => match orientation % 4 {
+            0 => [
+                false, false, false, false,
+                false, false, false, false,
+                false, true , true , true ,
+                false, false, true , false,
+            ],
+            1 => [
+                false, false, false, false,
+                false, false, true , false,
+                false, true , true , false,
+                false, false, true , false,
+            ],
+            2 => [
+                false, false, false, false,
+                false, false, true , false,
+                false, true , true , true ,
+                false, false, false, false,
+            ],
+            3 => [
+                false, false, false, false,
+                false, false, true , false,
+                false, false, true , true ,
+                false, false, true , false,
+            ],
+            _ => unreachable!("Piece Orientation does not compute")
+        },
+
+        Type::J => match orientation % 4 {
+            0 => [
+                false, false, false, false,
+                false, false, false, false,
+                false, true , true , true ,
+                false, true , false, false,
+            ],
+            1 => [
+                false, false, false, false,
+                false, true , true , false,
+                false, false, true , false,
+                false, false, true , false,
+            ],
+            2 => [
+                false, false, false, false,
+                false, false, false, true,
+                false, true , true , true ,
+                false, false, false, false,
+            ],
+            3 => [
+                false, false, false, false,
+                false, false, true , false,
+                false, false, true , false,
+                false, false, true , true ,
+            ],
+            _ => unreachable!("Piece Orientation does not compute")
+        }
+
+        Type::L => match orientation % 4 {
+            0 => [
+                false, false, false, false,
+                false, false, false, false,
+                false, true , true , true ,
+                false, false, false, true ,
+            ],
+            1 => [
+                false, false, false, false,
+                false, false, true , false,
+                false, false, true , false,
+                false, true , true , false,
+            ],
+            2 => [
+                false, false, false, false,
+                false, true , false, false,
+                false, true , true , true ,
+                false, false, false, false,
+            ],
+            3 => [
+                false, false, false, false,
+                false, false, true , true ,
+                false, false, true , false,
+                false, false, true , false,
+            ],
+            _ => unreachable!("Piece Orientation does not compute")
+        }
+
+        Type::S => match orientation % 4 {
+            0 | 2 => [
+                false, false, false, false,
+                false, true , true , false,
+                false, false, true , true ,
+                false, false, false, false,
+            ],
+            1 | 3 => [
+                false, false, false, true ,
+                false, false, true , true ,
+                false, false, true , false,
+                false, false, false, false,
+            ],
+            _ => unreachable!("Piece Orientation does not compute")
+        }
+
+        Type::Z => match orientation % 4 {
+            0 | 2 => [
+                false, false, false, false,
+                false, false, true , true ,
+                false, true , true , false,
+                false, false, false, false,
+            ],
+            1 | 3 => [
+                false, false, true , false,
+                false, false, true , true ,
+                false, false, false, true ,
+                false, false, false, false,
+            ],
+            _ => unreachable!("Piece Orientation does not compute")
         }
     }
 }
@@ -0,0 +1,297 @@
+//! The pieces a game is played with, either one of the built-in sets or shapes read from text.
+//!
+//! Custom sets are written as one block per piece: a `piece` line, optionally followed by a color
+//! as `piece ff8000`, then the cells as a text grid with '.' for empty cells. Without further
+//! grids the piece rotates in the smallest square box around it, otherwise every rotation state
+//! gets its own grid, separated by lines containing a single '-', in counter-clockwise order.
+//!
+//! ```text
+//! piece 00ffff
+//! .X.
+//! XXX
+//! ```
+
+use std::sync::OnceLock;
+
+use super::piece::{self, Orientation, Type};
+
+#[derive(Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub struct Shape {
+    pub tp: Type,
+    /// RGB in [0, 1], the frontends pick one if there is none
+    pub color: Option<(f32, f32, f32)>,
+    /// edge length of the box the piece rotates in
+    pub size: i32,
+    /// cells of each orientation, counter-clockwise, repeated if there are less than four
+    pub rotations: Vec<Vec<(i32, i32)>>,
+    pub spawn: Orientation,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+#[derive(Serialize, Deserialize)]
+pub enum PieceSet {
+    /// the seven standard pieces
+    #[default]
+    Tetromino,
+    /// the 18 one-sided pentominoes
+    Pentomino,
+    /// the standard pieces with every block doubled in size
+    Big,
+    Custom(Vec<Shape>),
+}
+
+// one-sided pentominoes, in the orientation they spawn in
+const PENTOMINO_GRIDS: [&str; 18] = [
+    ".XX|XX.|.X.", "XX.|.XX|.X.", "XXXXX", "XXXX|X...", "XXXX|...X", "XXX.|..XX", ".XXX|XX..",
+    "XX|XX|X.", "XX|XX|.X", "XXX|.X.|.X.", "X.X|XXX", "X..|X..|XXX", "X..|XX.|.XX", ".X.|XXX|.X.",
+    "XXXX|.X..", "XXXX|..X.", "XX.|.X.|.XX", ".XX|.X.|XX.",
+];
+
+impl Shape {
+    /// Parses a text grid, top row first, any character other than '.' is a block.
+    /// The piece is centered in a square box and rotates in it.
+    pub fn from_grid(tp: Type, grid: &[&str], color: Option<(f32, f32, f32)>) -> Result<Self, String> {
+        let rows: Vec<&str> = grid.iter().map(|row| row.trim()).filter(|row| !row.is_empty()).collect();
+        let width = rows.iter().map(|row| row.chars().count()).max().unwrap_or(0) as i32;
+        let height = rows.len() as i32;
+        let size = width.max(height);
+
+        if size == 0 || size > piece::MAX_SIZE {
+            return Err(format!("Pieces need to fit into {0}x{0} blocks", piece::MAX_SIZE));
+        }
+
+        let (dx, dy) = ((size - width) / 2, (size - height) / 2);
+        let mut cells = Vec::new();
+        for (row, line) in rows.iter().enumerate() {
+            for (x, c) in line.chars().enumerate() {
+                if c != '.' {
+                    cells.push((x as i32 + dx, size - 1 - row as i32 - dy));
+                }
+            }
+        }
+
+        if cells.is_empty() {
+            return Err(String::from("Piece without blocks"));
+        }
+
+        let mut rotations = vec!(cells);
+        for i in 0..3 {
+            let next = rotations[i].iter().map(|(x, y)| (size - 1 - y, *x)).collect();
+            rotations.push(next);
+        }
+
+        Ok(Shape { tp, color, size, rotations, spawn: 0 })
+    }
+
+    /// Whether the cells are inside the box and the box fits into a piece, shapes read from
+    /// files or the network can be anything
+    pub fn check(&self) -> Result<(), String> {
+        if self.size <= 0 || self.size > piece::MAX_SIZE {
+            return Err(format!("Pieces need to fit into {0}x{0} blocks, not {1}x{1}", piece::MAX_SIZE, self.size));
+        }
+        if self.rotations.is_empty() || self.rotations.len() > 4 {
+            return Err(format!("Pieces have 1 to 4 rotation states, not {}", self.rotations.len()));
+        }
+        if self.spawn > 3 {
+            return Err(format!("Invalid spawn orientation {}", self.spawn));
+        }
+        let outside = self.rotations.iter().flatten().find(|(x, y)| *x < 0 || *y < 0 || *x >= self.size || *y >= self.size);
+        match outside {
+            Some((x, y)) => Err(format!("Block at {},{} is outside of the {}x{} box", x, y, self.size, self.size)),
+            None => Ok(()),
+        }
+    }
+
+    /// The same shape with every block turned into a square of `factor` x `factor` blocks
+    pub fn scaled(&self, factor: i32) -> Self {
+        let rotations = self.rotations.iter()
+            .map(|cells| cells.iter()
+                .flat_map(|(x, y)| (0..factor * factor).map(move |i| (x * factor + i % factor, y * factor + i / factor)))
+                .collect())
+            .collect();
+
+        Shape {
+            size: self.size * factor,
+            rotations,
+            ..self.clone()
+        }
+    }
+
+    fn tetromino(tp: Type) -> Self {
+        let rotations = (0..4)
+            .map(|orientation| {
+                let blocks = piece::tetromino_blocks(tp, orientation);
+                (0..16).filter(|i| blocks[*i]).map(|i| ((i % 4) as i32, (i / 4) as i32)).collect()
+            })
+            .collect();
+
+        Shape { tp, color: None, size: 4, rotations, spawn: 2 }
+    }
+}
+
+fn tetrominoes() -> Vec<Shape> {
+    [Type::O, Type::I, Type::T, Type::L, Type::J, Type::S, Type::Z].iter()
+        .map(|tp| Shape::tetromino(*tp))
+        .collect()
+}
+
+fn pentominoes() -> Vec<Shape> {
+    PENTOMINO_GRIDS.iter().enumerate()
+        .map(|(i, grid)| Shape::from_grid(Type::Custom(i as u8), &grid.split('|').collect::<Vec<&str>>(), None).unwrap())
+        .collect()
+}
+
+fn big() -> Vec<Shape> {
+    tetrominoes().iter().map(|shape| shape.scaled(2)).collect()
+}
+
+// the built-in sets get built the first time they're used
+static TETROMINOES: OnceLock<Vec<Shape>> = OnceLock::new();
+static PENTOMINOES: OnceLock<Vec<Shape>> = OnceLock::new();
+static BIG: OnceLock<Vec<Shape>> = OnceLock::new();
+
+impl PieceSet {
+    pub fn shapes(&self) -> &[Shape] {
+        match self {
+            PieceSet::Tetromino => TETROMINOES.get_or_init(tetrominoes),
+            PieceSet::Pentomino => PENTOMINOES.get_or_init(pentominoes),
+            PieceSet::Big => BIG.get_or_init(big),
+            PieceSet::Custom(shapes) => shapes,
+        }
+    }
+
+    pub fn types(&self) -> Vec<Type> {
+        self.shapes().iter().map(|shape| shape.tp).collect()
+    }
+
+    pub fn shape(&self, tp: Type) -> Option<&Shape> {
+        self.shapes().iter().find(|shape| shape.tp == tp)
+    }
+
+    /// The piece in its spawn orientation
+    pub fn piece(&self, tp: Type) -> piece::Piece {
+        match self.shape(tp) {
+            Some(shape) => piece::Piece::from_shape(shape, shape.spawn),
+            None => piece::Piece::new(tp, 2),
+        }
+    }
+
+    /// Checks the shapes of a custom set
+    pub fn check(&self) -> Result<(), String> {
+        match self {
+            PieceSet::Custom(shapes) if shapes.is_empty() => Err(String::from("No pieces defined")),
+            PieceSet::Custom(shapes) if shapes.len() > Type::max_custom() => Err(format!("At most {} pieces are supported", Type::max_custom())),
+            PieceSet::Custom(shapes) => shapes.iter().try_for_each(|shape| shape.check()),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            PieceSet::Tetromino => "Tetrominoes",
+            PieceSet::Pentomino => "Pentominoes",
+            PieceSet::Big => "Big",
+            PieceSet::Custom(_) => "Custom",
+        }
+    }
+
+    /// Reads a custom set, see the module documentation for the format
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut shapes: Vec<Shape> = Vec::new();
+        let mut lines = text.lines().map(|line| line.trim()).filter(|line| !line.is_empty()).peekable();
+
+        while let Some(line) = lines.next() {
+            let mut words = line.split_whitespace();
+            if words.next() != Some("piece") {
+                return Err(format!("Expected 'piece', got '{}'", line));
+            }
+            let color = match words.next() {
+                Some(hex) => Some(parse_color(hex)?),
+                None => None,
+            };
+
+            // grids of the rotation states
+            let mut grids = vec!(Vec::new());
+            while let Some(line) = lines.peek().cloned().filter(|line| !line.starts_with("piece")) {
+                if line == "-" {
+                    grids.push(Vec::new());
+                } else {
+                    grids.last_mut().unwrap().push(line);
+                }
+                lines.next();
+            }
+
+            if shapes.len() >= Type::max_custom() {
+                return Err(format!("At most {} pieces are supported", Type::max_custom()));
+            }
+            let tp = Type::Custom(shapes.len() as u8);
+
+            let mut shape = Shape::from_grid(tp, &grids[0], color)?;
+            if grids.len() > 1 {
+                let states = grids.iter()
+                    .map(|grid| Shape::from_grid(tp, grid, color))
+                    .collect::<Result<Vec<Shape>, String>>()?;
+                shape.size = states.iter().map(|state| state.size).max().unwrap();
+                // keep all states aligned at the top left of the largest box
+                shape.rotations = states.iter()
+                    .map(|state| state.rotations[0].iter().map(|(x, y)| (*x, *y - state.size + shape.size)).collect())
+                    .collect();
+            }
+            shapes.push(shape);
+        }
+
+        let set = PieceSet::Custom(shapes);
+        set.check()?;
+        Ok(set)
+    }
+}
+
+fn parse_color(hex: &str) -> Result<(f32, f32, f32), String> {
+    let value = u32::from_str_radix(hex.trim_start_matches('#'), 16)
+        .ok()
+        .filter(|_| hex.trim_start_matches('#').len() == 6)
+        .ok_or(format!("Invalid color '{}', expected RRGGBB", hex))?;
+
+    let channel = |shift: u32| ((value >> shift) & 0xff) as f32 / 255.0;
+    Ok((channel(16), channel(8), channel(0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shapes() {
+        // the standard pieces still spawn the way they always did
+        let t = PieceSet::Tetromino.piece(Type::T);
+        assert_eq!(t, piece::Piece::new(Type::T, 2));
+        assert_eq!(t.spawn_position(10, 20), (3, 17));
+
+        let big = PieceSet::Big.piece(Type::O);
+        assert_eq!(big.size(), 8);
+        assert_eq!(big.cells().count(), 16);
+
+        for shape in PieceSet::Pentomino.shapes() {
+            assert!(shape.rotations.iter().all(|cells| cells.len() == 5));
+        }
+
+        let set = PieceSet::parse("
+            piece ff0000
+            XXX
+            piece
+            XX
+            -
+            X.
+            X.
+        ").unwrap();
+        let shapes = set.shapes();
+        assert_eq!(shapes.len(), 2);
+        assert_eq!(shapes[0].color, Some((1.0, 0.0, 0.0)));
+        assert_eq!(shapes[1].rotations.len(), 2);
+
+        assert!(PieceSet::parse("XX").is_err());
+        assert!(PieceSet::parse("").is_err());
+        assert!(PieceSet::parse("piece zz\nXX").is_err());
+    }
+}
@@ -0,0 +1,117 @@
+//! Drills: preset boards with a fixed piece sequence and a goal to reach with them.
+
+use super::game::Game;
+use super::piece;
+use super::notation::{self, Position};
+use super::{Config, PieceSet};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Goal {
+    /// Clear at least this many lines
+    Lines(i32),
+    /// Clear four lines at once
+    Tetris,
+    /// Clear lines until the board is empty
+    PerfectClear,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Judgement {
+    Playing,
+    Success,
+    Failed,
+}
+
+#[derive(Clone)]
+pub struct Drill {
+    pub name: String,
+    pub position: Position,
+    pub goal: Goal,
+}
+
+impl Drill {
+    pub fn new(name: &str, position: Position, goal: Goal) -> Self {
+        Drill {
+            name: String::from(name),
+            position,
+            goal,
+        }
+    }
+
+    fn from_text(name: &str, grid: &str, queue: &str, goal: Goal) -> Self {
+        let stack = notation::parse_grid(grid, 10, 20).expect("Invalid built-in drill");
+        let queue = notation::parse_queue(queue).expect("Invalid built-in drill");
+        Self::new(name, Position::new(stack, queue), goal)
+    }
+
+    /// Drills that are always available, for the default 10x20 board
+    pub fn builtin() -> Vec<Drill> {
+        vec!(
+            Self::from_text("Take the tetris", "
+                XXXXXXXXX.
+                XXXXXXXXX.
+                XXXXXXXXX.
+                XXXXXXXXX.
+            ", "OLJI", Goal::Tetris),
+            Self::from_text("Downstack", "
+                XXXX.XXXXX
+                XXX.XXXXXX
+                XXXXXX.XXX
+                XX.XXXXXXX
+            ", "TSZLJIOTSZLJ", Goal::Lines(4)),
+            Self::from_text("Perfect clear", "
+                XXXXXX....
+                XXXXXX....
+            ", "II", Goal::PerfectClear),
+        )
+    }
+
+    /// Plays the drill with the standard pieces on its own board, whatever the config says
+    pub fn start(&self, config: &Config) -> Game {
+        let config = Config {
+            width: self.position.stack.width(),
+            height: self.position.stack.height(),
+            pieces: PieceSet::Tetromino,
+            ..config.clone()
+        };
+        Game::from_position(&config, &self.position)
+    }
+
+    /// Checks the goal, the drill fails when the game is lost or all of its pieces got placed
+    pub fn judge(&self, game: &Game) -> Judgement {
+        let snapshot = game.snapshot();
+
+        let reached = match self.goal {
+            Goal::Lines(lines) => snapshot.lines() >= lines,
+            Goal::Tetris => snapshot.tetrises() > 0,
+            Goal::PerfectClear => {
+                // the stack is only cleared up once the line clear animation is over
+                let blocks = snapshot.stack().blocks();
+                snapshot.animation().is_none() && snapshot.lines() > 0 &&
+                    (0..blocks.height()).all(|y| (0..blocks.width()).all(|x| *blocks.at(x, y) == piece::Type::None))
+            }
+        };
+
+        // the piece that is currently falling, or waiting to spawn, is already counted
+        let placed = snapshot.stats().total() as usize - 1;
+
+        if reached {
+            Judgement::Success
+        } else if game.lost() || (placed >= self.position.queue.len() && snapshot.animation().is_none()) {
+            Judgement::Failed
+        } else {
+            Judgement::Playing
+        }
+    }
+}
+
+impl std::fmt::Display for Goal {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Goal::Lines(1) => write!(f, "Clear a line"),
+            Goal::Lines(lines) => write!(f, "Clear {} lines", lines),
+            Goal::Tetris => write!(f, "Score a tetris"),
+            Goal::PerfectClear => write!(f, "Clear the whole board"),
+        }
+    }
+}
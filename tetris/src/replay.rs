@@ -1,6 +1,9 @@
 use super::Config;
 use super::state::*;
 use super::piece;
+use super::stack::Stack;
+
+use array2d::Array2D;
 
 #[derive(Debug, FromPrimitive, ToPrimitive)]
 enum EntryType {
@@ -11,8 +14,11 @@ enum EntryType {
     Merge,
     Spawn,
     NextPiece,
+    // run of blocks of the initial board, uses the dt bits as well, see Entry::board
+    Board,
 }
 
+#[derive(Clone, Copy)]
 struct Entry (
     u16
 );
@@ -37,6 +43,17 @@ impl Entry {
     fn detail(&self) -> u8 {
         (self.0 & 0x1f) as u8
     }
+
+    // 13 bits of payload: five for the type, eight for the length of the run
+    fn board(tp: piece::Type, count: usize) -> Self {
+        let payload = ((tp.to_int() as usize) << 8) | (count - 1);
+        Self::from(payload >> 5, EntryType::Board, (payload & 0x1f) as u8)
+    }
+
+    fn board_run(&self) -> (piece::Type, usize) {
+        let payload = (self.dt() << 5) | self.detail() as usize;
+        (piece::Type::from_int((payload >> 8) as u32), (payload & 0xff) + 1)
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -48,6 +65,37 @@ pub struct Replay {
     data: Vec<u16>,
 }
 
+/// The entries recorded after the first `from` ones, to send a game that is still going on bit by bit
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayDelta {
+    pub from: usize,
+    /// end of the recording
+    pub time: i32,
+    pub data: Vec<u16>,
+}
+
+/// Layout of `Replay` before level curves, for binary encoded replays stored by older servers
+#[derive(Deserialize)]
+pub struct LegacyReplay {
+    config: super::LegacyConfig,
+    first: piece::Type,
+    second: piece::Type,
+    time: i32,
+    data: Vec<u16>,
+}
+
+impl From<LegacyReplay> for Replay {
+    fn from(legacy: LegacyReplay) -> Self {
+        Replay {
+            config: legacy.config.into(),
+            first: legacy.first,
+            second: legacy.second,
+            time: legacy.time,
+            data: legacy.data,
+        }
+    }
+}
+
 impl Replay {
     pub fn new(config: &Config, first: piece::Type, second: piece::Type, time: i32) -> Self {
         Replay {
@@ -69,6 +117,48 @@ impl Replay {
         self.time = time;
     }
 
+    /// Stores the board the game started on, must be called before any other entry gets added
+    pub fn set_stack(&mut self, stack: &Stack) {
+        assert!(self.data.is_empty(), "The initial board has to be the first replay entry");
+
+        // runs of equal blocks, row by row from the bottom, trailing empty blocks are left out
+        let mut runs: Vec<(piece::Type, usize)> = Vec::new();
+        stack.blocks().for_each(&mut |_x, _y, tp| {
+            match runs.last_mut() {
+                Some(run) if run.0 == *tp && run.1 < 256 => run.1 += 1,
+                _ => runs.push((*tp, 1)),
+            }
+        });
+        if runs.last().is_some_and(|run| run.0 == piece::Type::None) {
+            runs.pop();
+        }
+
+        for run in runs {
+            self.data.push(Entry::board(run.0, run.1).0);
+        }
+    }
+
+    /// The board the game started on, the config's board size has to be checked
+    fn stack(&self) -> Stack {
+        let width = self.config.width as usize;
+        let mut blocks = Array2D::new(width, self.config.height as usize, piece::Type::None);
+        let mut idx = 0;
+        for entry in self.data.iter().map(|entry| Entry(*entry)) {
+            if let EntryType::Board = entry.entry_type() {
+                let (tp, count) = entry.board_run();
+                for _ in 0..count {
+                    if idx / width < blocks.height() {
+                        blocks.set(idx % width, idx / width, tp);
+                    }
+                    idx += 1;
+                }
+            } else {
+                break;
+            }
+        }
+        Stack::from_blocks(blocks)
+    }
+
     pub fn add_move(&mut self, time: i32, rotate: Option<bool>, x: i32, y: i32) {
         if let Some(clockwise) = rotate {
             self.add(time, EntryType::Rot, if clockwise { 1 } else { 0 });
@@ -82,14 +172,130 @@ impl Replay {
     }
 
     pub fn add_merge(&mut self, time: i32, drop: i32, next: piece::Piece) {
+        // the detail only has five bits, longer soft drops take several entries
+        let mut drop = drop.max(0);
+        while drop > 0x1f {
+            self.add(time, EntryType::Merge, 0x1f);
+            drop -= 0x1f;
+        }
         self.add(time, EntryType::Merge, drop as u8);
-        self.add(time, EntryType::NextPiece, next.get_type() as u8);
+        self.add(time, EntryType::NextPiece, next.get_type().to_int() as u8);
     }
 
     pub fn add_new_piece(&mut self, time: i32) {
         self.add(time, EntryType::Spawn, 0);
     }
 
+    /// Config and first pieces without any entries, what a live game starts out from
+    pub fn header(&self) -> Self {
+        Self::new(&self.config, self.first, self.second, 0)
+    }
+
+    pub fn delta(&self, from: usize) -> ReplayDelta {
+        ReplayDelta {
+            from,
+            time: self.time,
+            data: self.data[from.min(self.data.len())..].to_vec(),
+        }
+    }
+
+    /// Appends the entries of a delta, which has to start where this replay ends
+    pub fn apply(&mut self, delta: &ReplayDelta) -> Result<(), String> {
+        if delta.from != self.data.len() {
+            return Err(format!("Delta starts at entry {}, replay has {}", delta.from, self.data.len()));
+        }
+        if delta.time < self.time {
+            return Err(format!("Delta ends at frame {}, before the replay at {}", delta.time, self.time));
+        }
+        for (idx, entry) in delta.data.iter().enumerate() {
+            self.check_entry(Entry(*entry)).map_err(|err| format!("Entry {}: {}", delta.from + idx, err))?;
+        }
+        self.data.extend_from_slice(&delta.data);
+        self.time = delta.time;
+        Ok(())
+    }
+
+    fn check_piece(&self, tp: piece::Type) -> Result<(), String> {
+        if self.config.pieces.types().contains(&tp) {
+            Ok(())
+        } else {
+            Err(format!("{:?} isn't part of the piece set", tp))
+        }
+    }
+
+    /// Checks what can be checked without playing the game, for replays from files or the network
+    pub fn validate(&self) -> Result<(), String> {
+        self.config.check_board()?;
+        self.config.pieces.check()?;
+        self.check_piece(self.first)?;
+        self.check_piece(self.second)?;
+        if self.time < 0 {
+            return Err(format!("Replay ends at frame {}", self.time));
+        }
+        for (idx, entry) in self.data.iter().enumerate() {
+            self.check_entry(Entry(*entry)).map_err(|err| format!("Entry {}: {}", idx, err))?;
+        }
+        Ok(())
+    }
+
+    /// Key presses that moved each of the merged pieces, in order. Every rotation is a press of
+    /// its own, shifts in the same direction are one press as long as they come in the rhythm
+    /// of auto shift. Soft drop doesn't count.
+    pub fn inputs_per_piece(&self) -> Vec<i32> {
+        let mut ret = Vec::new();
+        let mut inputs = 0;
+        let mut time = 0;
+        // direction and frame of the last shift
+        let mut shift: Option<(i32, i32)> = None;
+
+        for entry in self.data.iter().map(|entry| Entry(*entry)) {
+            if let EntryType::Board = entry.entry_type() {
+                continue;
+            }
+            time += entry.dt() as i32;
+
+            match entry.entry_type() {
+                EntryType::Rot => inputs += 1,
+                EntryType::MoveX => {
+                    let direction = entry.detail() as i32 - 16;
+                    let held = shift.map_or(false, |(last, at)| {
+                        last == direction && (time - at == self.config.das_step || time - at == self.config.das_initial)
+                    });
+                    if !held {
+                        inputs += 1;
+                    }
+                    shift = Some((direction, time));
+                }
+                EntryType::NextPiece => {
+                    ret.push(inputs);
+                    inputs = 0;
+                    shift = None;
+                }
+                _ => {}
+            }
+        }
+        ret
+    }
+
+    // what can't be replayed no matter what came before
+    fn check_entry(&self, entry: Entry) -> Result<(), String> {
+        match entry.entry_type() {
+            EntryType::NextPiece => self.check_piece(piece::Type::from_int(entry.detail() as u32)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Current end of the recording, to `rewind` to later on
+    pub fn position(&self) -> (usize, i32) {
+        (self.data.len(), self.time)
+    }
+
+    /// Forgets everything that got recorded after `position`
+    pub fn rewind(&mut self, position: (usize, i32)) {
+        self.data.truncate(position.0);
+        self.time = position.1;
+    }
+
     pub fn config(&self) -> &Config {
         &self.config
     }
@@ -97,6 +303,23 @@ impl Replay {
     pub fn frames(&self) -> i32 {
         self.time
     }
+
+    /// Length in seconds
+    pub fn duration(&self) -> f32 {
+        self.time as f32 / self.config.fps()
+    }
+
+    /// Text representation for replay files, same encoding as the one used for the server
+    pub fn to_text(&self) -> String {
+        super::networking::encode(self)
+    }
+
+    /// Parses a replay file, which may also contain the plain JSON serialization
+    pub fn from_text(text: &str) -> Option<Self> {
+        let text = text.trim();
+        super::networking::decode::<Self>(text)
+            .or_else(|| serde_json::from_str(text).ok())
+    }
 }
 
 impl std::fmt::Debug for Replay {
@@ -113,72 +336,125 @@ pub struct Replayer {
 
     pub paused: bool,
     pub speed: f32,
+    /// start and end in seconds of a part that plays over and over
+    pub looped: Option<(f32, f32)>,
 
     frames: usize,
     time: f32,
+
+    // entries played so far, with the time, soft drop and piece position they left behind
+    played: usize,
+    played_time: i32,
+    drop: i32,
+    posx: i32,
+    posy: i32,
 }
 
 impl Replayer {
-    pub fn new(replay: &Replay) -> Self {
-        let state = GameHistory::new(
+    /// Fails for replays that don't play back, like broken files or ones of another piece set
+    pub fn new(replay: &Replay) -> Result<Self, String> {
+        // the board gets decoded right away
+        replay.config.check_board()?;
+        replay.config.pieces.check()?;
+        replay.check_piece(replay.first)?;
+        replay.check_piece(replay.second)?;
+        let state = GameHistory::with_stack(
             &replay.config,
-            piece::Piece::new(replay.first, 2),
-            piece::Piece::new(replay.second, 2)
+            replay.stack(),
+            replay.config.pieces.piece(replay.first),
+            replay.config.pieces.piece(replay.second)
         );
+        let (_, posx, posy) = state.snapshot().piece().ok_or_else(|| String::from("The game starts without a piece"))?;
 
         let mut ret = Replayer {
             config: replay.config.clone(),
             state,
             paused: false,
             speed: 1.0,
-            frames: replay.frames().max(0) as usize,
+            looped: None,
+            frames: 0,
             time: 0.0,
+            played: 0,
+            played_time: 0,
+            drop: 0,
+            posx,
+            posy,
         };
+        ret.extend(replay)?;
+        Ok(ret)
+    }
 
-        // keep track of current time, piece, soft-drop
-        let mut time = 0;
-        let mut drop = 0;
-        let mut posx = ret.state.snapshot().piece().unwrap().1;
-        let mut posy = ret.state.snapshot().piece().unwrap().2;
-
-        for entry in &replay.data {
+    /// Plays the entries recorded since the last call, to follow a game that is still going on.
+    /// `replay` has to be the one the replayer was created from, it may only have grown since.
+    /// Stops at the first entry that can't be played.
+    pub fn extend(&mut self, replay: &Replay) -> Result<(), String> {
+        let entries = replay.data.get(self.played..)
+            .ok_or_else(|| format!("Replay has {} entries, {} got played already", replay.data.len(), self.played))?;
+        for entry in entries {
             let entry = Entry(*entry);
-            let detail = entry.detail();
-            time += entry.dt() as i32;
+            replay.check_entry(entry)
+                .and_then(|_| self.play(entry))
+                .map_err(|err| format!("Entry {}: {}", self.played, err))?;
+            self.played += 1;
+        }
 
-            match entry.entry_type() {
-                EntryType::Nop => {
-                }
-                EntryType::MoveX => {
-                    let piece = ret.state.snapshot().piece().unwrap().0;
-                    posx += detail as i32 - 16;
-                    ret.state.try_move(time, piece, posx, posy);
-                }
-                EntryType::MoveDown => {
-                    let piece = ret.state.snapshot().piece().unwrap().0;
-                    posy += detail as i32 - 16;
-                    ret.state.try_move(time, piece, posx, posy);
-                }
-                EntryType::Rot => {
-                    let piece = ret.state.snapshot().piece().unwrap().0.rotate(detail != 0);
-                    ret.state.try_move(time, piece, posx, posy);
-                }
-                EntryType::Merge => {
-                    drop = detail;
-                }
-                EntryType::Spawn => {
-                    ret.state.start_new_piece(time);
-                    posx = ret.state.snapshot().piece().unwrap().1;
-                    posy = ret.state.snapshot().piece().unwrap().2;
+        self.frames = replay.frames().max(0) as usize;
+        Ok(())
+    }
+
+    fn piece(&self) -> Result<piece::Piece, String> {
+        self.state.snapshot().piece().map(|(piece, _, _)| piece).ok_or_else(|| String::from("There is no piece in play"))
+    }
+
+    fn play(&mut self, entry: Entry) -> Result<(), String> {
+        let detail = entry.detail();
+        let entry_type = entry.entry_type();
+        if let EntryType::Board = entry_type {
+            return Ok(());
+        }
+        self.played_time += entry.dt() as i32;
+        let time = self.played_time;
+
+        match entry_type {
+            EntryType::Nop | EntryType::Board => {
+            }
+            EntryType::MoveX => {
+                let piece = self.piece()?;
+                self.posx += detail as i32 - 16;
+                self.state.try_move(time, piece, self.posx, self.posy);
+            }
+            EntryType::MoveDown => {
+                let piece = self.piece()?;
+                self.posy += detail as i32 - 16;
+                self.state.try_move(time, piece, self.posx, self.posy);
+            }
+            EntryType::Rot => {
+                let piece = self.piece()?.rotate(detail != 0);
+                self.state.try_move(time, piece, self.posx, self.posy);
+            }
+            EntryType::Merge => {
+                // long soft drops are split over several entries
+                self.drop += detail as i32;
+            }
+            EntryType::Spawn => {
+                if self.state.snapshot().piece().is_some() {
+                    return Err(String::from("A piece spawns while the last one is still there"));
                 }
-                EntryType::NextPiece => {
-                    let tp = piece::Type::from_int(detail as u32);
-                    ret.state.merge(time, piece::Piece::new(tp, 2), drop as i32);
+                self.state.start_new_piece(time);
+                // a piece that doesn't fit ends the game
+                if let Some((_, posx, posy)) = self.state.snapshot().piece() {
+                    self.posx = posx;
+                    self.posy = posy;
                 }
             }
+            EntryType::NextPiece => {
+                self.piece()?;
+                let tp = piece::Type::from_int(detail as u32);
+                self.state.merge(time, self.config.pieces.piece(tp), self.drop);
+                self.drop = 0;
+            }
         }
-
-        ret
+        Ok(())
     }
 
     pub fn frame(&self) -> f32 {
@@ -189,18 +465,47 @@ impl Replayer {
         self.time = time.max(0.0).min(self.length());
     }
 
+    /// Jumps to the start of a frame
+    pub fn jump_frame(&mut self, frame: i32) {
+        self.jump(frame as f32 / self.config.fps());
+    }
+
+    /// Goes the given number of frames forward or back
+    pub fn step(&mut self, frames: i32) {
+        let frame = self.timestamp().saturating_add(frames);
+        self.jump_frame(frame);
+    }
+
     pub fn advance(&mut self, dt: f32) {
         if !self.paused {
             self.time = (self.time + dt).max(0.0).min(self.length());
+
+            // leaving the loop in playing direction starts it over
+            if let Some((start, end)) = self.looped.filter(|(start, end)| end > start) {
+                if dt >= 0.0 && (self.time < start || self.time >= end) {
+                    self.time = start;
+                } else if dt < 0.0 && (self.time <= start || self.time > end) {
+                    self.time = end;
+                }
+            }
         }
     }
 
     pub fn length(&self) -> f32 {
-        self.frames as f32 / 60.0
+        self.frames as f32 / self.config.fps()
     }
 
     pub fn timestamp(&self) -> i32 {
-        (self.time * 60.0) as i32
+        // a little extra so the start of a frame doesn't round down to the one before
+        (self.time * self.config.fps() + 0.001) as i32
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn history(&self) -> &GameHistory {
+        &self.state
     }
 
     pub fn snapshot(&self) -> &Snapshot {
@@ -0,0 +1,295 @@
+//! Plays a `Game` from scripted input and checks after every frame that the rules still hold.
+//! Used by the randomized tests and by the fuzz target in `fuzz/`, which feeds raw bytes to `fuzz`:
+//!
+//! ```text
+//! cargo fuzz run game
+//! ```
+
+use rand::Rng;
+use rand::rngs::StdRng;
+
+use super::{Config, Killscreen, LevelCurve, PieceSet};
+use super::analysis::Keys;
+use super::game::Game;
+use super::notation::Position;
+use super::replay::Replayer;
+use super::stack::Stack;
+
+/// Keys held down during one frame
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Input {
+    pub left: bool,
+    pub right: bool,
+    pub down: bool,
+    /// rotate this frame, clockwise or not
+    pub rotate: Option<bool>,
+    /// step back to the previous piece, like practice mode does
+    pub undo: bool,
+}
+
+impl Input {
+    /// Bits 0-2 are left, right and down, bit 3 rotates in the direction of bit 4.
+    /// All of the top three bits set is an undo.
+    pub fn from_byte(byte: u8) -> Self {
+        Input {
+            left: byte & 0x01 != 0,
+            right: byte & 0x02 != 0,
+            down: byte & 0x04 != 0,
+            rotate: if byte & 0x08 != 0 { Some(byte & 0x10 != 0) } else { None },
+            undo: byte & 0xe0 == 0xe0,
+        }
+    }
+}
+
+/// What `analysis::inputs` can tell from a recording of the frame, which doesn't show undos
+impl From<Input> for Keys {
+    fn from(input: Input) -> Self {
+        Keys {
+            left: input.left,
+            right: input.right,
+            down: input.down,
+            rotate: input.rotate,
+        }
+    }
+}
+
+pub struct SimDriver {
+    game: Game,
+    // follows the replay as it gets recorded, starts over after an undo rewound it
+    replayer: Option<Replayer>,
+    // replay position the reconstruction was last compared at
+    checked: Option<(usize, i32)>,
+}
+
+impl SimDriver {
+    pub fn new(config: &Config, seed: u64) -> Self {
+        let position = Position::new(Stack::new(config.width as usize, config.height as usize), Vec::new());
+        Self::from_position(config, &position, seed)
+    }
+
+    pub fn from_position(config: &Config, position: &Position, seed: u64) -> Self {
+        SimDriver {
+            game: Game::with_seed(config, position, seed),
+            replayer: None,
+            checked: None,
+        }
+    }
+
+    /// Configurations worth covering: every level curve, the killscreens and all piece sets,
+    /// on boards from tiny to tall
+    pub fn configs() -> Vec<Config> {
+        let with = |f: &dyn Fn(&mut Config)| {
+            let mut config = Config::new();
+            f(&mut config);
+            config
+        };
+
+        vec!(
+            Config::new(),
+            with(&|c| c.level = 19),
+            with(&|c| c.level = 29),
+            Config::pal(),
+            Config::with_curve(LevelCurve::TwentyG),
+            with(&|c| { c.curve = LevelCurve::Guideline; c.killscreen = Killscreen::End(1); }),
+            with(&|c| { c.level = 5; c.killscreen = Killscreen::Cap(6); }),
+            with(&|c| { c.width = 4; c.height = 10; }),
+            with(&|c| { c.width = 30; c.height = 40; }),
+            with(&|c| { c.pieces = PieceSet::Pentomino; c.width = 8; c.height = 16; }),
+            with(&|c| { c.pieces = PieceSet::Big; c.width = 12; c.height = 20; }),
+        )
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    /// Plays one frame with the given keys held, then checks the invariants
+    pub fn step(&mut self, input: Input) -> Result<(), String> {
+        let timestamp = self.game.timestamp();
+
+        if input.undo {
+            self.game.undo();
+            self.replayer = None;
+        }
+        self.game.left(input.left);
+        self.game.right(input.right);
+        self.game.down(input.down);
+        if let Some(clockwise) = input.rotate {
+            self.game.rotate(clockwise);
+        }
+        self.game.frame();
+
+        if !input.undo && self.game.timestamp() != timestamp + 1 {
+            return Err(format!("Frame went from {} to {}", timestamp, self.game.timestamp()));
+        }
+
+        self.check()
+    }
+
+    /// Plays all frames, stopping at the first broken invariant
+    pub fn run(&mut self, inputs: &[Input]) -> Result<(), String> {
+        for (frame, input) in inputs.iter().enumerate() {
+            self.step(*input).map_err(|err| format!("Frame {}, {:?}: {}", frame, input, err))?;
+        }
+        Ok(())
+    }
+
+    pub fn check(&mut self) -> Result<(), String> {
+        let snapshot = self.game.snapshot();
+        let replay = self.game.replay();
+
+        if let (Some((piece, x, y)), false) = (snapshot.piece(), self.game.lost()) {
+            if !snapshot.stack().fits(piece, x, y) {
+                return Err(format!("Piece overlaps the stack\n{}", snapshot));
+            }
+        }
+
+        // the replay ends with the last change to the game, which can't be in the future
+        if replay.frames() != snapshot.timestamp() || replay.frames() > self.game.timestamp() {
+            return Err(format!("Replay is {} frames long, last change at {}, now at {}",
+                replay.frames(), snapshot.timestamp(), self.game.timestamp()));
+        }
+
+        // nothing got recorded, so nothing changed since the last reconstruction
+        if self.checked == Some(replay.position()) {
+            return Ok(());
+        }
+        self.checked = Some(replay.position());
+
+        match self.replayer.as_mut() {
+            Some(replayer) => replayer.extend(replay)?,
+            None => self.replayer = Some(Replayer::new(replay)?),
+        }
+        let replayer = self.replayer.as_ref().unwrap();
+        if (replayer.length() * replayer.config().fps()).round() as i32 != replay.frames() {
+            return Err(format!("Replayer is {}s long, replay has {} frames", replayer.length(), replay.frames()));
+        }
+
+        let (live, replayed) = (self.game.history(), replayer.history());
+        if live.frame_count() != replayed.frame_count() {
+            return Err(format!("Game has {} snapshots, replay {}", live.frame_count(), replayed.frame_count()));
+        }
+
+        let (live, replayed) = (live.snapshot(), replayed.snapshot());
+        let same = live.timestamp() == replayed.timestamp()
+            && live.piece() == replayed.piece()
+            && live.next_piece() == replayed.next_piece()
+            && (live.score(), live.level(), live.lines()) == (replayed.score(), replayed.level(), replayed.lines())
+            && live.stack().blocks() == replayed.stack().blocks();
+        if !same {
+            return Err(format!("Replay differs from the game\n{}\nat {}, replayed\n{}\nat {}",
+                live, live.timestamp(), replayed, replayed.timestamp()));
+        }
+
+        Ok(())
+    }
+}
+
+/// Every key pressed and released at random, held for a few frames at a time
+pub fn random(rng: &mut StdRng, frames: usize) -> Vec<Input> {
+    let mut inputs = Vec::with_capacity(frames);
+    while inputs.len() < frames {
+        let input = Input::from_byte(rng.gen());
+        let hold = rng.gen_range(1, 20);
+        inputs.extend((0..hold).map(|i| Input { rotate: input.rotate.filter(|_| i == 0), undo: input.undo && i == 0, ..input }));
+    }
+    inputs.truncate(frames);
+    inputs
+}
+
+/// Runs of inputs that go for the corner cases: both directions at once, keys flipping every
+/// frame, rotations pressed with every other key, soft drop tapping and undo right after a lock
+pub fn adversarial(rng: &mut StdRng, frames: usize) -> Vec<Input> {
+    let mut inputs = Vec::with_capacity(frames);
+    while inputs.len() < frames {
+        let pattern = rng.gen_range(0, 7);
+        let length = rng.gen_range(10, 200);
+        let left = rng.gen();
+
+        for i in 0..length {
+            let even = i % 2 == 0;
+            inputs.push(match pattern {
+                0 => Input { left: true, right: true, down: even, ..Input::default() },
+                1 => Input { left: even, right: !even, ..Input::default() },
+                2 => Input { left, right: !left, down: even, ..Input::default() },
+                3 => Input { left, right: !left, rotate: Some(even), ..Input::default() },
+                4 => Input { down: true, rotate: Some(left), ..Input::default() },
+                5 => Input { down: true, undo: i % 15 == 14, ..Input::default() },
+                _ => Input::default(),
+            });
+        }
+    }
+    inputs.truncate(frames);
+    inputs
+}
+
+/// Entry point of the fuzz target: the first byte picks one of `SimDriver::configs`, the next
+/// eight seed the pieces, every byte after that is an `Input` held for a number of frames given
+/// by its top bits. Panics when an invariant breaks.
+pub fn fuzz(data: &[u8]) {
+    if data.len() < 9 {
+        return;
+    }
+
+    let configs = SimDriver::configs();
+    let config = &configs[data[0] as usize % configs.len()];
+    let mut seed = [0; 8];
+    seed.copy_from_slice(&data[1..9]);
+
+    let inputs: Vec<Input> = data[9..].iter()
+        .flat_map(|byte| {
+            let input = Input::from_byte(*byte);
+            let hold = if input.undo { 1 } else { 1 << (byte >> 5) };
+            (0..hold).map(move |i| Input { rotate: input.rotate.filter(|_| i == 0), ..input })
+        })
+        .collect();
+
+    let mut driver = SimDriver::new(config, u64::from_le_bytes(seed));
+    if let Err(err) = driver.run(&inputs) {
+        panic!("{}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn random_input() {
+        for (i, config) in SimDriver::configs().iter().enumerate() {
+            let mut rng = StdRng::seed_from_u64(i as u64);
+            let mut driver = SimDriver::new(config, i as u64);
+            driver.run(&random(&mut rng, 5000)).unwrap();
+        }
+    }
+
+    #[test]
+    fn adversarial_input() {
+        for (i, config) in SimDriver::configs().iter().enumerate() {
+            let mut rng = StdRng::seed_from_u64(100 + i as u64);
+            let mut driver = SimDriver::new(config, i as u64);
+            driver.run(&adversarial(&mut rng, 5000)).unwrap();
+        }
+    }
+
+    #[test]
+    fn deterministic() {
+        let inputs = adversarial(&mut StdRng::seed_from_u64(7), 3000);
+        let play = || {
+            let mut driver = SimDriver::new(&Config::new(), 42);
+            driver.run(&inputs).unwrap();
+            driver.game().replay().to_text()
+        };
+        assert_eq!(play(), play());
+    }
+
+    #[test]
+    fn fuzz_bytes() {
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..50 {
+            let data: Vec<u8> = (0..rng.gen_range(0, 400)).map(|_| rng.gen()).collect();
+            fuzz(&data);
+        }
+    }
+}
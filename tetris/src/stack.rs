@@ -21,28 +21,39 @@ impl Stack {
         }
     }
 
+    pub fn from_blocks(blocks: Array2D<piece::Type>) -> Self {
+        Stack {
+            generation: 0,
+            width: blocks.width() as i32,
+            height: blocks.height() as i32,
+            blocks,
+        }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
     pub fn blocks(&self) -> &Array2D<piece::Type> {
         &self.blocks
     }
 
     pub fn fits(&self, piece: piece::Piece, x: i32, y: i32) -> bool {
-        let blocks = piece.blocks();
-
-        for i in 0..4 {
-            let x = x + i as i32;
-            for j in 0..4 {
-                let y = y + j as i32;
-                if blocks[4 * j + i] {
-                    // can't go left or right
-                    if x < 0 || x >= self.width || y < 0 {
-                        return false;
-                    }
+        for (i, j) in piece.cells() {
+            let (x, y) = (x + i, y + j);
 
-                    // ignore if the tile would go over the top of the field
-                    if y < self.height && *self.blocks.at(x as usize, y as usize) != piece::Type::None {
-                        return false;
-                    }
-                }
+            // can't go left or right
+            if x < 0 || x >= self.width || y < 0 {
+                return false;
+            }
+
+            // ignore if the tile would go over the top of the field
+            if y < self.height && *self.blocks.at(x as usize, y as usize) != piece::Type::None {
+                return false;
             }
         }
 
@@ -50,19 +61,13 @@ impl Stack {
     }
 
     pub fn merge(&self, piece: piece::Piece, x: i32, y: i32) -> Self {
-        let piece_blocks = piece.blocks();
         let mut blocks = self.blocks.clone();
 
         // merge tile into existing blocks
-        for i in 0..4 {
-            let x = x + i as i32;
-            for j in 0..4 {
-                let y = y + j as i32;
-                if piece_blocks[4 * j + i] {
-                    if x >= 0 && x < self.width && y >= 0 && y < self.height {
-                        blocks.set(x as usize, y as usize, piece.get_type());
-                    }
-                }
+        for (i, j) in piece.cells() {
+            let (x, y) = (x + i, y + j);
+            if x >= 0 && x < self.width && y >= 0 && y < self.height {
+                blocks.set(x as usize, y as usize, piece.get_type());
             }
         }
 
@@ -104,3 +109,94 @@ impl Stack {
         }, rows)
     }
 }
+
+impl std::fmt::Display for Stack {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for y in (0..self.height).rev() {
+            write!(f, "|")?;
+            for x in 0..self.width {
+                write!(f, "{}", self.blocks.at(x as usize, y as usize).symbol())?;
+            }
+            writeln!(f, "|")?;
+        }
+        writeln!(f, "+{}+", "-".repeat(self.width as usize))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::notation::{grid, parse_grid};
+    use super::super::pieceset::Shape;
+
+    fn piece(rows: &[&str]) -> piece::Piece {
+        piece::Piece::from_shape(&Shape::from_grid(piece::Type::T, rows, None).unwrap(), 0)
+    }
+
+    #[test]
+    fn fits() {
+        let stack = parse_grid("
+            X...
+            XX..
+        ", 4, 4).unwrap();
+        let block = piece(&["X"]);
+
+        assert!(stack.fits(block, 3, 0));
+        assert!(!stack.fits(block, 1, 0));
+        assert!(!stack.fits(block, -1, 2));
+        assert!(!stack.fits(block, 4, 2));
+        assert!(!stack.fits(block, 2, -1));
+
+        // anything above the field is free, but the walls still count
+        assert!(stack.fits(block, 0, 4));
+        assert!(stack.fits(block, 0, 10));
+        assert!(!stack.fits(block, 4, 10));
+    }
+
+    #[test]
+    fn display() {
+        let stack = parse_grid("
+            ....
+            J...
+            JJ.I
+        ", 4, 3).unwrap();
+        assert_eq!(stack.to_string(), "|....|\n|J...|\n|JJ.I|\n+----+\n");
+    }
+
+    #[test]
+    fn merge_overhang() {
+        let stack = Stack::new(4, 4);
+        let bar = piece(&["X", "X"]);
+
+        // the half that sticks out at the top is cut off
+        let merged = stack.merge(bar, 1, 3);
+        assert_eq!(grid(&merged), ".T..\n....\n....\n....\n");
+
+        let merged = merged.merge(bar, 2, 4);
+        assert_eq!(grid(&merged), ".T..\n....\n....\n....\n");
+    }
+
+    #[test]
+    fn eliminate() {
+        let stack = parse_grid("
+            ..I.
+            XXXX
+            X.XX
+            XXXX
+        ", 4, 4).unwrap();
+
+        let (eliminated, rows) = stack.eliminate();
+        assert_eq!(rows, vec!(2, 0));
+        assert_eq!(grid(&eliminated), "..I.\nX.XX\n");
+
+        let (unchanged, rows) = eliminated.eliminate();
+        assert!(rows.is_empty());
+        assert_eq!(grid(&unchanged), grid(&eliminated));
+
+        // a full top row leaves an empty one behind
+        let top = parse_grid("XXXX\n....\n....\nX...", 4, 4).unwrap();
+        let (eliminated, rows) = top.eliminate();
+        assert_eq!(rows, vec!(3));
+        assert_eq!(grid(&eliminated), "X...\n");
+    }
+}
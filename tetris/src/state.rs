@@ -3,34 +3,49 @@ use std::rc::Rc;
 use super::piece;
 use super::stack;
 
-#[derive(Clone, Copy)]
+/// Count and drought of every piece type of the set
+#[derive(Clone)]
 pub struct PieceStats {
-    count: [i32; 7],
-    drought: [i32; 7],
+    types: Vec<piece::Type>,
+    count: Vec<i32>,
+    drought: Vec<i32>,
 }
 
 impl PieceStats {
-    fn new() -> Self {
+    fn new(types: Vec<piece::Type>) -> Self {
         PieceStats {
-            count: [0, 0, 0, 0, 0, 0, 0],
-            drought: [0, 0, 0, 0, 0, 0, 0],
+            count: vec!(0; types.len()),
+            drought: vec!(0; types.len()),
+            types,
         }
     }
 
     fn checkin(&self, tp: piece::Type) -> Self {
         let mut ret = self.clone();
-        for i in 0..7 {
-            ret.drought[i] += 1;
+        for drought in ret.drought.iter_mut() {
+            *drought += 1;
+        }
+        if let Some(piece_idx) = self.types.iter().position(|t| *t == tp) {
+            ret.count[piece_idx] += 1;
+            ret.drought[piece_idx] = 0;
         }
-        let piece_idx = tp as usize;
-        ret.count[piece_idx] += 1;
-        ret.drought[piece_idx] = 0;
         ret
     }
 
+    pub fn types(&self) -> &[piece::Type] {
+        &self.types
+    }
+
+    /// Number of pieces that were spawned so far
+    pub fn total(&self) -> i32 {
+        self.count.iter().sum()
+    }
+
     pub fn get(&self, tp: piece::Type) -> (i32, i32) {
-        let piece_idx = tp as usize;
-        (self.count[piece_idx], self.drought[piece_idx])
+        match self.types.iter().position(|t| *t == tp) {
+            Some(piece_idx) => (self.count[piece_idx], self.drought[piece_idx]),
+            None => (0, 0),
+        }
     }
 }
 
@@ -67,6 +82,7 @@ pub struct Snapshot {
     state: State,
 }
 
+#[derive(Clone)]
 pub struct GameHistory {
     config: super::Config,
     frames: Vec<Snapshot>
@@ -102,6 +118,10 @@ impl Snapshot {
         &self.turn.stats
     }
 
+    pub fn tetrises(&self) -> i32 {
+        self.turn.tetrises
+    }
+
     pub fn tetris_rate(&self) -> f32 {
         4.0 * self.turn.tetrises as f32 / self.turn.cleared.max(1) as f32
     }
@@ -147,6 +167,42 @@ impl Snapshot {
     }
 }
 
+impl std::fmt::Display for Snapshot {
+    /// Draws the field including the current piece, with score/level/lines/next piece to the right
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let blocks = self.stack().blocks();
+        let width = blocks.width() as i32;
+        let height = blocks.height() as i32;
+
+        let info = [
+            format!("Score: {}", self.score()),
+            format!("Level: {}", self.level()),
+            format!("Lines: {}", self.lines()),
+            format!("Next:  {}", self.next_piece().get_type().symbol()),
+        ];
+
+        for y in (0..height).rev() {
+            write!(f, "|")?;
+            for x in 0..width {
+                let mut symbol = blocks.at(x as usize, y as usize).symbol();
+                if let Some((piece, px, py)) = self.piece() {
+                    let (i, j) = (x - px, y - py);
+                    if piece.cells().any(|cell| cell == (i, j)) {
+                        symbol = piece.get_type().symbol();
+                    }
+                }
+                write!(f, "{}", symbol)?;
+            }
+            write!(f, "|")?;
+            if let Some(line) = info.get((height - 1 - y) as usize) {
+                write!(f, "  {}", line)?;
+            }
+            writeln!(f)?;
+        }
+        writeln!(f, "+{}+", "-".repeat(width as usize))
+    }
+}
+
 impl GameHistory {
     pub fn try_move(&mut self, timestamp: i32, piece: piece::Piece, x: i32, y: i32) -> bool {
         // make sure we are progressing ever forward
@@ -172,13 +228,13 @@ impl GameHistory {
 
         let new_frame = match last_frame.state {
             State::Piece{..} => panic!("GameHistory::new_piece() without ARE/Animation frame"),
-            _ => Snapshot {
-                timestamp,
-                turn: last_frame.turn.clone(),
-                state: State::Piece {
-                    piece: last_frame.next_piece(),
-                    x: self.config.width / 2 - 2,
-                    y: self.config.height - 3,
+            _ => {
+                let piece = last_frame.next_piece();
+                let (x, y) = piece.spawn_position(self.config.width, self.config.height);
+                Snapshot {
+                    timestamp,
+                    turn: last_frame.turn.clone(),
+                    state: State::Piece { piece, x, y }
                 }
             }
         };
@@ -209,6 +265,8 @@ impl GameHistory {
             2 => 100,
             3 => 300,
             4 => 1200,
+            // only pieces bigger than tetrominoes clear more rows at once
+            n if n as i32 <= piece::MAX_SIZE => 1200 * (n as i32 - 3),
             _ => panic!("This is bad")
         } + soft_drop);
 
@@ -216,8 +274,8 @@ impl GameHistory {
         let mut level = last_frame.level();
         let mut left_to_clear = last_frame.turn.left_to_clear - eliminated.1.len() as i32;
         if left_to_clear <= 0 {
-            level += 1;
-            left_to_clear += 10;
+            level = self.config.next_level(level);
+            left_to_clear += self.config.curve.lines_per_level();
         }
         let tetrises = last_frame.turn.tetrises + (if eliminated.1.len() == 4 { 1 } else { 0 });
 
@@ -258,27 +316,29 @@ impl GameHistory {
     }
 
     pub fn new(config: &super::Config, first: piece::Piece, second: piece::Piece) -> Self {
+        Self::with_stack(config, stack::Stack::new(config.width as usize, config.height as usize), first, second)
+    }
+
+    /// Starts the game on an existing board
+    pub fn with_stack(config: &super::Config, stack: stack::Stack, first: piece::Piece, second: piece::Piece) -> Self {
         let turn0 = Rc::new(Turn {
             score: 0,
             level: config.level,
             left_to_clear: config.transition(),
             cleared: 0,
             tetrises: 0,
-            stack: stack::Stack::new(config.width as usize, config.height as usize),
+            stack,
             next_piece: second,
-            stats: PieceStats::new().checkin(first.get_type())
+            stats: PieceStats::new(config.pieces.types()).checkin(first.get_type())
         });
+        let (x, y) = first.spawn_position(config.width, config.height);
 
         GameHistory {
             config: config.clone(),
             frames: vec!(Snapshot {
                 timestamp: 0,
                 turn: turn0.clone(),
-                state: State::Piece {
-                    piece: first,
-                    x: config.width as i32 / 2 - 2,
-                    y: config.height as i32 - 3,
-                }
+                state: State::Piece { piece: first, x, y }
             })
         }
     }
@@ -287,6 +347,23 @@ impl GameHistory {
         self.frames.last().unwrap()
     }
 
+    pub fn config(&self) -> &super::Config {
+        &self.config
+    }
+
+    pub fn frames(&self) -> &[Snapshot] {
+        &self.frames
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Drops all snapshots after the first `len` ones, the first snapshot is always kept
+    pub fn truncate(&mut self, len: usize) {
+        self.frames.truncate(len.max(1));
+    }
+
     pub fn snapshot_at(&self, timestamp: i32) -> &Snapshot {
         let mut ret = self.frames.first().unwrap();
         for frame in &self.frames {
@@ -297,4 +374,35 @@ impl GameHistory {
         }
         ret
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::notation::parse_grid;
+
+    #[test]
+    fn display() {
+        let mut config = super::super::Config::new();
+        config.width = 6;
+        config.height = 6;
+        let stack = parse_grid("
+            ......
+            ......
+            ......
+            ......
+            ......
+            OO.III
+        ", 6, 6).unwrap();
+        let history = GameHistory::with_stack(&config, stack, config.pieces.piece(piece::Type::T), config.pieces.piece(piece::Type::L));
+        // the piece that's falling is drawn into the field
+        assert_eq!(history.snapshot().to_string(), concat!(
+            "|..TTT.|  Score: 0\n",
+            "|...T..|  Level: 0\n",
+            "|......|  Lines: 0\n",
+            "|......|  Next:  L\n",
+            "|......|\n",
+            "|OO.III|\n",
+            "+------+\n",
+        ));
+    }
+}
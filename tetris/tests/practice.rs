@@ -0,0 +1,123 @@
+//! Practice mode: stepping back with undo and judging drills.
+
+extern crate tetris;
+
+use tetris::game::Game;
+use tetris::notation::{parse_grid, parse_queue, Position};
+use tetris::practice::{Drill, Goal, Judgement};
+use tetris::stack::Stack;
+use tetris::Config;
+
+/// Soft drops the falling piece and plays until the next one spawns
+fn drop(game: &mut Game) {
+    game.down(true);
+    while game.snapshot().piece().is_some() && !game.lost() {
+        game.frame();
+    }
+    game.down(false);
+    while game.snapshot().piece().is_none() && !game.lost() {
+        game.frame();
+    }
+}
+
+/// Rotates, then shifts all the way to the wall in `direction` before dropping
+fn place(game: &mut Game, rotate: bool, direction: i32) {
+    if rotate {
+        game.rotate(true);
+    }
+    game.left(direction < 0);
+    game.right(direction > 0);
+    for _ in 0..60 {
+        game.frame();
+    }
+    game.left(false);
+    game.right(false);
+    drop(game);
+}
+
+fn drill(grid: &str, queue: &str, goal: Goal) -> Drill {
+    Drill::new("test", Position::new(parse_grid(grid, 10, 20).unwrap(), parse_queue(queue).unwrap()), goal)
+}
+
+#[test]
+fn undo_deals_the_same_pieces() {
+    let mut game = Game::with_seed(&Config::new(), &Position::new(Stack::new(10, 20), Vec::new()), 5);
+    for direction in [-1, 1, 0].iter() {
+        place(&mut game, false, *direction);
+    }
+
+    let mut played = game.clone();
+    for direction in [-1, 1, -1, 1].iter() {
+        place(&mut played, true, *direction);
+    }
+
+    // back to where `game` is, then the same moves again
+    let mut undone = played.clone();
+    for _ in 0..4 {
+        undone.undo();
+    }
+    assert_eq!(undone.replay().to_text(), game.replay().to_text());
+    for direction in [-1, 1, -1, 1].iter() {
+        place(&mut undone, true, *direction);
+    }
+    assert_eq!(undone.replay().to_text(), played.replay().to_text());
+    assert_eq!(undone.snapshot().next_piece(), played.snapshot().next_piece());
+}
+
+#[test]
+fn undo_revives_a_lost_game() {
+    let mut game = Game::with_seed(&Config::new(), &Position::new(Stack::new(10, 20), Vec::new()), 9);
+    while !game.lost() {
+        drop(&mut game);
+    }
+    game.undo();
+    assert!(!game.lost());
+    assert!(game.snapshot().piece().is_some());
+    // the top out and the locks before it never got picked up, they are undone as well
+    assert!(game.take_events().is_empty());
+}
+
+#[test]
+fn judge_drills() {
+    let config = Config::new();
+    let tetris = drill("
+        XXXXXXXXX.
+        XXXXXXXXX.
+        XXXXXXXXX.
+        XXXXXXXXX.
+    ", "I", Goal::Tetris);
+    let mut game = tetris.start(&config);
+    assert_eq!(tetris.judge(&game), Judgement::Playing);
+    place(&mut game, true, 1);
+    assert_eq!(tetris.judge(&game), Judgement::Success);
+
+    // the queue ran out
+    let lines = drill("
+        XXXXXXXX..
+    ", "O", Goal::Lines(2));
+    let mut game = lines.start(&config);
+    place(&mut game, false, 1);
+    assert_eq!(game.snapshot().lines(), 1);
+    assert_eq!(lines.judge(&game), Judgement::Failed);
+
+    // only once the cleared lines are gone
+    let clear = drill("
+        XXXXXX....
+        XXXXXX....
+    ", "II", Goal::PerfectClear);
+    let mut game = clear.start(&config);
+    place(&mut game, false, 1);
+    assert_eq!(clear.judge(&game), Judgement::Playing);
+    place(&mut game, false, 1);
+    assert_eq!(clear.judge(&game), Judgement::Success);
+}
+
+#[test]
+fn builtin_drills() {
+    for drill in Drill::builtin() {
+        let game = drill.start(&Config::new());
+        assert!(!game.lost(), "{}", drill.name);
+        assert_eq!(drill.judge(&game), Judgement::Playing, "{}", drill.name);
+        assert_eq!(game.snapshot().piece().unwrap().0.get_type(), drill.position.queue[0], "{}", drill.name);
+    }
+}
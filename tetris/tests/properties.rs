@@ -0,0 +1,123 @@
+//! Random play on a range of configurations, checking rules that have to hold no matter
+//! which keys get pressed or which pieces come.
+
+extern crate rand;
+extern crate serde_json;
+extern crate tetris;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tetris::game::{Event, Game, Outcome};
+use tetris::replay::{Replay, Replayer};
+use tetris::sim::SimDriver;
+use tetris::Config;
+
+/// games played per configuration
+const SEEDS: u64 = 5;
+
+/// every configuration with a few different seeds
+fn games() -> Vec<(u64, Config)> {
+    SimDriver::configs().into_iter()
+        .enumerate()
+        .flat_map(|(i, config)| (0..SEEDS).map(move |seed| (i as u64 * SEEDS + seed, config.clone())))
+        .collect()
+}
+
+/// Presses keys at random, holding them for a while, until the game is lost or over
+fn play(config: &Config, seed: u64, frames: usize, mut check: impl FnMut(&Game)) -> Game {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut game = Game::new(config);
+
+    for _ in 0..frames {
+        match rng.gen_range(0, 8) {
+            0 => game.left(rng.gen()),
+            1 => game.right(rng.gen()),
+            2 => game.down(rng.gen_bool(0.3)),
+            3 => game.rotate(rng.gen()),
+            _ => {}
+        }
+
+        let outcome = game.frame();
+        check(&game);
+        if let Some(Outcome::Death) = outcome {
+            break;
+        }
+    }
+
+    game
+}
+
+#[test]
+fn piece_never_overlaps_stack() {
+    for (seed, config) in &games() {
+        play(config, *seed, 20000, |game| {
+            let snapshot = game.snapshot();
+            if let (Some((piece, x, y)), false) = (snapshot.piece(), game.lost()) {
+                assert!(snapshot.stack().fits(piece, x, y), "{}", snapshot);
+            }
+        });
+    }
+}
+
+#[test]
+fn progress_is_monotonic() {
+    for (seed, config) in &games() {
+        let mut last = (0, config.level, 0);
+        play(config, 100 + seed, 20000, |game| {
+            let snapshot = game.snapshot();
+            let now = (snapshot.score(), snapshot.level(), snapshot.lines());
+            assert!(now.0 >= last.0 && now.1 >= last.1 && now.2 >= last.2, "{:?} after {:?}", now, last);
+            last = now;
+        });
+    }
+}
+
+#[test]
+fn events_add_up() {
+    for (seed, config) in &games() {
+        let mut game = play(config, 400 + *seed, 20000, |_| {});
+        let events = game.take_events();
+
+        let count = |f: &dyn Fn(&Event) -> bool| events.iter().filter(|event| f(event)).count() as i32;
+        let cleared: usize = events.iter().map(|event| match event { Event::Clear(rows) => rows.len(), _ => 0 }).sum();
+
+        let snapshot = game.snapshot();
+        assert_eq!(cleared as i32, snapshot.lines());
+        assert_eq!(count(&|event| matches!(event, Event::LevelUp(_))), snapshot.level() - config.level);
+        assert_eq!(count(&|event| matches!(event, Event::Lock(_))), snapshot.stats().total() - 1);
+        assert_eq!(events.last() == Some(&Event::TopOut), game.lost());
+        assert_eq!(events.first().map(|event| matches!(event, Event::Spawn(_))), Some(true));
+    }
+}
+
+#[test]
+fn replayer_matches_game() {
+    for (seed, config) in &games() {
+        let game = play(config, 200 + seed, 20000, |_| {});
+
+        let mut replayer = Replayer::new(game.replay()).unwrap();
+        let length = replayer.length();
+        replayer.jump(length);
+
+        let (played, replayed) = (game.snapshot(), replayer.snapshot());
+        assert_eq!(played.score(), replayed.score());
+        assert_eq!(played.level(), replayed.level());
+        assert_eq!(played.lines(), replayed.lines());
+        assert_eq!(played.stack().to_string(), replayed.stack().to_string());
+    }
+}
+
+#[test]
+fn replay_round_trip() {
+    for (seed, config) in &games() {
+        let game = play(config, 300 + seed, 5000, |_| {});
+        let text = game.replay().to_text();
+
+        let decoded = Replay::from_text(&text).unwrap();
+        assert_eq!(decoded.to_text(), text);
+        assert!(decoded.config() == config);
+
+        let json = serde_json::to_string(game.replay()).unwrap();
+        assert_eq!(Replay::from_text(&json).unwrap().to_text(), text);
+    }
+}
@@ -0,0 +1,207 @@
+//! Recorded games that have to play back to exactly the same result. If one of these breaks,
+//! either the rules changed or old replays can no longer be watched.
+
+extern crate bincode;
+extern crate tetris;
+
+use tetris::analysis::{inputs, Marker, Timeline};
+use tetris::game::Game;
+use tetris::notation::Position;
+use tetris::piece::Type;
+use tetris::pieceset::{PieceSet, Shape};
+use tetris::replay::{LegacyReplay, Replay, ReplayDelta, Replayer};
+use tetris::stack::Stack;
+
+/// file, final score, level and lines
+const GOLDEN: [(&str, &str, i32, i32, i32); 7] = [
+    ("nes_level0", include_str!("replays/nes_level0.txt"), 2778, 1, 18),
+    ("nes_level18", include_str!("replays/nes_level18.txt"), 192717, 18, 84),
+    ("pal_level9", include_str!("replays/pal_level9.txt"), 37770, 9, 27),
+    ("guideline_killscreen", include_str!("replays/guideline_killscreen.txt"), 3198, 2, 20),
+    ("pentomino", include_str!("replays/pentomino.txt"), 1661, 1, 12),
+    ("big", include_str!("replays/big.txt"), 35521, 7, 72),
+    ("downstack", include_str!("replays/downstack.txt"), 7575, 3, 30),
+];
+
+#[test]
+fn golden_replays() {
+    for (name, text, score, level, lines) in GOLDEN.iter() {
+        let replay = Replay::from_text(text).unwrap_or_else(|| panic!("{}: can't decode", name));
+        let mut replayer = Replayer::new(&replay).unwrap();
+        let length = replayer.length();
+        replayer.jump(length);
+
+        let snapshot = replayer.snapshot();
+        assert_eq!((snapshot.score(), snapshot.level(), snapshot.lines()), (*score, *level, *lines), "{}", name);
+    }
+}
+
+#[test]
+fn golden_replays_reencode() {
+    for (name, text, ..) in GOLDEN.iter() {
+        let replay = Replay::from_text(text).unwrap();
+        assert_eq!(replay.to_text(), text.trim(), "{}", name);
+    }
+}
+
+/// Recorded before level curves and piece sets, as a replay file and as stored by older servers
+#[test]
+fn old_format_replays() {
+    let text = Replay::from_text(include_str!("replays/old_format.txt")).expect("can't decode the replay file");
+    let binary: Replay = bincode::deserialize::<LegacyReplay>(include_bytes!("replays/old_format.bin"))
+        .expect("can't decode the binary replay").into();
+    assert_eq!(binary.to_text(), text.to_text());
+
+    for replay in [text, binary].iter() {
+        let mut replayer = Replayer::new(replay).unwrap();
+        let length = replayer.length();
+        replayer.jump(length);
+
+        let snapshot = replayer.snapshot();
+        assert_eq!(replayer.timestamp(), 8476);
+        assert_eq!((snapshot.score(), snapshot.level(), snapshot.lines()), (28914, 5, 58));
+    }
+}
+
+#[test]
+fn stepping_and_sync() {
+    let replay = Replay::from_text(GOLDEN[0].1).unwrap();
+    let timeline = Timeline::from_replay(&replay).unwrap();
+    let mut replayer = Replayer::new(&replay).unwrap();
+
+    replayer.jump_frame(100);
+    assert_eq!(replayer.timestamp(), 100);
+    replayer.step(1);
+    assert_eq!(replayer.timestamp(), 101);
+    replayer.step(-2);
+    assert_eq!(replayer.timestamp(), 99);
+
+    // playing past the end of the loop starts it over
+    replayer.looped = Some((1.0, 2.0));
+    replayer.jump(1.9);
+    replayer.advance(0.2);
+    assert_eq!(replayer.frame(), 1.0);
+
+    // a game is in sync with itself, and every lock is marked
+    for p in &timeline.placements {
+        assert_eq!(timeline.sync(&timeline, p.spawned + 1), p.spawned + 1);
+    }
+    let markers = timeline.markers();
+    let tetrises = markers.iter().filter(|(_, marker)| *marker == Marker::Tetris).count() as i32;
+    assert_eq!(tetrises * 4, timeline.summary().tetris_lines);
+    assert!(markers.iter().any(|(_, marker)| *marker == Marker::LevelUp));
+
+    let keys = inputs(replayer.history(), 0, replayer.history().snapshot().timestamp());
+    assert!(keys.left && keys.right && keys.rotate.is_some());
+}
+
+#[test]
+fn counted_inputs() {
+    let config = tetris::Config::new();
+    let position = Position::new(Stack::new(10, 20), vec!(Type::T, Type::T, Type::T));
+    let mut game = Game::with_seed(&config, &position, 1);
+    let drop = |game: &mut Game| {
+        game.down(true);
+        while game.snapshot().piece().is_some() {
+            game.frame();
+        }
+        game.down(false);
+        while game.snapshot().piece().is_none() {
+            game.frame();
+        }
+    };
+
+    // held all the way to the wall
+    game.left(true);
+    for _ in 0..60 {
+        game.frame();
+    }
+    game.left(false);
+    drop(&mut game);
+
+    // tapped twice and rotated
+    for _ in 0..2 {
+        game.right(true);
+        game.frame();
+        game.right(false);
+        game.frame();
+    }
+    game.rotate(true);
+    drop(&mut game);
+
+    let inputs: Vec<(i32, i32)> = Timeline::from_replay(game.replay()).unwrap().placements.iter()
+        .map(|p| (p.inputs, p.optimal_inputs))
+        .collect();
+    assert_eq!(inputs, vec!((1, 1), (3, 3)));
+}
+
+#[test]
+fn live_deltas() {
+    for (name, text, ..) in GOLDEN.iter() {
+        let replay = Replay::from_text(text).unwrap();
+        let (entries, _) = replay.position();
+
+        // sent in pieces, the way a live game reaches its spectators
+        let mut live = replay.header();
+        let mut from = 0;
+        while from < entries {
+            let delta = replay.delta(from);
+            let delta = ReplayDelta { data: delta.data[..delta.data.len().min(37)].to_vec(), ..delta };
+            from += delta.data.len();
+            live.apply(&delta).unwrap();
+        }
+        live.apply(&replay.delta(entries)).unwrap();
+
+        assert_eq!(live.to_text(), replay.to_text(), "{}", name);
+        assert!(live.apply(&replay.delta(0)).is_err());
+    }
+}
+
+#[test]
+fn broken_deltas() {
+    let replay = Replay::from_text(GOLDEN[0].1).unwrap();
+    let mut replayer = Replayer::new(&replay).unwrap();
+    assert!(replayer.extend(&replay.header()).is_err());
+
+    // the next piece is none of the tetrominoes
+    let mut live = replay.header();
+    assert!(live.apply(&ReplayDelta { from: 0, time: 10, data: vec!(6 << 5 | 0x1f) }).is_err());
+    assert!(live.apply(&ReplayDelta { from: 0, time: -1, data: Vec::new() }).is_err());
+
+    // a piece spawns while the first one is still falling
+    assert!(live.apply(&ReplayDelta { from: 0, time: 10, data: vec!(5 << 5) }).is_ok());
+    assert!(Replayer::new(&live).is_err());
+}
+
+#[test]
+fn broken_files() {
+    for &(name, text, _, _, _) in GOLDEN.iter() {
+        assert!(Replay::from_text(text).unwrap().validate().is_ok(), "{}", name);
+    }
+    // pieces that don't fit into their box
+    for cells in vec!(vec!((-1, 0), (0, 0)), vec!((0, 0), (8, 0)), vec!((3, 0), (3, 4))) {
+        let mut config = tetris::Config::new();
+        config.pieces = PieceSet::Custom(vec!(Shape { tp: Type::Custom(0), color: None, size: 4, rotations: vec!(cells), spawn: 0 }));
+        let replay = Replay::new(&config, Type::Custom(0), Type::Custom(0), 0);
+        assert!(replay.validate().is_err());
+        assert!(Replayer::new(&replay).is_err());
+    }
+    let mut config = tetris::Config::new();
+    config.pieces = PieceSet::Custom(vec!(Shape { tp: Type::Custom(0), color: None, size: 9, rotations: vec!(vec!((0, 0))), spawn: 0 }));
+    assert!(Replay::new(&config, Type::Custom(0), Type::Custom(0), 0).validate().is_err());
+
+    // boards that can't be decoded
+    for &(width, height) in &[(0, 20), (-10, 20), (10, -1), (10, 100000)] {
+        let mut config = tetris::Config::new();
+        config.width = width;
+        config.height = height;
+        let mut replay = Replay::new(&config, Type::T, Type::T, 0);
+        replay.set_stack(&Stack::new(10, 20));
+        assert!(replay.validate().is_err());
+        assert!(Replayer::new(&replay).is_err());
+    }
+
+    // base64, but not of UTF-8
+    assert!(Replay::from_text("//79").is_none());
+    assert!(Replay::from_text("not a replay").is_none());
+}
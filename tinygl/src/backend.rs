@@ -0,0 +1,1279 @@
+//! Everything a tinygl `Context` asks of OpenGL goes through a `Backend`. `Gl` is the real
+//! thing, the `NullBackend` only keeps track of the objects, bindings, uniform values and draw
+//! calls, so rendering code runs without a GPU and tests can look at what it would have drawn.
+
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use gl::types::*;
+
+use super::program::{self, Uniform, UniformBlock, Variable};
+
+// from EXT_disjoint_timer_query, which isn't in the bindings
+#[cfg(target_os = "emscripten")]
+const GPU_DISJOINT_EXT: GLenum = 0x8FBB;
+
+pub trait Backend {
+    fn gen_buffer(&mut self) -> GLuint;
+    fn delete_buffer(&mut self, buffer: GLuint);
+    fn bind_buffer(&mut self, target: GLenum, buffer: GLuint);
+    fn buffer_data(&mut self, target: GLenum, data: &[u8], usage: GLenum);
+    /// Like `buffer_data` with undefined contents, which orphans the old storage
+    fn allocate_buffer(&mut self, target: GLenum, size: usize, usage: GLenum);
+    fn buffer_sub_data(&mut self, target: GLenum, offset: usize, data: &[u8]);
+    /// Binds to an indexed target like gl::UNIFORM_BUFFER, and to the generic one
+    fn bind_buffer_base(&mut self, target: GLenum, binding: GLuint, buffer: GLuint);
+
+    fn gen_texture(&mut self) -> GLuint;
+    fn delete_texture(&mut self, texture: GLuint);
+    fn active_texture(&mut self, unit: GLuint);
+    fn bind_texture(&mut self, target: GLenum, texture: GLuint);
+    fn tex_parameter(&mut self, target: GLenum, name: GLenum, value: GLint);
+    /// `layers` is None for 2D textures
+    ///
+    /// # Safety
+    /// `data` is null or points to an image of the given size, format and type
+    #[allow(clippy::too_many_arguments)]
+    unsafe fn tex_image(&mut self, target: GLenum, size: (GLsizei, GLsizei), layers: Option<GLsizei>, internal: GLenum, format: GLenum, datatype: GLenum, data: *const GLvoid);
+    fn generate_mipmap(&mut self, target: GLenum);
+    /// Immutable storage for `levels` mip levels, `depth` is the layers of arrays and 3D textures
+    fn tex_storage(&mut self, target: GLenum, levels: GLsizei, internal: GLenum, size: (GLsizei, GLsizei), depth: Option<GLsizei>);
+    /// `target` is the face for cube maps, the z of `offset` and `size` is for arrays and 3D
+    /// textures
+    #[allow(clippy::too_many_arguments)]
+    fn tex_sub_image(&mut self, target: GLenum, level: GLint, offset: (GLint, GLint, GLint), size: (GLsizei, GLsizei, GLsizei), format: GLenum, datatype: GLenum, data: &[u8]);
+    fn compressed_tex_sub_image(&mut self, target: GLenum, level: GLint, offset: (GLint, GLint, GLint), size: (GLsizei, GLsizei, GLsizei), internal: GLenum, data: &[u8]);
+    fn gen_sampler(&mut self) -> GLuint;
+    fn delete_sampler(&mut self, sampler: GLuint);
+    fn bind_sampler(&mut self, unit: GLuint, sampler: GLuint);
+    fn sampler_parameter(&mut self, sampler: GLuint, name: GLenum, value: GLint);
+
+    fn gen_framebuffer(&mut self) -> GLuint;
+    fn delete_framebuffer(&mut self, framebuffer: GLuint);
+    fn bind_framebuffer(&mut self, framebuffer: GLuint);
+    /// `layer` attaches one layer of an array texture
+    fn framebuffer_texture(&mut self, attachment: GLenum, texture: GLuint, layer: Option<GLint>);
+    fn gen_renderbuffer(&mut self) -> GLuint;
+    fn delete_renderbuffer(&mut self, renderbuffer: GLuint);
+    fn renderbuffer_storage(&mut self, renderbuffer: GLuint, internal: GLenum, size: (GLsizei, GLsizei));
+    fn framebuffer_renderbuffer(&mut self, attachment: GLenum, renderbuffer: GLuint);
+    fn draw_buffers(&mut self, buffers: &[GLenum]);
+    fn read_buffer(&mut self, buffer: GLenum);
+    fn pixel_store(&mut self, name: GLenum, value: GLint);
+    /// # Safety
+    /// `dst` has room for an image of the given size, format and type
+    unsafe fn read_pixels(&mut self, size: (GLsizei, GLsizei), format: GLenum, datatype: GLenum, dst: *mut GLvoid);
+    fn viewport(&mut self, x: GLint, y: GLint, w: GLsizei, h: GLsizei);
+
+    /// Success, shader and log
+    fn compile_shader(&mut self, shader_type: GLenum, src: &str) -> (bool, GLuint, String);
+    fn delete_shader(&mut self, shader: GLuint);
+    /// The program if it linked, and the log
+    fn link_program(&mut self, vs: GLuint, fs: GLuint) -> (Option<GLuint>, String);
+    fn delete_program(&mut self, program: GLuint);
+    fn use_program(&mut self, program: GLuint);
+    /// Arrays can be named like "lights[0]"
+    fn active_attributes(&mut self, program: GLuint) -> Vec<Variable>;
+    /// Also the ones inside uniform blocks, with location -1
+    fn active_uniforms(&mut self, program: GLuint) -> Vec<Variable>;
+    fn active_uniform_blocks(&mut self, program: GLuint) -> Vec<UniformBlock>;
+    /// -1 if there is no such uniform, for elements of arrays like "lights[2]"
+    fn uniform_location(&mut self, program: GLuint, name: &str) -> GLint;
+    fn uniform(&mut self, location: GLint, value: &Uniform);
+    /// The values all have the same type
+    fn uniform_array(&mut self, location: GLint, values: &[Uniform]);
+    fn uniform_block_binding(&mut self, program: GLuint, block: GLuint, binding: GLuint);
+
+    fn gen_vertex_array(&mut self) -> GLuint;
+    fn delete_vertex_array(&mut self, vertex_array: GLuint);
+    /// 0 for the default one
+    fn bind_vertex_array(&mut self, vertex_array: GLuint);
+    fn vertex_attrib_divisor(&mut self, location: GLuint, divisor: GLuint);
+    fn enable_vertex_attrib(&mut self, location: GLuint, enable: bool);
+    fn vertex_attrib_pointer(&mut self, location: GLuint, size: GLint, datatype: GLenum, normed: bool, stride: GLsizei, offset: GLsizei);
+
+    fn enable(&mut self, cap: GLenum, enable: bool);
+    fn depth_func(&mut self, func: GLenum);
+    fn clear_color(&mut self, color: (f32, f32, f32, f32));
+    fn clear(&mut self, mask: GLbitfield);
+
+    fn draw_arrays(&mut self, mode: GLenum, first: GLint, count: GLsizei, instances: Option<GLsizei>);
+    fn draw_elements(&mut self, mode: GLenum, count: GLsizei, datatype: GLenum, offset: GLsizei, instances: Option<GLsizei>);
+
+    /// Whether there are timestamp queries, WebGL and GL ES only have them as an extension
+    fn timer_queries(&mut self) -> bool;
+    fn gen_query(&mut self) -> GLuint;
+    fn delete_query(&mut self, query: GLuint);
+    /// The query gets the GPU time once the commands before it are done
+    fn query_timestamp(&mut self, query: GLuint);
+    /// In nanoseconds, None while the GPU isn't there yet
+    fn query_result(&mut self, query: GLuint) -> Option<u64>;
+    /// Whether the timer got unreliable since the last call, e.g. because the GPU changed its
+    /// clock. The timestamps of the queries that were in flight then are meaningless.
+    fn timer_disjoint(&mut self) -> bool;
+}
+
+/// Plain OpenGL through the `gl` crate
+pub struct Gl;
+
+impl Backend for Gl {
+    fn gen_buffer(&mut self) -> GLuint {
+        let mut buffer = 0;
+        unsafe { gl::GenBuffers(1, &mut buffer); }
+        buffer
+    }
+
+    fn delete_buffer(&mut self, buffer: GLuint) {
+        unsafe { gl::DeleteBuffers(1, &buffer); }
+    }
+
+    fn bind_buffer(&mut self, target: GLenum, buffer: GLuint) {
+        unsafe { gl::BindBuffer(target, buffer); }
+    }
+
+    fn buffer_data(&mut self, target: GLenum, data: &[u8], usage: GLenum) {
+        unsafe { gl::BufferData(target, data.len() as GLsizeiptr, data.as_ptr() as *const GLvoid, usage); }
+    }
+
+    fn allocate_buffer(&mut self, target: GLenum, size: usize, usage: GLenum) {
+        unsafe { gl::BufferData(target, size as GLsizeiptr, std::ptr::null(), usage); }
+    }
+
+    fn buffer_sub_data(&mut self, target: GLenum, offset: usize, data: &[u8]) {
+        unsafe { gl::BufferSubData(target, offset as GLintptr, data.len() as GLsizeiptr, data.as_ptr() as *const GLvoid); }
+    }
+
+    fn bind_buffer_base(&mut self, target: GLenum, binding: GLuint, buffer: GLuint) {
+        unsafe { gl::BindBufferBase(target, binding, buffer); }
+    }
+
+    fn gen_texture(&mut self) -> GLuint {
+        let mut tex = 0;
+        unsafe { gl::GenTextures(1, &mut tex); }
+        tex
+    }
+
+    fn delete_texture(&mut self, texture: GLuint) {
+        unsafe { gl::DeleteTextures(1, &texture); }
+    }
+
+    fn active_texture(&mut self, unit: GLuint) {
+        unsafe { gl::ActiveTexture(gl::TEXTURE0 + unit); }
+    }
+
+    fn bind_texture(&mut self, target: GLenum, texture: GLuint) {
+        unsafe { gl::BindTexture(target, texture); }
+    }
+
+    fn tex_parameter(&mut self, target: GLenum, name: GLenum, value: GLint) {
+        unsafe { gl::TexParameteri(target, name, value); }
+    }
+
+    unsafe fn tex_image(&mut self, target: GLenum, size: (GLsizei, GLsizei), layers: Option<GLsizei>, internal: GLenum, format: GLenum, datatype: GLenum, data: *const GLvoid) {
+        match layers {
+            Some(layers) => gl::TexImage3D(target, 0, internal as _, size.0, size.1, layers, 0, format, datatype, data),
+            None => gl::TexImage2D(target, 0, internal as _, size.0, size.1, 0, format, datatype, data),
+        }
+    }
+
+    fn generate_mipmap(&mut self, target: GLenum) {
+        unsafe { gl::GenerateMipmap(target); }
+    }
+
+    fn tex_storage(&mut self, target: GLenum, levels: GLsizei, internal: GLenum, size: (GLsizei, GLsizei), depth: Option<GLsizei>) {
+        unsafe {
+            match depth {
+                Some(depth) => gl::TexStorage3D(target, levels, internal, size.0, size.1, depth),
+                None => gl::TexStorage2D(target, levels, internal, size.0, size.1),
+            }
+        }
+    }
+
+    fn tex_sub_image(&mut self, target: GLenum, level: GLint, offset: (GLint, GLint, GLint), size: (GLsizei, GLsizei, GLsizei), format: GLenum, datatype: GLenum, data: &[u8]) {
+        let data = data.as_ptr() as *const GLvoid;
+        unsafe {
+            match target {
+                gl::TEXTURE_2D_ARRAY | gl::TEXTURE_3D => gl::TexSubImage3D(target, level, offset.0, offset.1, offset.2, size.0, size.1, size.2, format, datatype, data),
+                _ => gl::TexSubImage2D(target, level, offset.0, offset.1, size.0, size.1, format, datatype, data),
+            }
+        }
+    }
+
+    fn compressed_tex_sub_image(&mut self, target: GLenum, level: GLint, offset: (GLint, GLint, GLint), size: (GLsizei, GLsizei, GLsizei), internal: GLenum, data: &[u8]) {
+        let len = data.len() as GLsizei;
+        let data = data.as_ptr() as *const GLvoid;
+        unsafe {
+            match target {
+                gl::TEXTURE_2D_ARRAY | gl::TEXTURE_3D => gl::CompressedTexSubImage3D(target, level, offset.0, offset.1, offset.2, size.0, size.1, size.2, internal, len, data),
+                _ => gl::CompressedTexSubImage2D(target, level, offset.0, offset.1, size.0, size.1, internal, len, data),
+            }
+        }
+    }
+
+    fn gen_sampler(&mut self) -> GLuint {
+        let mut sampler = 0;
+        unsafe { gl::GenSamplers(1, &mut sampler); }
+        sampler
+    }
+
+    fn delete_sampler(&mut self, sampler: GLuint) {
+        unsafe { gl::DeleteSamplers(1, &sampler); }
+    }
+
+    fn bind_sampler(&mut self, unit: GLuint, sampler: GLuint) {
+        unsafe { gl::BindSampler(unit, sampler); }
+    }
+
+    fn sampler_parameter(&mut self, sampler: GLuint, name: GLenum, value: GLint) {
+        unsafe { gl::SamplerParameteri(sampler, name, value); }
+    }
+
+    fn gen_framebuffer(&mut self) -> GLuint {
+        let mut fbo = 0;
+        unsafe { gl::GenFramebuffers(1, &mut fbo); }
+        fbo
+    }
+
+    fn delete_framebuffer(&mut self, framebuffer: GLuint) {
+        unsafe { gl::DeleteFramebuffers(1, &framebuffer); }
+    }
+
+    fn bind_framebuffer(&mut self, framebuffer: GLuint) {
+        unsafe { gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer); }
+    }
+
+    fn framebuffer_texture(&mut self, attachment: GLenum, texture: GLuint, layer: Option<GLint>) {
+        unsafe {
+            match layer {
+                Some(layer) => gl::FramebufferTextureLayer(gl::FRAMEBUFFER, attachment, texture, 0, layer),
+                None => gl::FramebufferTexture2D(gl::FRAMEBUFFER, attachment, gl::TEXTURE_2D, texture, 0),
+            }
+        }
+    }
+
+    fn gen_renderbuffer(&mut self) -> GLuint {
+        let mut rb = 0;
+        unsafe { gl::GenRenderbuffers(1, &mut rb); }
+        rb
+    }
+
+    fn delete_renderbuffer(&mut self, renderbuffer: GLuint) {
+        unsafe { gl::DeleteRenderbuffers(1, &renderbuffer); }
+    }
+
+    fn renderbuffer_storage(&mut self, renderbuffer: GLuint, internal: GLenum, size: (GLsizei, GLsizei)) {
+        unsafe {
+            gl::BindRenderbuffer(gl::RENDERBUFFER, renderbuffer);
+            gl::RenderbufferStorage(gl::RENDERBUFFER, internal, size.0, size.1);
+        }
+    }
+
+    fn framebuffer_renderbuffer(&mut self, attachment: GLenum, renderbuffer: GLuint) {
+        unsafe { gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, attachment, gl::RENDERBUFFER, renderbuffer); }
+    }
+
+    fn draw_buffers(&mut self, buffers: &[GLenum]) {
+        unsafe { gl::DrawBuffers(buffers.len() as _, buffers.as_ptr()); }
+    }
+
+    fn read_buffer(&mut self, buffer: GLenum) {
+        unsafe { gl::ReadBuffer(buffer); }
+    }
+
+    fn pixel_store(&mut self, name: GLenum, value: GLint) {
+        unsafe { gl::PixelStorei(name, value); }
+    }
+
+    unsafe fn read_pixels(&mut self, size: (GLsizei, GLsizei), format: GLenum, datatype: GLenum, dst: *mut GLvoid) {
+        gl::ReadPixels(0, 0, size.0, size.1, format, datatype, dst);
+    }
+
+    fn viewport(&mut self, x: GLint, y: GLint, w: GLsizei, h: GLsizei) {
+        unsafe { gl::Viewport(x, y, w, h); }
+    }
+
+    fn compile_shader(&mut self, shader_type: GLenum, src: &str) -> (bool, GLuint, String) {
+        let csrc = std::ffi::CString::new(src.as_bytes()).expect("Invalid string");
+
+        unsafe {
+            let shader = gl::CreateShader(shader_type);
+
+            gl::ShaderSource(shader, 1, &(csrc.as_ptr() as *const GLchar), &(csrc.as_bytes().len() as GLint));
+            gl::CompileShader(shader);
+
+            let mut result = 0;
+            gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut result);
+
+            let mut log = String::new();
+            let mut len = 0;
+            gl::GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut len);
+
+            if len > 1 {
+                let mut buf = vec![0u8; len as usize];
+                gl::GetShaderInfoLog(
+                    shader,
+                    len,
+                    std::ptr::null_mut(),
+                    buf.as_mut_ptr() as *mut GLchar,
+                );
+                buf.truncate((len as usize) - 1); // skip the trailing null character
+                log = String::from_utf8_lossy(&buf).into_owned();
+            }
+
+            ((result != 0), shader, log)
+        }
+    }
+
+    fn delete_shader(&mut self, shader: GLuint) {
+        unsafe { gl::DeleteShader(shader); }
+    }
+
+    fn link_program(&mut self, vs: GLuint, fs: GLuint) -> (Option<GLuint>, String) {
+        unsafe {
+            let program = gl::CreateProgram();
+            gl::AttachShader(program, vs);
+            gl::AttachShader(program, fs);
+            gl::LinkProgram(program);
+
+            let mut result = 0;
+            gl::GetProgramiv(program, gl::LINK_STATUS, &mut result);
+
+            let mut prog_log = String::new();
+            let mut len = 0;
+            gl::GetProgramiv(program, gl::INFO_LOG_LENGTH, &mut len);
+
+            if len > 1 {
+                let mut buf = vec![0u8; len as usize];
+                gl::GetProgramInfoLog(
+                    program,
+                    len,
+                    std::ptr::null_mut(),
+                    buf.as_mut_ptr() as *mut GLchar,
+                );
+                buf.truncate((len as usize) - 1); // skip the trailing null character
+                prog_log = String::from_utf8_lossy(&buf).into_owned();
+            }
+
+            if result == 0 {
+                gl::DeleteProgram(program);
+                (None, prog_log)
+            } else {
+                (Some(program), prog_log)
+            }
+        }
+    }
+
+    fn delete_program(&mut self, program: GLuint) {
+        unsafe { gl::DeleteProgram(program); }
+    }
+
+    fn use_program(&mut self, program: GLuint) {
+        unsafe { gl::UseProgram(program); }
+    }
+
+    fn active_attributes(&mut self, program: GLuint) -> Vec<Variable> {
+        let mut ret = Vec::new();
+        unsafe {
+            let mut count = 0;
+            gl::GetProgramiv(program, gl::ACTIVE_ATTRIBUTES, &mut count);
+
+            let mut buf = vec!(0 as GLchar; 256);
+            let (mut name_len, mut size, mut datatype) = (0, 0, 0);
+            for i in 0..count {
+                gl::GetActiveAttrib(program, i as GLuint, 255, &mut name_len, &mut size, &mut datatype, buf.as_mut_ptr());
+                let name = std::ffi::CStr::from_ptr(buf.as_ptr()).to_str().unwrap().to_owned();
+                let location = gl::GetAttribLocation(program, buf.as_ptr());
+                ret.push(Variable { name, location, datatype, size });
+            }
+        }
+        ret
+    }
+
+    fn active_uniforms(&mut self, program: GLuint) -> Vec<Variable> {
+        let mut ret = Vec::new();
+        unsafe {
+            let mut count = 0;
+            gl::GetProgramiv(program, gl::ACTIVE_UNIFORMS, &mut count);
+
+            let mut buf = vec!(0 as GLchar; 256);
+            let (mut name_len, mut size, mut datatype) = (0, 0, 0);
+            for i in 0..count {
+                gl::GetActiveUniform(program, i as GLuint, 255, &mut name_len, &mut size, &mut datatype, buf.as_mut_ptr());
+                let name = std::ffi::CStr::from_ptr(buf.as_ptr()).to_str().unwrap().to_owned();
+                let location = gl::GetUniformLocation(program, buf.as_ptr());
+                ret.push(Variable { name, location, datatype, size });
+            }
+        }
+        ret
+    }
+
+    fn active_uniform_blocks(&mut self, program: GLuint) -> Vec<UniformBlock> {
+        let mut ret = Vec::new();
+        unsafe {
+            let mut count = 0;
+            gl::GetProgramiv(program, gl::ACTIVE_UNIFORM_BLOCKS, &mut count);
+
+            let mut buf = vec!(0 as GLchar; 256);
+            let (mut name_len, mut size) = (0, 0);
+            for index in 0..count as GLuint {
+                gl::GetActiveUniformBlockName(program, index, 255, &mut name_len, buf.as_mut_ptr());
+                gl::GetActiveUniformBlockiv(program, index, gl::UNIFORM_BLOCK_DATA_SIZE, &mut size);
+                let name = std::ffi::CStr::from_ptr(buf.as_ptr()).to_str().unwrap().to_owned();
+                ret.push(UniformBlock { name, index, size });
+            }
+        }
+        ret
+    }
+
+    fn uniform_location(&mut self, program: GLuint, name: &str) -> GLint {
+        let cname = std::ffi::CString::new(name).expect("Invalid string");
+        unsafe { gl::GetUniformLocation(program, cname.as_ptr()) }
+    }
+
+    fn uniform(&mut self, l: GLint, value: &Uniform) {
+        use cgmath::prelude::*;
+        unsafe {
+            match value {
+                Uniform::Signed(i) => gl::Uniform1i(l, *i),
+                Uniform::Unsigned(ui) => gl::Uniform1ui(l, *ui),
+                Uniform::Float(f) => gl::Uniform1f(l, *f),
+                Uniform::Vec2(v) => gl::Uniform2fv(l, 1, v.as_ptr()),
+                Uniform::Vec3(v) => gl::Uniform3fv(l, 1, v.as_ptr()),
+                Uniform::Vec4(v) => gl::Uniform4fv(l, 1, v.as_ptr()),
+                Uniform::Mat2(m) => gl::UniformMatrix2fv(l, 1, 0, m.as_ptr()),
+                Uniform::Mat3(m) => gl::UniformMatrix3fv(l, 1, 0, m.as_ptr()),
+                Uniform::Mat4(m) => gl::UniformMatrix4fv(l, 1, 0, m.as_ptr()),
+            }
+        }
+    }
+
+    fn uniform_array(&mut self, l: GLint, values: &[Uniform]) {
+        let count = values.len() as GLsizei;
+        let floats: Vec<f32> = values.iter().flat_map(Uniform::floats).collect();
+        unsafe {
+            match values.first() {
+                Some(Uniform::Signed(_)) => {
+                    let ints: Vec<i32> = values.iter().filter_map(|value| match value { Uniform::Signed(i) => Some(*i), _ => None }).collect();
+                    gl::Uniform1iv(l, count, ints.as_ptr())
+                }
+                Some(Uniform::Unsigned(_)) => {
+                    let ints: Vec<u32> = values.iter().filter_map(|value| match value { Uniform::Unsigned(ui) => Some(*ui), _ => None }).collect();
+                    gl::Uniform1uiv(l, count, ints.as_ptr())
+                }
+                Some(Uniform::Float(_)) => gl::Uniform1fv(l, count, floats.as_ptr()),
+                Some(Uniform::Vec2(_)) => gl::Uniform2fv(l, count, floats.as_ptr()),
+                Some(Uniform::Vec3(_)) => gl::Uniform3fv(l, count, floats.as_ptr()),
+                Some(Uniform::Vec4(_)) => gl::Uniform4fv(l, count, floats.as_ptr()),
+                Some(Uniform::Mat2(_)) => gl::UniformMatrix2fv(l, count, 0, floats.as_ptr()),
+                Some(Uniform::Mat3(_)) => gl::UniformMatrix3fv(l, count, 0, floats.as_ptr()),
+                Some(Uniform::Mat4(_)) => gl::UniformMatrix4fv(l, count, 0, floats.as_ptr()),
+                None => {}
+            }
+        }
+    }
+
+    fn uniform_block_binding(&mut self, program: GLuint, block: GLuint, binding: GLuint) {
+        unsafe { gl::UniformBlockBinding(program, block, binding); }
+    }
+
+    fn gen_vertex_array(&mut self) -> GLuint {
+        let mut vertex_array = 0;
+        unsafe { gl::GenVertexArrays(1, &mut vertex_array); }
+        vertex_array
+    }
+
+    fn delete_vertex_array(&mut self, vertex_array: GLuint) {
+        unsafe { gl::DeleteVertexArrays(1, &vertex_array); }
+    }
+
+    fn bind_vertex_array(&mut self, vertex_array: GLuint) {
+        unsafe { gl::BindVertexArray(vertex_array); }
+    }
+
+    fn vertex_attrib_divisor(&mut self, location: GLuint, divisor: GLuint) {
+        unsafe { gl::VertexAttribDivisor(location, divisor); }
+    }
+
+    fn enable_vertex_attrib(&mut self, location: GLuint, enable: bool) {
+        unsafe {
+            if enable {
+                gl::EnableVertexAttribArray(location);
+            } else {
+                gl::DisableVertexAttribArray(location);
+            }
+        }
+    }
+
+    fn vertex_attrib_pointer(&mut self, location: GLuint, size: GLint, datatype: GLenum, normed: bool, stride: GLsizei, offset: GLsizei) {
+        unsafe { gl::VertexAttribPointer(location, size, datatype, if normed {1} else {0}, stride, offset as *const GLvoid); }
+    }
+
+    fn enable(&mut self, cap: GLenum, enable: bool) {
+        unsafe {
+            if enable {
+                gl::Enable(cap);
+            } else {
+                gl::Disable(cap);
+            }
+        }
+    }
+
+    fn depth_func(&mut self, func: GLenum) {
+        unsafe { gl::DepthFunc(func); }
+    }
+
+    fn clear_color(&mut self, color: (f32, f32, f32, f32)) {
+        unsafe { gl::ClearColor(color.0, color.1, color.2, color.3); }
+    }
+
+    fn clear(&mut self, mask: GLbitfield) {
+        unsafe { gl::Clear(mask); }
+    }
+
+    fn draw_arrays(&mut self, mode: GLenum, first: GLint, count: GLsizei, instances: Option<GLsizei>) {
+        unsafe {
+            match instances {
+                Some(instances) => gl::DrawArraysInstanced(mode, first, count, instances),
+                None => gl::DrawArrays(mode, first, count),
+            }
+        }
+    }
+
+    fn draw_elements(&mut self, mode: GLenum, count: GLsizei, datatype: GLenum, offset: GLsizei, instances: Option<GLsizei>) {
+        unsafe {
+            match instances {
+                Some(instances) => gl::DrawElementsInstanced(mode, count, datatype, offset as _, instances),
+                None => gl::DrawElements(mode, count, datatype, offset as _),
+            }
+        }
+    }
+
+    fn timer_queries(&mut self) -> bool {
+        if !gl::QueryCounter::is_loaded() || !gl::GetQueryObjectui64v::is_loaded() {
+            return false;
+        }
+        let mut bits = 0;
+        unsafe {
+            let error = gl::GetError();
+            gl::GetQueryiv(gl::TIMESTAMP, gl::QUERY_COUNTER_BITS, &mut bits);
+            // an unknown target leaves an error behind, which mustn't hide the one before
+            gl::GetError();
+            if error != gl::NO_ERROR {
+                println!("tinygl: GL error 0x{:04x} before checking for timer queries", error);
+            }
+        }
+        bits > 0
+    }
+
+    fn gen_query(&mut self) -> GLuint {
+        let mut query = 0;
+        unsafe { gl::GenQueries(1, &mut query); }
+        query
+    }
+
+    fn delete_query(&mut self, query: GLuint) {
+        unsafe { gl::DeleteQueries(1, &query); }
+    }
+
+    fn query_timestamp(&mut self, query: GLuint) {
+        unsafe { gl::QueryCounter(query, gl::TIMESTAMP); }
+    }
+
+    fn query_result(&mut self, query: GLuint) -> Option<u64> {
+        let mut available = 0;
+        let mut result = 0;
+        unsafe {
+            gl::GetQueryObjectuiv(query, gl::QUERY_RESULT_AVAILABLE, &mut available);
+            if available == 0 {
+                return None;
+            }
+            gl::GetQueryObjectui64v(query, gl::QUERY_RESULT, &mut result);
+        }
+        Some(result)
+    }
+
+    #[cfg(target_os = "emscripten")]
+    fn timer_disjoint(&mut self) -> bool {
+        let mut disjoint = 0;
+        unsafe { gl::GetIntegerv(GPU_DISJOINT_EXT, &mut disjoint); }
+        disjoint != 0
+    }
+
+    // desktop GL has no such thing
+    #[cfg(not(target_os = "emscripten"))]
+    fn timer_disjoint(&mut self) -> bool {
+        false
+    }
+}
+
+/// Pixels that went into a texture with `tex_sub_image` or `compressed_tex_sub_image`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Upload {
+    pub texture: GLuint,
+    /// the face for cube maps
+    pub target: GLenum,
+    pub level: GLint,
+    pub offset: (GLint, GLint, GLint),
+    pub size: (GLsizei, GLsizei, GLsizei),
+    pub data: Vec<u8>,
+}
+
+/// A vertex attribute as it was set up for a draw call
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attribute {
+    pub buffer: GLuint,
+    pub size: GLint,
+    pub datatype: GLenum,
+    pub stride: GLsizei,
+    pub offset: GLsizei,
+    pub divisor: GLuint,
+}
+
+#[derive(Debug, Clone)]
+pub struct Draw {
+    pub mode: GLenum,
+    /// vertices, or indices for indexed draws
+    pub count: GLsizei,
+    pub instances: Option<GLsizei>,
+    pub indexed: bool,
+    pub program: GLuint,
+    pub framebuffer: GLuint,
+    pub vertex_array: GLuint,
+    /// uniform values of the program by name
+    pub uniforms: HashMap<String, Uniform>,
+    /// enabled attributes by name
+    pub attributes: HashMap<String, Attribute>,
+    pub enabled: HashSet<GLenum>,
+    pub depth_func: GLenum,
+}
+
+#[derive(Debug, Default)]
+pub struct NullProgram {
+    pub attributes: Vec<Variable>,
+    pub uniforms: Vec<Variable>,
+    /// blocks don't know their size
+    pub blocks: Vec<UniformBlock>,
+    /// binding point by block index
+    pub block_bindings: HashMap<GLuint, GLuint>,
+    pub values: HashMap<i32, Uniform>,
+}
+
+/// What the null backend knows about the GL state
+#[derive(Debug, Default)]
+pub struct NullState {
+    next_id: GLuint,
+
+    /// live buffers with their data
+    pub buffers: HashMap<GLuint, Vec<u8>>,
+    /// live textures with the size of their image, if any
+    pub textures: HashMap<GLuint, Option<(GLsizei, GLsizei)>>,
+    /// parameters of live textures
+    pub texture_parameters: HashMap<GLuint, HashMap<GLenum, GLint>>,
+    /// live samplers with their parameters
+    pub samplers: HashMap<GLuint, HashMap<GLenum, GLint>>,
+    pub framebuffers: HashSet<GLuint>,
+    /// texture and layer by framebuffer and attachment
+    pub attachments: HashMap<(GLuint, GLenum), (GLuint, Option<GLint>)>,
+    pub renderbuffers: HashSet<GLuint>,
+    // source of the shaders that didn't get deleted yet
+    shaders: HashMap<GLuint, (GLenum, String)>,
+    pub programs: HashMap<GLuint, NullProgram>,
+
+    /// bound buffer by target
+    pub bound_buffers: HashMap<GLenum, GLuint>,
+    /// bound uniform buffer by binding point
+    pub uniform_buffers: HashMap<GLuint, GLuint>,
+    /// bound texture by unit and target
+    pub bound_textures: HashMap<(GLuint, GLenum), GLuint>,
+    pub active_texture: GLuint,
+    /// bound sampler by unit
+    pub bound_samplers: HashMap<GLuint, GLuint>,
+    pub framebuffer: GLuint,
+    pub program: GLuint,
+    pub enabled: HashSet<GLenum>,
+    pub depth_func: GLenum,
+    pub clear_color: (f32, f32, f32, f32),
+    pub viewport: (GLint, GLint, GLsizei, GLsizei),
+
+    /// vertex attributes by location with whether they are enabled, by vertex array. 0 is the
+    /// default one.
+    pub vertex_arrays: HashMap<GLuint, HashMap<GLuint, (bool, Attribute)>>,
+    pub vertex_array: GLuint,
+
+    pub clears: usize,
+    pub draws: Vec<Draw>,
+    pub uploads: Vec<Upload>,
+
+    pub timer_queries: bool,
+    /// live queries with their timestamp, if they got one
+    pub queries: HashMap<GLuint, Option<u64>>,
+    /// queries get no timestamps, like a GPU that is behind
+    pub stalled: bool,
+    /// reported and reset by the next `timer_disjoint`
+    pub disjoint: bool,
+    /// in nanoseconds, every drawn vertex takes one
+    pub gpu_time: u64,
+}
+
+impl NullState {
+    fn gen(&mut self) -> GLuint {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    /// Count of the GL objects that are still around
+    pub fn live_objects(&self) -> usize {
+        self.buffers.len() + self.textures.len() + self.samplers.len() + self.framebuffers.len() + self.renderbuffers.len()
+            + self.shaders.len() + self.programs.len() + self.queries.len()
+            + self.vertex_arrays.keys().filter(|&&vertex_array| vertex_array != 0).count()
+    }
+
+    /// The texture bound to the active unit, cube map faces are bound as the cube map
+    fn bound_texture(&self, target: GLenum) -> GLuint {
+        let target = match target {
+            gl::TEXTURE_CUBE_MAP_POSITIVE_X..=gl::TEXTURE_CUBE_MAP_NEGATIVE_Z => gl::TEXTURE_CUBE_MAP,
+            _ => target,
+        };
+        self.bound_textures.get(&(self.active_texture, target)).cloned().unwrap_or(0)
+    }
+
+    fn upload(&mut self, target: GLenum, level: GLint, offset: (GLint, GLint, GLint), size: (GLsizei, GLsizei, GLsizei), data: &[u8]) {
+        let texture = self.bound_texture(target);
+        self.uploads.push(Upload { texture, target, level, offset, size, data: data.to_vec() });
+    }
+
+    fn attribute(&mut self, location: GLuint) -> &mut (bool, Attribute) {
+        self.vertex_arrays.entry(self.vertex_array).or_default().entry(location).or_insert((false, Attribute {
+            buffer: 0, size: 0, datatype: 0, stride: 0, offset: 0, divisor: 0,
+        }))
+    }
+
+    fn record(&mut self, mode: GLenum, count: GLsizei, instances: Option<GLsizei>, indexed: bool) {
+        let program = self.programs.get(&self.program);
+        let uniforms = program.map_or(HashMap::new(), |program| {
+            let mut uniforms = HashMap::new();
+            for uniform in &program.uniforms {
+                for i in 0..uniform.size {
+                    if let Some(value) = program.values.get(&(uniform.location + i)) {
+                        let name = if uniform.size > 1 { format!("{}[{}]", uniform.name, i) } else { uniform.name.clone() };
+                        uniforms.insert(name, *value);
+                    }
+                }
+            }
+            uniforms
+        });
+        let attributes = program.map_or(HashMap::new(), |program| {
+            program.attributes.iter()
+                .filter_map(|attribute| match self.vertex_arrays.get(&self.vertex_array).and_then(|attributes| attributes.get(&(attribute.location as GLuint))) {
+                    Some((true, setup)) => Some((attribute.name.clone(), setup.clone())),
+                    _ => None,
+                })
+                .collect()
+        });
+
+        self.gpu_time += count.max(0) as u64 * instances.unwrap_or(1).max(0) as u64;
+        self.draws.push(Draw {
+            mode,
+            count,
+            instances,
+            indexed,
+            program: self.program,
+            framebuffer: self.framebuffer,
+            vertex_array: self.vertex_array,
+            uniforms,
+            attributes,
+            enabled: self.enabled.clone(),
+            depth_func: self.depth_func,
+        });
+    }
+}
+
+/// The words of every statement without layout and precision qualifiers, the array size if
+/// there is a literal one, and whether it opens a block
+fn statements(src: &str) -> Vec<(Vec<String>, GLint, bool)> {
+    let src: String = src.lines().map(|line| line.split("//").next().unwrap()).collect::<Vec<_>>().join("\n");
+    let mut ret = Vec::new();
+    for piece in src.split_inclusive(&[';', '{', '}'][..]) {
+        let opens_block = piece.ends_with('{');
+        let statement = piece.trim_end_matches(&[';', '{', '}'][..]).trim_start();
+        let statement = if statement.starts_with("layout") { statement.split_once(')').map_or("", |split| split.1) } else { statement };
+
+        let mut parts = statement.splitn(2, '[');
+        let words = parts.next().unwrap().split_whitespace()
+            .filter(|word| !["lowp", "mediump", "highp"].contains(word))
+            .map(String::from)
+            .collect();
+        let size = parts.next().and_then(|size| size.split(']').next().unwrap().trim().parse().ok()).unwrap_or(1);
+        ret.push((words, size, opens_block));
+    }
+    ret
+}
+
+/// The variables declared by lines like "uniform vec3 color;" or "in vec2 vertex;", with
+/// made up locations
+fn declarations(src: &str, qualifiers: &[&str]) -> Vec<Variable> {
+    let mut ret = Vec::new();
+    for (words, size, opens_block) in statements(src) {
+        if words.len() == 3 && qualifiers.contains(&&*words[0]) && !opens_block {
+            ret.push(Variable { name: words[2].clone(), location: 0, datatype: program::type_from_name(&words[1]), size });
+        }
+    }
+    ret
+}
+
+/// The names of the blocks declared like "uniform Lights { ... };"
+fn uniform_blocks(src: &str) -> Vec<String> {
+    statements(src).into_iter()
+        .filter(|(words, _, opens_block)| *opens_block && words.len() == 2 && words[0] == "uniform")
+        .map(|(words, _, _)| words[1].clone())
+        .collect()
+}
+
+/// Doesn't draw anything, but keeps the state GL would have. Clones share that state, so a
+/// test can hand one to `Context::with_backend` and look at the other.
+#[derive(Clone, Default)]
+pub struct NullBackend {
+    state: Rc<RefCell<NullState>>,
+}
+
+impl NullBackend {
+    pub fn new() -> Self {
+        let ret = NullBackend::default();
+        ret.state.borrow_mut().depth_func = gl::LESS;
+        ret.state.borrow_mut().timer_queries = true;
+        ret
+    }
+
+    /// Like a GL without timer queries
+    pub fn without_timer_queries() -> Self {
+        let ret = Self::new();
+        ret.state.borrow_mut().timer_queries = false;
+        ret
+    }
+
+    pub fn state(&self) -> Ref<'_, NullState> {
+        self.state.borrow()
+    }
+
+    /// For tests that play the GPU, e.g. one that falls behind
+    pub fn state_mut(&self) -> RefMut<'_, NullState> {
+        self.state.borrow_mut()
+    }
+
+    /// Forgets the draw calls so far
+    pub fn take_draws(&self) -> Vec<Draw> {
+        std::mem::take(&mut self.state.borrow_mut().draws)
+    }
+}
+
+impl Backend for NullBackend {
+    fn gen_buffer(&mut self) -> GLuint {
+        let mut state = self.state.borrow_mut();
+        let id = state.gen();
+        state.buffers.insert(id, Vec::new());
+        id
+    }
+
+    fn delete_buffer(&mut self, buffer: GLuint) {
+        let mut state = self.state.borrow_mut();
+        state.buffers.remove(&buffer);
+        state.bound_buffers.retain(|_, bound| *bound != buffer);
+    }
+
+    fn bind_buffer(&mut self, target: GLenum, buffer: GLuint) {
+        self.state.borrow_mut().bound_buffers.insert(target, buffer);
+    }
+
+    fn buffer_data(&mut self, target: GLenum, data: &[u8], _usage: GLenum) {
+        let mut state = self.state.borrow_mut();
+        let bound = state.bound_buffers.get(&target).cloned().unwrap_or(0);
+        if let Some(buffer) = state.buffers.get_mut(&bound) {
+            *buffer = data.to_vec();
+        }
+    }
+
+    fn allocate_buffer(&mut self, target: GLenum, size: usize, _usage: GLenum) {
+        let mut state = self.state.borrow_mut();
+        let bound = state.bound_buffers.get(&target).cloned().unwrap_or(0);
+        if let Some(buffer) = state.buffers.get_mut(&bound) {
+            *buffer = vec![0; size];
+        }
+    }
+
+    fn buffer_sub_data(&mut self, target: GLenum, offset: usize, data: &[u8]) {
+        let mut state = self.state.borrow_mut();
+        let bound = state.bound_buffers.get(&target).cloned().unwrap_or(0);
+        // GL ignores writes past the end with GL_INVALID_VALUE
+        if let Some(buffer) = state.buffers.get_mut(&bound).filter(|buffer| offset + data.len() <= buffer.len()) {
+            buffer[offset..offset + data.len()].copy_from_slice(data);
+        }
+    }
+
+    fn bind_buffer_base(&mut self, target: GLenum, binding: GLuint, buffer: GLuint) {
+        let mut state = self.state.borrow_mut();
+        state.bound_buffers.insert(target, buffer);
+        if target == gl::UNIFORM_BUFFER {
+            state.uniform_buffers.insert(binding, buffer);
+        }
+    }
+
+    fn gen_texture(&mut self) -> GLuint {
+        let mut state = self.state.borrow_mut();
+        let id = state.gen();
+        state.textures.insert(id, None);
+        id
+    }
+
+    fn delete_texture(&mut self, texture: GLuint) {
+        let mut state = self.state.borrow_mut();
+        state.textures.remove(&texture);
+        state.texture_parameters.remove(&texture);
+        state.bound_textures.retain(|_, bound| *bound != texture);
+    }
+
+    fn active_texture(&mut self, unit: GLuint) {
+        self.state.borrow_mut().active_texture = unit;
+    }
+
+    fn bind_texture(&mut self, target: GLenum, texture: GLuint) {
+        let mut state = self.state.borrow_mut();
+        let unit = state.active_texture;
+        state.bound_textures.insert((unit, target), texture);
+    }
+
+    fn tex_parameter(&mut self, target: GLenum, name: GLenum, value: GLint) {
+        let mut state = self.state.borrow_mut();
+        let bound = state.bound_texture(target);
+        if state.textures.contains_key(&bound) {
+            state.texture_parameters.entry(bound).or_default().insert(name, value);
+        }
+    }
+
+    unsafe fn tex_image(&mut self, target: GLenum, size: (GLsizei, GLsizei), _layers: Option<GLsizei>, _internal: GLenum, _format: GLenum, _datatype: GLenum, _data: *const GLvoid) {
+        let mut state = self.state.borrow_mut();
+        let unit = state.active_texture;
+        let bound = state.bound_textures.get(&(unit, target)).cloned().unwrap_or(0);
+        if let Some(texture) = state.textures.get_mut(&bound) {
+            *texture = Some(size);
+        }
+    }
+
+    fn generate_mipmap(&mut self, _target: GLenum) {
+    }
+
+    fn tex_storage(&mut self, target: GLenum, _levels: GLsizei, _internal: GLenum, size: (GLsizei, GLsizei), _depth: Option<GLsizei>) {
+        let mut state = self.state.borrow_mut();
+        let bound = state.bound_texture(target);
+        if let Some(texture) = state.textures.get_mut(&bound) {
+            *texture = Some(size);
+        }
+    }
+
+    fn tex_sub_image(&mut self, target: GLenum, level: GLint, offset: (GLint, GLint, GLint), size: (GLsizei, GLsizei, GLsizei), _format: GLenum, _datatype: GLenum, data: &[u8]) {
+        self.state.borrow_mut().upload(target, level, offset, size, data);
+    }
+
+    fn compressed_tex_sub_image(&mut self, target: GLenum, level: GLint, offset: (GLint, GLint, GLint), size: (GLsizei, GLsizei, GLsizei), _internal: GLenum, data: &[u8]) {
+        self.state.borrow_mut().upload(target, level, offset, size, data);
+    }
+
+    fn gen_sampler(&mut self) -> GLuint {
+        let mut state = self.state.borrow_mut();
+        let id = state.gen();
+        state.samplers.insert(id, HashMap::new());
+        id
+    }
+
+    fn delete_sampler(&mut self, sampler: GLuint) {
+        let mut state = self.state.borrow_mut();
+        state.samplers.remove(&sampler);
+        state.bound_samplers.retain(|_, bound| *bound != sampler);
+    }
+
+    fn bind_sampler(&mut self, unit: GLuint, sampler: GLuint) {
+        self.state.borrow_mut().bound_samplers.insert(unit, sampler);
+    }
+
+    fn sampler_parameter(&mut self, sampler: GLuint, name: GLenum, value: GLint) {
+        if let Some(parameters) = self.state.borrow_mut().samplers.get_mut(&sampler) {
+            parameters.insert(name, value);
+        }
+    }
+
+    fn gen_framebuffer(&mut self) -> GLuint {
+        let mut state = self.state.borrow_mut();
+        let id = state.gen();
+        state.framebuffers.insert(id);
+        id
+    }
+
+    fn delete_framebuffer(&mut self, framebuffer: GLuint) {
+        let mut state = self.state.borrow_mut();
+        state.framebuffers.remove(&framebuffer);
+        state.attachments.retain(|&(attached_to, _), _| attached_to != framebuffer);
+        if state.framebuffer == framebuffer {
+            state.framebuffer = 0;
+        }
+    }
+
+    fn bind_framebuffer(&mut self, framebuffer: GLuint) {
+        self.state.borrow_mut().framebuffer = framebuffer;
+    }
+
+    fn framebuffer_texture(&mut self, attachment: GLenum, texture: GLuint, layer: Option<GLint>) {
+        let mut state = self.state.borrow_mut();
+        let framebuffer = state.framebuffer;
+        state.attachments.insert((framebuffer, attachment), (texture, layer));
+    }
+
+    fn gen_renderbuffer(&mut self) -> GLuint {
+        let mut state = self.state.borrow_mut();
+        let id = state.gen();
+        state.renderbuffers.insert(id);
+        id
+    }
+
+    fn delete_renderbuffer(&mut self, renderbuffer: GLuint) {
+        self.state.borrow_mut().renderbuffers.remove(&renderbuffer);
+    }
+
+    fn renderbuffer_storage(&mut self, _renderbuffer: GLuint, _internal: GLenum, _size: (GLsizei, GLsizei)) {
+    }
+
+    fn framebuffer_renderbuffer(&mut self, _attachment: GLenum, _renderbuffer: GLuint) {
+    }
+
+    fn draw_buffers(&mut self, _buffers: &[GLenum]) {
+    }
+
+    fn read_buffer(&mut self, _buffer: GLenum) {
+    }
+
+    fn pixel_store(&mut self, _name: GLenum, _value: GLint) {
+    }
+
+    /// Nothing got drawn, so everything reads as zero
+    unsafe fn read_pixels(&mut self, size: (GLsizei, GLsizei), format: GLenum, datatype: GLenum, dst: *mut GLvoid) {
+        let channels = match format {
+            gl::RED | gl::DEPTH_COMPONENT => 1,
+            gl::RG => 2,
+            gl::RGB => 3,
+            _ => 4,
+        };
+        let bytes = match datatype {
+            gl::UNSIGNED_BYTE | gl::BYTE => 1,
+            gl::UNSIGNED_SHORT | gl::SHORT | gl::HALF_FLOAT => 2,
+            _ => 4,
+        };
+        std::ptr::write_bytes(dst as *mut u8, 0, (size.0 * size.1) as usize * channels * bytes);
+    }
+
+    fn viewport(&mut self, x: GLint, y: GLint, w: GLsizei, h: GLsizei) {
+        self.state.borrow_mut().viewport = (x, y, w, h);
+    }
+
+    /// Fails on `#error` lines only, with a log in the format of WebGL
+    fn compile_shader(&mut self, shader_type: GLenum, src: &str) -> (bool, GLuint, String) {
+        let mut state = self.state.borrow_mut();
+        let id = state.gen();
+        state.shaders.insert(id, (shader_type, src.to_string()));
+
+        let log: String = src.lines().enumerate()
+            .filter_map(|(i, line)| line.trim().strip_prefix("#error").map(|message| (i + 1, message.trim())))
+            .map(|(line, message)| format!("ERROR: 0:{}: '#error' : {}\n", line, message))
+            .collect();
+        (log.is_empty(), id, log)
+    }
+
+    fn delete_shader(&mut self, shader: GLuint) {
+        self.state.borrow_mut().shaders.remove(&shader);
+    }
+
+    /// Makes up locations for the attributes and uniforms the shaders declare
+    fn link_program(&mut self, vs: GLuint, fs: GLuint) -> (Option<GLuint>, String) {
+        let mut state = self.state.borrow_mut();
+        let (vsrc, fsrc) = match (state.shaders.get(&vs), state.shaders.get(&fs)) {
+            (Some(vs), Some(fs)) => (vs.1.clone(), fs.1.clone()),
+            _ => return (None, String::from("No such shader")),
+        };
+
+        let mut uniforms = declarations(&vsrc, &["uniform"]);
+        for uniform in declarations(&fsrc, &["uniform"]) {
+            if !uniforms.iter().any(|existing| existing.name == uniform.name) {
+                uniforms.push(uniform);
+            }
+        }
+        let mut blocks = uniform_blocks(&vsrc);
+        for block in uniform_blocks(&fsrc) {
+            if !blocks.contains(&block) {
+                blocks.push(block);
+            }
+        }
+
+        // array elements get consecutive locations
+        let mut location = 0;
+        for uniform in &mut uniforms {
+            uniform.location = location;
+            location += uniform.size;
+        }
+        let mut attributes = declarations(&vsrc, &["attribute", "in"]);
+        for (i, attribute) in attributes.iter_mut().enumerate() {
+            attribute.location = i as GLint;
+        }
+
+        let id = state.gen();
+        state.programs.insert(id, NullProgram {
+            attributes,
+            uniforms,
+            blocks: blocks.into_iter().enumerate().map(|(index, name)| UniformBlock { name, index: index as GLuint, size: 0 }).collect(),
+            block_bindings: HashMap::new(),
+            values: HashMap::new(),
+        });
+        (Some(id), String::new())
+    }
+
+    fn delete_program(&mut self, program: GLuint) {
+        self.state.borrow_mut().programs.remove(&program);
+    }
+
+    fn use_program(&mut self, program: GLuint) {
+        self.state.borrow_mut().program = program;
+    }
+
+    fn active_attributes(&mut self, program: GLuint) -> Vec<Variable> {
+        self.state.borrow().programs.get(&program).map_or(Vec::new(), |program| program.attributes.clone())
+    }
+
+    fn active_uniforms(&mut self, program: GLuint) -> Vec<Variable> {
+        self.state.borrow().programs.get(&program).map_or(Vec::new(), |program| program.uniforms.clone())
+    }
+
+    fn active_uniform_blocks(&mut self, program: GLuint) -> Vec<UniformBlock> {
+        self.state.borrow().programs.get(&program).map_or(Vec::new(), |program| program.blocks.clone())
+    }
+
+    fn uniform_location(&mut self, program: GLuint, name: &str) -> GLint {
+        let state = self.state.borrow();
+        let uniforms = state.programs.get(&program).map_or(&[][..], |program| &program.uniforms[..]);
+        let (base, index) = program::split_index(name).unwrap_or((name, 0));
+        match uniforms.iter().find(|uniform| uniform.name == base) {
+            Some(uniform) if (index as GLint) < uniform.size => uniform.location + index as GLint,
+            _ => -1,
+        }
+    }
+
+    fn uniform(&mut self, location: GLint, value: &Uniform) {
+        let mut state = self.state.borrow_mut();
+        let current = state.program;
+        if let Some(program) = state.programs.get_mut(&current) {
+            program.values.insert(location, *value);
+        }
+    }
+
+    fn uniform_array(&mut self, location: GLint, values: &[Uniform]) {
+        for (i, value) in values.iter().enumerate() {
+            self.uniform(location + i as GLint, value);
+        }
+    }
+
+    fn uniform_block_binding(&mut self, program: GLuint, block: GLuint, binding: GLuint) {
+        if let Some(program) = self.state.borrow_mut().programs.get_mut(&program) {
+            program.block_bindings.insert(block, binding);
+        }
+    }
+
+    fn gen_vertex_array(&mut self) -> GLuint {
+        let mut state = self.state.borrow_mut();
+        let id = state.gen();
+        state.vertex_arrays.insert(id, HashMap::new());
+        id
+    }
+
+    fn delete_vertex_array(&mut self, vertex_array: GLuint) {
+        let mut state = self.state.borrow_mut();
+        state.vertex_arrays.remove(&vertex_array);
+        if state.vertex_array == vertex_array {
+            state.vertex_array = 0;
+        }
+    }
+
+    fn bind_vertex_array(&mut self, vertex_array: GLuint) {
+        self.state.borrow_mut().vertex_array = vertex_array;
+    }
+
+    fn vertex_attrib_divisor(&mut self, location: GLuint, divisor: GLuint) {
+        self.state.borrow_mut().attribute(location).1.divisor = divisor;
+    }
+
+    fn enable_vertex_attrib(&mut self, location: GLuint, enable: bool) {
+        self.state.borrow_mut().attribute(location).0 = enable;
+    }
+
+    fn vertex_attrib_pointer(&mut self, location: GLuint, size: GLint, datatype: GLenum, _normed: bool, stride: GLsizei, offset: GLsizei) {
+        let mut state = self.state.borrow_mut();
+        let buffer = state.bound_buffers.get(&gl::ARRAY_BUFFER).cloned().unwrap_or(0);
+        let attribute = state.attribute(location);
+        attribute.1 = Attribute { buffer, size, datatype, stride, offset, divisor: attribute.1.divisor };
+    }
+
+    fn enable(&mut self, cap: GLenum, enable: bool) {
+        let mut state = self.state.borrow_mut();
+        if enable {
+            state.enabled.insert(cap);
+        } else {
+            state.enabled.remove(&cap);
+        }
+    }
+
+    fn depth_func(&mut self, func: GLenum) {
+        self.state.borrow_mut().depth_func = func;
+    }
+
+    fn clear_color(&mut self, color: (f32, f32, f32, f32)) {
+        self.state.borrow_mut().clear_color = color;
+    }
+
+    fn clear(&mut self, _mask: GLbitfield) {
+        self.state.borrow_mut().clears += 1;
+    }
+
+    fn draw_arrays(&mut self, mode: GLenum, _first: GLint, count: GLsizei, instances: Option<GLsizei>) {
+        self.state.borrow_mut().record(mode, count, instances, false);
+    }
+
+    fn draw_elements(&mut self, mode: GLenum, count: GLsizei, _datatype: GLenum, _offset: GLsizei, instances: Option<GLsizei>) {
+        self.state.borrow_mut().record(mode, count, instances, true);
+    }
+
+    fn timer_queries(&mut self) -> bool {
+        self.state.borrow().timer_queries
+    }
+
+    fn gen_query(&mut self) -> GLuint {
+        let mut state = self.state.borrow_mut();
+        let id = state.gen();
+        state.queries.insert(id, None);
+        id
+    }
+
+    fn delete_query(&mut self, query: GLuint) {
+        self.state.borrow_mut().queries.remove(&query);
+    }
+
+    fn query_timestamp(&mut self, query: GLuint) {
+        let mut state = self.state.borrow_mut();
+        let time = if state.stalled { None } else { Some(state.gpu_time) };
+        if let Some(timestamp) = state.queries.get_mut(&query) {
+            *timestamp = time;
+        }
+    }
+
+    fn query_result(&mut self, query: GLuint) -> Option<u64> {
+        self.state.borrow().queries.get(&query).cloned().unwrap_or(None)
+    }
+
+    fn timer_disjoint(&mut self) -> bool {
+        std::mem::replace(&mut self.state.borrow_mut().disjoint, false)
+    }
+}
@@ -1,101 +1,279 @@
 use gl::types::*;
+use super::context::{self, Context, Resource};
+use super::program::Uniform;
+
+fn as_bytes<T>(data: &[T]) -> &[u8] {
+    unsafe {
+        std::slice::from_raw_parts(data.as_ptr() as *const u8, std::mem::size_of_val(data))
+    }
+}
 
 struct BufferBase {
+    ctx: Context,
     target: GLenum,
-    buffer: GLuint
+    buffer: GLuint,
+    usage: GLenum,
+    // in bytes, what GL has allocated
+    capacity: usize
 }
 
 impl BufferBase {
-    fn new<T>(target: GLenum, usage: GLenum, data: &Vec<T>) -> BufferBase {
-        let mut buffer = 0;
-
-        unsafe {
-            gl::GenBuffers(1, &mut buffer);
-            gl::BindBuffer(target, buffer);
-            gl::BufferData(target,
-                           (data.len() * std::mem::size_of::<T>()) as GLsizeiptr,
-                           data.as_ptr() as *const GLvoid,
-                           usage);
-            gl::BindBuffer(target, 0);
-        }
+    fn new(ctx: &Context, target: GLenum, usage: GLenum, data: &[u8]) -> BufferBase {
+        let buffer = ctx.backend(|b| b.gen_buffer());
+        ctx.register(Resource::Buffer, buffer);
 
-        BufferBase {
+        let mut ret = BufferBase {
+            ctx: ctx.clone(),
             target,
-            buffer
+            buffer,
+            usage,
+            capacity: 0
+        };
+        ret.upload(data);
+        ret
+    }
+
+    fn with_capacity(ctx: &Context, target: GLenum, usage: GLenum, capacity: usize) -> BufferBase {
+        let mut ret = Self::new(ctx, target, usage, &[]);
+        ret.update(capacity, &[]);
+        ret
+    }
+
+    // the index buffer binding belongs to the bound vertex array, which would lose its indices
+    fn release_vertex_array(&self) {
+        if self.target == gl::ELEMENT_ARRAY_BUFFER {
+            self.ctx.bind_vertex_array(0);
         }
     }
 
+    fn upload(&mut self, data: &[u8]) {
+        self.release_vertex_array();
+        self.ctx.backend(|b| {
+            b.bind_buffer(self.target, self.buffer);
+            b.buffer_data(self.target, data, self.usage);
+            b.bind_buffer(self.target, 0);
+        });
+        self.capacity = data.len();
+    }
+
+    /// Orphans the storage and writes `data` to the start of the new one, which grows to
+    /// `capacity` bytes if it's smaller
+    fn update(&mut self, capacity: usize, data: &[u8]) {
+        let capacity = self.capacity.max(capacity).max(data.len());
+        self.release_vertex_array();
+        self.ctx.backend(|b| {
+            b.bind_buffer(self.target, self.buffer);
+            b.allocate_buffer(self.target, capacity, self.usage);
+            if !data.is_empty() {
+                b.buffer_sub_data(self.target, 0, data);
+            }
+            b.bind_buffer(self.target, 0);
+        });
+        self.capacity = capacity;
+    }
+
+    fn update_range(&self, offset: usize, data: &[u8]) {
+        self.release_vertex_array();
+        self.ctx.backend(|b| {
+            b.bind_buffer(self.target, self.buffer);
+            b.buffer_sub_data(self.target, offset, data);
+            b.bind_buffer(self.target, 0);
+        });
+    }
+
     fn bind(&self) {
         if self.buffer != 0 {
-            unsafe {
-                gl::BindBuffer(self.target, self.buffer);
-            }
+            self.ctx.bind_buffer(self.target, self.buffer);
         }
     }
+
+    fn set_label(&self, label: &str) {
+        self.ctx.set_label(Resource::Buffer, self.buffer, label);
+    }
 }
 
 impl Drop for BufferBase {
     fn drop(&mut self) {
-        unsafe {
-            gl::DeleteBuffers(1, &self.buffer);
-        }
+        self.ctx.unregister(Resource::Buffer, self.buffer);
+        self.ctx.backend(|b| b.delete_buffer(self.buffer));
     }
 }
 
+/// The sizes of updates are rounded up to powers of two, so buffers that change size every
+/// frame settle on one allocation
+fn grown(size: usize) -> usize {
+    size.next_power_of_two()
+}
+
 pub struct VertexBuffer {
-    buffer: BufferBase
+    buffer: BufferBase,
+    size: usize
+}
+
+/// The types indices can have
+pub trait IndexType: Copy {
+    const DATATYPE: GLenum;
+    const NAME: &'static str;
+}
+
+impl IndexType for u8 {
+    const DATATYPE: GLenum = gl::UNSIGNED_BYTE;
+    const NAME: &'static str = "u8";
+}
+
+impl IndexType for u16 {
+    const DATATYPE: GLenum = gl::UNSIGNED_SHORT;
+    const NAME: &'static str = "u16";
+}
+
+impl IndexType for u32 {
+    const DATATYPE: GLenum = gl::UNSIGNED_INT;
+    const NAME: &'static str = "u32";
 }
 
 pub struct IndexBuffer {
     buffer: BufferBase,
     idx_type: GLenum,
+    type_name: &'static str,
     size: usize
 }
 
 impl VertexBuffer {
-    pub fn from<T>(data: &Vec<T>) -> VertexBuffer {
+    pub fn from<T>(ctx: &Context, data: &[T]) -> VertexBuffer {
+        VertexBuffer {
+            buffer: BufferBase::new(ctx, gl::ARRAY_BUFFER, gl::STATIC_DRAW, as_bytes(data)),
+            size: std::mem::size_of_val(data)
+        }
+    }
+
+    /// An empty buffer for data that changes now and then, with room for `capacity` bytes
+    pub fn dynamic(ctx: &Context, capacity: usize) -> VertexBuffer {
+        VertexBuffer {
+            buffer: BufferBase::with_capacity(ctx, gl::ARRAY_BUFFER, gl::DYNAMIC_DRAW, capacity),
+            size: 0
+        }
+    }
+
+    /// Like `dynamic`, for data that gets replaced every frame or even between draws
+    pub fn stream(ctx: &Context, capacity: usize) -> VertexBuffer {
         VertexBuffer {
-            buffer: BufferBase::new(gl::ARRAY_BUFFER, gl::STATIC_DRAW, data)
+            buffer: BufferBase::with_capacity(ctx, gl::ARRAY_BUFFER, gl::STREAM_DRAW, capacity),
+            size: 0
+        }
+    }
+
+    /// Replaces the contents without a new GL buffer. The old storage is orphaned, so draws
+    /// that still use it don't stall, and only grows if the data doesn't fit.
+    pub fn update<T>(&mut self, data: &[T]) {
+        let size = std::mem::size_of_val(data);
+        self.buffer.update(if size > self.buffer.capacity { grown(size) } else { 0 }, as_bytes(data));
+        self.size = size;
+    }
+
+    /// Overwrites part of the contents, `offset` in bytes
+    pub fn update_range<T>(&mut self, offset: usize, data: &[T]) -> Result<(), String> {
+        let size = std::mem::size_of_val(data);
+        if offset + size > self.size {
+            return Err(format!("can't write {} bytes at {} into a vertex buffer of {} bytes", size, offset, self.size));
         }
+        self.buffer.update_range(offset, as_bytes(data));
+        Ok(())
+    }
+
+    /// In bytes
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// In bytes, how much `update` can take without allocating more
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity
     }
 
     pub fn bind(&self) {
         self.buffer.bind();
     }
 
-    pub fn release() {
-        unsafe { gl::BindBuffer(gl::ARRAY_BUFFER, 0); }
+    pub fn set_label(&self, label: &str) {
+        self.buffer.set_label(label);
+    }
+
+    pub fn release(ctx: &Context) {
+        ctx.bind_buffer(gl::ARRAY_BUFFER, 0);
     }
 }
 
 impl IndexBuffer {
-    pub fn from32(data: &Vec<u32>) -> IndexBuffer {
+    pub fn new<T: IndexType>(ctx: &Context, data: &[T]) -> IndexBuffer {
         IndexBuffer {
-            buffer: BufferBase::new(gl::ELEMENT_ARRAY_BUFFER, gl::STATIC_DRAW, data),
-            idx_type: gl::UNSIGNED_INT,
+            buffer: BufferBase::new(ctx, gl::ELEMENT_ARRAY_BUFFER, gl::STATIC_DRAW, as_bytes(data)),
+            idx_type: T::DATATYPE,
+            type_name: T::NAME,
             size: data.len()
         }
     }
 
-    pub fn from16(data: &Vec<u16>) -> IndexBuffer {
+    pub fn from32(ctx: &Context, data: &[u32]) -> IndexBuffer {
+        Self::new(ctx, data)
+    }
+
+    pub fn from16(ctx: &Context, data: &[u16]) -> IndexBuffer {
+        Self::new(ctx, data)
+    }
+
+    /// An empty buffer for indices of type `T` that change, with room for `capacity` of them
+    pub fn dynamic<T: IndexType>(ctx: &Context, capacity: usize) -> IndexBuffer {
         IndexBuffer {
-            buffer: BufferBase::new(gl::ELEMENT_ARRAY_BUFFER, gl::STATIC_DRAW, data),
-            idx_type: gl::UNSIGNED_SHORT,
-            size: data.len()
+            buffer: BufferBase::with_capacity(ctx, gl::ELEMENT_ARRAY_BUFFER, gl::DYNAMIC_DRAW, capacity * std::mem::size_of::<T>()),
+            idx_type: T::DATATYPE,
+            type_name: T::NAME,
+            size: 0
         }
     }
 
+    fn check_type<T: IndexType>(&self) -> Result<(), String> {
+        if T::DATATYPE == self.idx_type {
+            Ok(())
+        } else {
+            Err(format!("index buffer holds {} indices, not {}", self.type_name, T::NAME))
+        }
+    }
+
+    /// Replaces the indices like `VertexBuffer::update`, they have to be of the buffer's type.
+    /// Unbinds the vertex array that is bound, so it keeps its indices.
+    pub fn update<T: IndexType>(&mut self, data: &[T]) -> Result<(), String> {
+        self.check_type::<T>()?;
+        let size = std::mem::size_of_val(data);
+        self.buffer.update(if size > self.buffer.capacity { grown(size) } else { 0 }, as_bytes(data));
+        self.size = data.len();
+        Ok(())
+    }
+
+    /// Overwrites part of the indices, `offset` in indices
+    pub fn update_range<T: IndexType>(&mut self, offset: usize, data: &[T]) -> Result<(), String> {
+        self.check_type::<T>()?;
+        if offset + data.len() > self.size {
+            return Err(format!("can't write {} indices at {} into an index buffer of {}", data.len(), offset, self.size));
+        }
+        self.buffer.update_range(offset * std::mem::size_of::<T>(), as_bytes(data));
+        Ok(())
+    }
+
     pub fn bind(&self) {
         self.buffer.bind();
     }
 
-    pub fn release() {
-        unsafe { gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, 0); }
+    pub fn set_label(&self, label: &str) {
+        self.buffer.set_label(label);
+    }
+
+    pub fn release(ctx: &Context) {
+        ctx.bind_buffer(gl::ELEMENT_ARRAY_BUFFER, 0);
     }
 
     pub fn draw(&self, mode: GLenum, count: GLsizei, ofs: GLsizei) {
         self.buffer.bind();
-        unsafe { gl::DrawElements(mode, count, self.idx_type, ofs as _); }
+        context::draw_elements(&self.buffer.ctx, mode, count, self.idx_type, ofs);
     }
 
     pub fn draw_all(&self, mode: GLenum) -> usize {
@@ -106,4 +284,118 @@ impl IndexBuffer {
     pub fn count(&self) -> usize {
         self.size
     }
+
+    /// gl::UNSIGNED_BYTE, gl::UNSIGNED_SHORT or gl::UNSIGNED_INT
+    pub fn index_type(&self) -> GLenum {
+        self.idx_type
+    }
+}
+
+/// Lays out values like a `layout(std140)` uniform block does
+#[derive(Debug, Clone, Default)]
+pub struct Std140 {
+    data: Vec<u8>
+}
+
+impl Std140 {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn align(&mut self, alignment: usize) {
+        let len = self.data.len().div_ceil(alignment) * alignment;
+        self.data.resize(len, 0);
+    }
+
+    fn put_floats(&mut self, floats: &[f32]) {
+        for f in floats {
+            self.data.extend_from_slice(&f.to_ne_bytes());
+        }
+    }
+
+    /// Matrices are columns of vec4s
+    fn put_columns(&mut self, floats: &[f32], rows: usize) {
+        for column in floats.chunks(rows) {
+            self.align(16);
+            self.put_floats(column);
+        }
+        self.align(16);
+    }
+
+    pub fn push(&mut self, value: Uniform) -> &mut Self {
+        match value {
+            Uniform::Signed(i) => { self.align(4); self.data.extend_from_slice(&i.to_ne_bytes()); }
+            Uniform::Unsigned(ui) => { self.align(4); self.data.extend_from_slice(&ui.to_ne_bytes()); }
+            Uniform::Float(_) => { self.align(4); self.put_floats(&value.floats()); }
+            Uniform::Vec2(_) => { self.align(8); self.put_floats(&value.floats()); }
+            Uniform::Vec3(_) | Uniform::Vec4(_) => { self.align(16); self.put_floats(&value.floats()); }
+            Uniform::Mat2(_) => self.put_columns(&value.floats(), 2),
+            Uniform::Mat3(_) => self.put_columns(&value.floats(), 3),
+            Uniform::Mat4(_) => self.put_columns(&value.floats(), 4),
+        }
+        self
+    }
+
+    /// Every element starts at a multiple of 16 bytes
+    pub fn push_array(&mut self, values: &[Uniform]) -> &mut Self {
+        for value in values {
+            self.align(16);
+            self.push(*value);
+        }
+        self.align(16);
+        self
+    }
+
+    /// The members of a struct, for arrays of structs call it for every element
+    pub fn push_struct<F: FnOnce(&mut Std140)>(&mut self, members: F) -> &mut Self {
+        self.align(16);
+        members(self);
+        self.align(16);
+        self
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+/// The data of uniform blocks, see `Program::set_uniform_block`
+pub struct UniformBuffer {
+    buffer: BufferBase,
+    size: usize
+}
+
+impl UniformBuffer {
+    pub fn new(ctx: &Context, data: &Std140) -> UniformBuffer {
+        UniformBuffer {
+            buffer: BufferBase::new(ctx, gl::UNIFORM_BUFFER, gl::DYNAMIC_DRAW, data.bytes()),
+            size: data.len()
+        }
+    }
+
+    pub fn update(&mut self, data: &Std140) {
+        self.buffer.upload(data.bytes());
+        self.size = data.len();
+    }
+
+    pub fn bind_base(&self, binding: u32) {
+        self.buffer.ctx.bind_buffer_base(gl::UNIFORM_BUFFER, binding, self.buffer.buffer);
+    }
+
+    pub fn set_label(&self, label: &str) {
+        self.buffer.set_label(label);
+    }
+
+    /// In bytes
+    pub fn size(&self) -> usize {
+        self.size
+    }
 }
@@ -0,0 +1,423 @@
+//! The state tinygl keeps about one GL context: the backend, what is bound, the objects that
+//! are alive and what got drawn this frame. Every tinygl object keeps a handle to the context
+//! it was created with, the handles are cheap to clone but can't leave the thread of the context.
+//!
+//! With debug checks on, misuse that GL would silently accept gets reported as a warning.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use gl::types::*;
+
+use super::backend::{Backend, Gl};
+
+const NUM_TEXTURE_UNITS: usize = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Resource {
+    Buffer,
+    Texture,
+    Sampler,
+    Query,
+    FrameBuffer,
+    RenderBuffer,
+    Program,
+    VertexArray,
+}
+
+impl Resource {
+    fn name(&self) -> &'static str {
+        match self {
+            Resource::Buffer => "buffer",
+            Resource::Texture => "texture",
+            Resource::Sampler => "sampler",
+            Resource::Query => "query",
+            Resource::FrameBuffer => "framebuffer",
+            Resource::RenderBuffer => "renderbuffer",
+            Resource::Program => "program",
+            Resource::VertexArray => "vertex array",
+        }
+    }
+}
+
+/// What happened between two `end_frame`s
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FrameSummary {
+    pub program_binds: usize,
+    pub texture_binds: usize,
+    pub buffer_binds: usize,
+    pub framebuffer_binds: usize,
+    pub draws: usize,
+    /// over all draws, times the instances
+    pub vertices: usize,
+}
+
+impl std::fmt::Display for FrameSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} draws, {} vertices, binds: {} programs, {} textures, {} buffers, {} framebuffers",
+               self.draws, self.vertices, self.program_binds, self.texture_binds, self.buffer_binds, self.framebuffer_binds)
+    }
+}
+
+struct State {
+    backend: Box<dyn Backend>,
+    debug: bool,
+    print_shader_errors: bool,
+
+    next_program: usize,
+    // the id of the bound Program
+    program: Option<usize>,
+    cleared_vertex_bindings: bool,
+    vertex_array: GLuint,
+    texture_unit: usize,
+    // target and texture per unit
+    textures: Vec<(GLenum, GLuint)>,
+    samplers: Vec<GLuint>,
+
+    // the label of every live object
+    resources: HashMap<(Resource, GLuint), String>,
+    warnings: Vec<String>,
+    warned: HashSet<String>,
+
+    frame: FrameSummary,
+    last_frame: FrameSummary,
+}
+
+#[derive(Clone)]
+pub struct Context {
+    state: Rc<RefCell<State>>,
+}
+
+impl Context {
+    /// A context for the GL context that is current, the gl functions have to be loaded
+    pub fn new() -> Self {
+        Self::with_backend(Box::new(Gl))
+    }
+
+    pub fn with_backend(backend: Box<dyn Backend>) -> Self {
+        Context {
+            state: Rc::new(RefCell::new(State {
+                backend,
+                debug: cfg!(debug_assertions),
+                print_shader_errors: true,
+                next_program: 0,
+                program: None,
+                cleared_vertex_bindings: true,
+                vertex_array: 0,
+                texture_unit: 0,
+                textures: vec!((0, 0); NUM_TEXTURE_UNITS),
+                samplers: vec!(0; NUM_TEXTURE_UNITS),
+                resources: HashMap::new(),
+                warnings: Vec::new(),
+                warned: HashSet::new(),
+                frame: FrameSummary::default(),
+                last_frame: FrameSummary::default(),
+            }))
+        }
+    }
+
+    /// Runs `f` with the backend, which must not call back into the context
+    pub fn backend<R, F: FnOnce(&mut dyn Backend) -> R>(&self, f: F) -> R {
+        f(&mut *self.state.borrow_mut().backend)
+    }
+
+    /// Debug checks are on in debug builds
+    pub fn set_debug(&self, debug: bool) {
+        self.state.borrow_mut().debug = debug;
+    }
+
+    pub fn debug(&self) -> bool {
+        self.state.borrow().debug
+    }
+
+    pub fn set_print_compilation_errors(&self, print: bool) {
+        self.state.borrow_mut().print_shader_errors = print;
+    }
+
+    pub fn print_compilation_errors(&self) -> bool {
+        self.state.borrow().print_shader_errors
+    }
+
+    /// Kind, handle and label of every object that is alive
+    pub fn live_resources(&self) -> Vec<(Resource, GLuint, String)> {
+        let mut ret: Vec<_> = self.state.borrow().resources.iter()
+            .map(|(&(kind, handle), label)| (kind, handle, label.clone()))
+            .collect();
+        ret.sort_by_key(|&(kind, handle, _)| (kind.name(), handle));
+        ret
+    }
+
+    pub fn resource_counts(&self) -> HashMap<Resource, usize> {
+        let mut ret = HashMap::new();
+        for &(kind, _) in self.state.borrow().resources.keys() {
+            *ret.entry(kind).or_insert(0) += 1;
+        }
+        ret
+    }
+
+    /// Every warning of the debug checks so far, each one once
+    pub fn warnings(&self) -> Vec<String> {
+        self.state.borrow().warnings.clone()
+    }
+
+    /// Starts counting the next frame
+    pub fn end_frame(&self) -> FrameSummary {
+        let mut state = self.state.borrow_mut();
+        let frame = std::mem::take(&mut state.frame);
+        state.last_frame = frame.clone();
+        frame
+    }
+
+    /// The frame before the last `end_frame`
+    pub fn last_frame(&self) -> FrameSummary {
+        self.state.borrow().last_frame.clone()
+    }
+
+    pub(crate) fn register(&self, kind: Resource, handle: GLuint) {
+        if handle != 0 {
+            self.state.borrow_mut().resources.insert((kind, handle), String::new());
+        }
+    }
+
+    pub(crate) fn unregister(&self, kind: Resource, handle: GLuint) {
+        self.state.borrow_mut().resources.remove(&(kind, handle));
+    }
+
+    pub(crate) fn set_label(&self, kind: Resource, handle: GLuint, label: &str) {
+        if let Some(existing) = self.state.borrow_mut().resources.get_mut(&(kind, handle)) {
+            *existing = label.to_string();
+        }
+    }
+
+    fn describe(&self, kind: Resource, handle: GLuint) -> String {
+        match self.state.borrow().resources.get(&(kind, handle)) {
+            Some(label) if !label.is_empty() => format!("{} '{}'", kind.name(), label),
+            _ => format!("{} {}", kind.name(), handle),
+        }
+    }
+
+    /// Prints the warning and keeps it in `warnings`, unless it was reported before
+    pub fn warn(&self, warning: String) {
+        let mut state = self.state.borrow_mut();
+        if state.warned.insert(warning.clone()) {
+            println!("tinygl: {}", warning);
+            state.warnings.push(warning);
+        }
+    }
+
+    pub(crate) fn new_program_id(&self) -> usize {
+        let mut state = self.state.borrow_mut();
+        state.next_program += 1;
+        state.next_program
+    }
+
+    pub(crate) fn bind_program(&self, id: usize, program: GLuint) {
+        if self.debug() && !self.state.borrow().cleared_vertex_bindings {
+            let warning = format!("{} bound while the vertex attributes of the last one are still enabled", self.describe(Resource::Program, program));
+            self.warn(warning);
+        }
+
+        let mut state = self.state.borrow_mut();
+        state.program = Some(id);
+        state.frame.program_binds += 1;
+        state.backend.use_program(program);
+    }
+
+    /// Reports a program that gets used without being bound
+    pub(crate) fn check_program(&self, id: usize, program: Option<GLuint>) {
+        if self.debug() && self.state.borrow().program != Some(id) {
+            let name = program.map_or(String::from("an invalid program"), |program| self.describe(Resource::Program, program));
+            self.warn(format!("{} used without being bound", name));
+        }
+    }
+
+    pub(crate) fn set_vertex_bindings_cleared(&self, cleared: bool) {
+        self.state.borrow_mut().cleared_vertex_bindings = cleared;
+    }
+
+    pub(crate) fn bind_vertex_array(&self, vertex_array: GLuint) {
+        let mut state = self.state.borrow_mut();
+        state.vertex_array = vertex_array;
+        state.backend.bind_vertex_array(vertex_array);
+    }
+
+    pub(crate) fn delete_vertex_array(&self, vertex_array: GLuint) {
+        let mut state = self.state.borrow_mut();
+        // GL falls back to the default one
+        if state.vertex_array == vertex_array {
+            state.vertex_array = 0;
+        }
+        state.backend.delete_vertex_array(vertex_array);
+    }
+
+    /// Reports attributes set up outside of the default vertex array, which would change a mesh
+    pub(crate) fn check_vertex_array(&self) {
+        let vertex_array = self.state.borrow().vertex_array;
+        if self.debug() && vertex_array != 0 {
+            let warning = format!("vertex attributes set up while {} is bound", self.describe(Resource::VertexArray, vertex_array));
+            self.warn(warning);
+        }
+    }
+
+    pub(crate) fn active_texture(&self, unit: u32) {
+        let mut state = self.state.borrow_mut();
+        state.backend.active_texture(unit);
+        state.texture_unit = unit as usize;
+    }
+
+    pub(crate) fn bind_texture(&self, target: GLenum, texture: GLuint) {
+        let mut state = self.state.borrow_mut();
+        let unit = state.texture_unit;
+
+        // a texture of another target would stay bound to that unit
+        if unit < NUM_TEXTURE_UNITS {
+            let (bound, _) = state.textures[unit];
+            if bound != target && bound != 0 {
+                state.backend.bind_texture(bound, 0);
+            }
+            state.textures[unit] = (target, texture);
+        }
+
+        state.frame.texture_binds += 1;
+        state.backend.bind_texture(target, texture);
+    }
+
+    pub(crate) fn delete_texture(&self, texture: GLuint) {
+        let units: Vec<usize> = self.state.borrow().textures.iter().enumerate()
+            .filter(|&(_, &(_, bound))| bound == texture)
+            .map(|(unit, _)| unit)
+            .collect();
+
+        if self.debug() && !units.is_empty() {
+            let warning = format!("{} deleted while bound to texture unit {:?}", self.describe(Resource::Texture, texture), units);
+            self.warn(warning);
+        }
+
+        self.unregister(Resource::Texture, texture);
+        let mut state = self.state.borrow_mut();
+        // GL falls back to no texture for those units
+        for unit in units {
+            state.textures[unit] = (0, 0);
+        }
+        state.backend.delete_texture(texture);
+    }
+
+    /// 0 leaves filtering and wrapping to the texture again
+    pub(crate) fn bind_sampler(&self, unit: u32, sampler: GLuint) {
+        let mut state = self.state.borrow_mut();
+        if let Some(bound) = state.samplers.get_mut(unit as usize) {
+            if *bound == sampler {
+                return;
+            }
+            *bound = sampler;
+        }
+        state.backend.bind_sampler(unit, sampler);
+    }
+
+    pub(crate) fn delete_sampler(&self, sampler: GLuint) {
+        let units: Vec<usize> = self.state.borrow().samplers.iter().enumerate()
+            .filter(|&(_, &bound)| bound == sampler)
+            .map(|(unit, _)| unit)
+            .collect();
+
+        if self.debug() && !units.is_empty() {
+            let warning = format!("{} deleted while bound to texture unit {:?}", self.describe(Resource::Sampler, sampler), units);
+            self.warn(warning);
+        }
+
+        self.unregister(Resource::Sampler, sampler);
+        let mut state = self.state.borrow_mut();
+        for unit in units {
+            state.samplers[unit] = 0;
+        }
+        state.backend.delete_sampler(sampler);
+    }
+
+    pub(crate) fn bind_buffer(&self, target: GLenum, buffer: GLuint) {
+        let mut state = self.state.borrow_mut();
+        state.frame.buffer_binds += 1;
+        state.backend.bind_buffer(target, buffer);
+    }
+
+    pub(crate) fn bind_buffer_base(&self, target: GLenum, binding: GLuint, buffer: GLuint) {
+        let mut state = self.state.borrow_mut();
+        state.frame.buffer_binds += 1;
+        state.backend.bind_buffer_base(target, binding, buffer);
+    }
+
+    pub(crate) fn bind_framebuffer(&self, framebuffer: GLuint) {
+        let mut state = self.state.borrow_mut();
+        state.frame.framebuffer_binds += 1;
+        state.backend.bind_framebuffer(framebuffer);
+    }
+
+    fn count_draw(&self, count: GLsizei, instances: GLsizei) {
+        if self.debug() && self.state.borrow().program.is_none() {
+            self.warn(String::from("draw call without a bound program"));
+        }
+
+        let mut state = self.state.borrow_mut();
+        state.frame.draws += 1;
+        state.frame.vertices += count.max(0) as usize * instances.max(0) as usize;
+    }
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// the GL state the renderers set directly
+
+pub fn enable(ctx: &Context, cap: GLenum) {
+    ctx.backend(|b| b.enable(cap, true))
+}
+
+pub fn disable(ctx: &Context, cap: GLenum) {
+    ctx.backend(|b| b.enable(cap, false))
+}
+
+pub fn depth_func(ctx: &Context, func: GLenum) {
+    ctx.backend(|b| b.depth_func(func))
+}
+
+pub fn clear_color(ctx: &Context, r: f32, g: f32, b: f32, a: f32) {
+    ctx.backend(|backend| backend.clear_color((r, g, b, a)))
+}
+
+pub fn clear(ctx: &Context, mask: GLbitfield) {
+    ctx.backend(|b| b.clear(mask))
+}
+
+pub fn viewport(ctx: &Context, x: GLint, y: GLint, w: GLsizei, h: GLsizei) {
+    ctx.backend(|b| b.viewport(x, y, w, h))
+}
+
+/// For textures tinygl doesn't own, like the ones of C++ libraries, so that it knows what's bound
+pub fn bind_texture(ctx: &Context, unit: u32, target: GLenum, texture: GLuint) {
+    ctx.active_texture(unit);
+    ctx.bind_texture(target, texture);
+}
+
+pub fn draw_arrays(ctx: &Context, mode: GLenum, first: GLint, count: GLsizei) {
+    ctx.count_draw(count, 1);
+    ctx.backend(|b| b.draw_arrays(mode, first, count, None))
+}
+
+pub fn draw_arrays_instanced(ctx: &Context, mode: GLenum, first: GLint, count: GLsizei, instances: GLsizei) {
+    ctx.count_draw(count, instances);
+    ctx.backend(|b| b.draw_arrays(mode, first, count, Some(instances)))
+}
+
+/// Draws with the bound index buffer, `offset` in bytes
+pub fn draw_elements(ctx: &Context, mode: GLenum, count: GLsizei, datatype: GLenum, offset: GLsizei) {
+    ctx.count_draw(count, 1);
+    ctx.backend(|b| b.draw_elements(mode, count, datatype, offset, None))
+}
+
+pub fn draw_elements_instanced(ctx: &Context, mode: GLenum, count: GLsizei, datatype: GLenum, offset: GLsizei, instances: GLsizei) {
+    ctx.count_draw(count, instances);
+    ctx.backend(|b| b.draw_elements(mode, count, datatype, offset, Some(instances)))
+}
@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use gl::types::*;
-use super::texture::Texture;
+use super::texture::{Texture, TextureKind};
+use super::context::{Context, Resource};
 
 struct FrameBufferOutput {
     size: (GLsizei, GLsizei),
@@ -12,8 +13,8 @@ struct FrameBufferOutput {
 }
 
 impl FrameBufferOutput {
-    fn create(size: (GLsizei, GLsizei), internal: GLenum, format: GLenum, datatype: GLenum) -> Texture {
-        let mut texture = Texture::new(gl::TEXTURE_2D);
+    fn create(ctx: &Context, size: (GLsizei, GLsizei), internal: GLenum, format: GLenum, datatype: GLenum) -> Texture {
+        let mut texture = Texture::new(ctx, gl::TEXTURE_2D);
         texture.bind_at(0);
         texture.filter(gl::TEXTURE_MIN_FILTER, gl::NEAREST as _);
         texture.filter(gl::TEXTURE_MAG_FILTER, gl::NEAREST as _);
@@ -21,20 +22,20 @@ impl FrameBufferOutput {
         texture
     }
 
-    fn new(size: (GLsizei, GLsizei), internal: GLenum, format: GLenum, datatype: GLenum, index: usize) -> Self {
+    fn new(ctx: &Context, size: (GLsizei, GLsizei), internal: GLenum, format: GLenum, datatype: GLenum, index: usize) -> Self {
         FrameBufferOutput {
             size,
             internal,
             format,
             datatype,
-            texture: Some(Self::create(size, internal, format, datatype)),
+            texture: Some(Self::create(ctx, size, internal, format, datatype)),
             index
         }
     }
 
-    fn take(&mut self) -> Texture {
+    fn take(&mut self, ctx: &Context) -> Texture {
         let ret = self.texture.take();
-        self.texture = Some(Self::create(self.size, self.internal, self.format, self.datatype));
+        self.texture = Some(Self::create(ctx, self.size, self.internal, self.format, self.datatype));
         ret.unwrap()
     }
 }
@@ -44,26 +45,32 @@ enum DepthAttachment {
     None,
     RenderBuffer ( GLuint ),
     Texture ( super::texture::Texture ),
-    ExternalTexture ( GLuint ),
+    // owned by whoever attached it
+    ExternalTexture,
 }
 
 pub struct FrameBufferObject {
+    ctx: Context,
     size: (GLsizei, GLsizei),
     fbo: GLuint,
     depth: DepthAttachment,
-    textures: HashMap<String, FrameBufferOutput>
+    textures: HashMap<String, FrameBufferOutput>,
+    // color attachments the framebuffer doesn't own
+    attached: usize
 }
 
 impl FrameBufferObject {
-    pub fn new(size: (GLsizei, GLsizei)) -> Self {
-        let mut fbo = 0;
-        unsafe { gl::GenFramebuffers(1, &mut fbo); }
+    pub fn new(ctx: &Context, size: (GLsizei, GLsizei)) -> Self {
+        let fbo = ctx.backend(|b| b.gen_framebuffer());
+        ctx.register(Resource::FrameBuffer, fbo);
 
         FrameBufferObject {
+            ctx: ctx.clone(),
             size,
             fbo,
             depth: DepthAttachment::None,
-            textures: HashMap::new()
+            textures: HashMap::new(),
+            attached: 0
         }
     }
 
@@ -71,54 +78,92 @@ impl FrameBufferObject {
         (self.size.0 as u32, self.size.1 as u32)
     }
 
+    /// Labels the framebuffer and its textures, those as "label.name"
+    pub fn set_label(&self, label: &str) {
+        self.ctx.set_label(Resource::FrameBuffer, self.fbo, label);
+        for (name, output) in self.textures.iter() {
+            if let Some(texture) = output.texture.as_ref() {
+                texture.set_label(&format!("{}.{}", label, name));
+            }
+        }
+        match self.depth {
+            DepthAttachment::RenderBuffer(rb) => self.ctx.set_label(Resource::RenderBuffer, rb, &format!("{}.depth", label)),
+            DepthAttachment::Texture(ref texture) => texture.set_label(&format!("{}.depth", label)),
+            _ => {}
+        }
+    }
+
     pub fn add(&mut self, name: &str, internal: GLenum, format: GLenum, datatype: GLenum) {
         if self.textures.contains_key(name) {
             println!("Texture {} already attached", name);
             return;
         }
 
-        let index = self.textures.len() as _;
-        let texture = FrameBufferOutput::new(self.size, internal, format, datatype, index);
+        let index = self.textures.len() + self.attached;
+        let texture = FrameBufferOutput::new(&self.ctx, self.size, internal, format, datatype, index);
 
-        unsafe {
-            gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
-            gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0 + index as u32, gl::TEXTURE_2D, texture.texture.as_ref().unwrap().handle(), 0);
-            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
-        }
+        self.ctx.backend(|b| {
+            b.bind_framebuffer(self.fbo);
+            b.framebuffer_texture(gl::COLOR_ATTACHMENT0 + index as u32, texture.texture.as_ref().unwrap().handle(), None);
+            b.bind_framebuffer(0);
+        });
         self.textures.insert(name.to_string(), texture);
     }
 
+    /// Renders into the next color attachment, a texture of the framebuffer's size that has to
+    /// outlive the framebuffer
+    pub fn attach(&mut self, texture: &Texture) -> Result<(), String> {
+        let descriptor = texture.descriptor().ok_or_else(|| String::from("texture wasn't created with a descriptor"))?;
+        if descriptor.kind != TextureKind::Flat || descriptor.format.is_depth() {
+            return Err(format!("can't attach a {:?} texture of format {:?} as color", descriptor.kind, descriptor.format));
+        }
+        if descriptor.size != self.size {
+            return Err(format!("texture of size {:?} doesn't fit a framebuffer of size {:?}", descriptor.size, self.size));
+        }
+
+        let index = (self.textures.len() + self.attached) as u32;
+        self.ctx.backend(|b| {
+            b.bind_framebuffer(self.fbo);
+            b.framebuffer_texture(gl::COLOR_ATTACHMENT0 + index, texture.handle(), None);
+            b.bind_framebuffer(0);
+        });
+        self.attached += 1;
+        Ok(())
+    }
+
     pub fn add_depth_renderbuffer(&mut self) {
         if let DepthAttachment::None = self.depth {
-            unsafe {
-                let mut depth = 0;
-                gl::GenRenderbuffers(1, &mut depth);
-                gl::BindRenderbuffer(gl::RENDERBUFFER, depth);
-                gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH_COMPONENT24, self.size.0, self.size.1);
-                gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
-                gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::RENDERBUFFER, depth);
-                gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
-
-                self.depth = DepthAttachment::RenderBuffer(depth);
-            }
+            let depth = self.ctx.backend(|b| {
+                let depth = b.gen_renderbuffer();
+                b.renderbuffer_storage(depth, gl::DEPTH_COMPONENT24, self.size);
+                b.bind_framebuffer(self.fbo);
+                b.framebuffer_renderbuffer(gl::DEPTH_ATTACHMENT, depth);
+                b.bind_framebuffer(0);
+                depth
+            });
+            self.ctx.register(Resource::RenderBuffer, depth);
+
+            self.depth = DepthAttachment::RenderBuffer(depth);
         }
     }
 
     pub fn add_depth_texture(&mut self) {
         if let DepthAttachment::None = self.depth {
-            let mut texture = Texture::new(gl::TEXTURE_2D);
+            let mut texture = Texture::new(&self.ctx, gl::TEXTURE_2D);
             texture.bind_at(0);
             texture.filter(gl::TEXTURE_MIN_FILTER, gl::LINEAR as _);
             texture.filter(gl::TEXTURE_MAG_FILTER, gl::LINEAR as _);
 
-            unsafe {
-                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_COMPARE_MODE, gl::COMPARE_REF_TO_TEXTURE as _);
-                gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_COMPARE_FUNC, gl::LEQUAL as _);
-                texture.teximage(self.size, gl::DEPTH_COMPONENT24, gl::DEPTH_COMPONENT, gl::UNSIGNED_INT, std::ptr::null());
-                gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
-                gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::TEXTURE_2D, texture.handle(), 0);
-                gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
-            }
+            self.ctx.backend(|b| {
+                b.tex_parameter(gl::TEXTURE_2D, gl::TEXTURE_COMPARE_MODE, gl::COMPARE_REF_TO_TEXTURE as _);
+                b.tex_parameter(gl::TEXTURE_2D, gl::TEXTURE_COMPARE_FUNC, gl::LEQUAL as _);
+            });
+            unsafe { texture.teximage(self.size, gl::DEPTH_COMPONENT24, gl::DEPTH_COMPONENT, gl::UNSIGNED_INT, std::ptr::null()); }
+            self.ctx.backend(|b| {
+                b.bind_framebuffer(self.fbo);
+                b.framebuffer_texture(gl::DEPTH_ATTACHMENT, texture.handle(), None);
+                b.bind_framebuffer(0);
+            });
 
             self.depth = DepthAttachment::Texture(texture);
         }
@@ -126,20 +171,40 @@ impl FrameBufferObject {
 
     pub fn attach_depth_texture(&mut self, texture: &Texture, layer: Option<u32>) {
         if let DepthAttachment::None = self.depth {
-            unsafe {
-                gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
-                if let Some(layer) = layer {
-                    gl::FramebufferTextureLayer(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, texture.handle(), 0, layer as _);
-                } else {
-                    gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::TEXTURE_2D, texture.handle(), 0);
-                }
-                gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
-            }
+            self.ctx.backend(|b| {
+                b.bind_framebuffer(self.fbo);
+                b.framebuffer_texture(gl::DEPTH_ATTACHMENT, texture.handle(), layer.map(|layer| layer as _));
+                b.bind_framebuffer(0);
+            });
 
-            self.depth = DepthAttachment::ExternalTexture(texture.handle());
+            self.depth = DepthAttachment::ExternalTexture;
         }
     }
 
+    /// Renders depth into a layer of a depth array texture of the framebuffer's size, which
+    /// has to outlive the framebuffer
+    pub fn attach_depth_layer(&mut self, texture: &Texture, layer: GLsizei) -> Result<(), String> {
+        let descriptor = texture.descriptor().ok_or_else(|| String::from("texture wasn't created with a descriptor"))?;
+        if !descriptor.format.is_depth() {
+            return Err(format!("can't attach {:?} as depth", descriptor.format));
+        }
+        if !matches!(descriptor.kind, TextureKind::Array(_)) {
+            return Err(format!("can't attach a layer of a {:?} texture", descriptor.kind));
+        }
+        if layer < 0 || layer >= descriptor.layers() {
+            return Err(format!("texture has no layer {}", layer));
+        }
+        if descriptor.size != self.size {
+            return Err(format!("texture of size {:?} doesn't fit a framebuffer of size {:?}", descriptor.size, self.size));
+        }
+        if !matches!(self.depth, DepthAttachment::None) {
+            return Err(String::from("framebuffer has a depth attachment already"));
+        }
+
+        self.attach_depth_texture(texture, Some(layer as _));
+        Ok(())
+    }
+
     pub fn depth_texture(&self) -> Option<&Texture> {
         match &self.depth {
             DepthAttachment::Texture(ref texture) => Some(texture),
@@ -156,28 +221,30 @@ impl FrameBufferObject {
 
     pub fn bind(&self) {
         let mut draw_buffers: Vec<GLenum> = Vec::new();
-        for i in 0..self.textures.len() {
+        for i in 0..self.textures.len() + self.attached {
             draw_buffers.push((gl::COLOR_ATTACHMENT0 + i as u32) as _);
         }
-        unsafe {
-            gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
-            gl::DrawBuffers(draw_buffers.len() as _, draw_buffers.as_ptr());
-            gl::Viewport(0, 0, self.size.0 as _, self.size.1 as _);
-        }
+        self.ctx.bind_framebuffer(self.fbo);
+        self.ctx.backend(|b| {
+            b.draw_buffers(&draw_buffers);
+            b.viewport(0, 0, self.size.0 as _, self.size.1 as _);
+        });
     }
 
-    pub fn unbind() {
-        unsafe { gl::BindFramebuffer(gl::FRAMEBUFFER, 0) }
+    pub fn unbind(ctx: &Context) {
+        ctx.bind_framebuffer(0);
     }
 
     pub unsafe fn read(&self, name: &str, dst: *mut std::ffi::c_void) {
         let texture = self.textures.get(name).expect("No such texture found");
 
-        gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
-        gl::Viewport(0, 0, self.size.0 as _, self.size.1 as _);
-        gl::ReadBuffer((gl::COLOR_ATTACHMENT0 + texture.index as u32) as _);
-        gl::ReadPixels(0, 0, self.size.0 as _, self.size.1 as _, texture.format, texture.datatype, dst);
-        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        self.ctx.backend(|b| {
+            b.bind_framebuffer(self.fbo);
+            b.viewport(0, 0, self.size.0 as _, self.size.1 as _);
+            b.read_buffer((gl::COLOR_ATTACHMENT0 + texture.index as u32) as _);
+            b.read_pixels(self.size, texture.format, texture.datatype, dst);
+            b.bind_framebuffer(0);
+        });
     }
 
     pub fn texture(&self, name: &str) -> Option<&Texture> {
@@ -189,14 +256,15 @@ impl FrameBufferObject {
     }
 
     pub fn take(&mut self, name: &str) -> Option<Texture> {
-        let ret = self.textures.get_mut(name).map(|tex| tex.take());
+        let ctx = &self.ctx;
+        let ret = self.textures.get_mut(name).map(|tex| tex.take(ctx));
         if let Some(new_tex) = self.textures.get(name).as_ref() {
             let glid = new_tex.texture.as_ref().unwrap().handle();
-            unsafe {
-                gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
-                gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0 + new_tex.index as u32, gl::TEXTURE_2D, glid, 0);
-                gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
-            }
+            self.ctx.backend(|b| {
+                b.bind_framebuffer(self.fbo);
+                b.framebuffer_texture(gl::COLOR_ATTACHMENT0 + new_tex.index as u32, glid, None);
+                b.bind_framebuffer(0);
+            });
         }
         ret
     }
@@ -204,11 +272,15 @@ impl FrameBufferObject {
 
 impl Drop for FrameBufferObject {
     fn drop(&mut self) {
-        unsafe {
-            gl::DeleteFramebuffers(1, &mut self.fbo);
+        self.ctx.unregister(Resource::FrameBuffer, self.fbo);
+        if let DepthAttachment::RenderBuffer(rb) = self.depth {
+            self.ctx.unregister(Resource::RenderBuffer, rb);
+        }
+        self.ctx.backend(|b| {
+            b.delete_framebuffer(self.fbo);
             if let DepthAttachment::RenderBuffer(rb) = self.depth {
-                gl::DeleteRenderbuffers(1, &rb);
+                b.delete_renderbuffer(rb);
             }
-        }
+        });
     }
 }
@@ -0,0 +1,401 @@
+//! Passes that declare the textures they read and write. A `RenderGraph` runs them in an order
+//! where every texture is written before it is read, renders them into framebuffers it
+//! creates, shares textures between targets that aren't needed at the same time and binds the
+//! inputs of a pass to texture units. Layered passes render into one layer of array textures at
+//! a time.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use gl::types::*;
+use super::context::{self, Context};
+use super::fbo::FrameBufferObject;
+use super::program::Program;
+use super::texture::{Texture, TextureDescriptor, TextureFormat};
+
+/// A pass of a `RenderGraph`, which draws itself between `RenderGraph::begin` and `end`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pass {
+    name: String,
+    size: (GLsizei, GLsizei),
+    screen: bool,
+    layers: GLsizei,
+    inputs: Vec<String>,
+    outputs: Vec<(String, TextureFormat)>,
+}
+
+impl Pass {
+    /// Renders into textures of the given size
+    pub fn new(name: &str, size: (GLsizei, GLsizei)) -> Self {
+        Pass {
+            name: name.to_string(),
+            size,
+            screen: false,
+            layers: 0,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+        }
+    }
+
+    /// Renders into the default framebuffer, it can't have outputs
+    pub fn screen(name: &str, size: (GLsizei, GLsizei)) -> Self {
+        Pass { screen: true, ..Self::new(name, size) }
+    }
+
+    /// Renders into one of the layers of depth array textures, see `RenderGraph::begin_layer`
+    pub fn layered(name: &str, size: (GLsizei, GLsizei), layers: GLsizei) -> Self {
+        Pass { layers, ..Self::new(name, size) }
+    }
+
+    /// Gets bound to the next texture unit, starting with 0
+    pub fn input(mut self, target: &str) -> Self {
+        self.inputs.push(target.to_string());
+        self
+    }
+
+    /// The next color attachment, or the depth attachment for depth formats
+    pub fn output(mut self, target: &str, format: TextureFormat) -> Self {
+        self.outputs.push((target.to_string(), format));
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn size(&self) -> (GLsizei, GLsizei) {
+        self.size
+    }
+
+    /// 0 unless the pass is layered
+    pub fn layers(&self) -> GLsizei {
+        self.layers
+    }
+
+    pub fn inputs(&self) -> &[String] {
+        &self.inputs
+    }
+
+    pub fn outputs(&self) -> &[(String, TextureFormat)] {
+        &self.outputs
+    }
+}
+
+struct Target {
+    format: TextureFormat,
+    size: (GLsizei, GLsizei),
+    layers: GLsizei,
+    // the positions in the order of the pass that writes it and the last that reads it
+    first: usize,
+    last: usize,
+    texture: usize,
+}
+
+pub struct RenderGraph {
+    ctx: Context,
+    passes: Vec<Pass>,
+    kept: HashSet<String>,
+
+    // what compile sets up, order has indices into passes
+    compiled: bool,
+    order: Vec<usize>,
+    targets: HashMap<String, Target>,
+    textures: Vec<Texture>,
+    // by pass and layer
+    framebuffers: HashMap<(usize, GLsizei), FrameBufferObject>,
+}
+
+/// The pass between `RenderGraph::begin` and `end`
+pub struct PassContext<'a> {
+    graph: &'a RenderGraph,
+    pass: &'a Pass,
+}
+
+impl<'a> PassContext<'a> {
+    pub fn name(&self) -> &str {
+        &self.pass.name
+    }
+
+    pub fn size(&self) -> (GLsizei, GLsizei) {
+        self.pass.size
+    }
+
+    /// The texture unit the input is bound to
+    pub fn unit(&self, input: &str) -> Option<u32> {
+        self.pass.inputs.iter().position(|name| name == input).map(|unit| unit as u32)
+    }
+
+    /// The first unit that's free for other textures
+    pub fn units(&self) -> u32 {
+        self.pass.inputs.len() as u32
+    }
+
+    pub fn texture(&self, input: &str) -> Option<&'a Texture> {
+        self.unit(input).and_then(|_| self.graph.texture(input))
+    }
+
+    /// Points the sampler at the unit of the input, the program has to be bound
+    pub fn set_sampler(&self, program: &Program, uniform: &str, input: &str) -> Result<(), String> {
+        let unit = self.unit(input).ok_or_else(|| format!("pass '{}' doesn't read '{}'", self.pass.name, input))?;
+        let texture = self.graph.texture(input).ok_or_else(|| format!("No such target: {}", input))?;
+        program.set_sampler(uniform, texture, unit)
+    }
+}
+
+impl RenderGraph {
+    pub fn new(ctx: &Context) -> Self {
+        RenderGraph {
+            ctx: ctx.clone(),
+            passes: Vec::new(),
+            kept: HashSet::new(),
+            compiled: false,
+            order: Vec::new(),
+            targets: HashMap::new(),
+            textures: Vec::new(),
+            framebuffers: HashMap::new(),
+        }
+    }
+
+    /// Passes run in the order they are added, unless they read what a later one writes
+    pub fn add(&mut self, pass: Pass) -> &mut Self {
+        self.passes.push(pass);
+        self.compiled = false;
+        self
+    }
+
+    /// Keeps the texture of a target to itself, for reading it after the graph ran
+    pub fn keep(&mut self, target: &str) -> &mut Self {
+        self.kept.insert(target.to_string());
+        self.compiled = false;
+        self
+    }
+
+    pub fn pass(&self, name: &str) -> Option<&Pass> {
+        self.passes.iter().find(|pass| pass.name == name)
+    }
+
+    /// The names of the passes in the order they have to run, empty before `compile`
+    pub fn order(&self) -> Vec<&str> {
+        self.order.iter().map(|&pass| self.passes[pass].name.as_str()).collect()
+    }
+
+    /// Of a target, after `compile`. Targets that aren't kept share their texture with
+    /// others, so they are only valid from their pass up to the last pass that reads them.
+    pub fn texture(&self, target: &str) -> Option<&Texture> {
+        self.targets.get(target).map(|target| &self.textures[target.texture])
+    }
+
+    fn writers(&self) -> Result<HashMap<&str, usize>, String> {
+        let mut writers = HashMap::new();
+        for (index, pass) in self.passes.iter().enumerate() {
+            if pass.screen && !pass.outputs.is_empty() {
+                return Err(format!("pass '{}' renders to the screen, it can't have outputs", pass.name));
+            }
+            if pass.layers < 0 || (pass.layers > 0 && (pass.screen || pass.outputs.iter().any(|(_, format)| !format.is_depth()))) {
+                return Err(format!("pass '{}' can only render layers of depth targets", pass.name));
+            }
+            if pass.outputs.iter().filter(|(_, format)| format.is_depth()).count() > 1 {
+                return Err(format!("pass '{}' has more than one depth output", pass.name));
+            }
+            for (target, format) in &pass.outputs {
+                if format.is_compressed() {
+                    return Err(format!("pass '{}' can't render into {:?}", pass.name, format));
+                }
+                if let Some(other) = writers.insert(target.as_str(), index) {
+                    return Err(format!("target '{}' is written by '{}' and '{}'", target, self.passes[other].name, pass.name));
+                }
+            }
+        }
+        Ok(writers)
+    }
+
+    /// Every pass comes after the passes that write its inputs, otherwise in the order they
+    /// were added
+    fn sort(&self, writers: &HashMap<&str, usize>) -> Result<Vec<usize>, String> {
+        let mut dependencies = Vec::new();
+        for (index, pass) in self.passes.iter().enumerate() {
+            let mut before = HashSet::new();
+            for input in &pass.inputs {
+                match writers.get(input.as_str()) {
+                    None => return Err(format!("pass '{}' reads '{}', which no pass writes", pass.name, input)),
+                    Some(&writer) if writer == index => {
+                        return Err(format!("pass '{}' reads '{}', which it writes", pass.name, input));
+                    }
+                    Some(&writer) => { before.insert(writer); }
+                }
+            }
+            dependencies.push(before);
+        }
+
+        let mut order: Vec<usize> = Vec::new();
+        while order.len() < self.passes.len() {
+            let next = (0..self.passes.len())
+                .find(|pass| !order.contains(pass) && dependencies[*pass].iter().all(|before| order.contains(before)));
+            match next {
+                Some(pass) => order.push(pass),
+                None => {
+                    let cycle: Vec<&str> = (0..self.passes.len())
+                        .filter(|pass| !order.contains(pass))
+                        .map(|pass| self.passes[pass].name.as_str())
+                        .collect();
+                    return Err(format!("passes {:?} depend on each other", cycle));
+                }
+            }
+        }
+        Ok(order)
+    }
+
+    /// Resolves the order and creates the textures and framebuffers, which fails for inputs
+    /// no pass writes, targets written twice and passes that depend on each other
+    pub fn compile(&mut self) -> Result<(), String> {
+        let (order, mut targets) = {
+            let writers = self.writers()?;
+            let order = self.sort(&writers)?;
+
+            let mut targets = HashMap::new();
+            for (position, &index) in order.iter().enumerate() {
+                let pass = &self.passes[index];
+                for (name, format) in &pass.outputs {
+                    // kept and unread targets live until the end
+                    let last = if self.kept.contains(name) { order.len() } else { position };
+                    targets.insert(name.clone(), Target { format: *format, size: pass.size, layers: pass.layers, first: position, last, texture: 0 });
+                }
+            }
+            for (position, &index) in order.iter().enumerate() {
+                for input in &self.passes[index].inputs {
+                    let target = targets.get_mut(input).unwrap();
+                    target.last = target.last.max(position);
+                }
+            }
+            for (name, target) in targets.iter_mut() {
+                if target.last == target.first && !self.kept.contains(name) {
+                    target.last = order.len();
+                }
+            }
+            (order, targets)
+        };
+
+        // targets take the texture of one that was last read before they get written, in
+        // the order they get written so the result doesn't depend on the hash map
+        let mut names: Vec<String> = targets.keys().cloned().collect();
+        names.sort_by_key(|name| (targets[name].first, name.clone()));
+        let mut textures: Vec<(Texture, usize)> = Vec::new();
+        for name in &names {
+            let target = targets.get_mut(name).unwrap();
+            let descriptor = match target.layers {
+                0 => TextureDescriptor::flat(target.format, target.size),
+                layers => TextureDescriptor::array(target.format, target.size, layers),
+            };
+            let free = if self.kept.contains(name) { None } else {
+                textures.iter().position(|(texture, last)| *last < target.first && texture.descriptor() == Some(&descriptor))
+            };
+            target.texture = match free {
+                Some(free) => free,
+                None => {
+                    let mut texture = Texture::with_descriptor(&self.ctx, descriptor)?;
+                    texture.filter(gl::TEXTURE_MIN_FILTER, gl::NEAREST);
+                    texture.filter(gl::TEXTURE_MAG_FILTER, gl::NEAREST);
+                    textures.push((texture, 0));
+                    textures.len() - 1
+                }
+            };
+            // kept ones can't be shared later on
+            textures[target.texture].1 = if self.kept.contains(name) { usize::MAX } else { target.last };
+        }
+        for name in &names {
+            textures[targets[name].texture].0.set_label(name);
+        }
+
+        let mut framebuffers = HashMap::new();
+        for (index, pass) in self.passes.iter().enumerate().filter(|(_, pass)| !pass.screen) {
+            for layer in 0..pass.layers.max(1) {
+                let mut fbo = FrameBufferObject::new(&self.ctx, pass.size);
+                for (name, format) in &pass.outputs {
+                    let texture = &textures[targets[name].texture].0;
+                    if pass.layers > 0 {
+                        fbo.attach_depth_layer(texture, layer)?;
+                    } else if format.is_depth() {
+                        fbo.attach_depth_texture(texture, None);
+                    } else {
+                        fbo.attach(texture)?;
+                    }
+                }
+                fbo.set_label(&pass.name);
+                framebuffers.insert((index, layer), fbo);
+            }
+        }
+
+        // the old framebuffers go before the textures they render into
+        self.framebuffers = framebuffers;
+        self.textures = textures.into_iter().map(|(texture, _)| texture).collect();
+        for target in targets.values_mut() {
+            target.last = target.last.min(order.len() - 1);
+        }
+        self.targets = targets;
+        self.order = order;
+        self.compiled = true;
+        Ok(())
+    }
+
+    /// Binds the framebuffer of the pass and its inputs to their units. Passes have to begin
+    /// in the order of `order`.
+    pub fn begin(&self, name: &str) -> Result<PassContext<'_>, String> {
+        self.begin_layer(name, 0)
+    }
+
+    /// Like `begin`, but renders into the given layer of a layered pass
+    pub fn begin_layer(&self, name: &str, layer: GLsizei) -> Result<PassContext<'_>, String> {
+        if !self.compiled {
+            return Err(String::from("render graph has to be compiled first"));
+        }
+        let (index, pass) = self.passes.iter().enumerate()
+            .find(|(_, pass)| pass.name == name)
+            .ok_or_else(|| format!("No such pass: {}", name))?;
+        if layer < 0 || layer >= pass.layers.max(1) {
+            return Err(format!("pass '{}' has no layer {}", name, layer));
+        }
+
+        match self.framebuffers.get(&(index, layer)) {
+            Some(fbo) => fbo.bind(),
+            None => {
+                FrameBufferObject::unbind(&self.ctx);
+                context::viewport(&self.ctx, 0, 0, pass.size.0, pass.size.1);
+            }
+        }
+        for (unit, input) in pass.inputs.iter().enumerate() {
+            self.textures[self.targets[input].texture].bind_at(unit as u32);
+        }
+
+        Ok(PassContext { graph: self, pass })
+    }
+
+    /// Back to the default framebuffer
+    pub fn end(&self) {
+        FrameBufferObject::unbind(&self.ctx);
+    }
+
+    /// The passes in order with their targets, for debugging
+    pub fn dump(&self) -> String {
+        let mut out = String::new();
+        if !self.compiled {
+            let _ = writeln!(out, "render graph with {} passes, not compiled", self.passes.len());
+            return out;
+        }
+
+        let _ = writeln!(out, "render graph with {} passes, {} targets in {} textures", self.passes.len(), self.targets.len(), self.textures.len());
+        for (position, &index) in self.order.iter().enumerate() {
+            let pass = &self.passes[index];
+            let into = if pass.screen { String::from(" to the screen") } else if pass.layers > 0 { format!(" in {} layers", pass.layers) } else { String::new() };
+            let _ = writeln!(out, "{}. {} {}x{}{}", position + 1, pass.name, pass.size.0, pass.size.1, into);
+            for (unit, input) in pass.inputs.iter().enumerate() {
+                let _ = writeln!(out, "   reads {} at unit {}", input, unit);
+            }
+            for (name, format) in &pass.outputs {
+                let target = &self.targets[name];
+                let lifetime = if self.kept.contains(name) { String::from("kept") } else { format!("until {}", self.passes[self.order[target.last]].name) };
+                let _ = writeln!(out, "   writes {} as {:?} into texture {} ({})", name, format, target.texture, lifetime);
+            }
+        }
+        out
+    }
+}
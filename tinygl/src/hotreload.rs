@@ -0,0 +1,104 @@
+//! Shader sources that are read from files when they exist, and read again when the files change,
+//! so shaders can be edited while the program runs.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::SystemTime;
+
+struct ShaderFile {
+    code: String,
+    modified: Option<SystemTime>,
+}
+
+#[derive(Default)]
+struct Registry {
+    dir: Option<PathBuf>,
+    files: HashMap<String, ShaderFile>,
+    changed: HashSet<String>,
+}
+
+/// Shader sources by file name. Without a directory to watch, or while a file can't be read, the
+/// embedded copy is used. Clones share the same files.
+#[derive(Clone, Default)]
+pub struct ShaderRegistry {
+    registry: Rc<RefCell<Registry>>,
+}
+
+fn read(dir: &Path, name: &str) -> Option<(SystemTime, String)> {
+    let path = dir.join(name);
+    let modified = fs::metadata(&path).and_then(|metadata| metadata.modified()).ok()?;
+    let code = fs::read_to_string(&path).ok()?;
+    Some((modified, code))
+}
+
+impl ShaderRegistry {
+    /// Only the embedded copies, for builds without the shader files around
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Files in `dir` replace the embedded copies, and get read again by `poll` when they change
+    pub fn watch<P: AsRef<Path>>(dir: P) -> Self {
+        let ret = Self::default();
+        ret.registry.borrow_mut().dir = Some(dir.as_ref().to_path_buf());
+        ret
+    }
+
+    pub fn add(&self, name: &str, embedded: &str) -> &Self {
+        let mut registry = self.registry.borrow_mut();
+        let (modified, code) = match registry.dir.as_ref().and_then(|dir| read(dir, name)) {
+            Some((modified, code)) => (Some(modified), code),
+            None => (None, embedded.to_string()),
+        };
+        registry.files.insert(name.to_string(), ShaderFile { code, modified });
+        self
+    }
+
+    /// The current code of a file, or an empty string for unknown ones
+    pub fn get(&self, name: &str) -> String {
+        match self.registry.borrow().files.get(name) {
+            Some(file) => file.code.clone(),
+            None => {
+                println!("tinygl: unknown shader file {}", name);
+                String::new()
+            }
+        }
+    }
+
+    /// Reads the files that were modified since the last poll, and returns whether any of them
+    /// changed. Files that can't be read keep their last code.
+    pub fn poll(&self) -> bool {
+        let mut registry = self.registry.borrow_mut();
+        let registry = &mut *registry;
+        registry.changed.clear();
+
+        let dir = match registry.dir {
+            Some(ref dir) => dir,
+            None => return false,
+        };
+        for (name, file) in &mut registry.files {
+            let modified = fs::metadata(dir.join(name)).and_then(|metadata| metadata.modified()).ok();
+            if modified.is_none() || modified == file.modified {
+                continue;
+            }
+            if let Some((modified, code)) = read(dir, name) {
+                file.modified = Some(modified);
+                if code != file.code {
+                    file.code = code;
+                    registry.changed.insert(name.clone());
+                }
+            }
+        }
+
+        !registry.changed.is_empty()
+    }
+
+    /// Whether any of the files changed in the last poll
+    pub fn changed(&self, names: &[&str]) -> bool {
+        let registry = self.registry.borrow();
+        names.iter().any(|name| registry.changed.contains(*name))
+    }
+}
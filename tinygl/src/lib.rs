@@ -1,18 +1,38 @@
 extern crate gl;
 extern crate cgmath;
 
+pub mod backend;
+pub mod context;
 mod buffer;
 mod texture;
 mod fbo;
 mod program;
+mod preprocessor;
+mod hotreload;
+mod mesh;
+mod graph;
+mod profiler;
 pub mod shapes;
 
-pub use buffer::IndexBuffer;
+pub use buffer::{IndexBuffer, IndexType};
 pub use buffer::VertexBuffer;
+pub use buffer::{UniformBuffer, Std140};
 
-pub use texture::Texture;
+pub use texture::{Texture, TextureDescriptor, TextureFormat, TextureKind, Sampler};
 
 pub use fbo::FrameBufferObject;
 
 pub use program::Uniform;
-pub use program::Program;
\ No newline at end of file
+pub use program::Program;
+pub use program::{Variable, UniformBlock, Uniforms, type_name};
+pub use program::ShaderError;
+
+pub use preprocessor::{Preprocessor, Source};
+pub use hotreload::ShaderRegistry;
+
+pub use mesh::{Format, VertexAttribute, VertexLayout, Mesh};
+pub use graph::{Pass, PassContext, RenderGraph};
+pub use profiler::{Profiler, ProfileFrame, ProfileScope};
+
+pub use context::{Context, Resource, FrameSummary};
+pub use context::{enable, disable, depth_func, clear_color, clear, viewport, bind_texture, draw_arrays, draw_arrays_instanced, draw_elements, draw_elements_instanced};
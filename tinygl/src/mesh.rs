@@ -0,0 +1,287 @@
+//! Vertex buffers that describe their vertices with a `VertexLayout`, so a `Mesh` can set them up
+//! in a vertex array object for any program with matching attributes.
+
+use std::cell::Cell;
+
+use gl::types::*;
+use super::buffer::{IndexBuffer, VertexBuffer};
+use super::context::{self, Context, Resource};
+use super::program::{self, Program};
+
+/// The format of a vertex attribute in a buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Float,
+    Vec2,
+    Vec3,
+    Vec4,
+    /// four bytes the shader gets as a vec4 from 0 to 1, like colors
+    Bytes4Norm,
+}
+
+impl Format {
+    pub fn components(&self) -> GLint {
+        match self {
+            Format::Float => 1,
+            Format::Vec2 => 2,
+            Format::Vec3 => 3,
+            Format::Vec4 | Format::Bytes4Norm => 4,
+        }
+    }
+
+    pub fn datatype(&self) -> GLenum {
+        match self {
+            Format::Bytes4Norm => gl::UNSIGNED_BYTE,
+            _ => gl::FLOAT,
+        }
+    }
+
+    pub fn normalized(&self) -> bool {
+        *self == Format::Bytes4Norm
+    }
+
+    /// In bytes
+    pub fn size(&self) -> usize {
+        match self {
+            Format::Bytes4Norm => 4,
+            _ => 4 * self.components() as usize,
+        }
+    }
+
+    /// GL fills in the components an attribute has beyond the ones of the format, but only
+    /// float attributes can be fed
+    fn fits(&self, datatype: GLenum) -> bool {
+        let components = match datatype {
+            gl::FLOAT => 1,
+            gl::FLOAT_VEC2 => 2,
+            gl::FLOAT_VEC3 => 3,
+            gl::FLOAT_VEC4 => 4,
+            _ => return false,
+        };
+        self.components() <= components
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VertexAttribute {
+    pub name: String,
+    pub format: Format,
+    /// In bytes from the start of the vertex
+    pub offset: usize,
+}
+
+/// The attributes of the vertices in a buffer, one after the other
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct VertexLayout {
+    attributes: Vec<VertexAttribute>,
+    stride: usize,
+    divisor: u32,
+}
+
+impl VertexLayout {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Comes after the attributes so far
+    pub fn attribute(mut self, name: &str, format: Format) -> Self {
+        self.attributes.push(VertexAttribute { name: name.to_string(), format, offset: self.stride });
+        self.stride += format.size();
+        self
+    }
+
+    /// Per instance instead of per vertex, the next one comes every `divisor` instances
+    pub fn instanced(mut self, divisor: u32) -> Self {
+        self.divisor = divisor;
+        self
+    }
+
+    pub fn attributes(&self) -> &[VertexAttribute] {
+        &self.attributes
+    }
+
+    pub fn attribute_named(&self, name: &str) -> Option<&VertexAttribute> {
+        self.attributes.iter().find(|attribute| attribute.name == name)
+    }
+
+    /// In bytes
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// 0 for per vertex attributes
+    pub fn divisor(&self) -> u32 {
+        self.divisor
+    }
+}
+
+/// Vertex buffers and maybe indices, drawn with the attributes of a program. The vertex array
+/// object gets set up again when the buffers or the program change.
+pub struct Mesh {
+    ctx: Context,
+    vertex_array: GLuint,
+    buffers: Vec<(VertexBuffer, VertexLayout)>,
+    indices: Option<IndexBuffer>,
+    // the id of the program the vertex array is set up for
+    program: Cell<Option<usize>>,
+}
+
+impl Mesh {
+    pub fn new(ctx: &Context) -> Self {
+        let vertex_array = ctx.backend(|b| b.gen_vertex_array());
+        ctx.register(Resource::VertexArray, vertex_array);
+
+        Mesh {
+            ctx: ctx.clone(),
+            vertex_array,
+            buffers: Vec::new(),
+            indices: None,
+            program: Cell::new(None),
+        }
+    }
+
+    /// Returns the slot of the buffer for `set_buffer`
+    pub fn add_buffer(&mut self, buffer: VertexBuffer, layout: VertexLayout) -> usize {
+        self.buffers.push((buffer, layout));
+        self.program.set(None);
+        self.buffers.len() - 1
+    }
+
+    /// Uploads floats, like the ones of util3d's `BufferBuilder`, as a buffer of the layout. Fails
+    /// if the layout has other formats or the data aren't whole vertices.
+    pub fn add_floats(&mut self, data: &[f32], layout: VertexLayout) -> Result<usize, String> {
+        if layout.attributes().iter().any(|attribute| attribute.format == Format::Bytes4Norm) {
+            return Err(String::from("floats can't fill a layout with bytes"));
+        }
+        if layout.stride() == 0 || (4 * data.len()) % layout.stride() != 0 {
+            return Err(format!("{} floats aren't whole vertices of {} bytes", data.len(), layout.stride()));
+        }
+        Ok(self.add_buffer(VertexBuffer::from(&self.ctx, data), layout))
+    }
+
+    /// Replaces the buffer in a slot, with the same layout
+    pub fn set_buffer(&mut self, slot: usize, buffer: VertexBuffer) {
+        self.buffers[slot].0 = buffer;
+        self.program.set(None);
+    }
+
+    /// For updating the buffer in a slot, which keeps the vertex array set up
+    pub fn buffer_mut(&mut self, slot: usize) -> &mut VertexBuffer {
+        &mut self.buffers[slot].0
+    }
+
+    /// Like `buffer_mut`, the vertex array mustn't be bound while updating them
+    pub fn indices_mut(&mut self) -> Option<&mut IndexBuffer> {
+        self.indices.as_mut()
+    }
+
+    pub fn set_indices(&mut self, indices: IndexBuffer) {
+        self.indices = Some(indices);
+        self.program.set(None);
+    }
+
+    pub fn set_label(&self, label: &str) {
+        self.ctx.set_label(Resource::VertexArray, self.vertex_array, label);
+    }
+
+    /// The count of the per vertex buffer with the fewest vertices
+    pub fn vertices(&self) -> usize {
+        self.buffers.iter()
+            .filter(|(_, layout)| layout.divisor() == 0 && layout.stride() > 0)
+            .map(|(buffer, layout)| buffer.size() / layout.stride())
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Like `vertices` for the per instance buffers, None without them
+    pub fn instances(&self) -> Option<usize> {
+        self.buffers.iter()
+            .filter(|(_, layout)| layout.divisor() > 0 && layout.stride() > 0)
+            .map(|(buffer, layout)| buffer.size() / layout.stride() * layout.divisor() as usize)
+            .min()
+    }
+
+    /// The first buffer with the attribute in its layout
+    fn find(&self, name: &str) -> Option<(&VertexBuffer, &VertexLayout, &VertexAttribute)> {
+        self.buffers.iter()
+            .filter_map(|(buffer, layout)| layout.attribute_named(name).map(|attribute| (buffer, layout, attribute)))
+            .next()
+    }
+
+    /// Every attribute of the program has to be in one of the layouts, in a format that fits
+    pub fn check(&self, program: &Program) -> Result<(), String> {
+        // built-ins like gl_VertexID have no location
+        for attribute in program.attributes().iter().filter(|attribute| attribute.location >= 0) {
+            match self.find(&attribute.name) {
+                None => return Err(format!("attribute '{}' is in none of the vertex layouts", attribute.name)),
+                Some((_, _, found)) if !found.format.fits(attribute.datatype) => {
+                    return Err(format!("attribute '{}' is a {}, which a {:?} doesn't fit", attribute.name, program::type_name(attribute.datatype), found.format));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn setup(&self, program: &Program) {
+        self.ctx.bind_vertex_array(self.vertex_array);
+        for attribute in program.attributes().iter().filter(|attribute| attribute.location >= 0) {
+            if let Some((buffer, layout, found)) = self.find(&attribute.name) {
+                let location = attribute.location as GLuint;
+                buffer.bind();
+                self.ctx.backend(|b| {
+                    b.enable_vertex_attrib(location, true);
+                    b.vertex_attrib_pointer(location, found.format.components(), found.format.datatype(), found.format.normalized(), layout.stride() as _, found.offset as _);
+                    b.vertex_attrib_divisor(location, layout.divisor());
+                });
+            }
+        }
+        VertexBuffer::release(&self.ctx);
+        if let Some(ref indices) = self.indices {
+            indices.bind();
+        }
+        self.program.set(Some(program.id()));
+    }
+
+    /// Binds the vertex array for drawing with `program`, which reports mismatches of the
+    /// attributes without binding anything. Needs a `release` before setting up attributes
+    /// without a mesh again.
+    pub fn bind(&self, program: &Program) -> Result<(), String> {
+        if self.program.get() == Some(program.id()) {
+            self.ctx.bind_vertex_array(self.vertex_array);
+            return Ok(());
+        }
+
+        self.check(program)?;
+        self.setup(program);
+        Ok(())
+    }
+
+    pub fn release(ctx: &Context) {
+        ctx.bind_vertex_array(0);
+    }
+
+    /// Draws all indices, or all vertices without them, for all instances if there are per
+    /// instance buffers. The program has to be bound.
+    pub fn draw(&self, program: &Program, mode: GLenum) -> Result<(), String> {
+        program.assert_bound();
+        self.bind(program)?;
+
+        match (&self.indices, self.instances()) {
+            (Some(indices), None) => context::draw_elements(&self.ctx, mode, indices.count() as _, indices.index_type(), 0),
+            (Some(indices), Some(instances)) => context::draw_elements_instanced(&self.ctx, mode, indices.count() as _, indices.index_type(), 0, instances as _),
+            (None, None) => context::draw_arrays(&self.ctx, mode, 0, self.vertices() as _),
+            (None, Some(instances)) => context::draw_arrays_instanced(&self.ctx, mode, 0, self.vertices() as _, instances as _),
+        }
+
+        Self::release(&self.ctx);
+        Ok(())
+    }
+}
+
+impl Drop for Mesh {
+    fn drop(&mut self) {
+        self.ctx.unregister(Resource::VertexArray, self.vertex_array);
+        self.ctx.delete_vertex_array(self.vertex_array);
+    }
+}
@@ -0,0 +1,116 @@
+//! Puts shader sources together from named parts, and remembers where every line came from so
+//! compile errors can point at the original file and line.
+
+use std::collections::HashMap;
+
+/// Shader code with the file and line of each of its lines
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Source {
+    code: String,
+    origins: Vec<(String, usize)>,
+}
+
+impl Source {
+    /// Code that is a file on its own
+    pub fn new(name: &str, code: &str) -> Self {
+        let mut ret = Source::default();
+        for (i, line) in code.lines().enumerate() {
+            ret.push_line(line, name, i + 1);
+        }
+        ret
+    }
+
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.origins.len()
+    }
+
+    /// File and line of a line of the code, all counting from 1
+    pub fn origin(&self, line: usize) -> Option<(&str, usize)> {
+        line.checked_sub(1)
+            .and_then(|i| self.origins.get(i))
+            .map(|(file, line)| (&file[..], *line))
+    }
+
+    fn push_line(&mut self, line: &str, file: &str, lineno: usize) {
+        self.code.push_str(line);
+        self.code.push('\n');
+        self.origins.push((file.to_string(), lineno));
+    }
+}
+
+/// Replaces `#include "name"` lines with the parts added under that name, and puts the defines
+/// in front. Parts can be whole shaders, their `#version` line is left out.
+#[derive(Debug, Clone, Default)]
+pub struct Preprocessor {
+    includes: HashMap<String, String>,
+    defines: Vec<(String, String)>,
+}
+
+impl Preprocessor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_include(&mut self, name: &str, code: &str) -> &mut Self {
+        self.includes.insert(name.to_string(), code.to_string());
+        self
+    }
+
+    /// Replaces an earlier define of the same name
+    pub fn define(&mut self, name: &str, value: &str) -> &mut Self {
+        self.defines.retain(|define| define.0 != name);
+        self.defines.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Unknown and recursive includes become `#error`s, so compiling fails at their line
+    pub fn process(&self, name: &str, code: &str) -> Source {
+        let mut ret = Source::default();
+        for (i, (define, value)) in self.defines.iter().enumerate() {
+            ret.push_line(&format!("#define {} {}", define, value), "<defines>", i + 1);
+        }
+        self.include(&mut ret, name, code, &mut vec!(name.to_string()));
+        ret
+    }
+
+    fn include(&self, out: &mut Source, name: &str, code: &str, stack: &mut Vec<String>) {
+        for (i, line) in code.lines().enumerate() {
+            // only the first line of the whole source can have one
+            if stack.len() > 1 && Self::is_version(line) {
+                continue;
+            }
+            let included = match Self::included(line) {
+                Some(included) => included,
+                None => {
+                    out.push_line(line, name, i + 1);
+                    continue;
+                }
+            };
+
+            if stack.iter().any(|file| file == included) {
+                out.push_line(&format!("#error include cycle {} -> {}", stack.join(" -> "), included), name, i + 1);
+            } else if let Some(code) = self.includes.get(included) {
+                stack.push(included.to_string());
+                self.include(out, included, code, stack);
+                stack.pop();
+            } else {
+                out.push_line(&format!("#error unknown include {}", included), name, i + 1);
+            }
+        }
+    }
+
+    fn is_version(line: &str) -> bool {
+        line.trim().strip_prefix('#').is_some_and(|rest| rest.trim_start().starts_with("version"))
+    }
+
+    /// The name in `#include "name"` or `#include <name>`
+    fn included(line: &str) -> Option<&str> {
+        let rest = line.trim().strip_prefix('#')?.trim_start().strip_prefix("include")?.trim();
+        rest.strip_prefix('"').and_then(|name| name.strip_suffix('"'))
+            .or_else(|| rest.strip_prefix('<').and_then(|name| name.strip_suffix('>')))
+    }
+}
@@ -0,0 +1,363 @@
+//! Timing of named scopes within frames. Scopes get GPU timestamps where there are timer
+//! queries and CPU times otherwise, which only tell how long it took to issue the commands.
+//! GPU times come in a few frames late, so only finished frames show up in `frames`. Frames
+//! during which the GPU timer went unreliable get dropped.
+
+use std::collections::VecDeque;
+use std::fs;
+use std::time::Instant;
+
+use gl::types::*;
+
+use super::context::{Context, Resource};
+
+// frames waiting for their queries, beyond that the oldest gets dropped
+const MAX_PENDING: usize = 8;
+
+/// A scope of a finished frame, in milliseconds since the profiler got created
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileScope {
+    pub name: String,
+    /// count of the scopes around it
+    pub depth: usize,
+    pub start: f64,
+    pub duration: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileFrame {
+    pub index: usize,
+    pub start: f64,
+    pub duration: f64,
+    /// whether the times are from the GPU
+    pub gpu: bool,
+    /// in the order they began
+    pub scopes: Vec<ProfileScope>,
+}
+
+impl ProfileFrame {
+    /// Over all scopes with that name
+    pub fn duration_of(&self, name: &str) -> f64 {
+        self.scopes.iter().filter(|scope| scope.name == name).map(|scope| scope.duration).sum()
+    }
+}
+
+// CPU times and the queries of a scope that isn't read yet
+struct Recorded {
+    name: String,
+    depth: usize,
+    start: f64,
+    end: f64,
+    queries: Option<(GLuint, GLuint)>,
+}
+
+impl Recorded {
+    fn close(&mut self, (end, query): (f64, GLuint)) {
+        self.end = end;
+        self.queries = self.queries.map(|(start, _)| (start, query));
+    }
+}
+
+struct PendingFrame {
+    index: usize,
+    scope: Recorded,
+    scopes: Vec<Recorded>,
+}
+
+impl PendingFrame {
+    fn queries(&self) -> Vec<GLuint> {
+        std::iter::once(&self.scope).chain(self.scopes.iter())
+            .filter_map(|scope| scope.queries)
+            .flat_map(|(start, end)| vec!(start, end))
+            .collect()
+    }
+}
+
+pub struct Profiler {
+    ctx: Context,
+    epoch: Instant,
+    supported: bool,
+    gpu: bool,
+    history: usize,
+    next_index: usize,
+
+    current: Option<PendingFrame>,
+    // the scopes that are open, None for the ones begun outside of a frame
+    open: Vec<Option<usize>>,
+    pending: VecDeque<PendingFrame>,
+    free_queries: Vec<GLuint>,
+    frames: VecDeque<ProfileFrame>,
+}
+
+impl Profiler {
+    /// Keeps the last `history` frames, uses timer queries if there are any
+    pub fn new(ctx: &Context, history: usize) -> Self {
+        let supported = ctx.backend(|b| b.timer_queries());
+        Profiler {
+            ctx: ctx.clone(),
+            epoch: Instant::now(),
+            supported,
+            gpu: supported,
+            history,
+            next_index: 0,
+            current: None,
+            open: Vec::new(),
+            pending: VecDeque::new(),
+            free_queries: Vec::new(),
+            frames: VecDeque::new(),
+        }
+    }
+
+    pub fn timer_queries(&self) -> bool {
+        self.supported
+    }
+
+    /// Falls back to CPU times from the next frame on, GPU times need timer queries
+    pub fn set_gpu(&mut self, gpu: bool) {
+        self.gpu = gpu && self.supported;
+    }
+
+    pub fn gpu(&self) -> bool {
+        self.gpu
+    }
+
+    fn now(&self) -> f64 {
+        let elapsed = self.epoch.elapsed();
+        elapsed.as_secs() as f64 * 1000.0 + elapsed.subsec_nanos() as f64 / 1000000.0
+    }
+
+    fn query(&mut self) -> GLuint {
+        let query = match self.free_queries.pop() {
+            Some(query) => query,
+            None => {
+                let query = self.ctx.backend(|b| b.gen_query());
+                self.ctx.register(Resource::Query, query);
+                query
+            }
+        };
+        self.ctx.backend(|b| b.query_timestamp(query));
+        query
+    }
+
+    // the CPU time and, for GPU times, a timestamp query
+    fn stamp(&mut self, gpu: bool) -> (f64, GLuint) {
+        let query = if gpu { self.query() } else { 0 };
+        (self.now(), query)
+    }
+
+    fn start(&mut self, name: &str, depth: usize, gpu: bool) -> Recorded {
+        let (start, query) = self.stamp(gpu);
+        let queries = if gpu { Some((query, 0)) } else { None };
+        Recorded { name: name.to_string(), depth, start, end: start, queries }
+    }
+
+    /// Ends the last frame, if it didn't get ended
+    pub fn begin_frame(&mut self) {
+        if self.current.is_some() {
+            self.end_frame();
+        }
+
+        let index = self.next_index;
+        self.next_index += 1;
+        let gpu = self.gpu;
+        let scope = self.start(&format!("frame {}", index), 0, gpu);
+        self.current = Some(PendingFrame { index, scope, scopes: Vec::new() });
+    }
+
+    pub fn begin(&mut self, name: &str) {
+        let (depth, gpu) = match self.current.as_ref() {
+            Some(frame) => (self.open.len() + 1, frame.scope.queries.is_some()),
+            None => {
+                self.ctx.warn(format!("profiler scope '{}' begun outside of a frame", name));
+                self.open.push(None);
+                return;
+            }
+        };
+
+        let scope = self.start(name, depth, gpu);
+        let frame = self.current.as_mut().unwrap();
+        frame.scopes.push(scope);
+        self.open.push(Some(frame.scopes.len() - 1));
+    }
+
+    /// Ends the innermost scope
+    pub fn end(&mut self) {
+        match self.open.pop() {
+            Some(Some(index)) => {
+                let gpu = self.current.as_ref().unwrap().scope.queries.is_some();
+                let stamp = self.stamp(gpu);
+                self.current.as_mut().unwrap().scopes[index].close(stamp);
+            },
+            Some(None) => {},
+            None => self.ctx.warn(String::from("profiler scope ended without being begun")),
+        }
+    }
+
+    /// Closes the scopes that are still open, and reads the frames that are done
+    pub fn end_frame(&mut self) {
+        if self.current.is_none() {
+            self.ctx.warn(String::from("profiler frame ended without being begun"));
+            return;
+        }
+
+        while let Some(open) = self.open.last().cloned() {
+            if let Some(index) = open {
+                let name = self.current.as_ref().unwrap().scopes[index].name.clone();
+                self.ctx.warn(format!("profiler scope '{}' not ended within its frame", name));
+            }
+            self.end();
+        }
+
+        let mut frame = self.current.take().unwrap();
+        let stamp = self.stamp(frame.scope.queries.is_some());
+        frame.scope.close(stamp);
+        self.pending.push_back(frame);
+        if self.pending.len() > MAX_PENDING {
+            let dropped = self.pending.pop_front().unwrap();
+            self.delete_queries(dropped.queries());
+        }
+        if self.supported && self.ctx.backend(|b| b.timer_disjoint()) {
+            let (dropped, kept): (VecDeque<PendingFrame>, VecDeque<PendingFrame>) = self.pending.drain(..).partition(|frame| frame.scope.queries.is_some());
+            self.pending = kept;
+            for frame in dropped {
+                self.delete_queries(frame.queries());
+            }
+        }
+        self.collect();
+    }
+
+    // for queries that may still be in flight, a new one could get the old timestamp
+    fn delete_queries(&mut self, queries: Vec<GLuint>) {
+        for query in queries {
+            self.ctx.unregister(Resource::Query, query);
+            self.ctx.backend(|b| b.delete_query(query));
+        }
+    }
+
+    // converts the frames whose timestamps are all there, in order
+    fn collect(&mut self) {
+        while let Some(frame) = self.pending.pop_front() {
+            let results = self.ctx.backend(|b| frame.queries().into_iter().map(|query| b.query_result(query)).collect::<Option<Vec<u64>>>());
+            let gpu = frame.scope.queries.is_some();
+            let timestamps = match results {
+                Some(timestamps) => timestamps,
+                None => {
+                    self.pending.push_front(frame);
+                    return;
+                }
+            };
+
+            // GPU times start at the CPU time of the frame, so the frames keep their order
+            let origin = frame.scope.start;
+            let times: Vec<(f64, f64)> = if gpu {
+                let base = timestamps[0];
+                timestamps.chunks(2)
+                    .map(|pair| (origin + (pair[0].saturating_sub(base)) as f64 / 1000000.0, pair[1].saturating_sub(pair[0]) as f64 / 1000000.0))
+                    .collect()
+            } else {
+                std::iter::once(&frame.scope).chain(frame.scopes.iter()).map(|scope| (scope.start, scope.end - scope.start)).collect()
+            };
+
+            self.free_queries.extend(frame.queries());
+            self.frames.push_back(ProfileFrame {
+                index: frame.index,
+                start: times[0].0,
+                duration: times[0].1,
+                gpu,
+                scopes: frame.scopes.into_iter().zip(times.into_iter().skip(1))
+                    .map(|(scope, (start, duration))| ProfileScope { name: scope.name, depth: scope.depth, start, duration })
+                    .collect(),
+            });
+            while self.frames.len() > self.history {
+                self.frames.pop_front();
+            }
+        }
+    }
+
+    /// The finished frames, oldest first
+    pub fn frames(&self) -> &VecDeque<ProfileFrame> {
+        &self.frames
+    }
+
+    pub fn last_frame(&self) -> Option<&ProfileFrame> {
+        self.frames.back()
+    }
+
+    /// Depth and name of the scopes in the history, in the order they first showed up
+    pub fn scope_names(&self) -> Vec<(usize, String)> {
+        let mut ret: Vec<(usize, String)> = Vec::new();
+        for frame in &self.frames {
+            for scope in &frame.scopes {
+                if !ret.iter().any(|&(depth, ref name)| depth == scope.depth && *name == scope.name) {
+                    ret.push((scope.depth, scope.name.clone()));
+                }
+            }
+        }
+        ret
+    }
+
+    /// Duration of the scope in every frame of the history, 0 where it didn't run
+    pub fn scope_history(&self, name: &str) -> Vec<f32> {
+        self.frames.iter().map(|frame| frame.duration_of(name) as f32).collect()
+    }
+
+    /// Duration of the frames in the history
+    pub fn frame_history(&self) -> Vec<f32> {
+        self.frames.iter().map(|frame| frame.duration as f32).collect()
+    }
+
+    /// Over the last `count` frames of the history
+    pub fn average(&self, name: &str, count: usize) -> f64 {
+        let frames: Vec<&ProfileFrame> = self.frames.iter().rev().take(count).collect();
+        if frames.is_empty() {
+            return 0.0;
+        }
+        frames.iter().map(|frame| frame.duration_of(name)).sum::<f64>() / frames.len() as f64
+    }
+
+    /// The history as JSON in the trace event format, which chrome://tracing can load
+    pub fn chrome_trace(&self) -> String {
+        let mut events = Vec::new();
+        for frame in &self.frames {
+            let category = if frame.gpu { "gpu" } else { "cpu" };
+            events.push(trace_event(&format!("frame {}", frame.index), "frame", frame.start, frame.duration, frame.index));
+            for scope in &frame.scopes {
+                events.push(trace_event(&scope.name, category, scope.start, scope.duration, frame.index));
+            }
+        }
+        format!("{{\"traceEvents\":[\n{}\n],\"displayTimeUnit\":\"ms\"}}\n", events.join(",\n"))
+    }
+
+    pub fn save_chrome_trace(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.chrome_trace()).map_err(|err| format!("Couldn't write {}: {}", path, err))
+    }
+}
+
+impl Drop for Profiler {
+    fn drop(&mut self) {
+        let mut queries = std::mem::take(&mut self.free_queries);
+        for frame in self.current.iter().chain(self.pending.iter()) {
+            queries.extend(frame.queries());
+        }
+        self.delete_queries(queries);
+    }
+}
+
+// a complete event, with times in microseconds
+fn trace_event(name: &str, category: &str, start: f64, duration: f64, frame: usize) -> String {
+    format!("{{\"name\":\"{}\",\"cat\":\"{}\",\"ph\":\"X\",\"ts\":{:.3},\"dur\":{:.3},\"pid\":1,\"tid\":1,\"args\":{{\"frame\":{}}}}}",
+            escape(name), category, start * 1000.0, duration * 1000.0, frame)
+}
+
+fn escape(text: &str) -> String {
+    let mut ret = String::new();
+    for c in text.chars() {
+        match c {
+            '"' => ret.push_str("\\\""),
+            '\\' => ret.push_str("\\\\"),
+            c if (c as u32) < 0x20 => ret.push_str(&format!("\\u{:04x}", c as u32)),
+            c => ret.push(c),
+        }
+    }
+    ret
+}
@@ -1,16 +1,17 @@
 use std::cell::RefCell;
 use std::collections::HashMap;
 use gl::types::*;
-use cgmath::prelude::*;
-use super::buffer::VertexBuffer;
-
-static mut PRINT_SHADER_ERRORS: bool = true;
-
-static mut BOUND_PROGRAM: i32 = 0;
-static mut PROGRAM_ID: i32 = 0;
-static mut CLEARED_VERTEX_BINDINGS: bool = true;
+use super::buffer::{VertexBuffer, UniformBuffer};
+use super::context::{Context, Resource};
+use super::preprocessor::Source;
+use super::texture::{Sampler, Texture};
 
+// goes after the #version line of every shader
+const HEADER: &str = "precision highp float;\nprecision highp int;\nprecision highp sampler2D;\n";
+// the lines in front of the source, with the #version line
+const HEADER_LINES: usize = 4;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Uniform {
     Signed(i32),
     Unsigned(u32),
@@ -25,10 +26,189 @@ pub enum Uniform {
     Mat4(cgmath::Matrix4<f32>),
 }
 
+impl Uniform {
+    /// The GLSL type it sets
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Uniform::Signed(_) => "int",
+            Uniform::Unsigned(_) => "uint",
+            Uniform::Float(_) => "float",
+            Uniform::Vec2(_) => "vec2",
+            Uniform::Vec3(_) => "vec3",
+            Uniform::Vec4(_) => "vec4",
+            Uniform::Mat2(_) => "mat2",
+            Uniform::Mat3(_) => "mat3",
+            Uniform::Mat4(_) => "mat4",
+        }
+    }
+
+    /// Whether it can be set on a uniform of the given GL type, anything goes for unknown types
+    pub fn fits(&self, datatype: GLenum) -> bool {
+        datatype == 0 || match self {
+            Uniform::Signed(_) => datatype == gl::INT || datatype == gl::BOOL || is_sampler(datatype),
+            Uniform::Unsigned(_) => datatype == gl::UNSIGNED_INT || datatype == gl::BOOL,
+            Uniform::Float(_) => datatype == gl::FLOAT || datatype == gl::BOOL,
+            Uniform::Vec2(_) => datatype == gl::FLOAT_VEC2,
+            Uniform::Vec3(_) => datatype == gl::FLOAT_VEC3,
+            Uniform::Vec4(_) => datatype == gl::FLOAT_VEC4,
+            Uniform::Mat2(_) => datatype == gl::FLOAT_MAT2,
+            Uniform::Mat3(_) => datatype == gl::FLOAT_MAT3,
+            Uniform::Mat4(_) => datatype == gl::FLOAT_MAT4,
+        }
+    }
+
+    /// The float components, column by column for matrices, none for the integer types
+    pub fn floats(&self) -> Vec<f32> {
+        match self {
+            Uniform::Signed(_) | Uniform::Unsigned(_) => Vec::new(),
+            Uniform::Float(f) => vec!(*f),
+            Uniform::Vec2(v) => AsRef::<[f32; 2]>::as_ref(v).to_vec(),
+            Uniform::Vec3(v) => AsRef::<[f32; 3]>::as_ref(v).to_vec(),
+            Uniform::Vec4(v) => AsRef::<[f32; 4]>::as_ref(v).to_vec(),
+            Uniform::Mat2(m) => AsRef::<[f32; 4]>::as_ref(m).to_vec(),
+            Uniform::Mat3(m) => AsRef::<[f32; 9]>::as_ref(m).to_vec(),
+            Uniform::Mat4(m) => AsRef::<[f32; 16]>::as_ref(m).to_vec(),
+        }
+    }
+}
+
+const TYPES: &[(GLenum, &str)] = &[
+    (gl::FLOAT, "float"),
+    (gl::FLOAT_VEC2, "vec2"),
+    (gl::FLOAT_VEC3, "vec3"),
+    (gl::FLOAT_VEC4, "vec4"),
+    (gl::INT, "int"),
+    (gl::INT_VEC2, "ivec2"),
+    (gl::INT_VEC3, "ivec3"),
+    (gl::INT_VEC4, "ivec4"),
+    (gl::UNSIGNED_INT, "uint"),
+    (gl::UNSIGNED_INT_VEC2, "uvec2"),
+    (gl::UNSIGNED_INT_VEC3, "uvec3"),
+    (gl::UNSIGNED_INT_VEC4, "uvec4"),
+    (gl::BOOL, "bool"),
+    (gl::BOOL_VEC2, "bvec2"),
+    (gl::BOOL_VEC3, "bvec3"),
+    (gl::BOOL_VEC4, "bvec4"),
+    (gl::FLOAT_MAT2, "mat2"),
+    (gl::FLOAT_MAT3, "mat3"),
+    (gl::FLOAT_MAT4, "mat4"),
+    (gl::SAMPLER_2D, "sampler2D"),
+    (gl::SAMPLER_3D, "sampler3D"),
+    (gl::SAMPLER_CUBE, "samplerCube"),
+    (gl::SAMPLER_2D_SHADOW, "sampler2DShadow"),
+    (gl::SAMPLER_2D_ARRAY, "sampler2DArray"),
+    (gl::SAMPLER_2D_ARRAY_SHADOW, "sampler2DArrayShadow"),
+    (gl::SAMPLER_CUBE_SHADOW, "samplerCubeShadow"),
+    (gl::INT_SAMPLER_2D, "isampler2D"),
+    (gl::INT_SAMPLER_3D, "isampler3D"),
+    (gl::INT_SAMPLER_CUBE, "isamplerCube"),
+    (gl::INT_SAMPLER_2D_ARRAY, "isampler2DArray"),
+    (gl::UNSIGNED_INT_SAMPLER_2D, "usampler2D"),
+    (gl::UNSIGNED_INT_SAMPLER_3D, "usampler3D"),
+    (gl::UNSIGNED_INT_SAMPLER_CUBE, "usamplerCube"),
+    (gl::UNSIGNED_INT_SAMPLER_2D_ARRAY, "usampler2DArray"),
+];
+
+/// The GLSL name of a GL type like gl::FLOAT_VEC3
+pub fn type_name(datatype: GLenum) -> &'static str {
+    TYPES.iter().find(|t| t.0 == datatype).map_or("unknown type", |t| t.1)
+}
+
+/// 0 for structs and types tinygl doesn't know
+pub(crate) fn type_from_name(name: &str) -> GLenum {
+    TYPES.iter().find(|t| t.1 == name).map_or(0, |t| t.0)
+}
+
+fn is_sampler(datatype: GLenum) -> bool {
+    type_name(datatype).contains("sampler")
+}
+
+/// The texture target a sampler type samples from
+fn sampler_target(datatype: GLenum) -> GLenum {
+    let name = type_name(datatype);
+    if name.contains("Cube") {
+        gl::TEXTURE_CUBE_MAP
+    } else if name.contains("2DArray") {
+        gl::TEXTURE_2D_ARRAY
+    } else if name.contains("3D") {
+        gl::TEXTURE_3D
+    } else {
+        gl::TEXTURE_2D
+    }
+}
+
+fn target_name(target: GLenum) -> &'static str {
+    match target {
+        gl::TEXTURE_2D_ARRAY => "2D array texture",
+        gl::TEXTURE_3D => "3D texture",
+        gl::TEXTURE_CUBE_MAP => "cube map",
+        _ => "2D texture",
+    }
+}
+
+/// "lights[2]" is ("lights", 2)
+pub(crate) fn split_index(name: &str) -> Option<(&str, usize)> {
+    let open = name.rfind('[')?;
+    let index = name[open + 1..].strip_suffix(']')?.parse().ok()?;
+    Some((&name[..open], index))
+}
+
+/// An active attribute or uniform, arrays go by their name without "[0]"
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variable {
+    pub name: String,
+    pub location: GLint,
+    /// like gl::FLOAT_VEC3, 0 if unknown
+    pub datatype: GLenum,
+    /// the length of arrays, 1 otherwise
+    pub size: GLint,
+}
+
+impl Variable {
+    pub fn type_name(&self) -> &'static str {
+        type_name(self.datatype)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UniformBlock {
+    pub name: String,
+    pub index: GLuint,
+    /// in bytes, 0 if unknown
+    pub size: GLint,
+}
+
+/// A group of uniforms that gets set together, like the parameters of a render pass. The
+/// compiler drops unused uniforms, so better set all of them even when one fails.
+pub trait Uniforms {
+    fn set(&self, program: &Program) -> Result<(), String>;
+}
+
+/// A line of a compile log, with the file and line of the source it is about. Lines from outside
+/// of the source, like link errors, have no file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShaderError {
+    pub file: String,
+    /// 0 if unknown
+    pub line: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match (self.file.is_empty(), self.line) {
+            (_, 0) => write!(f, "{}", self.message),
+            (true, line) => write!(f, "{}: {}", line, self.message),
+            (false, line) => write!(f, "{}:{}: {}", self.file, line, self.message),
+        }
+    }
+}
+
 pub struct Program {
-    id: i32,
-    vertex_source: String,
-    fragment_source: String,
+    ctx: Context,
+    id: usize,
+    vertex_source: Source,
+    fragment_source: Source,
 
     vertex_shader_log: String,
     fragment_shader_log: String,
@@ -36,201 +216,139 @@ pub struct Program {
 
     program: Option<GLuint>,
 
+    attributes: Vec<Variable>,
+    uniforms: HashMap<String, Variable>,
+    blocks: HashMap<String, UniformBlock>,
+
     attribute_locations: RefCell<HashMap<String, Option<u32>>>,
-    uniform_locations: RefCell<HashMap<String, Option<i32>>>
+    // locations of array elements like "lights[2]"
+    element_locations: RefCell<HashMap<String, GLint>>
 }
 
 impl Program {
-    pub fn set_print_compilation_errors(print: bool) {
-        unsafe { PRINT_SHADER_ERRORS = print; }
-    }
-
-    pub fn print_compilation_errors() -> bool {
-        unsafe { PRINT_SHADER_ERRORS }
-    }
-
-    fn compile_shader(src: &str, shader_type: GLuint, version: i32) -> (bool, GLuint, String) {
+    fn compile_shader(ctx: &Context, src: &Source, shader_type: GLuint, version: i32) -> (bool, GLuint, String) {
         let glsl = match version {
             100 => "100",
             300 => "300 es",
             _ => "100"
         };
-        let src = format!("#version {}
-            precision highp float;
-            precision highp int;
-            precision highp sampler2D;
-        ", glsl) + src;
-
-        let csrc = std::ffi::CString::new(src.as_bytes()).expect("Invalid string");
-
-        unsafe {
-            let shader = gl::CreateShader(shader_type);
-
-            gl::ShaderSource(shader, 1, &(csrc.as_ptr() as *const GLchar), &(csrc.as_bytes().len() as GLint));
-            gl::CompileShader(shader);
-
-            let mut result = 0;
-            gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut result);
-
-            let mut log = String::new();
-            let mut len = 0;
-            gl::GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut len);
-
-            if len > 1 {
-                let mut buf = Vec::with_capacity(len as usize);
-                buf.set_len((len as usize) - 1); // subtract 1 to skip the trailing null character
-                gl::GetShaderInfoLog(
-                    shader,
-                    len,
-                    std::ptr::null_mut(),
-                    buf.as_mut_ptr() as *mut GLchar,
-                );
-                log = String::from_utf8(buf).ok().unwrap();
-            }
+        let src = format!("#version {}\n{}{}", glsl, HEADER, src.code());
 
-            ((result != 0), shader, log)
-        }
+        ctx.backend(|b| b.compile_shader(shader_type, &src))
     }
 
-    fn link_program(vs: GLuint, fs: GLuint) -> (Option<GLuint>, String) {
-        unsafe {
-            let program = gl::CreateProgram();
-            gl::AttachShader(program, vs);
-            gl::AttachShader(program, fs);
-            gl::LinkProgram(program);
-
-            let mut result = 0;
-            gl::GetProgramiv(program, gl::LINK_STATUS, &mut result);
-
-            let mut prog_log = String::new();
-            let mut len = 0;
-            gl::GetProgramiv(program, gl::INFO_LOG_LENGTH, &mut len);
-
-            if len > 1 {
-                let mut buf = Vec::with_capacity(len as usize);
-                buf.set_len((len as usize) - 1); // subtract 1 to skip the trailing null character
-                gl::GetProgramInfoLog(
-                    program,
-                    len,
-                    std::ptr::null_mut(),
-                    buf.as_mut_ptr() as *mut GLchar,
-                );
-                prog_log = String::from_utf8(buf).ok().unwrap();
-            }
-
-            if result == 0 {
-                gl::DeleteProgram(program);
-                (None, prog_log)
-            } else {
-                (Some(program), prog_log)
-            }
-        }
+    pub fn new(ctx: &Context, vsrc: &str, fsrc: &str) -> Self {
+        Self::new_versioned(ctx, vsrc, fsrc, 300)
     }
 
-    pub fn new(vsrc: &str, fsrc: &str) -> Self {
-        Self::new_versioned(vsrc, fsrc, 300)
+    fn print_lines(src: &Source) {
+        for line in 1..=src.line_count() {
+            let (file, fileline) = src.origin(line).unwrap();
+            println!("{:>24}:{:<4} {}", file, fileline, src.code().lines().nth(line - 1).unwrap());
+        }
     }
 
-    fn print_lines(src: &str) {
-        for ln in src.split("\n").enumerate() {
-            println!("{:4} {}", ln.0 + 5, ln.1);
-        }
+    pub fn new_versioned(ctx: &Context, vsrc: &str, fsrc: &str, version: i32) -> Self {
+        Self::from_sources(ctx, &Source::new("vertex", vsrc), &Source::new("fragment", fsrc), version)
     }
 
-    pub fn new_versioned(vsrc: &str, fsrc: &str, version: i32) -> Self {
-        let id = unsafe { let id = PROGRAM_ID; PROGRAM_ID += 1; id };
-        let vs = Self::compile_shader(vsrc, gl::VERTEX_SHADER, version);
-        let fs = Self::compile_shader(fsrc, gl::FRAGMENT_SHADER, version);
+    /// Errors get mapped back to the files the sources came from
+    pub fn from_sources(ctx: &Context, vsrc: &Source, fsrc: &Source, version: i32) -> Self {
+        let id = ctx.new_program_id();
+        let vs = Self::compile_shader(ctx, vsrc, gl::VERTEX_SHADER, version);
+        let fs = Self::compile_shader(ctx, fsrc, gl::FRAGMENT_SHADER, version);
         let mut prog = None;
         let mut prog_log = None;
-        let mut attrs = HashMap::new();
+        let mut attributes = Vec::new();
         let mut uniforms = HashMap::new();
+        let mut blocks = HashMap::new();
 
-        if !vs.0 && Self::print_compilation_errors() && !vs.2.is_empty() {
+        if !vs.0 && ctx.print_compilation_errors() && !vs.2.is_empty() {
             Self::print_lines(vsrc);
-            Self::print_errors("Vertex Shader Log:", &vs.2);
+            Self::print_errors("Vertex Shader Log:", &Self::errors(&vs.2, Some(vsrc)));
         }
-        if !fs.0 && Self::print_compilation_errors() && !fs.2.is_empty() {
+        if !fs.0 && ctx.print_compilation_errors() && !fs.2.is_empty() {
             Self::print_lines(fsrc);
-            Self::print_errors("Fragment Shader Log:", &fs.2);
+            Self::print_errors("Fragment Shader Log:", &Self::errors(&fs.2, Some(fsrc)));
         }
 
         if vs.0 && fs.0 {
-            let ret = Self::link_program(vs.1, fs.1);
+            let ret = ctx.backend(|b| b.link_program(vs.1, fs.1));
             prog = ret.0;
             prog_log = Some(ret.1);
 
-            if prog.is_none() && Self::print_compilation_errors() && !prog_log.as_ref().unwrap().is_empty() {
+            if prog.is_none() && ctx.print_compilation_errors() && !prog_log.as_ref().unwrap().is_empty() {
                 println!("Vertex Shader:");
                 Self::print_lines(vsrc);
                 println!("Fragment Shader:");
                 Self::print_lines(fsrc);
-                Self::print_errors("Program Link Log:", prog_log.as_ref().unwrap());
+                Self::print_errors("Program Link Log:", &Self::errors(prog_log.as_ref().unwrap(), None));
             }
         }
 
-        unsafe {
-            gl::DeleteShader(vs.1);
-            gl::DeleteShader(fs.1);
-        }
+        ctx.backend(|b| {
+            b.delete_shader(vs.1);
+            b.delete_shader(fs.1);
+        });
 
         // load attribute and uniform locations
         if let Some(program) = prog {
-            unsafe {
-                let mut uniform_count = 0;
-                let mut attribute_count = 0;
-
-                gl::GetProgramiv(program, gl::ACTIVE_ATTRIBUTES, &mut attribute_count);
-                gl::GetProgramiv(program, gl::ACTIVE_UNIFORMS, &mut uniform_count);
-
-                let buf_len = 255;
-                let mut buf = Vec::with_capacity(255 + 1);
-                buf.set_len(buf_len);
-
-                let mut name_len = 0;
-                let mut size = 0;
-                let mut datatype = 0;
-
-                for i in 0..attribute_count {
-                    gl::GetActiveAttrib(program, i as GLuint, buf_len as GLsizei, &mut name_len, &mut size, &mut datatype, buf.as_mut_ptr() as *mut GLchar);
-                    let name = std::ffi::CStr::from_ptr(buf.as_ptr()).to_str().unwrap().to_owned();
-                    let loc = gl::GetAttribLocation(program, buf.as_ptr() as _);
-                    attrs.insert(name, Some(loc as u32));
+            ctx.register(Resource::Program, program);
+            ctx.backend(|b| {
+                for mut attribute in b.active_attributes(program) {
+                    attribute.name = Self::array_name(attribute.name);
+                    attributes.push(attribute);
                 }
-
-                for i in 0..uniform_count {
-                    gl::GetActiveUniform(program, i as GLuint, buf_len as GLsizei, &mut name_len, &mut size, &mut datatype, buf.as_mut_ptr() as *mut GLchar);
-                    let name = std::ffi::CStr::from_ptr(buf.as_ptr()).to_str().unwrap().to_owned();
-                    let location = gl::GetUniformLocation(program, buf.as_ptr());
-                    uniforms.insert(name, Some(location));
+                // the ones in blocks have no location
+                for mut uniform in b.active_uniforms(program).into_iter().filter(|uniform| uniform.location >= 0) {
+                    uniform.name = Self::array_name(uniform.name);
+                    uniforms.insert(uniform.name.clone(), uniform);
                 }
-
-                gl::UseProgram(program);
-            }
+                for block in b.active_uniform_blocks(program) {
+                    blocks.insert(block.name.clone(), block);
+                }
+            });
+            attributes.sort_by_key(|attribute| attribute.location);
         }
 
         Program {
+            ctx: ctx.clone(),
             id,
-            vertex_source: String::from(vsrc),
-            fragment_source: String::from(fsrc),
+            vertex_source: vsrc.clone(),
+            fragment_source: fsrc.clone(),
             vertex_shader_log: vs.2,
             fragment_shader_log: fs.2,
             program_log: prog_log,
             program: prog,
-            attribute_locations: RefCell::new(attrs),
-            uniform_locations: RefCell::new(uniforms)
+            attribute_locations: RefCell::new(attributes.iter().filter(|attribute| attribute.location >= 0).map(|attribute| (attribute.name.clone(), Some(attribute.location as u32))).collect()),
+            attributes,
+            uniforms,
+            blocks,
+            element_locations: RefCell::new(HashMap::new())
         }
     }
 
-    fn assert_bound(&self) {
-        assert!(unsafe { BOUND_PROGRAM } == self.id);
+    /// GL names arrays like their first element
+    fn array_name(name: String) -> String {
+        match name.strip_suffix("[0]") {
+            Some(array) => array.to_string(),
+            None => name
+        }
     }
 
-    pub fn vertex_source(&self) -> &String {
+    pub(crate) fn id(&self) -> usize {
+        self.id
+    }
+
+    pub(crate) fn assert_bound(&self) {
+        self.ctx.check_program(self.id, self.program);
+    }
+
+    pub fn vertex_source(&self) -> &Source {
         &self.vertex_source
     }
 
-    pub fn fragment_source(&self) -> &String {
+    pub fn fragment_source(&self) -> &Source {
         &self.fragment_source
     }
 
@@ -238,17 +356,41 @@ impl Program {
         self.program.is_some()
     }
 
-    fn print_errors(header: &str, log: &str) {
+    pub fn set_label(&self, label: &str) {
+        if let Some(prog) = self.program {
+            self.ctx.set_label(Resource::Program, prog, label);
+        }
+    }
+
+    fn print_errors(header: &str, errors: &[ShaderError]) {
         println!("{}", header);
-        for (a, b) in Self::errors(log) {
-            for ln in b {
-                println!("{} {}", a, ln);
-            }
+        for error in errors {
+            println!("{}", error);
+        }
+    }
+
+    /// Line and message of a log line like "ERROR: 0:12: message" (WebGL), "0:12(5): error:
+    /// message" (Mesa) or "0(12) : error C0000: message" (NVIDIA)
+    fn parse_log_line(line: &str) -> Option<(usize, &str)> {
+        let line = line.strip_prefix("ERROR: ").or_else(|| line.strip_prefix("WARNING: ")).unwrap_or(line);
+
+        // the number of the source string, then the line
+        let source_len = line.find(|c: char| !c.is_ascii_digit())?;
+        if source_len == 0 {
+            return None;
         }
+        let rest = &line[source_len..];
+        let rest = rest.strip_prefix(':').or_else(|| rest.strip_prefix('('))?;
+        let line_len = rest.find(|c: char| !c.is_ascii_digit())?;
+        let lineno = rest[..line_len].parse().ok()?;
+
+        // whatever comes after the position, like a column, ends with a colon
+        let message = rest[line_len..].split_once(':')?.1.trim();
+        Some((lineno, message))
     }
 
-    fn errors(log: &str) -> HashMap<i32, Vec<String>> {
-        let mut ret = HashMap::new();
+    fn errors(log: &str, source: Option<&Source>) -> Vec<ShaderError> {
+        let mut ret = Vec::new();
 
         for line in log.lines() {
             // only for reasonably sized lines
@@ -256,42 +398,40 @@ impl Program {
                 continue;
             }
 
-            let mut lineno = 0;
-            let mut message = line;
-
-            // WebGL format
-            if line.starts_with("ERROR: ") {
-                let line = line.split_at(7).1;
-
-                // extract first colon
-                line.find(":").map(|firstcolon| {
-                    // extract second colon
-                    let line = line.split_at(firstcolon + 1).1;
-                    line.find(":").map(|secondcolon| {
-                        if let Some(ln) = line.split_at(secondcolon).0.parse().ok() {
-                            lineno = ln;
-                        }
-                        message = line.split_at(secondcolon + 2).1;
-                    })
-                });
-            }
-
-            ret.entry(lineno).or_insert(Vec::new()).push(message.to_string());
+            let error = match Self::parse_log_line(line) {
+                Some((lineno, message)) => {
+                    let origin = source.and_then(|source| lineno.checked_sub(HEADER_LINES).and_then(|line| source.origin(line)));
+                    match origin {
+                        Some((file, line)) => ShaderError { file: file.to_string(), line, message: message.to_string() },
+                        None => ShaderError { file: String::new(), line: lineno, message: message.to_string() },
+                    }
+                }
+                None => ShaderError { file: String::new(), line: 0, message: line.to_string() },
+            };
+            ret.push(error);
         }
 
         ret
     }
 
-    pub fn vertex_errors(&self) -> HashMap<i32, Vec<String>> {
-        Self::errors(&self.vertex_shader_log)
+    pub fn vertex_errors(&self) -> Vec<ShaderError> {
+        Self::errors(&self.vertex_shader_log, Some(&self.vertex_source))
+    }
+
+    pub fn fragment_errors(&self) -> Vec<ShaderError> {
+        Self::errors(&self.fragment_shader_log, Some(&self.fragment_source))
     }
 
-    pub fn fragment_errors(&self) -> HashMap<i32, Vec<String>> {
-        Self::errors(&self.fragment_shader_log)
+    pub fn link_errors(&self) -> Option<Vec<ShaderError>> {
+        self.program_log.as_ref().map(|log| Self::errors(log, None))
     }
 
-    pub fn link_errors(&self) -> Option<HashMap<i32, Vec<String>>> {
-        self.program_log.as_ref().map(|log| Self::errors(log))
+    /// All the errors, one per line
+    pub fn error_report(&self) -> String {
+        let mut errors = self.vertex_errors();
+        errors.extend(self.fragment_errors());
+        errors.extend(self.link_errors().unwrap_or_default());
+        errors.iter().map(|error| error.to_string()).collect::<Vec<_>>().join("\n")
     }
 
     pub fn vertex_log(&self) -> String {
@@ -315,15 +455,14 @@ impl Program {
             println!("Program Link Log:");
             println!("{}", proglog);
         }
-        println!("Attributes: {:?}", self.attribute_locations);
-        println!("Uniforms: {:?}", self.uniform_locations);
+        println!("Attributes: {:?}", self.attributes);
+        println!("Uniforms: {:?}", self.uniforms());
+        println!("Uniform Blocks: {:?}", self.uniform_blocks());
     }
 
     pub fn bind(&self) {
         if let Some(prog) = self.program {
-            unsafe { gl::UseProgram(prog); }
-            unsafe { BOUND_PROGRAM = self.id; }
-            assert!(unsafe { CLEARED_VERTEX_BINDINGS });
+            self.ctx.bind_program(self.id, prog);
         }
     }
 
@@ -331,6 +470,50 @@ impl Program {
         self.program
     }
 
+    /// The active attributes by location
+    pub fn attributes(&self) -> &[Variable] {
+        &self.attributes
+    }
+
+    /// The active uniforms outside of blocks, by name
+    pub fn uniforms(&self) -> Vec<Variable> {
+        let mut ret: Vec<Variable> = self.uniforms.values().cloned().collect();
+        ret.sort_by(|a, b| a.name.cmp(&b.name));
+        ret
+    }
+
+    pub fn uniform_blocks(&self) -> Vec<UniformBlock> {
+        let mut ret: Vec<UniformBlock> = self.blocks.values().cloned().collect();
+        ret.sort_by_key(|block| block.index);
+        ret
+    }
+
+    /// Also finds elements of arrays like "lights[2]", their size is what's left of the array
+    pub fn uniform_info(&self, name: &str) -> Option<Variable> {
+        self.find_uniform(name).ok()
+    }
+
+    fn find_uniform(&self, name: &str) -> Result<Variable, String> {
+        if let Some(uniform) = self.uniforms.get(name) {
+            return Ok(uniform.clone());
+        }
+
+        if let Some((array, index)) = split_index(name) {
+            if let Some(uniform) = self.uniforms.get(array) {
+                if index as GLint >= uniform.size {
+                    return Err(format!("uniform '{}' has {} elements, there is no {}", array, uniform.size, name));
+                }
+
+                let program = self.program.unwrap_or(0);
+                let location = *self.element_locations.borrow_mut().entry(name.to_string())
+                    .or_insert_with(|| self.ctx.backend(|b| b.uniform_location(program, name)));
+                return Ok(Variable { name: name.to_string(), location, datatype: uniform.datatype, size: uniform.size - index as GLint });
+            }
+        }
+
+        Err(format!("No such uniform: {}", name))
+    }
+
     pub fn vertex_attrib_location(&self, attrib: &str) -> Option<u32> {
         self.assert_bound();
 
@@ -350,21 +533,22 @@ impl Program {
         self.assert_bound();
 
         if let Some(l) = self.vertex_attrib_location(attrib) {
-            unsafe { gl::VertexAttribDivisor(l, divisor); }
+            self.ctx.backend(|b| b.vertex_attrib_divisor(l, divisor));
         }
     }
 
     pub fn vertex_attrib_buffer(&self, attrib: &str, buffer: &VertexBuffer, size: GLint, datatype: GLenum, normed: bool, stride: GLsizei, offset: GLsizei) {
         self.assert_bound();
-        unsafe { CLEARED_VERTEX_BINDINGS = false; }
+        self.ctx.check_vertex_array();
+        self.ctx.set_vertex_bindings_cleared(false);
 
         if let Some(l) = self.vertex_attrib_location(attrib) {
             buffer.bind();
-            unsafe {
+            self.ctx.backend(|b| {
                 // TODO: keep track of those to reduce GL calls:
-                gl::EnableVertexAttribArray(l);
-                gl::VertexAttribPointer(l, size, datatype, if normed {1} else {0}, stride, offset as *const GLvoid);
-            }
+                b.enable_vertex_attrib(l, true);
+                b.vertex_attrib_pointer(l, size, datatype, normed, stride, offset);
+            });
         }
     }
 
@@ -372,7 +556,7 @@ impl Program {
         self.assert_bound();
 
         if let Some(l) = self.vertex_attrib_location(attrib) {
-            unsafe { gl::DisableVertexAttribArray(l); }
+            self.ctx.backend(|b| b.enable_vertex_attrib(l, false));
         }
     }
 
@@ -381,49 +565,110 @@ impl Program {
 
         for loc in self.attribute_locations.borrow().iter() {
             if let Some(loc) = loc.1 {
-                unsafe { gl::DisableVertexAttribArray(*loc); }
+                self.ctx.backend(|b| b.enable_vertex_attrib(*loc, false));
             }
         }
 
-        unsafe { CLEARED_VERTEX_BINDINGS = true; }
+        self.ctx.set_vertex_bindings_cleared(true);
     }
 
+    /// Like `set_uniform`, but errors only get reported once
     pub fn uniform(&self, uniform: &str, value: Uniform) {
+        if let Err(err) = self.set_uniform(uniform, value) {
+            self.ctx.warn(err);
+        }
+    }
+
+    /// Fails for unknown uniforms and values of the wrong type
+    pub fn set_uniform(&self, uniform: &str, value: Uniform) -> Result<(), String> {
         self.assert_bound();
 
-        // spit warning on first try, then remember that it's not there
-        let opt = self.uniform_locations.borrow().get(uniform).map(|r| *r);
-        let loc = match opt {
-            None => {
-                println!("No such uniform: {}", uniform);
-                self.uniform_locations.borrow_mut().insert(uniform.to_string(), None);
-                None
-            }
-            Some(l) => l
-        };
+        let variable = self.find_uniform(uniform)?;
+        if !value.fits(variable.datatype) {
+            return Err(format!("uniform '{}' is a {}, not a {}", uniform, variable.type_name(), value.type_name()));
+        }
 
-        if let Some(l) = loc {
-            unsafe {
-                match value {
-                    Uniform::Signed(i) => gl::Uniform1i(l, i),
-                    Uniform::Unsigned(ui) => gl::Uniform1ui(l, ui),
-                    Uniform::Float(f) => gl::Uniform1f(l, f),
-                    Uniform::Vec2(v) => gl::Uniform2fv(l, 1, v.as_ptr()),
-                    Uniform::Vec3(v) => gl::Uniform3fv(l, 1, v.as_ptr()),
-                    Uniform::Vec4(v) => gl::Uniform4fv(l, 1, v.as_ptr()),
-                    Uniform::Mat2(m) => gl::UniformMatrix2fv(l, 1, 0, m.as_ptr()),
-                    Uniform::Mat3(m) => gl::UniformMatrix3fv(l, 1, 0, m.as_ptr()),
-                    Uniform::Mat4(m) => gl::UniformMatrix4fv(l, 1, 0, m.as_ptr()),
-                }
-            }
+        self.ctx.backend(|b| b.uniform(variable.location, &value));
+        Ok(())
+    }
+
+    /// Sets the first values of an array, or the ones from an element on like "lights[2]"
+    pub fn set_uniform_array(&self, uniform: &str, values: &[Uniform]) -> Result<(), String> {
+        self.assert_bound();
+
+        let variable = self.find_uniform(uniform)?;
+        if let Some(value) = values.iter().find(|value| !value.fits(variable.datatype)) {
+            return Err(format!("uniform '{}' is a {}, not a {}", uniform, variable.type_name(), value.type_name()));
+        }
+        if values.iter().any(|value| std::mem::discriminant(value) != std::mem::discriminant(&values[0])) {
+            return Err(format!("values of different types for uniform '{}'", uniform));
         }
+        if values.len() > variable.size as usize {
+            return Err(format!("uniform '{}' holds {} values, not {}", uniform, variable.size, values.len()));
+        }
+
+        if !values.is_empty() {
+            self.ctx.backend(|b| b.uniform_array(variable.location, values));
+        }
+        Ok(())
+    }
+
+    /// Binds the texture to the unit and points the sampler there. Sampler objects bound to the
+    /// unit get unbound, so the texture's parameters apply.
+    pub fn set_sampler(&self, uniform: &str, texture: &Texture, unit: u32) -> Result<(), String> {
+        self.bind_sampler(uniform, texture, None, unit)
+    }
+
+    /// Like `set_sampler`, with the parameters of `sampler` instead of the texture's
+    pub fn set_sampler_with(&self, uniform: &str, texture: &Texture, sampler: &Sampler, unit: u32) -> Result<(), String> {
+        self.bind_sampler(uniform, texture, Some(sampler), unit)
+    }
+
+    fn bind_sampler(&self, uniform: &str, texture: &Texture, sampler: Option<&Sampler>, unit: u32) -> Result<(), String> {
+        self.assert_bound();
+
+        let variable = self.find_uniform(uniform)?;
+        if variable.datatype != 0 && !is_sampler(variable.datatype) {
+            return Err(format!("uniform '{}' is a {}, not a sampler", uniform, variable.type_name()));
+        }
+        if variable.datatype != 0 && sampler_target(variable.datatype) != texture.target() {
+            return Err(format!("uniform '{}' is a {}, which can't sample a {}", uniform, variable.type_name(), target_name(texture.target())));
+        }
+
+        texture.bind_at(unit);
+        match sampler {
+            Some(sampler) => sampler.bind_at(unit),
+            None => Sampler::release(&self.ctx, unit),
+        }
+        self.ctx.backend(|b| b.uniform(variable.location, &Uniform::Signed(unit as i32)));
+        Ok(())
+    }
+
+    pub fn set_uniforms<U: Uniforms>(&self, uniforms: &U) -> Result<(), String> {
+        uniforms.set(self)
+    }
+
+    /// Connects the block to the binding point and binds the buffer there
+    pub fn set_uniform_block(&self, block: &str, buffer: &UniformBuffer, binding: u32) -> Result<(), String> {
+        self.assert_bound();
+
+        let info = self.blocks.get(block).ok_or_else(|| format!("No such uniform block: {}", block))?;
+        if info.size as usize > buffer.size() {
+            return Err(format!("uniform block '{}' needs {} bytes, the buffer has {}", block, info.size, buffer.size()));
+        }
+
+        let program = self.program.unwrap_or(0);
+        self.ctx.backend(|b| b.uniform_block_binding(program, info.index, binding));
+        buffer.bind_base(binding);
+        Ok(())
     }
 }
 
 impl Drop for Program {
     fn drop(&mut self) {
         if let Some(prog) = self.program {
-            unsafe { gl::DeleteProgram(prog); }
+            self.ctx.unregister(Resource::Program, prog);
+            self.ctx.backend(|b| b.delete_program(prog));
         }
     }
 }
@@ -1,11 +1,14 @@
 use cgmath::{Vector2, Vector3};
 
+use super::context::{self, Context};
+
 pub struct FullscreenQuad {
+    ctx: Context,
     vertices: super::VertexBuffer,
 }
 
 impl FullscreenQuad {
-    pub fn new() -> Self {
+    pub fn new(ctx: &Context) -> Self {
         let mut vertices = Vec::<Vector2<f32>>::new();
 
         vertices.push(Vector2::new(-1.0, -1.0));
@@ -16,9 +19,10 @@ impl FullscreenQuad {
         vertices.push(Vector2::new(1.0,  -1.0));
         vertices.push(Vector2::new(1.0,   1.0));
 
-        let vertices = super::VertexBuffer::from(&vertices);
+        let vertices = super::VertexBuffer::from(ctx, &vertices);
 
         FullscreenQuad {
+            ctx: ctx.clone(),
             vertices,
         }
     }
@@ -30,7 +34,7 @@ impl FullscreenQuad {
     pub fn render(&self, program: &super::Program, attrname: &str) {
         program.bind();
         program.vertex_attrib_buffer(attrname, &self.vertices, 2, gl::FLOAT, false, 0, 0);
-        unsafe { gl::DrawArrays(gl::TRIANGLES, 0, 6) }
+        context::draw_arrays(&self.ctx, gl::TRIANGLES, 0, 6);
         program.disable_all_vertex_attribs();
     }
 }
@@ -49,7 +53,7 @@ pub struct Plane {
 }
 
 impl Plane {
-    pub fn new(x_extent: u16, y_extent: u16, orientation: Orientation) -> Self {
+    pub fn new(ctx: &Context, x_extent: u16, y_extent: u16, orientation: Orientation) -> Self {
         let mut vertices = Vec::new();
         let mut indices = Vec::new();
 
@@ -78,8 +82,8 @@ impl Plane {
         }
 
         Self {
-            vertices: super::VertexBuffer::from(&vertices),
-            indices: super::IndexBuffer::from16(&indices),
+            vertices: super::VertexBuffer::from(ctx, &vertices),
+            indices: super::IndexBuffer::from16(ctx, &indices),
         }
     }
 
@@ -90,7 +94,7 @@ impl Plane {
     }
 
     pub fn render(&self) {
-        unsafe { gl::DrawElements(gl::TRIANGLES, 0, self.indices.count() as _, std::ptr::null()) }
+        self.indices.draw_all(gl::TRIANGLES);
     }
 }
 
@@ -167,15 +171,24 @@ impl Cube {
         }
     }
 
-    pub fn indices(&self) -> super::IndexBuffer {
-        super::IndexBuffer::from16(&self.indices)
+    pub fn indices(&self, ctx: &Context) -> super::IndexBuffer {
+        super::IndexBuffer::from16(ctx, &self.indices)
+    }
+
+    pub fn vertices(&self, ctx: &Context) -> super::VertexBuffer {
+        super::VertexBuffer::from(ctx, &self.vertices)
     }
 
-    pub fn vertices(&self) -> super::VertexBuffer {
-        super::VertexBuffer::from(&self.vertices)
+    pub fn normals(&self, ctx: &Context) -> super::VertexBuffer {
+        super::VertexBuffer::from(ctx, &self.normals)
     }
 
-    pub fn normals(&self) -> super::VertexBuffer {
-        super::VertexBuffer::from(&self.normals)
+    /// With the attributes "vertex" and "normal"
+    pub fn mesh(&self, ctx: &Context) -> super::Mesh {
+        let mut mesh = super::Mesh::new(ctx);
+        mesh.add_buffer(self.vertices(ctx), super::VertexLayout::new().attribute("vertex", super::Format::Vec3));
+        mesh.add_buffer(self.normals(ctx), super::VertexLayout::new().attribute("normal", super::Format::Vec3));
+        mesh.set_indices(self.indices(ctx));
+        mesh
     }
 }
\ No newline at end of file
@@ -1,103 +1,473 @@
 use gl::types::*;
+use super::context::{Context, Resource};
 
-const NUM_TEX_TARGETS: usize = 128;
-static mut CURRENT_TEXTURE_UNIT: usize = 0;
-static mut BOUND_TEX_TARGETS: [GLenum; NUM_TEX_TARGETS] = [0; NUM_TEX_TARGETS];
+/// Internal formats of textures
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureFormat {
+    R8,
+    RG8,
+    RGB8,
+    RGBA8,
+    SRGB8Alpha8,
+    R16F,
+    RG16F,
+    RGBA16F,
+    R32F,
+    RG32F,
+    RGBA32F,
+    Depth16,
+    Depth24,
+    Depth32F,
+    Depth24Stencil8,
+    /// ETC2, blocks of 4x4 pixels in 8 bytes
+    Etc2RGB8,
+    /// ETC2 with EAC alpha, blocks of 4x4 pixels in 16 bytes
+    Etc2RGBA8,
+}
+
+impl TextureFormat {
+    pub fn internal(&self) -> GLenum {
+        match self {
+            TextureFormat::R8 => gl::R8,
+            TextureFormat::RG8 => gl::RG8,
+            TextureFormat::RGB8 => gl::RGB8,
+            TextureFormat::RGBA8 => gl::RGBA8,
+            TextureFormat::SRGB8Alpha8 => gl::SRGB8_ALPHA8,
+            TextureFormat::R16F => gl::R16F,
+            TextureFormat::RG16F => gl::RG16F,
+            TextureFormat::RGBA16F => gl::RGBA16F,
+            TextureFormat::R32F => gl::R32F,
+            TextureFormat::RG32F => gl::RG32F,
+            TextureFormat::RGBA32F => gl::RGBA32F,
+            TextureFormat::Depth16 => gl::DEPTH_COMPONENT16,
+            TextureFormat::Depth24 => gl::DEPTH_COMPONENT24,
+            TextureFormat::Depth32F => gl::DEPTH_COMPONENT32F,
+            TextureFormat::Depth24Stencil8 => gl::DEPTH24_STENCIL8,
+            TextureFormat::Etc2RGB8 => gl::COMPRESSED_RGB8_ETC2,
+            TextureFormat::Etc2RGBA8 => gl::COMPRESSED_RGBA8_ETC2_EAC,
+        }
+    }
+
+    /// Of the pixel data for uploads and readback, the internal format for compressed ones
+    pub fn format(&self) -> GLenum {
+        match self {
+            TextureFormat::R8 | TextureFormat::R16F | TextureFormat::R32F => gl::RED,
+            TextureFormat::RG8 | TextureFormat::RG16F | TextureFormat::RG32F => gl::RG,
+            TextureFormat::RGB8 => gl::RGB,
+            TextureFormat::RGBA8 | TextureFormat::SRGB8Alpha8 | TextureFormat::RGBA16F | TextureFormat::RGBA32F => gl::RGBA,
+            TextureFormat::Depth16 | TextureFormat::Depth24 | TextureFormat::Depth32F => gl::DEPTH_COMPONENT,
+            TextureFormat::Depth24Stencil8 => gl::DEPTH_STENCIL,
+            TextureFormat::Etc2RGB8 | TextureFormat::Etc2RGBA8 => self.internal(),
+        }
+    }
+
+    /// Of the pixel data, 0 for compressed formats
+    pub fn datatype(&self) -> GLenum {
+        match self {
+            TextureFormat::R8 | TextureFormat::RG8 | TextureFormat::RGB8 | TextureFormat::RGBA8 | TextureFormat::SRGB8Alpha8 => gl::UNSIGNED_BYTE,
+            TextureFormat::R16F | TextureFormat::RG16F | TextureFormat::RGBA16F => gl::HALF_FLOAT,
+            TextureFormat::R32F | TextureFormat::RG32F | TextureFormat::RGBA32F | TextureFormat::Depth32F => gl::FLOAT,
+            TextureFormat::Depth16 => gl::UNSIGNED_SHORT,
+            TextureFormat::Depth24 => gl::UNSIGNED_INT,
+            TextureFormat::Depth24Stencil8 => gl::UNSIGNED_INT_24_8,
+            TextureFormat::Etc2RGB8 | TextureFormat::Etc2RGBA8 => 0,
+        }
+    }
+
+    pub fn is_depth(&self) -> bool {
+        self.format() == gl::DEPTH_COMPONENT || self.format() == gl::DEPTH_STENCIL
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.datatype() == 0
+    }
+
+    /// In bytes, of a pixel or of a 4x4 block for compressed formats
+    pub fn pixel_size(&self) -> usize {
+        match self {
+            TextureFormat::R8 => 1,
+            TextureFormat::RG8 | TextureFormat::R16F | TextureFormat::Depth16 => 2,
+            TextureFormat::RGB8 => 3,
+            TextureFormat::RGBA8 | TextureFormat::SRGB8Alpha8 | TextureFormat::RG16F | TextureFormat::R32F
+                | TextureFormat::Depth24 | TextureFormat::Depth32F | TextureFormat::Depth24Stencil8 => 4,
+            TextureFormat::RGBA16F | TextureFormat::RG32F | TextureFormat::Etc2RGB8 => 8,
+            TextureFormat::RGBA32F | TextureFormat::Etc2RGBA8 => 16,
+        }
+    }
+
+    /// In bytes, of an image of the given size
+    pub fn data_size(&self, size: (GLsizei, GLsizei)) -> usize {
+        let (w, h) = (size.0.max(0) as usize, size.1.max(0) as usize);
+        if self.is_compressed() {
+            w.div_ceil(4) * h.div_ceil(4) * self.pixel_size()
+        } else {
+            w * h * self.pixel_size()
+        }
+    }
+}
+
+/// The shape of a texture
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureKind {
+    Flat,
+    /// with the number of layers
+    Array(GLsizei),
+    /// with the depth
+    Volume(GLsizei),
+    Cube,
+}
+
+/// What `Texture::with_descriptor` allocates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextureDescriptor {
+    pub kind: TextureKind,
+    pub format: TextureFormat,
+    pub size: (GLsizei, GLsizei),
+    /// mip levels
+    pub levels: GLsizei,
+}
+
+impl TextureDescriptor {
+    pub fn flat(format: TextureFormat, size: (GLsizei, GLsizei)) -> Self {
+        TextureDescriptor { kind: TextureKind::Flat, format, size, levels: 1 }
+    }
+
+    pub fn array(format: TextureFormat, size: (GLsizei, GLsizei), layers: GLsizei) -> Self {
+        TextureDescriptor { kind: TextureKind::Array(layers), format, size, levels: 1 }
+    }
+
+    pub fn volume(format: TextureFormat, size: (GLsizei, GLsizei), depth: GLsizei) -> Self {
+        TextureDescriptor { kind: TextureKind::Volume(depth), format, size, levels: 1 }
+    }
+
+    /// With square faces
+    pub fn cube(format: TextureFormat, size: GLsizei) -> Self {
+        TextureDescriptor { kind: TextureKind::Cube, format, size: (size, size), levels: 1 }
+    }
+
+    /// With all mip levels down to 1x1
+    pub fn mipmapped(mut self) -> Self {
+        let largest = self.size.0.max(self.size.1).max(1);
+        self.levels = 32 - largest.leading_zeros() as GLsizei;
+        self
+    }
+
+    pub fn target(&self) -> GLenum {
+        match self.kind {
+            TextureKind::Flat => gl::TEXTURE_2D,
+            TextureKind::Array(_) => gl::TEXTURE_2D_ARRAY,
+            TextureKind::Volume(_) => gl::TEXTURE_3D,
+            TextureKind::Cube => gl::TEXTURE_CUBE_MAP,
+        }
+    }
+
+    /// Of arrays, the slices of 3D textures or the faces of cube maps, in the order of
+    /// gl::TEXTURE_CUBE_MAP_POSITIVE_X and the following
+    pub fn layers(&self) -> GLsizei {
+        match self.kind {
+            TextureKind::Flat => 1,
+            TextureKind::Array(layers) | TextureKind::Volume(layers) => layers,
+            TextureKind::Cube => 6,
+        }
+    }
+
+    /// The size of a mip level
+    pub fn level_size(&self, level: GLsizei) -> (GLsizei, GLsizei) {
+        ((self.size.0 >> level).max(1), (self.size.1 >> level).max(1))
+    }
+
+    fn check_level(&self, level: GLsizei) -> Result<(), String> {
+        if level < 0 || level >= self.levels {
+            return Err(format!("texture has no mip level {}", level));
+        }
+        Ok(())
+    }
+
+    fn check(&self) -> Result<(), String> {
+        if self.size.0 <= 0 || self.size.1 <= 0 || self.layers() <= 0 {
+            return Err(format!("can't create a texture of size {:?} with {} layers", self.size, self.layers()));
+        }
+        if self.levels < 1 || self.levels > self.mipmapped().levels {
+            return Err(format!("a texture of size {:?} can't have {} mip levels", self.size, self.levels));
+        }
+        if let TextureKind::Volume(_) = self.kind {
+            if self.format.is_depth() || self.format.is_compressed() {
+                return Err(format!("3D textures can't be {:?}", self.format));
+            }
+        }
+        Ok(())
+    }
+}
 
 pub struct Texture {
+    ctx: Context,
     tex: GLuint,
     target: GLenum,
     size: Option<(GLsizei, GLsizei)>,
+    descriptor: Option<TextureDescriptor>,
 }
 
 impl Texture {
-    pub fn new(target: GLenum) -> Texture {
-        let mut tex = 0;
-        unsafe { gl::GenTextures(1, &mut tex); }
+    pub fn new(ctx: &Context, target: GLenum) -> Texture {
+        let tex = ctx.backend(|b| b.gen_texture());
+        ctx.register(Resource::Texture, tex);
         Texture {
+            ctx: ctx.clone(),
             tex,
             target,
-            size: None
+            size: None,
+            descriptor: None
         }
     }
 
-    pub fn from_data_2d(data: &Vec<u8>, size: (i32, i32)) -> Self {
-        let mut ret = Self::new(gl::TEXTURE_2D);
-        unsafe { ret.teximage((size.0 as _, size.1 as _), gl::RGBA, gl::RGBA, gl::UNSIGNED_BYTE, data.as_ptr() as _) }
-        ret.filter(gl::TEXTURE_MIN_FILTER, gl::LINEAR_MIPMAP_LINEAR);
+    /// Allocates storage for all mip levels and layers, which can't change afterwards. The
+    /// filters are linear, mipmapped if there are mip levels.
+    pub fn with_descriptor(ctx: &Context, descriptor: TextureDescriptor) -> Result<Texture, String> {
+        descriptor.check()?;
+
+        let mut ret = Self::new(ctx, descriptor.target());
+        ret.bind();
+        let depth = match descriptor.kind {
+            TextureKind::Array(layers) | TextureKind::Volume(layers) => Some(layers),
+            TextureKind::Flat | TextureKind::Cube => None,
+        };
+        ctx.backend(|b| b.tex_storage(descriptor.target(), descriptor.levels, descriptor.format.internal(), descriptor.size, depth));
+        ret.size = Some(descriptor.size);
+        ret.descriptor = Some(descriptor);
+
+        // the default of GL needs mip levels
+        ret.filter(gl::TEXTURE_MIN_FILTER, if descriptor.levels > 1 { gl::LINEAR_MIPMAP_LINEAR } else { gl::LINEAR });
         ret.filter(gl::TEXTURE_MAG_FILTER, gl::LINEAR);
+        Ok(ret)
+    }
+
+    pub fn from_data_2d(ctx: &Context, data: &Vec<u8>, size: (i32, i32)) -> Self {
+        let descriptor = TextureDescriptor::flat(TextureFormat::RGBA8, size).mipmapped();
+        let mut ret = Self::with_descriptor(ctx, descriptor).expect("Invalid texture size");
+        ret.set_image(0, 0, data).expect("Texture data doesn't match its size");
         ret.gen_mipmaps();
         ret
     }
 
+    /// None for textures that weren't created with a descriptor
+    pub fn descriptor(&self) -> Option<&TextureDescriptor> {
+        self.descriptor.as_ref()
+    }
+
+    pub fn target(&self) -> GLenum {
+        self.target
+    }
+
     pub fn handle(&self) -> GLuint {
         self.tex
     }
 
-    fn new_texture_unit_binding(&self) {
-        // keep track of old binding to that unit and maybe un-bind
-        let curr_unit = unsafe { CURRENT_TEXTURE_UNIT };
-        if curr_unit < NUM_TEX_TARGETS {
-            let bound = unsafe { BOUND_TEX_TARGETS[curr_unit] };
-            if bound != self.target {
-                if bound != 0 {
-                    unsafe { gl::BindTexture(bound, 0); }
-                }
-                unsafe { BOUND_TEX_TARGETS[curr_unit] = self.target; }
-            }
-        }
+    pub fn set_label(&self, label: &str) {
+        self.ctx.set_label(Resource::Texture, self.tex, label);
     }
 
     fn bind(&self) {
-        self.new_texture_unit_binding();
-        unsafe { gl::BindTexture(self.target, self.tex); }
+        self.ctx.bind_texture(self.target, self.tex);
     }
 
     pub fn bind_at(&self, unit: u32) {
-        unsafe {
-            gl::ActiveTexture(gl::TEXTURE0 + unit);
-            unsafe { CURRENT_TEXTURE_UNIT = unit as _; }
-            self.new_texture_unit_binding();
-            gl::BindTexture(self.target, self.tex);
-        }
+        self.ctx.active_texture(unit);
+        self.ctx.bind_texture(self.target, self.tex);
     }
 
     pub fn filter(&mut self, minmag: GLenum, value: GLenum) {
         self.bind();
-        unsafe { gl::TexParameteri(self.target, minmag, value as _); }
+        self.ctx.backend(|b| b.tex_parameter(self.target, minmag, value as _));
     }
 
     pub fn wrap(&mut self, wrap: GLenum, value: GLenum) {
         self.bind();
-        unsafe { gl::TexParameteri(self.target, wrap, value as _); }
+        self.ctx.backend(|b| b.tex_parameter(self.target, wrap, value as _));
+    }
+
+    /// Compares to the reference coordinate when sampled with a shadow sampler, None turns it off
+    pub fn compare(&mut self, func: Option<GLenum>) {
+        self.bind();
+        self.ctx.backend(|b| match func {
+            Some(func) => {
+                b.tex_parameter(self.target, gl::TEXTURE_COMPARE_MODE, gl::COMPARE_REF_TO_TEXTURE as _);
+                b.tex_parameter(self.target, gl::TEXTURE_COMPARE_FUNC, func as _);
+            }
+            None => b.tex_parameter(self.target, gl::TEXTURE_COMPARE_MODE, gl::NONE as _),
+        });
     }
 
     pub fn gen_mipmaps(&mut self) {
         self.bind();
-        unsafe { gl::GenerateMipmap(self.target); }
+        self.ctx.backend(|b| b.generate_mipmap(self.target));
     }
 
     pub unsafe fn teximage(&mut self, size: (GLsizei, GLsizei), internal: GLenum, format: GLenum, datatype: GLenum, data: *const GLvoid) {
         self.bind();
-        gl::TexImage2D(self.target, 0, internal as _, size.0, size.1, 0, format, datatype, data);
+        self.ctx.backend(|b| b.tex_image(self.target, size, None, internal, format, datatype, data));
         self.size = Some(size);
     }
 
     pub unsafe fn teximage_layer(&mut self, size: (GLsizei, GLsizei), layers: GLsizei, internal: GLenum, format: GLenum, datatype: GLenum, data: *const GLvoid) {
         self.bind();
-        gl::TexImage3D(self.target, 0, internal as _, size.0, size.1, layers, 0, format, datatype, data);
+        self.ctx.backend(|b| b.tex_image(self.target, size, Some(layers), internal, format, datatype, data));
         self.size = Some(size);
     }
 
     pub fn size(&self) -> Option<(GLsizei, GLsizei)> {
         self.size
     }
+
+    fn described(&self) -> Result<&TextureDescriptor, String> {
+        self.descriptor.as_ref().ok_or_else(|| String::from("texture wasn't created with a descriptor"))
+    }
+
+    /// Writes the pixels of a region of a mip level of a layer, see `TextureDescriptor::layers`.
+    /// `data` is in the descriptor's format, compressed regions start at multiples of 4.
+    pub fn update<T>(&mut self, level: GLsizei, layer: GLsizei, offset: (GLsizei, GLsizei), size: (GLsizei, GLsizei), data: &[T]) -> Result<(), String> {
+        let descriptor = *self.described()?;
+        let format = descriptor.format;
+        descriptor.check_level(level)?;
+        let level_size = descriptor.level_size(level);
+
+        if layer < 0 || layer >= descriptor.layers() {
+            return Err(format!("texture has no layer {}", layer));
+        }
+        if offset.0 < 0 || offset.1 < 0 || size.0 < 0 || size.1 < 0 || offset.0 + size.0 > level_size.0 || offset.1 + size.1 > level_size.1 {
+            return Err(format!("region at {:?} of size {:?} isn't inside mip level {} of size {:?}", offset, size, level, level_size));
+        }
+        if format.is_compressed() && (offset.0 % 4 != 0 || offset.1 % 4 != 0) {
+            return Err(format!("compressed region at {:?} doesn't start at a block", offset));
+        }
+        let bytes = std::mem::size_of_val(data);
+        if bytes != format.data_size(size) {
+            return Err(format!("{} bytes for a region of size {:?}, {:?} needs {}", bytes, size, format, format.data_size(size)));
+        }
+
+        let data = unsafe { std::slice::from_raw_parts(data.as_ptr() as *const u8, bytes) };
+        let (target, z) = match descriptor.kind {
+            TextureKind::Cube => (gl::TEXTURE_CUBE_MAP_POSITIVE_X + layer as GLenum, 0),
+            TextureKind::Array(_) | TextureKind::Volume(_) => (self.target, layer),
+            TextureKind::Flat => (self.target, 0),
+        };
+        let region = (offset.0, offset.1, z);
+        let size = (size.0, size.1, 1);
+
+        self.bind();
+        self.ctx.backend(|b| {
+            // rows of pixels aren't padded
+            b.pixel_store(gl::UNPACK_ALIGNMENT, 1);
+            if format.is_compressed() {
+                b.compressed_tex_sub_image(target, level, region, size, format.internal(), data);
+            } else {
+                b.tex_sub_image(target, level, region, size, format.format(), format.datatype(), data);
+            }
+        });
+        Ok(())
+    }
+
+    /// Writes a whole mip level of a layer
+    pub fn set_image<T>(&mut self, level: GLsizei, layer: GLsizei, data: &[T]) -> Result<(), String> {
+        let descriptor = self.described()?;
+        descriptor.check_level(level)?;
+        let size = descriptor.level_size(level);
+        self.update(level, layer, (0, 0), size, data)
+    }
+
+    /// Reads the first mip level of a layer back. GLES can only read color textures, through a
+    /// framebuffer, so cube maps, depth and compressed textures fail.
+    pub fn read(&self, layer: GLsizei) -> Result<Vec<u8>, String> {
+        let descriptor = *self.described()?;
+        let format = descriptor.format;
+        if descriptor.kind == TextureKind::Cube || format.is_depth() || format.is_compressed() {
+            return Err(format!("can't read back {:?} textures of format {:?}", descriptor.kind, format));
+        }
+        if layer < 0 || layer >= descriptor.layers() {
+            return Err(format!("texture has no layer {}", layer));
+        }
+
+        let mut data = vec![0u8; format.data_size(descriptor.size)];
+        let layer = match descriptor.kind {
+            TextureKind::Flat => None,
+            _ => Some(layer),
+        };
+        self.ctx.backend(|b| {
+            let fbo = b.gen_framebuffer();
+            b.bind_framebuffer(fbo);
+            b.framebuffer_texture(gl::COLOR_ATTACHMENT0, self.tex, layer);
+            b.read_buffer(gl::COLOR_ATTACHMENT0);
+            b.pixel_store(gl::PACK_ALIGNMENT, 1);
+            unsafe { b.read_pixels(descriptor.size, format.format(), format.datatype(), data.as_mut_ptr() as _); }
+            b.bind_framebuffer(0);
+            b.delete_framebuffer(fbo);
+        });
+        Ok(data)
+    }
+}
+
+/// Filtering, wrapping and comparison for the texture unit it is bound to, instead of the
+/// parameters of the texture
+pub struct Sampler {
+    ctx: Context,
+    sampler: GLuint,
+}
+
+impl Sampler {
+    pub fn new(ctx: &Context) -> Sampler {
+        let sampler = ctx.backend(|b| b.gen_sampler());
+        ctx.register(Resource::Sampler, sampler);
+        Sampler {
+            ctx: ctx.clone(),
+            sampler,
+        }
+    }
+
+    pub fn handle(&self) -> GLuint {
+        self.sampler
+    }
+
+    pub fn set_label(&self, label: &str) {
+        self.ctx.set_label(Resource::Sampler, self.sampler, label);
+    }
+
+    pub fn filter(&mut self, minmag: GLenum, value: GLenum) {
+        self.ctx.backend(|b| b.sampler_parameter(self.sampler, minmag, value as _));
+    }
+
+    pub fn wrap(&mut self, wrap: GLenum, value: GLenum) {
+        self.ctx.backend(|b| b.sampler_parameter(self.sampler, wrap, value as _));
+    }
+
+    /// Like `Texture::compare`
+    pub fn compare(&mut self, func: Option<GLenum>) {
+        self.ctx.backend(|b| match func {
+            Some(func) => {
+                b.sampler_parameter(self.sampler, gl::TEXTURE_COMPARE_MODE, gl::COMPARE_REF_TO_TEXTURE as _);
+                b.sampler_parameter(self.sampler, gl::TEXTURE_COMPARE_FUNC, func as _);
+            }
+            None => b.sampler_parameter(self.sampler, gl::TEXTURE_COMPARE_MODE, gl::NONE as _),
+        });
+    }
+
+    pub fn bind_at(&self, unit: u32) {
+        self.ctx.bind_sampler(unit, self.sampler);
+    }
+
+    pub fn release(ctx: &Context, unit: u32) {
+        ctx.bind_sampler(unit, 0);
+    }
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        self.ctx.delete_sampler(self.sampler);
+    }
 }
 
 impl Drop for Texture {
     fn drop(&mut self) {
         if self.tex > 0 {
-            unsafe { gl::DeleteTextures(1, &self.tex); }
+            self.ctx.delete_texture(self.tex);
         }
     }
 }
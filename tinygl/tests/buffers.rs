@@ -0,0 +1,50 @@
+extern crate gl;
+extern crate tinygl;
+
+use tinygl::backend::NullBackend;
+use tinygl::{Context, IndexBuffer, VertexBuffer};
+
+fn bytes(floats: &[f32]) -> Vec<u8> {
+    floats.iter().flat_map(|f| f.to_ne_bytes().to_vec()).collect()
+}
+
+#[test]
+fn updates_in_place() {
+    let null = NullBackend::new();
+    let ctx = Context::with_backend(Box::new(null.clone()));
+
+    let mut vbo = VertexBuffer::dynamic(&ctx, 16);
+    assert_eq!((vbo.size(), vbo.capacity()), (0, 16));
+    let buffers = null.state().buffers.keys().cloned().collect::<Vec<_>>();
+    assert_eq!(buffers.len(), 1);
+
+    vbo.update(&[1.0f32, 2.0]);
+    assert_eq!((vbo.size(), vbo.capacity()), (8, 16));
+    assert!(vbo.update_range(4, &[3.0f32]).is_ok());
+    assert_eq!(null.state().buffers[&buffers[0]][..8], bytes(&[1.0, 3.0])[..]);
+    assert_eq!(vbo.update_range(4, &[0.0f32; 2]), Err(String::from("can't write 8 bytes at 4 into a vertex buffer of 8 bytes")));
+
+    // grows to a power of two, still the same buffer
+    vbo.update(&[0.0f32; 5]);
+    assert_eq!((vbo.size(), vbo.capacity()), (20, 32));
+    assert_eq!(null.state().buffers.keys().cloned().collect::<Vec<_>>(), buffers);
+}
+
+#[test]
+fn remembers_index_types() {
+    let null = NullBackend::new();
+    let ctx = Context::with_backend(Box::new(null.clone()));
+
+    let mut indices = IndexBuffer::new(&ctx, &[0u8, 1, 2]);
+    assert_eq!(indices.index_type(), gl::UNSIGNED_BYTE);
+    assert_eq!(indices.update(&[0u16, 1, 2]), Err(String::from("index buffer holds u8 indices, not u16")));
+
+    let mut indices = IndexBuffer::dynamic::<u32>(&ctx, 3);
+    assert_eq!((indices.count(), indices.index_type()), (0, gl::UNSIGNED_INT));
+    assert!(indices.update(&[0u32, 1, 2, 2, 1, 3]).is_ok());
+    assert!(indices.update_range(3, &[4u32]).is_ok());
+    assert_eq!(indices.count(), 6);
+
+    let stored = null.state().buffers.values().find(|data| data.len() == 32).cloned().unwrap();
+    assert_eq!(stored[12..16], 4u32.to_ne_bytes());
+}
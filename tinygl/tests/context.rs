@@ -0,0 +1,125 @@
+extern crate gl;
+extern crate tinygl;
+
+use tinygl::backend::NullBackend;
+use tinygl::{Context, FrameSummary, Resource, Uniform};
+
+const VERTEX: &str = "
+    attribute vec2 vertex;
+    uniform float scale;
+    void main() {
+        gl_Position = vec4(vertex * scale, 0.0, 1.0);
+    }
+    ";
+
+const FRAGMENT: &str = "
+    uniform sampler2D tex;
+    void main() {
+        gl_FragColor = texture2D(tex, vec2(0.5));
+    }
+    ";
+
+fn context() -> Context {
+    let ctx = Context::with_backend(Box::new(NullBackend::new()));
+    ctx.set_debug(true);
+    ctx
+}
+
+#[test]
+fn registers_resources() {
+    let ctx = context();
+
+    let program = tinygl::Program::new_versioned(&ctx, VERTEX, FRAGMENT, 100);
+    program.set_label("blit");
+    let mut fbo = tinygl::FrameBufferObject::new(&ctx, (4, 4));
+    fbo.add("color", gl::RGBA, gl::RGBA, gl::UNSIGNED_BYTE);
+    fbo.add_depth_renderbuffer();
+    fbo.set_label("scene");
+
+    let counts = ctx.resource_counts();
+    assert_eq!(counts.get(&Resource::Program), Some(&1));
+    assert_eq!(counts.get(&Resource::FrameBuffer), Some(&1));
+    assert_eq!(counts.get(&Resource::RenderBuffer), Some(&1));
+    assert_eq!(counts.get(&Resource::Texture), Some(&1));
+
+    let labels: Vec<String> = ctx.live_resources().into_iter().map(|(_, _, label)| label).collect();
+    assert!(labels.contains(&String::from("blit")));
+    assert!(labels.contains(&String::from("scene.color")));
+    assert!(labels.contains(&String::from("scene.depth")));
+
+    drop(fbo);
+    drop(program);
+    assert!(ctx.live_resources().is_empty());
+}
+
+#[test]
+fn warns_about_misuse() {
+    let ctx = context();
+
+    let program = tinygl::Program::new_versioned(&ctx, VERTEX, FRAGMENT, 100);
+    program.set_label("blit");
+    program.uniform("scale", Uniform::Float(2.0));
+    assert_eq!(ctx.warnings(), vec!(String::from("program 'blit' used without being bound")));
+
+    let texture = tinygl::Texture::new(&ctx, gl::TEXTURE_2D);
+    texture.set_label("font");
+    texture.bind_at(3);
+    drop(texture);
+    assert_eq!(ctx.warnings()[1], "texture 'font' deleted while bound to texture unit [3]");
+
+    // each warning is reported once
+    program.uniform("scale", Uniform::Float(2.0));
+    assert_eq!(ctx.warnings().len(), 2);
+
+    let quiet = context();
+    quiet.set_debug(false);
+    let texture = tinygl::Texture::new(&quiet, gl::TEXTURE_2D);
+    texture.bind_at(0);
+    drop(texture);
+    assert!(quiet.warnings().is_empty());
+}
+
+#[test]
+fn summarizes_frames() {
+    let ctx = context();
+
+    let program = tinygl::Program::new_versioned(&ctx, VERTEX, FRAGMENT, 100);
+    let quad = tinygl::shapes::FullscreenQuad::new(&ctx);
+    let texture = tinygl::Texture::new(&ctx, gl::TEXTURE_2D);
+    ctx.end_frame();
+
+    texture.bind_at(0);
+    quad.render(&program, "vertex");
+    quad.render(&program, "vertex");
+
+    let frame = ctx.end_frame();
+    assert_eq!(frame, FrameSummary {
+        program_binds: 2,
+        texture_binds: 1,
+        buffer_binds: 2,
+        framebuffer_binds: 0,
+        draws: 2,
+        vertices: 12,
+    });
+    assert_eq!(ctx.last_frame(), frame);
+    assert_eq!(ctx.end_frame(), FrameSummary::default());
+    assert!(ctx.warnings().is_empty());
+}
+
+#[test]
+fn creating_a_program_keeps_the_bound_one() {
+    let null = NullBackend::new();
+    let ctx = Context::with_backend(Box::new(null.clone()));
+    ctx.set_debug(true);
+
+    let first = tinygl::Program::new_versioned(&ctx, VERTEX, FRAGMENT, 100);
+    first.bind();
+    let second = tinygl::Program::new_versioned(&ctx, VERTEX, FRAGMENT, 100);
+    assert!(first.set_uniform("scale", Uniform::Float(2.0)).is_ok());
+    tinygl::draw_arrays(&ctx, gl::TRIANGLES, 0, 3);
+
+    let draws = null.take_draws();
+    assert_eq!(draws[0].program, first.handle().unwrap());
+    assert_ne!(draws[0].program, second.handle().unwrap());
+    assert!(ctx.warnings().is_empty());
+}
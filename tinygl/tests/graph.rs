@@ -0,0 +1,117 @@
+extern crate gl;
+extern crate tinygl;
+
+use tinygl::backend::NullBackend;
+use tinygl::{Context, Pass, RenderGraph, TextureFormat};
+
+fn chain(ctx: &Context) -> RenderGraph {
+    let mut graph = RenderGraph::new(ctx);
+    graph
+        .add(Pass::screen("present", (8, 8)).input("blurred"))
+        .add(Pass::new("scene", (4, 4)).output("color", TextureFormat::RGBA8).output("depth", TextureFormat::Depth24))
+        .add(Pass::new("bright", (4, 4)).input("color").output("bright", TextureFormat::RGBA8))
+        .add(Pass::new("blur", (4, 4)).input("bright").input("depth").output("blurred", TextureFormat::RGBA8));
+    graph
+}
+
+#[test]
+fn orders_passes() {
+    let ctx = Context::with_backend(Box::new(NullBackend::new()));
+    let mut graph = chain(&ctx);
+    assert!(graph.order().is_empty());
+    assert!(graph.compile().is_ok());
+    assert_eq!(graph.order(), vec!("scene", "bright", "blur", "present"));
+
+    let mut graph = RenderGraph::new(&ctx);
+    graph.add(Pass::new("a", (4, 4)).input("b").output("a", TextureFormat::R8))
+        .add(Pass::new("b", (4, 4)).input("a").output("b", TextureFormat::R8));
+    assert_eq!(graph.compile(), Err(String::from("passes [\"a\", \"b\"] depend on each other")));
+
+    let mut graph = RenderGraph::new(&ctx);
+    graph.add(Pass::new("a", (4, 4)).input("missing"));
+    assert_eq!(graph.compile(), Err(String::from("pass 'a' reads 'missing', which no pass writes")));
+
+    let mut graph = RenderGraph::new(&ctx);
+    graph.add(Pass::new("a", (4, 4)).output("x", TextureFormat::R8))
+        .add(Pass::new("b", (4, 4)).output("x", TextureFormat::R8));
+    assert_eq!(graph.compile(), Err(String::from("target 'x' is written by 'a' and 'b'")));
+
+    let mut graph = RenderGraph::new(&ctx);
+    graph.add(Pass::screen("a", (4, 4)).output("x", TextureFormat::R8));
+    assert_eq!(graph.compile(), Err(String::from("pass 'a' renders to the screen, it can't have outputs")));
+}
+
+#[test]
+fn shares_textures() {
+    let ctx = Context::with_backend(Box::new(NullBackend::new()));
+    let mut graph = chain(&ctx);
+    assert!(graph.compile().is_ok());
+
+    // color is last read by bright, before blur writes blurred
+    let handle = |graph: &RenderGraph, target: &str| graph.texture(target).unwrap().handle();
+    assert_eq!(handle(&graph, "color"), handle(&graph, "blurred"));
+    assert_ne!(handle(&graph, "color"), handle(&graph, "bright"));
+    assert!(graph.dump().contains("writes blurred as RGBA8 into texture 0 (until present)"));
+
+    graph.keep("color");
+    assert!(graph.compile().is_ok());
+    assert_ne!(handle(&graph, "color"), handle(&graph, "blurred"));
+    assert!(graph.dump().contains("writes color as RGBA8 into texture 0 (kept)"));
+}
+
+#[test]
+fn binds_passes() {
+    let null = NullBackend::new();
+    let ctx = Context::with_backend(Box::new(null.clone()));
+    let mut graph = chain(&ctx);
+    assert!(graph.begin("scene").is_err());
+    assert!(graph.compile().is_ok());
+
+    {
+        let blur = graph.begin("blur").unwrap();
+        assert_eq!((blur.unit("depth"), blur.units()), (Some(1), 2));
+        assert_ne!(null.state().framebuffer, 0);
+        assert_eq!(null.state().viewport, (0, 0, 4, 4));
+        assert_eq!(null.state().bound_textures[&(1, gl::TEXTURE_2D)], graph.texture("depth").unwrap().handle());
+    }
+    {
+        let present = graph.begin("present").unwrap();
+        assert_eq!(present.texture("blurred").map(|texture| texture.handle()), graph.texture("blurred").map(|texture| texture.handle()));
+        assert_eq!((null.state().framebuffer, null.state().viewport), (0, (0, 0, 8, 8)));
+    }
+    graph.end();
+
+    // one framebuffer per pass that doesn't render to the screen
+    assert_eq!(null.state().framebuffers.len(), 3);
+    drop(graph);
+    assert_eq!(null.state().live_objects(), 0);
+}
+
+#[test]
+fn renders_layers() {
+    let null = NullBackend::new();
+    let ctx = Context::with_backend(Box::new(null.clone()));
+    let mut graph = RenderGraph::new(&ctx);
+    graph
+        .add(Pass::layered("shadows", (4, 4), 3).output("shadowMaps", TextureFormat::Depth24))
+        .add(Pass::screen("present", (8, 8)).input("shadowMaps"))
+        .keep("shadowMaps");
+    assert!(graph.compile().is_ok());
+    assert_eq!(graph.texture("shadowMaps").and_then(|texture| texture.descriptor()).map(|descriptor| descriptor.layers()), Some(3));
+    assert!(graph.dump().contains("shadows 4x4 in 3 layers"));
+
+    // one framebuffer per layer
+    assert_eq!(null.state().framebuffers.len(), 3);
+    let depth = graph.texture("shadowMaps").unwrap().handle();
+    assert!(null.state().attachments.values().any(|attachment| *attachment == (depth, Some(2))));
+
+    assert!(graph.begin_layer("shadows", 2).is_ok());
+    assert_ne!(null.state().framebuffer, 0);
+    assert_eq!(graph.begin_layer("shadows", 3).err(), Some(String::from("pass 'shadows' has no layer 3")));
+    assert_eq!(graph.begin_layer("present", 1).err(), Some(String::from("pass 'present' has no layer 1")));
+    graph.end();
+
+    let mut graph = RenderGraph::new(&ctx);
+    graph.add(Pass::layered("a", (4, 4), 2).output("x", TextureFormat::RGBA8));
+    assert_eq!(graph.compile(), Err(String::from("pass 'a' can only render layers of depth targets")));
+}
@@ -0,0 +1,50 @@
+extern crate tinygl;
+
+use std::fs;
+use std::time::{Duration, SystemTime};
+use tinygl::ShaderRegistry;
+
+fn write(path: &std::path::Path, code: &str, age: u64) {
+    fs::write(path, code).unwrap();
+    let file = fs::File::options().write(true).open(path).unwrap();
+    file.set_modified(SystemTime::now() - Duration::from_secs(age)).unwrap();
+}
+
+#[test]
+fn reloads_changed_files() {
+    let dir = std::env::temp_dir().join(format!("tinygl-hotreload-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    write(&dir.join("a.frag"), "void a() {}", 60);
+
+    let shaders = ShaderRegistry::watch(&dir);
+    shaders.add("a.frag", "embedded a").add("b.frag", "embedded b");
+    assert_eq!(shaders.get("a.frag"), "void a() {}");
+    assert_eq!(shaders.get("b.frag"), "embedded b");
+    assert!(!shaders.poll());
+
+    write(&dir.join("a.frag"), "void a() { discard; }", 30);
+    assert!(shaders.poll());
+    assert!(shaders.changed(&["b.frag", "a.frag"]));
+    assert!(!shaders.changed(&["b.frag"]));
+    assert_eq!(shaders.get("a.frag"), "void a() { discard; }");
+
+    // touching a file without changing it is no change
+    write(&dir.join("a.frag"), "void a() { discard; }", 10);
+    assert!(!shaders.poll());
+    assert!(!shaders.changed(&["a.frag"]));
+
+    write(&dir.join("b.frag"), "void b() {}", 0);
+    assert!(shaders.poll());
+    assert!(shaders.changed(&["b.frag"]));
+    assert_eq!(shaders.get("b.frag"), "void b() {}");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn uses_embedded_copies_without_a_directory() {
+    let shaders = ShaderRegistry::new();
+    shaders.add("a.frag", "embedded a");
+    assert_eq!(shaders.get("a.frag"), "embedded a");
+    assert!(!shaders.poll());
+}
@@ -0,0 +1,123 @@
+extern crate gl;
+extern crate tinygl;
+
+use tinygl::backend::NullBackend;
+use tinygl::{Context, Format, IndexBuffer, Mesh, Program, VertexBuffer, VertexLayout};
+
+const VERTEX: &str = "
+    in vec3 vertex;
+    in vec4 position;
+    in vec4 color;
+    void main() {
+        gl_Position = vec4(vertex, 1.0) + position + color;
+    }
+    ";
+
+const FRAGMENT: &str = "
+    out vec4 color;
+    void main() {
+        color = vec4(1.0);
+    }
+    ";
+
+fn instance_layout() -> VertexLayout {
+    VertexLayout::new()
+        .attribute("position", Format::Vec3)
+        .attribute("color", Format::Bytes4Norm)
+        .instanced(1)
+}
+
+#[test]
+fn lays_out_attributes() {
+    let layout = instance_layout();
+    assert_eq!(layout.stride(), 16);
+    assert_eq!(layout.divisor(), 1);
+    assert_eq!(layout.attribute_named("color").map(|color| color.offset), Some(12));
+    assert!(layout.attribute_named("normal").is_none());
+}
+
+#[test]
+fn takes_floats() {
+    let ctx = Context::with_backend(Box::new(NullBackend::new()));
+    let mut mesh = Mesh::new(&ctx);
+    let layout = || VertexLayout::new().attribute("vertex", Format::Vec3);
+
+    assert_eq!(mesh.add_floats(&[0.0; 9], layout()), Ok(0));
+    assert_eq!(mesh.vertices(), 3);
+    assert_eq!(mesh.add_floats(&[0.0; 8], layout()), Err(String::from("8 floats aren't whole vertices of 12 bytes")));
+    assert!(mesh.add_floats(&[0.0; 4], instance_layout()).is_err());
+}
+
+#[test]
+fn draws_instanced() {
+    let null = NullBackend::new();
+    let ctx = Context::with_backend(Box::new(null.clone()));
+    let program = Program::new(&ctx, VERTEX, FRAGMENT);
+    program.bind();
+
+    let mut mesh = Mesh::new(&ctx);
+    mesh.add_buffer(VertexBuffer::from(&ctx, &[0.0f32; 4 * 3]), VertexLayout::new().attribute("vertex", Format::Vec3));
+    let instances = mesh.add_buffer(VertexBuffer::from(&ctx, &[0u32; 2 * 4]), instance_layout());
+    mesh.set_indices(IndexBuffer::from16(&ctx, &[0, 1, 2, 2, 1, 3]));
+    assert_eq!((mesh.vertices(), mesh.instances()), (4, Some(2)));
+
+    assert!(mesh.draw(&program, gl::TRIANGLES).is_ok());
+    mesh.set_buffer(instances, VertexBuffer::from(&ctx, &[0u32; 3 * 4]));
+    assert!(mesh.draw(&program, gl::TRIANGLES).is_ok());
+
+    let draws = null.take_draws();
+    assert_eq!(draws.iter().map(|draw| (draw.count, draw.instances, draw.indexed)).collect::<Vec<_>>(),
+               vec!((6, Some(2), true), (6, Some(3), true)));
+    let draw = &draws[1];
+    assert_ne!(draw.vertex_array, 0);
+    assert_eq!((draw.attributes["color"].offset, draw.attributes["color"].stride), (12, 16));
+    assert_eq!(draw.attributes["position"].divisor, 1);
+    assert_eq!(draw.attributes["vertex"].divisor, 0);
+
+    // the mesh leaves the default vertex array alone
+    assert_eq!(null.state().vertex_array, 0);
+    assert!(ctx.warnings().is_empty());
+}
+
+#[test]
+fn reports_mismatches() {
+    let null = NullBackend::new();
+    let ctx = Context::with_backend(Box::new(null.clone()));
+    let program = Program::new(&ctx, VERTEX, FRAGMENT);
+    program.bind();
+
+    let mut mesh = Mesh::new(&ctx);
+    mesh.add_buffer(VertexBuffer::from(&ctx, &[0.0f32; 3 * 4]), VertexLayout::new().attribute("vertex", Format::Vec4));
+    assert_eq!(mesh.draw(&program, gl::TRIANGLES), Err(String::from("attribute 'vertex' is a vec3, which a Vec4 doesn't fit")));
+
+    let mut mesh = Mesh::new(&ctx);
+    mesh.add_buffer(VertexBuffer::from(&ctx, &[0.0f32; 3 * 3]), VertexLayout::new().attribute("vertex", Format::Vec3));
+    assert_eq!(mesh.bind(&program), Err(String::from("attribute 'position' is in none of the vertex layouts")));
+
+    assert!(null.take_draws().is_empty());
+    assert_eq!(null.state().vertex_array, 0);
+}
+
+#[test]
+fn keeps_its_indices() {
+    let null = NullBackend::new();
+    let ctx = Context::with_backend(Box::new(null.clone()));
+    let program = Program::new(&ctx, "in vec3 vertex; void main() { gl_Position = vec4(vertex, 1.0); }", FRAGMENT);
+    program.bind();
+
+    let mut mesh = Mesh::new(&ctx);
+    mesh.add_buffer(VertexBuffer::from(&ctx, &[0.0f32; 3 * 3]), VertexLayout::new().attribute("vertex", Format::Vec3));
+    mesh.set_indices(IndexBuffer::from16(&ctx, &[0, 1, 2]));
+    assert!(mesh.bind(&program).is_ok());
+    assert_ne!(null.state().vertex_array, 0);
+
+    // other indices don't go to the bound vertex array
+    let mut indices = IndexBuffer::dynamic::<u16>(&ctx, 6);
+    assert_eq!(null.state().vertex_array, 0);
+    assert!(mesh.bind(&program).is_ok());
+    assert!(indices.update(&[0u16, 1, 2]).is_ok());
+    assert_eq!(null.state().vertex_array, 0);
+    assert!(mesh.bind(&program).is_ok());
+    assert!(indices.update_range(1, &[2u16]).is_ok());
+    assert_eq!(null.state().vertex_array, 0);
+}
@@ -0,0 +1,90 @@
+extern crate gl;
+extern crate cgmath;
+extern crate tinygl;
+
+use cgmath::Vector3;
+use tinygl::backend::NullBackend;
+use tinygl::Uniform;
+
+const VERTEX: &str = "
+    // a comment with uniform float nothing;
+    attribute vec2 vertex;
+    attribute float size;
+    uniform mat4 view;
+    void main() {
+        gl_Position = view * vec4(vertex * size, 0.0, 1.0);
+    }
+    ";
+
+const FRAGMENT: &str = "
+    uniform vec3 color;
+    void main() {
+        gl_FragColor = vec4(color, 1.0);
+    }
+    ";
+
+#[test]
+fn records_draws() {
+    let null = NullBackend::new();
+    let ctx = tinygl::Context::with_backend(Box::new(null.clone()));
+
+    let program = tinygl::Program::new_versioned(&ctx, VERTEX, FRAGMENT, 100);
+    assert!(program.valid());
+    let vertices = tinygl::VertexBuffer::from(&ctx, &vec!(0.0f32, 0.0, 1.0, 0.0, 0.0, 1.0));
+    let sizes = tinygl::VertexBuffer::from(&ctx, &vec!(1.0f32, 2.0));
+
+    program.bind();
+    program.uniform("color", Uniform::Vec3(Vector3::new(1.0, 0.5, 0.0)));
+    program.vertex_attrib_buffer("vertex", &vertices, 2, gl::FLOAT, false, 8, 0);
+    program.vertex_attrib_buffer("size", &sizes, 1, gl::FLOAT, false, 4, 0);
+    program.vertex_attrib_divisor("size", 1);
+    tinygl::enable(&ctx, gl::BLEND);
+    tinygl::draw_arrays(&ctx, gl::TRIANGLES, 0, 3);
+    tinygl::disable(&ctx, gl::BLEND);
+    program.disable_vertex_attrib("size");
+    tinygl::draw_arrays(&ctx, gl::POINTS, 0, 1);
+    program.disable_all_vertex_attribs();
+
+    let draws = null.take_draws();
+    assert_eq!(draws.len(), 2);
+
+    let draw = &draws[0];
+    assert_eq!((draw.mode, draw.count), (gl::TRIANGLES, 3));
+    assert_eq!(draw.uniforms.get("color"), Some(&Uniform::Vec3(Vector3::new(1.0, 0.5, 0.0))));
+    assert_eq!(draw.uniforms.get("view"), None);
+    assert!(draw.enabled.contains(&gl::BLEND));
+    assert_eq!(draw.attributes["size"].divisor, 1);
+    assert_ne!(draw.attributes["vertex"].buffer, draw.attributes["size"].buffer);
+
+    assert!(!draws[1].enabled.contains(&gl::BLEND));
+    assert!(!draws[1].attributes.contains_key("size"));
+    assert!(null.state().draws.is_empty());
+}
+
+#[test]
+fn tracks_lifetimes() {
+    let null = NullBackend::new();
+    let ctx = tinygl::Context::with_backend(Box::new(null.clone()));
+
+    {
+        let _program = tinygl::Program::new_versioned(&ctx, VERTEX, FRAGMENT, 100);
+        let buffer = tinygl::VertexBuffer::from(&ctx, &vec!(1u8, 2, 3));
+        buffer.bind();
+
+        let mut fbo = tinygl::FrameBufferObject::new(&ctx, (16, 8));
+        fbo.add("color", gl::RGBA, gl::RGBA, gl::UNSIGNED_BYTE);
+        fbo.add_depth_renderbuffer();
+        fbo.bind();
+
+        let state = null.state();
+        assert_eq!(state.buffers.values().next().map(|data| data.len()), Some(3));
+        assert_eq!(state.textures.values().next(), Some(&Some((16, 8))));
+        assert_eq!(state.viewport, (0, 0, 16, 8));
+        assert_ne!(state.framebuffer, 0);
+        // the shaders are gone once the program got linked
+        assert_eq!(state.live_objects(), 5);
+    }
+
+    assert_eq!(null.state().live_objects(), 0);
+    assert_eq!(null.state().framebuffer, 0);
+}
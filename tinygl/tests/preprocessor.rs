@@ -0,0 +1,78 @@
+extern crate tinygl;
+
+use tinygl::backend::NullBackend;
+use tinygl::{Context, Preprocessor, Program, ShaderError, Source};
+
+const NOISE: &str = "float noise(vec3 p) {
+    return fract(sin(dot(p, vec3(12.9898, 78.233, 45.164))) * 43758.5453);
+}";
+
+const FRAGMENT: &str = "in vec3 position;
+out vec4 color;
+#include \"noise\"
+void main() {
+    color = vec4(noise(position) * SCALE);
+}";
+
+#[test]
+fn includes_and_defines() {
+    let source = Preprocessor::new()
+        .add_include("noise", NOISE)
+        .define("SCALE", "1.0")
+        .define("SCALE", "2.0")
+        .process("main.frag", FRAGMENT);
+
+    assert!(source.code().starts_with("#define SCALE 2.0\nin vec3 position;\n"));
+    assert!(source.code().contains("float noise(vec3 p) {"));
+    assert!(!source.code().contains("#include"));
+    assert_eq!(source.line_count(), 1 + 5 + 3);
+
+    assert_eq!(source.origin(1), Some(("<defines>", 1)));
+    assert_eq!(source.origin(3), Some(("main.frag", 2)));
+    assert_eq!(source.origin(5), Some(("noise", 2)));
+    assert_eq!(source.origin(7), Some(("main.frag", 4)));
+    assert_eq!(source.origin(0), None);
+    assert_eq!(source.origin(10), None);
+}
+
+#[test]
+fn leaves_out_versions_of_includes() {
+    let source = Preprocessor::new()
+        .add_include("noise", &format!("#version 300 es\n{}", NOISE))
+        .process("main.frag", FRAGMENT);
+
+    assert!(!source.code().contains("#version"));
+    assert_eq!(source.origin(3), Some(("noise", 2)));
+    assert_eq!(source.line_count(), 5 + 3);
+}
+
+#[test]
+fn reports_bad_includes() {
+    let source = Preprocessor::new()
+        .add_include("a", "#include <b>")
+        .add_include("b", "#include \"a\"\n#include \"c\"")
+        .process("main.frag", "#include \"a\"");
+
+    assert_eq!(source.code(), "#error include cycle main.frag -> a -> b -> a\n#error unknown include c\n");
+    assert_eq!(source.origin(1), Some(("b", 1)));
+    assert_eq!(source.origin(2), Some(("b", 2)));
+}
+
+#[test]
+fn maps_errors_to_files() {
+    let ctx = Context::with_backend(Box::new(NullBackend::new()));
+    let fragment = Preprocessor::new()
+        .add_include("noise", "float noise(vec3 p) {\n#error not implemented\n}")
+        .define("SCALE", "2.0")
+        .process("main.frag", FRAGMENT);
+    let program = Program::from_sources(&ctx, &Source::new("main.vert", "void main() {}"), &fragment, 300);
+
+    assert!(!program.valid());
+    assert!(program.vertex_errors().is_empty());
+    assert_eq!(program.fragment_errors(), vec!(ShaderError {
+        file: String::from("noise"),
+        line: 2,
+        message: String::from("'#error' : not implemented"),
+    }));
+    assert_eq!(program.error_report(), "noise:2: '#error' : not implemented");
+}
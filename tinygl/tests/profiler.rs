@@ -0,0 +1,122 @@
+extern crate gl;
+extern crate tinygl;
+
+use tinygl::backend::NullBackend;
+use tinygl::{Context, Profiler};
+
+fn frame(ctx: &Context, profiler: &mut Profiler) {
+    profiler.begin_frame();
+    profiler.begin("terrain");
+    tinygl::draw_arrays(ctx, gl::TRIANGLES, 0, 3000);
+    profiler.end();
+    profiler.begin("water");
+    profiler.begin("waves");
+    tinygl::draw_arrays(ctx, gl::TRIANGLES, 0, 1000);
+    profiler.end();
+    profiler.end();
+    profiler.end_frame();
+}
+
+#[test]
+fn times_scopes_on_the_gpu() {
+    let null = NullBackend::new();
+    let ctx = Context::with_backend(Box::new(null.clone()));
+    let mut profiler = Profiler::new(&ctx, 10);
+    assert!(profiler.gpu());
+
+    frame(&ctx, &mut profiler);
+    let last = profiler.last_frame().unwrap().clone();
+    assert!(last.gpu);
+    assert_eq!(last.duration, 0.004);
+    assert_eq!(last.duration_of("terrain"), 0.003);
+    assert_eq!(last.duration_of("waves"), 0.001);
+    // relative to a CPU time
+    assert!((last.scopes[2].start - last.start - 0.003).abs() < 1e-9);
+    assert_eq!(profiler.scope_names(), vec!((1, String::from("terrain")), (1, String::from("water")), (2, String::from("waves"))));
+
+    // the queries get reused
+    let queries = null.state().queries.len();
+    frame(&ctx, &mut profiler);
+    assert_eq!(null.state().queries.len(), queries);
+    assert_eq!(profiler.scope_history("water"), vec!(0.001, 0.001));
+
+    drop(profiler);
+    assert!(null.state().queries.is_empty());
+    assert!(ctx.live_resources().is_empty());
+}
+
+#[test]
+fn falls_back_to_the_cpu() {
+    let ctx = Context::with_backend(Box::new(NullBackend::without_timer_queries()));
+    let mut profiler = Profiler::new(&ctx, 2);
+    profiler.set_gpu(true);
+    assert!(!profiler.timer_queries());
+    assert!(!profiler.gpu());
+
+    for _ in 0..3 {
+        frame(&ctx, &mut profiler);
+    }
+    let indices: Vec<usize> = profiler.frames().iter().map(|frame| frame.index).collect();
+    assert_eq!(indices, vec!(1, 2));
+    let last = profiler.last_frame().unwrap();
+    assert!(!last.gpu);
+    assert!(last.duration >= last.duration_of("water"));
+    assert!(last.scopes[0].start >= last.start);
+
+    let trace = profiler.chrome_trace();
+    assert!(trace.starts_with("{\"traceEvents\":["));
+    assert_eq!(trace.matches("\"ph\":\"X\"").count(), 8);
+    assert!(trace.contains("{\"name\":\"frame 2\",\"cat\":\"frame\""));
+    assert!(trace.contains("{\"name\":\"waves\",\"cat\":\"cpu\""));
+}
+
+#[test]
+fn reports_unbalanced_scopes() {
+    let ctx = Context::with_backend(Box::new(NullBackend::new()));
+    let mut profiler = Profiler::new(&ctx, 10);
+    profiler.begin("early");
+    profiler.end();
+    profiler.end();
+    profiler.begin_frame();
+    profiler.begin("shadow");
+    profiler.end_frame();
+
+    assert_eq!(ctx.warnings(), vec!(
+        String::from("profiler scope 'early' begun outside of a frame"),
+        String::from("profiler scope ended without being begun"),
+        String::from("profiler scope 'shadow' not ended within its frame"),
+    ));
+    assert_eq!(profiler.last_frame().unwrap().scopes.len(), 1);
+}
+
+#[test]
+fn drops_frames_it_cant_trust() {
+    let null = NullBackend::new();
+    let ctx = Context::with_backend(Box::new(null.clone()));
+    let mut profiler = Profiler::new(&ctx, 10);
+
+    // frames that never get their timestamps don't pile up
+    null.state_mut().stalled = true;
+    frame(&ctx, &mut profiler);
+    let first: Vec<u32> = null.state().queries.keys().cloned().collect();
+    for _ in 0..19 {
+        frame(&ctx, &mut profiler);
+    }
+    assert!(profiler.frames().is_empty());
+    // and don't get reused while the GPU might still write to them
+    assert!(first.iter().all(|query| !null.state().queries.contains_key(query)));
+    let queries = null.state().queries.len();
+    frame(&ctx, &mut profiler);
+    assert_eq!(null.state().queries.len(), queries);
+
+    // the stalled frames span the disjoint event as well
+    null.state_mut().stalled = false;
+    frame(&ctx, &mut profiler);
+    null.state_mut().disjoint = true;
+    frame(&ctx, &mut profiler);
+    assert!(profiler.frames().is_empty());
+    frame(&ctx, &mut profiler);
+    let indices: Vec<usize> = profiler.frames().iter().map(|frame| frame.index).collect();
+    assert_eq!(indices, vec!(23));
+    assert_eq!(null.state().queries.len(), ctx.live_resources().len());
+}
@@ -0,0 +1,126 @@
+extern crate gl;
+extern crate tinygl;
+
+use tinygl::backend::NullBackend;
+use tinygl::{Context, FrameBufferObject, Program, Sampler, Texture, TextureDescriptor, TextureFormat};
+
+const VERTEX: &str = "
+    in vec2 vertex;
+    void main() {
+        gl_Position = vec4(vertex, 0.0, 1.0);
+    }
+    ";
+
+const FRAGMENT: &str = "
+    uniform sampler2DArray layers;
+    out vec4 color;
+    void main() {
+        color = texture(layers, vec3(0.0));
+    }
+    ";
+
+#[test]
+fn uploads_regions() {
+    let null = NullBackend::new();
+    let ctx = Context::with_backend(Box::new(null.clone()));
+
+    let descriptor = TextureDescriptor::array(TextureFormat::RGBA8, (4, 2), 3).mipmapped();
+    assert_eq!((descriptor.levels, descriptor.level_size(2)), (3, (1, 1)));
+    let mut texture = Texture::with_descriptor(&ctx, descriptor).unwrap();
+    assert_eq!(texture.size(), Some((4, 2)));
+
+    assert!(texture.set_image(1, 2, &[7u8; 2 * 4]).is_ok());
+    assert_eq!(texture.update(0, 3, (0, 0), (1, 1), &[0u8; 4]), Err(String::from("texture has no layer 3")));
+    assert_eq!(texture.update(0, 0, (3, 0), (2, 1), &[0u8; 8]), Err(String::from("region at (3, 0) of size (2, 1) isn't inside mip level 0 of size (4, 2)")));
+    assert_eq!(texture.set_image(2, 0, &[0u8; 3]), Err(String::from("3 bytes for a region of size (1, 1), RGBA8 needs 4")));
+    assert_eq!(texture.update(-1, 0, (0, 0), (1, 1), &[0u8; 4]), Err(String::from("texture has no mip level -1")));
+    assert_eq!(texture.set_image(40, 0, &[0u8; 4]), Err(String::from("texture has no mip level 40")));
+
+    let mut cube = Texture::with_descriptor(&ctx, TextureDescriptor::cube(TextureFormat::Etc2RGB8, 8)).unwrap();
+    assert!(cube.update(0, 1, (4, 4), (4, 4), &[0u8; 8]).is_ok());
+    assert_eq!(cube.update(0, 1, (2, 0), (4, 4), &[0u8; 8]), Err(String::from("compressed region at (2, 0) doesn't start at a block")));
+
+    let uploads = null.state().uploads.clone();
+    assert_eq!(uploads.len(), 2);
+    assert_eq!((uploads[0].texture, uploads[0].target, uploads[0].level), (texture.handle(), gl::TEXTURE_2D_ARRAY, 1));
+    assert_eq!((uploads[0].offset, uploads[0].size, uploads[0].data.len()), ((0, 0, 2), (2, 1, 1), 8));
+    assert_eq!((uploads[1].texture, uploads[1].target), (cube.handle(), gl::TEXTURE_CUBE_MAP_NEGATIVE_X));
+
+    assert!(Texture::with_descriptor(&ctx, TextureDescriptor::volume(TextureFormat::Depth24, (4, 4), 4)).is_err());
+    assert!(Texture::with_descriptor(&ctx, TextureDescriptor::flat(TextureFormat::R8, (0, 4))).is_err());
+}
+
+#[test]
+fn reads_back() {
+    let null = NullBackend::new();
+    let ctx = Context::with_backend(Box::new(null.clone()));
+
+    let texture = Texture::with_descriptor(&ctx, TextureDescriptor::array(TextureFormat::RG16F, (3, 2), 2)).unwrap();
+    assert_eq!(texture.read(1).map(|data| data.len()), Ok(3 * 2 * 4));
+    assert!(texture.read(2).is_err());
+
+    let depth = Texture::with_descriptor(&ctx, TextureDescriptor::flat(TextureFormat::Depth24, (2, 2))).unwrap();
+    assert_eq!(depth.read(0), Err(String::from("can't read back Flat textures of format Depth24")));
+
+    // the framebuffer for reading is gone again
+    assert!(null.state().framebuffers.is_empty());
+    assert_eq!(null.state().framebuffer, 0);
+}
+
+#[test]
+fn binds_samplers() {
+    let null = NullBackend::new();
+    let ctx = Context::with_backend(Box::new(null.clone()));
+    let program = Program::new(&ctx, VERTEX, FRAGMENT);
+    program.bind();
+
+    let layers = Texture::with_descriptor(&ctx, TextureDescriptor::array(TextureFormat::R8, (2, 2), 2)).unwrap();
+    let mut sampler = Sampler::new(&ctx);
+    sampler.filter(gl::TEXTURE_MIN_FILTER, gl::NEAREST);
+    sampler.compare(Some(gl::LEQUAL));
+    assert_eq!(null.state().samplers[&sampler.handle()][&gl::TEXTURE_MIN_FILTER], gl::NEAREST as i32);
+    assert_eq!(null.state().samplers[&sampler.handle()][&gl::TEXTURE_COMPARE_FUNC], gl::LEQUAL as i32);
+
+    assert!(program.set_sampler_with("layers", &layers, &sampler, 3).is_ok());
+    assert_eq!(null.state().bound_samplers[&3], sampler.handle());
+    assert!(program.set_sampler("layers", &layers, 3).is_ok());
+    assert_eq!(null.state().bound_samplers[&3], 0);
+
+    let flat = Texture::with_descriptor(&ctx, TextureDescriptor::flat(TextureFormat::R8, (2, 2))).unwrap();
+    assert_eq!(program.set_sampler("layers", &flat, 0), Err(String::from("uniform 'layers' is a sampler2DArray, which can't sample a 2D texture")));
+}
+
+#[test]
+fn binds_foreign_textures() {
+    let null = NullBackend::new();
+    let ctx = Context::with_backend(Box::new(null.clone()));
+
+    let texture = Texture::with_descriptor(&ctx, TextureDescriptor::flat(TextureFormat::R8, (2, 2))).unwrap();
+    texture.bind_at(4);
+    tinygl::bind_texture(&ctx, 4, gl::TEXTURE_3D, 77);
+    assert_eq!(null.state().bound_textures[&(4, gl::TEXTURE_3D)], 77);
+    // the 2D one doesn't stay bound next to it
+    assert_eq!(null.state().bound_textures.get(&(4, gl::TEXTURE_2D)).cloned().unwrap_or(0), 0);
+}
+
+#[test]
+fn attaches_depth_layers() {
+    let null = NullBackend::new();
+    let ctx = Context::with_backend(Box::new(null.clone()));
+
+    let depth = Texture::with_descriptor(&ctx, TextureDescriptor::array(TextureFormat::Depth24, (4, 4), 3)).unwrap();
+    let color = Texture::with_descriptor(&ctx, TextureDescriptor::array(TextureFormat::RGBA8, (4, 4), 3)).unwrap();
+
+    let mut fbo = FrameBufferObject::new(&ctx, (4, 4));
+    assert_eq!(fbo.attach_depth_layer(&depth, 3), Err(String::from("texture has no layer 3")));
+    assert_eq!(fbo.attach_depth_layer(&color, 0), Err(String::from("can't attach RGBA8 as depth")));
+    assert!(fbo.attach_depth_layer(&depth, 2).is_ok());
+    assert!(fbo.attach_depth_layer(&depth, 1).is_err());
+
+    let attachments = null.state().attachments.clone();
+    assert_eq!(attachments.len(), 1);
+    assert_eq!(attachments.values().next(), Some(&(depth.handle(), Some(2))));
+
+    let mut small = FrameBufferObject::new(&ctx, (2, 2));
+    assert_eq!(small.attach_depth_layer(&depth, 0), Err(String::from("texture of size (4, 4) doesn't fit a framebuffer of size (2, 2)")));
+}
@@ -0,0 +1,131 @@
+extern crate cgmath;
+extern crate gl;
+extern crate tinygl;
+
+use cgmath::{Matrix4, Vector3};
+use tinygl::backend::NullBackend;
+use tinygl::{Context, Program, Std140, Uniform, UniformBuffer};
+
+const VERTEX: &str = "
+    in vec2 vertex;
+    uniform highp float offsets[4];
+    void main() {
+        gl_Position = vec4(vertex + vec2(offsets[0]), 0.0, 1.0);
+    }
+    ";
+
+const FRAGMENT: &str = "
+    struct Light {
+        vec3 color;
+    };
+    layout(std140) uniform Lights {
+        vec4 ambient;
+        mat4 transforms[2];
+    };
+    uniform sampler2D tex;
+    uniform vec3 tint;
+    out vec4 color;
+    void main() {
+        color = texture(tex, vec2(0.5)) * ambient + vec4(tint, 0.0);
+    }
+    ";
+
+fn program() -> (NullBackend, Context, Program) {
+    let null = NullBackend::new();
+    let ctx = Context::with_backend(Box::new(null.clone()));
+    let program = Program::new(&ctx, VERTEX, FRAGMENT);
+    program.bind();
+    (null, ctx, program)
+}
+
+#[test]
+fn reflects_variables() {
+    let (_, _, program) = program();
+
+    let attributes: Vec<&str> = program.attributes().iter().map(|attribute| &attribute.name[..]).collect();
+    assert_eq!(attributes, vec!("vertex"));
+
+    let uniforms: Vec<(String, &str, i32)> = program.uniforms().into_iter()
+        .map(|uniform| (uniform.name.clone(), uniform.type_name(), uniform.size))
+        .collect();
+    assert_eq!(uniforms, vec!(
+        (String::from("offsets"), "float", 4),
+        (String::from("tex"), "sampler2D", 1),
+        (String::from("tint"), "vec3", 1),
+    ));
+
+    let element = program.uniform_info("offsets[1]").unwrap();
+    assert_eq!(element.size, 3);
+    assert!(program.uniform_info("offsets[4]").is_none());
+
+    let blocks: Vec<String> = program.uniform_blocks().into_iter().map(|block| block.name).collect();
+    assert_eq!(blocks, vec!(String::from("Lights")));
+}
+
+#[test]
+fn checks_types() {
+    let (null, ctx, program) = program();
+
+    assert_eq!(program.set_uniform("tint", Uniform::Float(1.0)), Err(String::from("uniform 'tint' is a vec3, not a float")));
+    assert_eq!(program.set_uniform("missing", Uniform::Float(1.0)), Err(String::from("No such uniform: missing")));
+    assert!(program.set_uniform("tint", Uniform::Vec3(Vector3::new(1.0, 0.0, 0.0))).is_ok());
+
+    // the untyped setter reports the error instead
+    program.uniform("tint", Uniform::Signed(1));
+    assert_eq!(ctx.warnings(), vec!(String::from("uniform 'tint' is a vec3, not a int")));
+
+    let texture = tinygl::Texture::new(&ctx, gl::TEXTURE_2D);
+    assert_eq!(program.set_sampler("tint", &texture, 2), Err(String::from("uniform 'tint' is a vec3, not a sampler")));
+    assert!(program.set_sampler("tex", &texture, 2).is_ok());
+    assert_eq!(null.state().bound_textures.get(&(2, gl::TEXTURE_2D)), Some(&texture.handle()));
+}
+
+#[test]
+fn sets_arrays() {
+    let (null, _, program) = program();
+
+    let values = [Uniform::Float(1.0), Uniform::Float(2.0)];
+    assert!(program.set_uniform_array("offsets", &values).is_ok());
+    assert!(program.set_uniform_array("offsets[3]", &[Uniform::Float(4.0)]).is_ok());
+    assert_eq!(program.set_uniform_array("offsets[3]", &values), Err(String::from("uniform 'offsets[3]' holds 1 values, not 2")));
+    assert!(program.set_uniform_array("offsets", &[Uniform::Float(1.0), Uniform::Signed(2)]).is_err());
+
+    let state = null.state();
+    let values = &state.programs[&program.handle().unwrap()].values;
+    assert_eq!(values.get(&1), Some(&Uniform::Float(2.0)));
+    assert_eq!(values.get(&3), Some(&Uniform::Float(4.0)));
+}
+
+#[test]
+fn lays_out_std140() {
+    let mut data = Std140::new();
+    data.push(Uniform::Float(1.0));
+    data.push(Uniform::Vec3(Vector3::new(1.0, 2.0, 3.0)));
+    data.push(Uniform::Float(4.0));
+    assert_eq!(data.len(), 32);
+
+    data.push_struct(|light| { light.push(Uniform::Float(1.0)).push(Uniform::Mat4(Matrix4::from_scale(1.0))); });
+    assert_eq!(data.len(), 32 + 80);
+
+    data.push_array(&[Uniform::Float(1.0), Uniform::Float(2.0)]);
+    assert_eq!(data.len(), 32 + 80 + 32);
+}
+
+#[test]
+fn binds_uniform_blocks() {
+    let (null, ctx, program) = program();
+
+    let mut data = Std140::new();
+    data.push(Uniform::Vec3(Vector3::new(0.1, 0.1, 0.1)));
+    data.push_array(&[Uniform::Mat4(Matrix4::from_scale(1.0)), Uniform::Mat4(Matrix4::from_scale(2.0))]);
+    let buffer = UniformBuffer::new(&ctx, &data);
+    assert_eq!(buffer.size(), 16 + 128);
+
+    assert!(program.set_uniform_block("Lights", &buffer, 1).is_ok());
+    assert_eq!(program.set_uniform_block("Shadows", &buffer, 1), Err(String::from("No such uniform block: Shadows")));
+
+    let state = null.state();
+    let bound = state.uniform_buffers[&1];
+    assert_eq!(state.buffers[&bound].len(), 16 + 128);
+    assert_eq!(state.programs[&program.handle().unwrap()].block_bindings.get(&0), Some(&1));
+}